@@ -1,22 +1,139 @@
 //! Десериализатор для формата Bioware GFF (Generic File Format)
 
-use std::io::{Read, Seek};
+use std::convert::TryFrom;
+use std::io::{Cursor, Read, Seek};
+use std::marker::PhantomData;
 use encoding::{DecoderTrap, EncodingRef};
-use serde::de::{self, IntoDeserializer, Visitor, DeserializeSeed};
+use serde::de::{self, Deserialize, DeserializeOwned, IntoDeserializer, Unexpected, Visitor, DeserializeSeed};
 
-use crate::value::{SimpleValueRef, Value};
+use crate::value::{SimpleValue, SimpleValueRef, Value};
 use crate::error::{Error, Result};
-use crate::parser::{Parser, Token};
+use crate::header::Header;
+use crate::index::{LabelIndex, StringIndex};
+use crate::string::{Game, Language};
+use crate::parser::{Limits, Parser, Token};
+use crate::ser::{LocStringData, LOC_STRING_TOKEN};
+pub(crate) use self::raw::GffDeserializer;
+use self::value::ResRefAccess;
 
+mod raw;
 mod string;
 mod value;
 
+/// Имя newtype-структуры, которую запрашивает десериализатор [`Value`]. Встретив запрос на
+/// чтение newtype-структуры с таким именем, GFF десериализатор сообщает посетителю о значениях
+/// типа `ResRef` особым образом, чтобы их можно было отличить от строк
+///
+/// [`Value`]: ../value/enum.Value.html
+pub(crate) const VALUE_TOKEN: &str = "$serde_gff::private::Value";
+/// Ключ единственной записи отображения, в виде которого [`Value`] получает значение типа
+/// `ResRef`. Так как ключ длиннее 16 байт, он не может совпасть с меткой поля.
+///
+/// Также является именем newtype-структуры, встретив которую сериализатор поля записывает
+/// ее содержимое в виде поля типа `ResRef`, а не `Void`, см. модуль [`resref`]
+///
+/// [`Value`]: ../value/enum.Value.html
+/// [`resref`]: ../resref/index.html
+pub(crate) const RESREF_TOKEN: &str = "$serde_gff::private::ResRef";
+
+/// Зарезервированное имя поля, в которое при десериализации структуры записывается
+/// идентификатор ее типа (`tag`) в виде `u32`. Например, для чтения тега достаточно объявить
+/// поле `#[serde(rename = "$tag")] tag: u32`. Поле заполняется только в том случае, если
+/// оно объявлено в десериализуемой структуре, и никогда не считывается из самого файла.
+///
+/// При сериализации значение такого поля (беззнаковое целое, не превышающее `u32`)
+/// записывается в качестве идентификатора типа структуры, а не в виде отдельного поля
+pub const TAG_FIELD: &str = "$tag";
+
+/// Состояние чтения синтетического поля [`TAG_FIELD`] с идентификатором типа структуры
+///
+/// [`TAG_FIELD`]: constant.TAG_FIELD.html
+#[derive(Debug, Clone, Copy)]
+enum PendingTag {
+  /// Ключ поля еще не был отдан посетителю
+  Key(u32),
+  /// Ключ отдан, ожидается чтение значения
+  Value(u32),
+}
+
 /// Структура для поддержки чтения GFF файлов в экосистеме serde
-pub struct Deserializer<R: Read + Seek> {
+///
+/// # Строки
+/// Способ передачи строк посетителю определяется параметром `S`. По умолчанию ([`Owned`])
+/// строки и массивы байт всегда передаются посетителю во владение (`visit_string` и
+/// `visit_byte_buf`), поскольку источником данных может быть любой поток. Поэтому поля
+/// типа `&str` и `&[u8]` десериализовать нельзя, а поля типа `Cow<str>` всегда получают
+/// копию строки.
+///
+/// Десериализатор [`SliceDeserializer`], читающий файл из буфера в памяти, передает строки
+/// (значения полей типа `String`) посетителю без копирования (`visit_borrowed_str`), поэтому
+/// их можно десериализовать в поля типа `&str` и `Cow<str>` с атрибутом `#[serde(borrow)]`.
+/// Такой десериализатор декодирует строки только в кодировке `UTF-8`
+///
+/// [`Owned`]: enum.Owned.html
+/// [`SliceDeserializer`]: type.SliceDeserializer.html
+pub struct Deserializer<R: Read + Seek, S = Owned> {
   /// Итератор, поставляющий токены в процессе разбора файла
   parser: Parser<R>,
   /// Подсмотренный вперед на один переход токен
   peeked: Option<Token>,
+  /// Индекс метки поля, значение которого читается в данный момент. Используется только
+  /// для формирования понятных сообщений об ошибках
+  label: Option<LabelIndex>,
+  /// Идентификатор типа читаемой структуры, который необходимо сообщить в виде поля
+  /// [`TAG_FIELD`] до чтения настоящих полей
+  ///
+  /// [`TAG_FIELD`]: constant.TAG_FIELD.html
+  tag: Option<PendingTag>,
+  /// Если `true`, то при десериализации структуры, имя одного из полей которой длиннее
+  /// 16 байт, возвращается ошибка, так как такое поле никогда не может быть прочитано
+  deny_unmatchable: bool,
+  /// Если `true`, то поля типа `ResRef` могут быть прочитаны в строку, а не только в массив байт
+  resref_as_string: bool,
+  /// Способ передачи строк посетителю
+  strings: PhantomData<S>,
+}
+
+/// Десериализатор GFF файла, находящегося в буфере в памяти, передающий строки посетителю
+/// без копирования, см. раздел "Строки" документации [`Deserializer`]
+///
+/// [`Deserializer`]: struct.Deserializer.html
+pub type SliceDeserializer<'de> = Deserializer<Cursor<&'de [u8]>, Borrowed<'de>>;
+
+/// Способ передачи посетителю строк, прочитанных из источника данных `R`
+pub trait ReadStr<'de, R: Read + Seek> {
+  /// Читает строку с указанным индексом и передает ее посетителю
+  fn read_str<V>(parser: &mut Parser<R>, index: StringIndex, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>;
+}
+/// Строки декодируются в кодировке парсера и передаются посетителю во владение
+#[derive(Debug)]
+pub enum Owned {}
+impl<'de, R: Read + Seek> ReadStr<'de, R> for Owned {
+  #[inline]
+  fn read_str<V>(parser: &mut Parser<R>, index: StringIndex, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    visitor.visit_string(parser.read_string(index)?)
+  }
+}
+/// Строки передаются посетителю в виде ссылок на буфер, из которого читается файл, если их
+/// байты являются корректной строкой `UTF-8` (см. [`Parser::read_str_borrowed`]). Остальные
+/// строки декодируются в кодировке парсера и передаются посетителю во владение
+///
+/// [`Parser::read_str_borrowed`]: ../parser/struct.Parser.html#method.read_str_borrowed
+#[derive(Debug)]
+pub struct Borrowed<'de>(PhantomData<&'de [u8]>);
+impl<'de> ReadStr<'de, Cursor<&'de [u8]>> for Borrowed<'de> {
+  fn read_str<V>(parser: &mut Parser<Cursor<&'de [u8]>>, index: StringIndex, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    match parser.read_str_borrowed(index) {
+      Ok(value) => visitor.visit_borrowed_str(value),
+      Err(Error::Encoding(_)) => visitor.visit_string(parser.read_string(index)?),
+      Err(err) => Err(err),
+    }
+  }
 }
 
 impl<R: Read + Seek> Deserializer<R> {
@@ -30,7 +147,7 @@ impl<R: Read + Seek> Deserializer<R> {
   /// # Ошибки
   /// В случае, если не удалось прочитать заголовок GFF файла -- например, он слишком короткий
   pub fn new(reader: R) -> Result<Self> {
-    Ok(Deserializer { parser: Parser::new(reader)?, peeked: None })
+    Ok(Deserializer { parser: Parser::new(reader)?, peeked: None, label: None, tag: None, deny_unmatchable: false, resref_as_string: true, strings: PhantomData })
   }
   /// Создает десериализатор для чтения GFF файла из указанного источника данных с использованием
   /// указанной кодировки для декодирования строк.
@@ -44,16 +161,191 @@ impl<R: Read + Seek> Deserializer<R> {
   /// # Ошибки
   /// В случае, если не удалось прочитать заголовок GFF файла -- например, он слишком короткий
   pub fn with_encoding(reader: R, encoding: EncodingRef, trap: DecoderTrap) -> Result<Self> {
-    Ok(Deserializer { parser: Parser::with_encoding(reader, encoding, trap)?, peeked: None })
+    Ok(Deserializer { parser: Parser::with_encoding(reader, encoding, trap)?, peeked: None, label: None, tag: None, deny_unmatchable: false, resref_as_string: true, strings: PhantomData })
+  }
+  /// Создает десериализатор для чтения GFF файла указанной игры из указанного источника данных
+  /// с кодировками строк, которые использует эта игра. См. [`Parser::for_game`]
+  ///
+  /// # Параметры
+  /// - `reader`: Источник данных для чтения файла
+  /// - `game`: Игра, создавшая файл
+  ///
+  /// # Ошибки
+  /// В случае, если не удалось прочитать заголовок GFF файла -- например, он слишком короткий
+  ///
+  /// [`Parser::for_game`]: ../parser/struct.Parser.html#method.for_game
+  pub fn for_game(reader: R, game: Game) -> Result<Self> {
+    Ok(Deserializer { parser: Parser::for_game(reader, game)?, peeked: None, label: None, tag: None, deny_unmatchable: false, resref_as_string: true, strings: PhantomData })
+  }
+  /// Создает десериализатор для чтения GFF файла из указанного источника данных с кодировкой
+  /// `UTF-8` для декодирования строк и указанными ограничениями на размеры читаемых данных.
+  /// Используется для чтения файлов из ненадежных источников. См. [`Parser::set_limits`]
+  ///
+  /// # Параметры
+  /// - `reader`: Источник данных для чтения файла
+  /// - `limits`: Ограничения на размеры строк, списков, суммарный размер данных и глубину вложенности
+  ///
+  /// # Ошибки
+  /// В случае, если не удалось прочитать заголовок GFF файла -- например, он слишком короткий
+  ///
+  /// [`Parser::set_limits`]: ../parser/struct.Parser.html#method.set_limits
+  pub fn with_limits(reader: R, limits: Limits) -> Result<Self> {
+    Ok(Deserializer { parser: Parser::with_limits(reader, limits)?, peeked: None, label: None, tag: None, deny_unmatchable: false, resref_as_string: true, strings: PhantomData })
+  }
+}
+
+impl<'de> SliceDeserializer<'de> {
+  /// Создает десериализатор для чтения GFF файла из буфера в памяти с использованием кодировки
+  /// `UTF-8` для декодирования строк. Строки передаются посетителю без копирования, ссылками
+  /// на указанный буфер
+  ///
+  /// # Параметры
+  /// - `bytes`: Буфер, содержащий файл
+  ///
+  /// # Ошибки
+  /// В случае, если не удалось прочитать заголовок GFF файла -- например, он слишком короткий
+  pub fn from_slice(bytes: &'de [u8]) -> Result<Self> {
+    Ok(Deserializer { parser: Parser::new(Cursor::new(bytes))?, peeked: None, label: None, tag: None, deny_unmatchable: false, resref_as_string: true, strings: PhantomData })
+  }
+  /// Создает десериализатор для чтения GFF файла из буфера в памяти с использованием указанной
+  /// кодировки для декодирования строк. Строки, байты которых являются корректной строкой
+  /// `UTF-8`, передаются посетителю без копирования, остальные декодируются указанной кодировкой
+  ///
+  /// # Параметры
+  /// - `bytes`: Буфер, содержащий файл
+  /// - `encoding`: Кодировка для декодирования символов в строках
+  /// - `trap`: Способ обработки символов в строках, которые не удалось декодировать с
+  ///   использованием выбранной кодировки
+  ///
+  /// # Ошибки
+  /// В случае, если не удалось прочитать заголовок GFF файла -- например, он слишком короткий
+  pub fn from_slice_with_encoding(bytes: &'de [u8], encoding: EncodingRef, trap: DecoderTrap) -> Result<Self> {
+    Ok(Deserializer { parser: Parser::with_encoding(Cursor::new(bytes), encoding, trap)?, peeked: None, label: None, tag: None, deny_unmatchable: false, resref_as_string: true, strings: PhantomData })
+  }
+}
+
+impl<R: Read + Seek, S> Deserializer<R, S> {
+  /// Читает все метки полей файла за один раз, после чего имена полей при десериализации
+  /// структур берутся из памяти, а не читаются из файла при каждом обращении.
+  /// См. [`Parser::preload_labels`]
+  ///
+  /// [`Parser::preload_labels`]: ../parser/struct.Parser.html#method.preload_labels
+  #[inline]
+  pub fn preload_labels(&mut self) -> Result<()> {
+    self.parser.preload_labels()
+  }
+  /// Включает или выключает проверку имен полей десериализуемых структур. Метка поля в GFF
+  /// файле не может быть длиннее 16 байт, поэтому поле, имя которого (например, заданное
+  /// через `#[serde(rename = "...")]`) длиннее, никогда не будет найдено в файле и всегда
+  /// будет считаться отсутствующим. По умолчанию проверка выключена.
+  ///
+  /// При включенной проверке десериализация такой структуры завершается ошибкой
+  /// [`Error::Deserialize`] с именем некорректного поля.
+  ///
+  /// [`Error::Deserialize`]: ../error/enum.Error.html#variant.Deserialize
+  #[inline]
+  pub fn deny_unmatchable_fields(&mut self, deny: bool) {
+    self.deny_unmatchable = deny;
+  }
+  /// Разрешает или запрещает чтение полей типа `ResRef` в строки. По умолчанию разрешено.
+  ///
+  /// Поле типа `ResRef` может быть прочитано как в строку (`String`, `&str`), так и в массив
+  /// байт (`Vec<u8>` с `#[serde(with = "serde_bytes")]` или `ByteBuf`). Имя ресурса хранится
+  /// в файле как набор байт без указания кодировки, поэтому при чтении в строку они должны
+  /// быть корректной строкой UTF-8. Если чтение в строку запрещено, на месте строки
+  /// допускаются только поля типа `String`, а `ResRef` читается только в массив байт.
+  #[inline]
+  pub fn with_resref_as_string(&mut self, allow: bool) {
+    self.resref_as_string = allow;
+  }
+  /// Устанавливает кодировку для декодирования частей локализуемых строк на указанном языке.
+  /// См. [`Parser::set_language_encoding`]
+  ///
+  /// [`Parser::set_language_encoding`]: ../parser/struct.Parser.html#method.set_language_encoding
+  #[inline]
+  pub fn set_language_encoding(&mut self, language: Language, encoding: EncodingRef) {
+    self.parser.set_language_encoding(language, encoding)
+  }
+  /// Устанавливает для всех известных языков кодировки, используемые движком Aurora.
+  /// См. [`Parser::use_language_encodings`]
+  ///
+  /// [`Parser::use_language_encodings`]: ../parser/struct.Parser.html#method.use_language_encodings
+  #[inline]
+  pub fn use_language_encodings(&mut self) {
+    self.parser.use_language_encodings()
+  }
+  /// Возвращает заголовок читаемого файла, например, для выбора типа, в который его следует
+  /// десериализовать, по сигнатуре. См. [`Parser::header`]
+  ///
+  /// [`Parser::header`]: ../parser/struct.Parser.html#method.header
+  #[inline]
+  pub fn header(&self) -> &Header {
+    self.parser.header()
+  }
+  /// Уничтожает десериализатор и возвращает нижележащий источник данных.
+  /// См. [`Parser::into_inner`]
+  ///
+  /// [`Parser::into_inner`]: ../parser/struct.Parser.html#method.into_inner
+  #[inline]
+  pub fn into_inner(self) -> R {
+    self.parser.into_inner()
+  }
+  /// Читает следующее простое значение из потока, минуя модель данных serde. Метки полей
+  /// пропускаются, значения, хранящиеся отдельно от описания поля, сразу же читаются.
+  ///
+  /// Если следующий токен является структурным (начало или конец структуры, элемента, списка
+  /// или всего файла), он поглощается и возвращается `None`. Таким образом, метод позволяет
+  /// прочитать все значения плоской структуры, не строя полностью дерево [`Value`].
+  ///
+  /// [`Value`]: ../value/enum.Value.html
+  pub fn next_any_simple(&mut self) -> Result<Option<SimpleValue>> {
+    loop {
+      return match self.next_token()? {
+        Token::Label(..) => continue,
+        Token::Value(value) => Ok(Some(self.parser.read_value(value)?)),
+        _ => Ok(None),
+      };
+    }
   }
 
+  /// Читает элементы списка, каждый из которых должен быть структурой с единственным полем
+  /// типа `Byte`, и возвращает значения этих полей. Токен начала списка уже должен быть прочитан
+  fn read_byte_list(&mut self) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    loop {
+      match self.next_token()? {
+        Token::ListEnd => return Ok(bytes),
+        Token::ItemBegin { count: 1, .. } => {},
+        token => return Err(self.parser.unexpected("struct with single Byte field", token)),
+      }
+      match self.next_token()? {
+        Token::Label(..) => {},
+        token => return Err(self.parser.unexpected("Label", token)),
+      }
+      match self.next_token()? {
+        Token::Value(SimpleValueRef::Byte(value)) => bytes.push(value),
+        token => return Err(self.parser.unexpected("Byte", token)),
+      }
+      match self.next_token()? {
+        Token::ItemEnd => {},
+        token => return Err(self.parser.unexpected("ItemEnd", token)),
+      }
+    }
+  }
   /// Возвращает следующий токен из потока, поглощая его
   #[inline]
   fn next_token(&mut self) -> Result<Token> {
-    match self.peeked.take() {
-      Some(v) => Ok(v),
-      None => self.parser.next_token(),
+    if let Some(token) = self.peeked.take() {
+      return Ok(token);
+    }
+    let token = self.parser.next_token()?;
+    match token {
+      Token::Label(index) => self.label = Some(index),
+      // Значение поля следует сразу за его меткой
+      Token::Value(..) | Token::StructBegin { .. } | Token::ListBegin(..) => {},
+      _ => self.label = None,
     }
+    Ok(token)
   }
   /// Подсматривает следующий токен в потоке, не поглощая его
   fn peek_token(&mut self) -> Result<&Token> {
@@ -65,6 +357,20 @@ impl<R: Read + Seek> Deserializer<R> {
       _ => unreachable!(),
     }
   }
+  /// Формирует ошибку о несоответствии вида GFF значения ожидаемому, включающую метку поля,
+  /// если значение является значением поля
+  fn mismatch(&mut self, expected: &str, token: Token) -> Error {
+    let found = match token {
+      Token::ListBegin(count) => format!("list with {} elements", count),
+      Token::RootBegin { .. } | Token::ItemBegin { .. } | Token::StructBegin { .. } => "struct".to_owned(),
+      token => return self.parser.unexpected("RootBegin, ItemBegin, StructBegin, ListBegin", token),
+    };
+    let label = self.label.and_then(|index| self.parser.read_label(index).ok());
+    match label {
+      Some(label) => Error::Deserialize(format!("Field `{}`: expected {}, but {} found", label, expected, found)),
+      None => Error::Deserialize(format!("Expected {}, but {} found", expected, found)),
+    }
+  }
   /// Десериализует все примитивные типы GFF файла (все типы, кроме структур и списков)
   fn deserialize_value<'de, V>(&mut self, value: SimpleValueRef, visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
@@ -76,6 +382,29 @@ impl<R: Read + Seek> Deserializer<R> {
   }
 }
 
+/// Десериализует значение из произвольного потока, содержащего GFF файл. Строки декодируются
+/// в кодировке `UTF-8`, см. [`Deserializer::new`]
+///
+/// [`Deserializer::new`]: struct.Deserializer.html#method.new
+#[inline]
+pub fn from_reader<R, T>(reader: R) -> Result<T>
+  where R: Read + Seek,
+        T: DeserializeOwned,
+{
+  let mut de = Deserializer::new(reader)?;
+  T::deserialize(&mut de)
+}
+/// Десериализует значение из массива байт, содержащего GFF файл. Строки декодируются
+/// в кодировке `UTF-8`, см. [`Deserializer::new`]
+///
+/// [`Deserializer::new`]: struct.Deserializer.html#method.new
+#[inline]
+pub fn from_slice<T>(bytes: &[u8]) -> Result<T>
+  where T: DeserializeOwned,
+{
+  from_reader(Cursor::new(bytes))
+}
+
 /// Реализует разбор простых типов данных.
 ///
 /// # Параметры
@@ -94,7 +423,7 @@ macro_rules! primitive {
       if let Token::Value(SimpleValueRef::$type(value)) = token {
         return visitor.$visit_method(value);
       }
-      return Err(Error::Unexpected(stringify!($type), token));
+      return Err(self.parser.unexpected(stringify!($type), token));
     }
   );
   ($dser_method:ident, $visit_method:ident, $type:ident, $read:ident) => (
@@ -105,37 +434,95 @@ macro_rules! primitive {
       if let Token::Value(SimpleValueRef::$type(value)) = token {
         return visitor.$visit_method(self.parser.$read(value)?);
       }
-      return Err(Error::Unexpected(stringify!($type), token));
+      return Err(self.parser.unexpected(stringify!($type), token));
+    }
+  );
+}
+/// Реализует разбор целочисленных типов данных. Помимо значения GFF типа, соответствующего
+/// типу Rust, принимает значение любого другого целочисленного GFF типа не шире типа Rust --
+/// это позволяет читать поля, тип которых был расширен в новых версиях файлов. Более широкие
+/// GFF типы не принимаются, даже если конкретное значение поместилось бы в тип Rust.
+/// Значение проверяется только при несовпадении знаковости типов
+///
+/// # Параметры
+/// - `dser_method`: реализуемый макросом метод
+/// - `visit_method`: метод типажа [`Visitor`], который будет вызван для создания конечного значения
+/// - `type`: тип Rust, в который преобразуется прочитанное значение
+/// - `expected`: перечисление принимаемых GFF типов для сообщения об ошибке
+///
+/// [`Visitor`]: https://docs.serde.rs/serde/de/trait.Visitor.html
+macro_rules! integer {
+  ($dser_method:ident, $visit_method:ident, $type:ty, $expected:expr) => (
+    fn $dser_method<V>(self, visitor: V) -> Result<V::Value>
+      where V: Visitor<'de>,
+    {
+      let token = self.next_token()?;
+      let width = match token {
+        Token::Value(SimpleValueRef::Byte(_))    | Token::Value(SimpleValueRef::Char(_))  => 1,
+        Token::Value(SimpleValueRef::Word(_))    | Token::Value(SimpleValueRef::Short(_)) => 2,
+        Token::Value(SimpleValueRef::Dword(_))   | Token::Value(SimpleValueRef::Int(_))   => 4,
+        Token::Value(SimpleValueRef::Dword64(_)) | Token::Value(SimpleValueRef::Int64(_)) => 8,
+        _ => 0,
+      };
+      if width == 0 || width > std::mem::size_of::<$type>() {
+        return Err(self.parser.unexpected($expected, token));
+      }
+      let value = match token {
+        Token::Value(SimpleValueRef::Byte(value))    => i128::from(value),
+        Token::Value(SimpleValueRef::Char(value))    => i128::from(value),
+        Token::Value(SimpleValueRef::Word(value))    => i128::from(value),
+        Token::Value(SimpleValueRef::Short(value))   => i128::from(value),
+        Token::Value(SimpleValueRef::Dword(value))   => i128::from(value),
+        Token::Value(SimpleValueRef::Int(value))     => i128::from(value),
+        Token::Value(SimpleValueRef::Dword64(value)) => i128::from(self.parser.read_u64(value)?),
+        Token::Value(SimpleValueRef::Int64(value))   => i128::from(self.parser.read_i64(value)?),
+        _ => unreachable!(),
+      };
+      match <$type>::try_from(value) {
+        Ok(value) => visitor.$visit_method(value),
+        Err(_) if value < 0 => Err(de::Error::invalid_value(Unexpected::Signed(value as i64), &stringify!($type))),
+        Err(_) => Err(de::Error::invalid_value(Unexpected::Unsigned(value as u64), &stringify!($type))),
+      }
     }
   );
 }
 macro_rules! complex {
   ($token:ident, $self:ident, $visitor:ident . $method:ident) => (
+    complex!($token, $self, $visitor.$method(&mut *$self))
+  );
+  ($token:ident, $self:ident, $visitor:ident . $method:ident ( $access:expr )) => (
     {
-      let value = $visitor.$method(&mut *$self)?;
+      let value = match $visitor.$method($access) {
+        Ok(value) => value,
+        Err(err) => {
+          // Не сообщенный из-за ошибки идентификатор не должен достаться следующей структуре
+          $self.tag = None;
+          return Err(err);
+        },
+      };
       let token = $self.next_token()?;
       if let Token::$token = token {
         Ok(value)
       } else {
-        Err(Error::Unexpected(stringify!($token), token))
+        Err($self.parser.unexpected(stringify!($token), token))
       }
     }
   );
 }
-impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R> {
+impl<'de, 'a, R: Read + Seek, S: ReadStr<'de, R>> de::Deserializer<'de> for &'a mut Deserializer<R, S> {
   type Error = Error;
 
   #[inline]
   fn is_human_readable(&self) -> bool { false }
 
-  primitive!(deserialize_i8 , visit_i8 , Char);
-  primitive!(deserialize_u8 , visit_u8 , Byte);
-  primitive!(deserialize_i16, visit_i16, Short);
-  primitive!(deserialize_u16, visit_u16, Word);
-  primitive!(deserialize_i32, visit_i32, Int);
-  primitive!(deserialize_u32, visit_u32, Dword);
-  primitive!(deserialize_i64, visit_i64, Int64, read_i64);
-  primitive!(deserialize_u64, visit_u64, Dword64, read_u64);
+  integer!(deserialize_i8 , visit_i8 , i8 , "Byte, Char");
+  integer!(deserialize_u8 , visit_u8 , u8 , "Byte, Char");
+  integer!(deserialize_i16, visit_i16, i16, "Byte, Char, Word, Short");
+  integer!(deserialize_u16, visit_u16, u16, "Byte, Char, Word, Short");
+  integer!(deserialize_i32, visit_i32, i32, "Byte, Char, Word, Short, Dword, Int");
+  integer!(deserialize_u32, visit_u32, u32, "Byte, Char, Word, Short, Dword, Int");
+  integer!(deserialize_i64, visit_i64, i64, "Byte, Char, Word, Short, Dword, Int, Dword64, Int64");
+  integer!(deserialize_u64, visit_u64, u64, "Byte, Char, Word, Short, Dword, Int, Dword64, Int64");
   primitive!(deserialize_f32, visit_f32, Float);
   primitive!(deserialize_f64, visit_f64, Double, read_f64);
 
@@ -146,27 +533,39 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
     if let Token::Value(SimpleValueRef::Byte(value)) = token {
       return visitor.visit_bool(value != 0);
     }
-    return Err(Error::Unexpected("Byte", token));
+    return Err(self.parser.unexpected("Byte", token));
   }
+  /// Читает поле типа `Byte` или `Char`, декодируя его байт в кодировке строк десериализатора
   fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
     let token = self.next_token()?;
     if let Token::Value(SimpleValueRef::Byte(value)) = token {
-      return visitor.visit_char(value as char);
+      return visitor.visit_char(self.parser.decode_char(value)?);
     }
     if let Token::Value(SimpleValueRef::Char(value)) = token {
-      return visitor.visit_char(value as u8 as char);
+      return visitor.visit_char(self.parser.decode_char(value as u8)?);
     }
-    return Err(Error::Unexpected("Byte, Char", token));
+    return Err(self.parser.unexpected("Byte, Char", token));
   }
 
-  #[inline]
+  /// Читает те же поля, что и [`deserialize_string`], но значения полей типа `String`
+  /// передает посетителю способом, определяемым параметром `S` десериализатора
+  ///
+  /// [`deserialize_string`]: #method.deserialize_string
   fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
+    if let Token::Value(SimpleValueRef::String(index)) = *self.peek_token()? {
+      self.next_token()?;
+      return S::read_str(&mut self.parser, index, visitor);
+    }
     self.deserialize_string(visitor)
   }
+  /// Читает поле типа `String`, а также поле типа `ResRef`, если это не запрещено
+  /// методом [`with_resref_as_string`]
+  ///
+  /// [`with_resref_as_string`]: struct.Deserializer.html#method.with_resref_as_string
   fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
@@ -175,10 +574,11 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
       Token::Value(SimpleValueRef::String(value)) => {
         visitor.visit_string(self.parser.read_string(value)?)
       },
-      Token::Value(SimpleValueRef::ResRef(value)) => {
+      Token::Value(SimpleValueRef::ResRef(value)) if self.resref_as_string => {
         visitor.visit_string(self.parser.read_resref(value)?.as_string()?)
       },
-      _ => Err(Error::Unexpected("String, ResRef", token)),
+      _ if self.resref_as_string => Err(self.parser.unexpected("String, ResRef", token)),
+      _ => Err(self.parser.unexpected("String", token)),
     }
   }
   #[inline]
@@ -187,6 +587,11 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
   {
     self.deserialize_byte_buf(visitor)
   }
+  /// Читает поля типа `Void` и `ResRef` независимо от настройки [`with_resref_as_string`],
+  /// а также списки структур с единственным полем типа `Byte`, в виде которых некоторые
+  /// файлы хранят двоичные данные
+  ///
+  /// [`with_resref_as_string`]: struct.Deserializer.html#method.with_resref_as_string
   fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
@@ -198,7 +603,8 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
       Token::Value(SimpleValueRef::ResRef(value)) => {
         visitor.visit_byte_buf(self.parser.read_resref(value)?.0)
       },
-      _ => Err(Error::Unexpected("Void, ResRef", token)),
+      Token::ListBegin(_) => visitor.visit_byte_buf(self.read_byte_list()?),
+      _ => Err(self.parser.unexpected("Void, ResRef, List", token)),
     }
   }
 
@@ -224,7 +630,7 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
         self.parser.skip_next(token);
         visitor.visit_unit()
       },
-      token => Err(Error::Unexpected("RootBegin, ItemBegin, StructBegin", token)),
+      token => Err(self.parser.unexpected("RootBegin, ItemBegin, StructBegin", token)),
     }
   }
 
@@ -234,15 +640,15 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
     let token = self.next_token()?;
     match token {
       Token::Value(value)       => self.deserialize_value(value, visitor),
-      Token::ListBegin { .. }   => complex!(ListEnd, self, visitor.visit_seq),
-      Token::RootBegin { .. }   => complex!(RootEnd, self, visitor.visit_map),
-      Token::ItemBegin { .. }   => complex!(ItemEnd, self, visitor.visit_map),
-      Token::StructBegin { .. } => complex!(StructEnd, self, visitor.visit_map),
+      Token::ListBegin(count)   => complex!(ListEnd, self, visitor.visit_seq(ListAccess { de: &mut *self, count })),
+      Token::RootBegin { count, .. }   => complex!(RootEnd, self, visitor.visit_map(StructAccess { de: &mut *self, count })),
+      Token::ItemBegin { count, .. }   => complex!(ItemEnd, self, visitor.visit_map(StructAccess { de: &mut *self, count })),
+      Token::StructBegin { count, .. } => complex!(StructEnd, self, visitor.visit_map(StructAccess { de: &mut *self, count })),
       Token::Label(index) => {
         let label = self.parser.read_label(index)?;
         visitor.visit_str(label.as_str()?)
       },
-      _ => unimplemented!("`deserialize_any`, token: {:?}", token)
+      token => Err(self.parser.unexpected("Value, Label, RootBegin, ItemBegin, StructBegin, ListBegin", token)),
     }
   }
   fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
@@ -270,7 +676,7 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
       Token::Value(Dword64(val))  => visitor.visit_string(self.parser.read_u64(val)?.to_string()),
       Token::Value(Int64(val))    => visitor.visit_string(self.parser.read_i64(val)?.to_string()),
       Token::Value(String(val))   => visitor.visit_string(self.parser.read_string(val)?),
-      _ => Err(Error::Unexpected("Byte, Char, Word, Short, Dword, Int, Int64, String", token)),
+      _ => Err(self.parser.unexpected("Byte, Char, Word, Short, Dword, Int, Int64, String", token)),
     }
   }
 
@@ -279,10 +685,11 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
   {
     let token = self.next_token()?;
     match token {
-      Token::RootBegin   { .. } => complex!(RootEnd,   self, visitor.visit_map),
-      Token::ItemBegin   { .. } => complex!(ItemEnd,   self, visitor.visit_map),
-      Token::StructBegin { .. } => complex!(StructEnd, self, visitor.visit_map),
-      token => Err(Error::Unexpected("RootBegin, ItemBegin, StructBegin", token)),
+      Token::RootBegin   { count, .. } => complex!(RootEnd,   self, visitor.visit_map(StructAccess { de: &mut *self, count })),
+      Token::ItemBegin   { count, .. } => complex!(ItemEnd,   self, visitor.visit_map(StructAccess { de: &mut *self, count })),
+      Token::StructBegin { count, .. } => complex!(StructEnd, self, visitor.visit_map(StructAccess { de: &mut *self, count })),
+      token @ Token::ListBegin(..) => Err(self.mismatch("struct", token)),
+      token => Err(self.parser.unexpected("RootBegin, ItemBegin, StructBegin", token)),
     }
   }
   fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
@@ -290,8 +697,9 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
   {
     let token = self.next_token()?;
     match token {
-      Token::ListBegin { .. } => complex!(ListEnd, self, visitor.visit_seq),
-      token => Err(Error::Unexpected("ListBegin", token)),
+      Token::ListBegin(count) => complex!(ListEnd, self, visitor.visit_seq(ListAccess { de: &mut *self, count })),
+      token @ Token::StructBegin { .. } => Err(self.mismatch("list", token)),
+      token => Err(self.parser.unexpected("ListBegin", token)),
     }
   }
 
@@ -302,49 +710,178 @@ impl<'de, 'a, R: Read + Seek> de::Deserializer<'de> for &'a mut Deserializer<R>
   {
     self.deserialize_unit(visitor)
   }
-  /// Разбирает в newtype структуру нижележащее значение
-  #[inline]
-  fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+  /// Разбирает в newtype структуру нижележащее значение. Если имя структуры совпадает с
+  /// [`LOC_STRING_TOKEN`], то ожидается поле типа `LocString`, которое передается посетителю
+  /// без потерь в виде массива байт в том формате, в котором оно хранится в файле
+  ///
+  /// [`LOC_STRING_TOKEN`]: ../ser/constant.LOC_STRING_TOKEN.html
+  fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
+    if name == LOC_STRING_TOKEN {
+      let token = self.next_token()?;
+      return match token {
+        Token::Value(SimpleValueRef::LocString(index)) => {
+          let value = self.parser.read_loc_string(index)?;
+          visitor.visit_byte_buf(LocStringData(&value).to_bytes()?)
+        },
+        _ => Err(self.parser.unexpected("LocString", token)),
+      };
+    }
+    if name == VALUE_TOKEN {
+      // Значение `ResRef` передается в виде отображения с особым ключом, ненулевой идентификатор
+      // типа структуры -- в виде поля `TAG_FIELD`, остальные значения разбираются так же, как и всегда
+      match *self.peek_token()? {
+        Token::Value(SimpleValueRef::ResRef(index)) => {
+          self.next_token()?;
+          let resref = self.parser.read_resref(index)?;
+          return visitor.visit_map(ResRefAccess::new(resref));
+        },
+        Token::RootBegin   { tag, .. } |
+        Token::ItemBegin   { tag, .. } |
+        Token::StructBegin { tag, .. } if tag.raw() != 0 => self.tag = Some(PendingTag::Key(tag.raw())),
+        _ => {},
+      }
+      return self.deserialize_any(visitor);
+    }
     visitor.visit_newtype_struct(self)
   }
-  fn deserialize_tuple<V>(self, len: usize, _visitor: V) -> Result<V::Value>
+  /// Разбирает кортеж из GFF списка, количество элементов которого должно в точности
+  /// совпадать с длиной кортежа
+  fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
     let token = self.next_token()?;
-    unimplemented!("`deserialize_tuple(len: {})` not yet supported. Token: {:?}", len, token)
+    match token {
+      Token::ListBegin(count) if count as usize == len => complex!(ListEnd, self, visitor.visit_seq(ListAccess { de: &mut *self, count })),
+      Token::ListBegin(count) => Err(de::Error::invalid_length(count as usize, &visitor)),
+      token => Err(self.parser.unexpected("ListBegin", token)),
+    }
   }
-  fn deserialize_tuple_struct<V>(self, name: &'static str, len: usize, _visitor: V) -> Result<V::Value>
+  #[inline]
+  fn deserialize_tuple_struct<V>(self, _name: &'static str, len: usize, visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
-    let token = self.next_token()?;
-    unimplemented!("`deserialize_tuple_struct(name: {}, len: {})` not yet supported. Token: {:?}", name, len, token)
+    self.deserialize_tuple(len, visitor)
   }
-  fn deserialize_struct<V>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+  /// Разбирает структуру из GFF структуры. Если среди полей структуры есть поле с именем
+  /// [`TAG_FIELD`], то перед настоящими полями сообщается поле с идентификатором типа структуры.
+  /// Если включена проверка [`deny_unmatchable_fields`], проверяет длину имен полей
+  ///
+  /// [`deny_unmatchable_fields`]: struct.Deserializer.html#method.deny_unmatchable_fields
+  ///
+  /// [`TAG_FIELD`]: constant.TAG_FIELD.html
+  fn deserialize_struct<V>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
+    if self.deny_unmatchable {
+      if let Some(field) = fields.iter().find(|f| f.len() > 16) {
+        return Err(Error::Deserialize(format!(
+          "Field `{}` of struct `{}` never matches a GFF label: label can contain up to 16 bytes, but field name contains {} bytes in UTF-8",
+          field, name, field.len()
+        )));
+      }
+    }
+    if fields.contains(&TAG_FIELD) {
+      match *self.peek_token()? {
+        Token::RootBegin   { tag, .. } |
+        Token::ItemBegin   { tag, .. } |
+        Token::StructBegin { tag, .. } => self.tag = Some(PendingTag::Key(tag.raw())),
+        _ => {},
+      }
+    }
     self.deserialize_map(visitor)
   }
-  fn deserialize_enum<V>(self, name: &'static str, variants: &'static [&'static str], _visitor: V) -> Result<V::Value>
+  /// Разбирает перечисление, записанное во внешне-тегированном представлении: структура с
+  /// единственным полем, метка которого является именем варианта, а значение -- его содержимым.
+  /// Unit-варианты, записанные в поле структуры, также могут быть представлены строкой с
+  /// именем варианта
+  fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value>
     where V: Visitor<'de>,
   {
     let token = self.next_token()?;
-    unimplemented!("`deserialize_enum(name: {}, variants: {})` not yet supported. Token: {:?}", name, variants.len(), token)
+    match token {
+      Token::RootBegin   { .. } => complex!(RootEnd,   self, visitor.visit_enum),
+      Token::ItemBegin   { .. } => complex!(ItemEnd,   self, visitor.visit_enum),
+      Token::StructBegin { .. } => complex!(StructEnd, self, visitor.visit_enum),
+      Token::Value(SimpleValueRef::String(value)) => {
+        let variant = self.parser.read_string(value)?;
+        visitor.visit_enum(variant.into_deserializer())
+      },
+      token => Err(self.parser.unexpected("RootBegin, ItemBegin, StructBegin, String", token)),
+    }
+  }
+}
+
+impl<'de, R: Read + Seek, S: ReadStr<'de, R>> de::EnumAccess<'de> for &mut Deserializer<R, S> {
+  type Error = Error;
+  type Variant = Self;
+
+  /// Читает метку единственного поля структуры, как имя варианта перечисления
+  #[inline]
+  fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where V: DeserializeSeed<'de>,
+  {
+    let variant = seed.deserialize(Field(&mut *self))?;
+    Ok((variant, self))
+  }
+}
+
+impl<'de, R: Read + Seek, S: ReadStr<'de, R>> de::VariantAccess<'de> for &mut Deserializer<R, S> {
+  type Error = Error;
+
+  /// Пропускает значение поля, хранящего unit-вариант -- обычно это строка с именем варианта
+  #[inline]
+  fn unit_variant(self) -> Result<()> {
+    de::IgnoredAny::deserialize(self)?;
+    Ok(())
+  }
+  #[inline]
+  fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where T: DeserializeSeed<'de>,
+  {
+    seed.deserialize(self)
+  }
+  #[inline]
+  fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    de::Deserializer::deserialize_tuple(self, len, visitor)
+  }
+  #[inline]
+  fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    de::Deserializer::deserialize_map(self, visitor)
   }
 }
 
-impl<'de, 'a, R: Read + Seek> de::MapAccess<'de> for &'a mut Deserializer<R> {
+/// Доступ к полям структуры, знающий количество еще не прочитанных полей
+struct StructAccess<'a, R: 'a + Read + Seek, S: 'a> {
+  /// Десериализатор, из которого читаются поля
+  de: &'a mut Deserializer<R, S>,
+  /// Количество оставшихся полей структуры, согласно токену начала структуры
+  count: u32,
+}
+
+impl<'de, 'a, R: Read + Seek, S: ReadStr<'de, R>> de::MapAccess<'de> for StructAccess<'a, R, S> {
   type Error = Error;
 
   fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
     where K: DeserializeSeed<'de>,
   {
-    let token = self.peek_token()?.clone();
+    if let Some(PendingTag::Key(tag)) = self.de.tag {
+      self.de.tag = Some(PendingTag::Value(tag));
+      return seed.deserialize(TAG_FIELD.into_deserializer()).map(Some);
+    }
+    let token = self.de.peek_token()?.clone();
     match token {
       Token::RootEnd | Token::ItemEnd | Token::StructEnd => Ok(None),
-      Token::Label(..) => seed.deserialize(Field(&mut **self)).map(Some),
-      token => Err(Error::Unexpected("Label", token)),
+      Token::Label(..) => {
+        self.count = self.count.saturating_sub(1);
+        seed.deserialize(Field(&mut *self.de)).map(Some)
+      },
+      token => Err(self.de.parser.unexpected("Label", token)),
     }
   }
 
@@ -352,23 +889,58 @@ impl<'de, 'a, R: Read + Seek> de::MapAccess<'de> for &'a mut Deserializer<R> {
   fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
     where V: DeserializeSeed<'de>,
   {
-    seed.deserialize(&mut **self)
+    if let Some(PendingTag::Value(tag)) = self.de.tag.take() {
+      return seed.deserialize(tag.into_deserializer());
+    }
+    seed.deserialize(&mut *self.de)
+  }
+  /// Возвращает количество оставшихся полей структуры, включая еще не сообщенное
+  /// поле [`TAG_FIELD`]
+  ///
+  /// [`TAG_FIELD`]: constant.TAG_FIELD.html
+  #[inline]
+  fn size_hint(&self) -> Option<usize> {
+    let tag = match self.de.tag {
+      Some(PendingTag::Key(_)) => 1,
+      _ => 0,
+    };
+    Some(self.count as usize + tag)
   }
 }
 
-impl<'de, 'a, R: Read + Seek> de::SeqAccess<'de> for &'a mut Deserializer<R> {
+/// Доступ к элементам списка, знающий количество еще не прочитанных элементов
+struct ListAccess<'a, R: 'a + Read + Seek, S: 'a> {
+  /// Десериализатор, из которого читаются элементы
+  de: &'a mut Deserializer<R, S>,
+  /// Количество оставшихся элементов списка, согласно токену [`ListBegin`]
+  ///
+  /// [`ListBegin`]: ../parser/enum.Token.html#variant.ListBegin
+  count: u32,
+}
+
+impl<'de, 'a, R: Read + Seek, S: ReadStr<'de, R>> de::SeqAccess<'de> for ListAccess<'a, R, S> {
   type Error = Error;
 
   fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where T: DeserializeSeed<'de>,
   {
-    let token = self.peek_token()?.clone();
+    let token = self.de.peek_token()?.clone();
     match token {
       Token::ListEnd => Ok(None),
-      Token::ItemBegin { .. } => seed.deserialize(&mut **self).map(Some),
-      token => Err(Error::Unexpected("ItemBegin", token)),
+      Token::ItemBegin { .. } => {
+        self.count = self.count.saturating_sub(1);
+        seed.deserialize(&mut *self.de).map(Some)
+      },
+      token => Err(self.de.parser.unexpected("ItemBegin", token)),
     }
   }
+  /// Возвращает количество оставшихся элементов списка. Реализации `Deserialize` из serde
+  /// ограничивают предварительное резервирование памяти, поэтому огромное количество
+  /// элементов в поврежденном файле не приведет к попытке выделить под них память
+  #[inline]
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.count as usize)
+  }
 }
 
 macro_rules! delegate {
@@ -398,9 +970,9 @@ macro_rules! delegate {
   );
 }
 /// Десериализатор для чтения идентификаторов полей
-struct Field<'a, R: 'a + Read + Seek>(&'a mut Deserializer<R>);
+struct Field<'a, R: 'a + Read + Seek, S: 'a>(&'a mut Deserializer<R, S>);
 
-impl<'de, 'a, R: 'a + Read + Seek> de::Deserializer<'de> for Field<'a, R> {
+impl<'de, 'a, R: 'a + Read + Seek, S: ReadStr<'de, R>> de::Deserializer<'de> for Field<'a, R, S> {
   type Error = Error;
 
   #[inline]
@@ -414,7 +986,7 @@ impl<'de, 'a, R: 'a + Read + Seek> de::Deserializer<'de> for Field<'a, R> {
       let label = self.0.parser.read_label(index)?;
       return visitor.visit_str(label.as_str()?);
     }
-    return Err(Error::Unexpected("Label", token));
+    return Err(self.0.parser.unexpected("Label", token));
   }
 
   delegate!(deserialize_i8);
@@ -506,3 +1078,1089 @@ mod empty_file {
     let _test: Struct = run("struct with fields");
   }
 }
+
+#[cfg(test)]
+mod helpers {
+  //! Тестирование функций десериализации верхнего уровня
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use crate::error::Error;
+  use crate::ser::to_vec;
+  use super::{from_reader, from_slice};
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Item { name: String, count: u16 }
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Storage { id: u64, items: Vec<Item> }
+
+  fn value() -> Storage {
+    Storage {
+      id: 42,
+      items: vec![Item { name: "sword".into(), count: 1 }, Item { name: "arrow".into(), count: 99 }],
+    }
+  }
+
+  #[test]
+  fn roundtrip() {
+    let data = to_vec((*b"GFF ").into(), &value()).expect("can't serialize value");
+
+    assert_eq!(from_slice::<Storage>(&data).expect("can't deserialize from slice"), value());
+    assert_eq!(from_reader::<_, Storage>(Cursor::new(data)).expect("can't deserialize from reader"), value());
+  }
+
+  #[test]
+  fn truncated_header() {
+    match from_slice::<Storage>(b"GFF V3.2") {
+      Err(Error::Io(_)) => {},
+      result => panic!("expected Error::Io, but {:?} found", result),
+    }
+  }
+}
+
+#[cfg(test)]
+mod all_file {
+  //! Тестирование полного цикла чтения и записи файла, содержащего поля всех типов.
+  //!
+  //! Файл `all.gff` не содержит полей с типами `ResRef` и `LocString`. Полный цикл через `Value`
+  //! для `ResRef` проверяется на значении, построенном в тесте, а `LocString` его пока не
+  //! переживает: она читается в `Value` через `GffString`, поэтому строка, содержащая только
+  //! `StrRef`, превращается в `Dword`, а при наличии внутренних строк `StrRef` теряется
+  use std::fs::File;
+  use std::io::Cursor;
+  use serde::Deserialize;
+  use crate::header::{Signature, Version};
+  use crate::ser::to_vec;
+  use crate::value::Value;
+  use super::Deserializer;
+
+  /// Проверяет, что заголовок файла доступен сразу после создания десериализатора
+  #[test]
+  fn header() {
+    let file = File::open("test-data/all.gff").expect("test file 'all.gff' not exist");
+    let de = Deserializer::new(file).expect("can't read GFF header");
+
+    assert_eq!(de.header().signature, Signature::Other(*b"GFF "));
+    assert_eq!(de.header().version, Version::V3_2);
+    assert!(de.header().fields.count > 0);
+  }
+
+  #[test]
+  fn roundtrip() {
+    let file = File::open("test-data/all.gff").expect("test file 'all.gff' not exist");
+    let mut de = Deserializer::new(file).expect("can't read GFF header");
+    let value = Value::deserialize(&mut de).expect("can't deserialize 'all.gff'");
+
+    let data = to_vec((*b"GFF ").into(), &value).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    let result = Value::deserialize(&mut de).expect("can't deserialize serialized value");
+
+    assert_eq!(result, value);
+  }
+
+  #[test]
+  fn resref() {
+    use indexmap::IndexMap;
+    use crate::ResRef;
+
+    let mut fields = IndexMap::new();
+    fields.insert("ResRef".parse().unwrap(), Value::ResRef(ResRef(b"resref".to_vec())));
+    fields.insert("Binary".parse().unwrap(), Value::ResRef(ResRef(vec![0xFF, 0])));
+    let value = Value::Struct { tag: 0xFFFF_FFFF, fields };
+
+    let data = to_vec((*b"GFF ").into(), &value).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    let result = Value::deserialize(&mut de).expect("can't deserialize serialized value");
+
+    assert_eq!(result, value);
+  }
+}
+
+#[cfg(test)]
+mod borrowed {
+  //! Тестирование десериализации в заимствующие типы
+  use std::borrow::Cow;
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use crate::error::Error;
+  use crate::ser::to_vec;
+  use super::{Deserializer, SliceDeserializer};
+
+  #[derive(Serialize)]
+  struct Owned { text: String }
+
+  #[derive(Deserialize)]
+  struct BorrowedCow<'a> {
+    #[serde(borrow)]
+    text: Cow<'a, str>,
+  }
+
+  #[derive(Debug, Deserialize)]
+  struct BorrowedStr<'a> { text: &'a str }
+
+  fn data() -> Vec<u8> {
+    to_vec((*b"GFF ").into(), &Owned { text: "ascii".into() }).unwrap()
+  }
+
+  /// Проверяет, что строка десериализуется в `&str`, ссылающуюся на исходный буфер
+  #[test]
+  fn str() {
+    let data = data();
+    let mut de = SliceDeserializer::from_slice(&data).unwrap();
+    let value = BorrowedStr::deserialize(&mut de).expect("can't deserialize &str");
+    assert_eq!(value.text, "ascii");
+    assert!(data.as_ptr_range().contains(&value.text.as_ptr()));
+  }
+
+  /// Проверяет, что строка десериализуется в `Cow::Borrowed`
+  #[test]
+  fn cow() {
+    let data = data();
+    let mut de = SliceDeserializer::from_slice(&data).unwrap();
+    let value = BorrowedCow::deserialize(&mut de).expect("can't deserialize Cow");
+    assert_eq!(value.text, "ascii");
+    assert!(matches!(value.text, Cow::Borrowed(_)));
+  }
+
+  /// Проверяет, что строки, не являющиеся корректными строками UTF-8, декодируются в
+  /// кодировке десериализатора
+  #[test]
+  fn not_utf8() {
+    use encoding::{DecoderTrap, EncoderTrap, Encoding};
+    use encoding::all::WINDOWS_1251;
+
+    let mut data = data();
+    let encoded = WINDOWS_1251.encode("текст", EncoderTrap::Strict).unwrap();
+    let pos = data.windows(5).position(|w| w == b"ascii").unwrap();
+    data[pos..pos + 5].copy_from_slice(&encoded);
+
+    let mut de = SliceDeserializer::from_slice_with_encoding(&data, WINDOWS_1251, DecoderTrap::Strict).unwrap();
+    let value = BorrowedCow::deserialize(&mut de).expect("can't deserialize Cow");
+    assert_eq!(value.text, "текст");
+    assert!(matches!(value.text, Cow::Owned(_)));
+
+    // Корректные строки UTF-8 по-прежнему не копируются
+    let data = self::data();
+    let mut de = SliceDeserializer::from_slice_with_encoding(&data, WINDOWS_1251, DecoderTrap::Strict).unwrap();
+    let value = BorrowedCow::deserialize(&mut de).expect("can't deserialize Cow");
+    assert!(matches!(value.text, Cow::Borrowed(_)));
+  }
+
+  /// Проверяет, что десериализатор произвольного потока всегда передает строки во владение
+  #[test]
+  fn stream_is_owned() {
+    let data = data();
+    let mut de = Deserializer::new(Cursor::new(&data[..])).unwrap();
+    let value = BorrowedCow::deserialize(&mut de).expect("can't deserialize Cow");
+    assert!(matches!(value.text, Cow::Owned(_)));
+
+    let mut de = Deserializer::new(Cursor::new(&data[..])).unwrap();
+    match BorrowedStr::deserialize(&mut de) {
+      Err(Error::Deserialize(_)) => {},
+      res => panic!("expected Error::Deserialize, but {:?} found", res),
+    }
+  }
+}
+
+#[cfg(test)]
+mod raw_gff {
+  //! Тестирование десериализации из структуры уже прочитанного GFF файла
+  use std::fs::File;
+  use serde::Deserialize;
+  use crate::error::Error;
+  use crate::raw::{FieldType, Gff};
+  use crate::value::Value;
+  use super::Deserializer;
+
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct Item { u8: u8, i8: i8 }
+
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct Struct { f32: f32, f64: f64 }
+
+  #[derive(Debug, PartialEq, Deserialize)]
+  #[allow(non_snake_case)]
+  struct Test {
+    u16: u16,
+    i64: i64,
+    string: String,
+    Struct: Struct,
+    list: Vec<Item>,
+  }
+
+  fn gff() -> Gff {
+    let mut file = File::open("test-data/all.gff").expect("test file 'all.gff' not exist");
+    Gff::read(&mut file).expect("can't read 'all.gff'")
+  }
+
+  /// Проверяет, что результат совпадает с результатом потокового десериализатора
+  #[test]
+  fn same_as_streaming() {
+    let gff = gff();
+
+    let file = File::open("test-data/all.gff").expect("test file 'all.gff' not exist");
+    let mut de = Deserializer::new(file).expect("can't read GFF header");
+
+    assert_eq!(Test::deserialize(&gff).expect("can't deserialize from Gff"), Test::deserialize(&mut de).unwrap());
+    assert_eq!(Value::deserialize(&gff).unwrap(), gff.to_value().unwrap());
+  }
+
+  /// Проверяет, что идентификатор типа структуры сообщается, если он запрошен
+  #[test]
+  fn tag() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Tagged {
+      #[serde(rename = "$tag")]
+      tag: u32,
+      u16: u16,
+    }
+
+    let mut gff = gff();
+    gff.structs[0].tag = 42;
+
+    let test = Test::deserialize(&gff).unwrap();
+    assert_eq!(Tagged::deserialize(&gff).unwrap(), Tagged { tag: 42, u16: test.u16 });
+  }
+
+  /// Проверяет, что ошибки в структуре файла сообщаются десериализатором
+  #[test]
+  fn cyclic() {
+    let mut gff = gff();
+    for s in &mut gff.structs { s.fields = 0; }
+    // Корневая структура содержит единственное поле, ссылающееся на нее же
+    gff.structs[0].fields = 1;
+    gff.structs[0].offset = 0;
+    gff.fields[0].tag = FieldType::Struct as u32;
+    gff.fields[0].data = [0; 4];
+
+    match Value::deserialize(&gff) {
+      Err(Error::CyclicStructure { .. }) => {},
+      res => panic!("expected Error::CyclicStructure, but {:?} found", res),
+    }
+  }
+
+  /// Проверяет, что слишком глубокая вложенность приводит к ошибке, а не к переполнению стека
+  #[test]
+  fn depth_limit() {
+    use indexmap::IndexMap;
+    use crate::parser::Limits;
+
+    /// Строит цепочку из `depth` уровней вложенности: структуры, содержащей список из
+    /// единственной структуры, которая содержит такой же список, и т.д.
+    fn nested(depth: usize) -> Gff {
+      let mut value = None;
+      for level in (0..depth).rev() {
+        value = Some(if level % 2 == 0 {
+          let mut fields = IndexMap::new();
+          if let Some(value) = value {
+            fields.insert("inner".parse().unwrap(), value);
+          }
+          Value::Struct { tag: 0, fields }
+        } else {
+          Value::List(value.into_iter().collect())
+        });
+      }
+      Gff::from_value((*b"GFF ").into(), &value.unwrap()).expect("can't convert Value to GFF")
+    }
+
+    let limit = Limits::default().max_depth;
+    Value::deserialize(&nested(limit)).expect("can't deserialize GFF");
+    match Value::deserialize(&nested(limit + 1)) {
+      Err(Error::DepthLimitExceeded(max)) => assert_eq!(max, limit),
+      res => panic!("expected Error::DepthLimitExceeded, but {:?} found", res),
+    }
+  }
+}
+
+#[cfg(test)]
+mod resref {
+  //! Тестирование сохранения типа `ResRef` при чтении в `Value`
+  use std::io::Cursor;
+  use indexmap::IndexMap;
+  use serde::{Deserialize, Serialize};
+  use serde::de::IntoDeserializer;
+  use crate::ResRef;
+  use crate::error::Error;
+  use crate::raw::{FieldType, Gff};
+  use crate::ser::to_vec;
+  use crate::value::Value;
+  use super::Deserializer;
+  use super::value::ValueDeserializer;
+
+  /// Создает файл со структурой из двух полей: `resref` типа `ResRef` и `string` типа `String`
+  fn data() -> Vec<u8> {
+    #[derive(Serialize)]
+    struct Storage { resref: String, string: String }
+
+    let data = to_vec((*b"GFF ").into(), &Storage { resref: "".into(), string: "text".into() }).unwrap();
+    // Сериализатор не умеет записывать `ResRef`, поэтому меняем тип первого поля вручную
+    // Пустая строка занимает 4 байта длины, столько же, сколько `ResRef` из 3 символов
+    let mut gff = Gff::read(&mut Cursor::new(data)).unwrap();
+    gff.fields[0].tag = FieldType::ResRef as u32;
+    gff.field_data[..4].copy_from_slice(b"\x03abc");
+
+    let mut data = Vec::new();
+    gff.write(&mut data).unwrap();
+    data
+  }
+
+  fn expected() -> Value {
+    let mut map = IndexMap::new();
+    map.insert("resref".parse().unwrap(), Value::ResRef(ResRef(b"abc".to_vec())));
+    map.insert("string".parse().unwrap(), Value::String("text".into()));
+    Value::Struct { tag: 0, fields: map }
+  }
+
+  #[test]
+  fn to_value() {
+    let mut de = Deserializer::new(Cursor::new(data())).unwrap();
+    assert_eq!(Value::deserialize(&mut de).unwrap(), expected());
+  }
+
+  #[test]
+  fn value_to_value() {
+    let de: ValueDeserializer<Error> = expected().into_deserializer();
+    assert_eq!(Value::deserialize(de).unwrap(), expected());
+  }
+
+  #[test]
+  fn to_resref() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Storage { resref: ResRef, string: ResRef }
+
+    let mut de = Deserializer::new(Cursor::new(data())).unwrap();
+    assert_eq!(Storage::deserialize(&mut de).unwrap(), Storage { resref: "abc".into(), string: "text".into() });
+
+    let storage = Storage::deserialize(expected().into_deserializer() as ValueDeserializer<Error>).unwrap();
+    assert_eq!(storage, Storage { resref: "abc".into(), string: "text".into() });
+
+    // Ссылки длиннее 16 байт не допускаются
+    #[derive(Serialize)]
+    struct Long { resref: String, string: String }
+    let data = to_vec((*b"GFF ").into(), &Long { resref: "abc".into(), string: "more_then_16_char".into() }).unwrap();
+    match Storage::deserialize(&mut Deserializer::new(Cursor::new(data)).unwrap()) {
+      Err(Error::Deserialize(msg)) => assert_eq!(msg, "invalid length 17, expected a ResRef with length <=16 bytes"),
+      res => panic!("expected Error::Deserialize, but {:?} found", res),
+    }
+  }
+
+  #[test]
+  fn to_string() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Storage { resref: String, string: String }
+
+    let mut de = Deserializer::new(Cursor::new(data())).unwrap();
+    assert_eq!(Storage::deserialize(&mut de).unwrap(), Storage { resref: "abc".into(), string: "text".into() });
+  }
+
+  #[test]
+  fn to_string_strict() {
+    #[derive(Debug, Deserialize)]
+    struct Storage {
+      #[allow(dead_code)]
+      resref: String,
+    }
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Bytes {
+      #[serde(with = "serde_bytes")]
+      resref: Vec<u8>,
+      string: String,
+    }
+
+    let mut de = Deserializer::new(Cursor::new(data())).unwrap();
+    de.with_resref_as_string(false);
+    match Storage::deserialize(&mut de) {
+      Err(Error::UnexpectedAt { expected: "String", .. }) => {},
+      result => panic!("expected Error::UnexpectedAt, but {:?} found", result),
+    }
+
+    // Чтение в массив байт по-прежнему возможно
+    let mut de = Deserializer::new(Cursor::new(data())).unwrap();
+    de.with_resref_as_string(false);
+    assert_eq!(Bytes::deserialize(&mut de).unwrap(), Bytes { resref: b"abc".to_vec(), string: "text".into() });
+  }
+}
+
+#[cfg(test)]
+mod simple {
+  //! Тестирование чтения простых значений без использования модели данных serde
+  use std::io::Cursor;
+  use serde::Serialize;
+  use crate::ser::to_vec;
+  use crate::value::SimpleValue;
+  use super::Deserializer;
+
+  #[test]
+  fn flat_struct() {
+    #[derive(Serialize)]
+    struct Flat { byte: u8, string: String, double: f64 }
+
+    let data = to_vec((*b"GFF ").into(), &Flat { byte: 1, string: "2".into(), double: 3.0 }).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+
+    // Начало корневой структуры
+    assert_eq!(de.next_any_simple().unwrap(), None);
+    assert_eq!(de.next_any_simple().unwrap(), Some(SimpleValue::Byte(1)));
+    assert_eq!(de.next_any_simple().unwrap(), Some(SimpleValue::String("2".into())));
+    assert_eq!(de.next_any_simple().unwrap(), Some(SimpleValue::Double(3.0)));
+    // Конец корневой структуры
+    assert_eq!(de.next_any_simple().unwrap(), None);
+    assert!(de.next_any_simple().is_err());
+  }
+}
+
+#[cfg(test)]
+mod chars {
+  //! Тестирование чтения символов из однобайтовых полей
+  use std::io::Cursor;
+  use encoding::DecoderTrap;
+  use encoding::all::WINDOWS_1251;
+  use serde::{Deserialize, Serialize};
+  use crate::de::from_slice;
+  use crate::error::Error;
+  use crate::ser::to_vec;
+  use super::Deserializer;
+
+  #[derive(Serialize)]
+  struct Bytes { byte: u8, char: i8 }
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Chars { byte: char, char: char }
+
+  #[test]
+  fn codepage() {
+    // Байт 0xC0 -- буква `А` в windows-1251
+    let data = to_vec((*b"GFF ").into(), &Bytes { byte: 0xC0, char: 0xC0u8 as i8 }).unwrap();
+    let mut de = Deserializer::with_encoding(Cursor::new(data), WINDOWS_1251, DecoderTrap::Strict).unwrap();
+    assert_eq!(Chars::deserialize(&mut de).unwrap(), Chars { byte: 'А', char: 'А' });
+  }
+
+  #[test]
+  fn utf8() {
+    let data = to_vec((*b"GFF ").into(), &Bytes { byte: b'a', char: b'b' as i8 }).unwrap();
+    assert_eq!(from_slice::<Chars>(&data).unwrap(), Chars { byte: 'a', char: 'b' });
+
+    // Отдельный байт >= 128 не является символом UTF-8
+    let data = to_vec((*b"GFF ").into(), &Bytes { byte: 0xC0, char: 0 }).unwrap();
+    match from_slice::<Chars>(&data) {
+      Err(Error::Encoding(_)) => {},
+      res => panic!("expected Error::Encoding, but {:?} found", res),
+    }
+  }
+}
+
+#[cfg(test)]
+mod widening {
+  //! Тестирование чтения целочисленных полей в более широкие типы Rust
+  use serde::{Deserialize, Serialize};
+  use crate::de::from_slice;
+  use crate::error::Error;
+  use crate::ser::to_vec;
+
+  #[derive(Serialize)]
+  struct Narrow { byte: u8, char: i8, dword: u32 }
+
+  #[test]
+  fn widen() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Wide { byte: u16, char: i64, dword: u64 }
+
+    let data = to_vec((*b"GFF ").into(), &Narrow { byte: 200, char: -5, dword: 70000 }).unwrap();
+    assert_eq!(from_slice::<Wide>(&data).unwrap(), Wide { byte: 200, char: -5, dword: 70000 });
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Dword { byte: u32, char: i32, dword: u32 }
+    assert_eq!(from_slice::<Dword>(&data).unwrap(), Dword { byte: 200, char: -5, dword: 70000 });
+  }
+
+  #[test]
+  fn overflow() {
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Short { byte: u16, char: i8, dword: u16 }
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Unsigned { byte: u16, char: u32, dword: u32 }
+
+    let data = to_vec((*b"GFF ").into(), &Narrow { byte: 200, char: -5, dword: 70000 }).unwrap();
+    match from_slice::<Short>(&data) {
+      Err(Error::UnexpectedAt { expected, .. }) => assert_eq!(expected, "Byte, Char, Word, Short"),
+      res => panic!("expected Error::UnexpectedAt, but {:?} found", res),
+    }
+    // Более широкий тип не принимается, даже если значение помещается в тип Rust
+    let data = to_vec((*b"GFF ").into(), &Narrow { byte: 200, char: -5, dword: 7 }).unwrap();
+    match from_slice::<Short>(&data) {
+      Err(Error::UnexpectedAt { expected, .. }) => assert_eq!(expected, "Byte, Char, Word, Short"),
+      res => panic!("expected Error::UnexpectedAt, but {:?} found", res),
+    }
+    // Значение типа той же ширины проверяется при несовпадении знаковости
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Signed { byte: i8, char: i8, dword: u32 }
+    match from_slice::<Signed>(&data) {
+      Err(Error::Deserialize(msg)) => assert_eq!(msg, "invalid value: integer `200`, expected i8"),
+      res => panic!("expected Error::Deserialize, but {:?} found", res),
+    }
+    match from_slice::<Unsigned>(&data) {
+      Err(Error::Deserialize(msg)) => assert_eq!(msg, "invalid value: integer `-5`, expected u32"),
+      res => panic!("expected Error::Deserialize, but {:?} found", res),
+    }
+  }
+}
+
+#[cfg(test)]
+mod size_hint {
+  //! Тестирование подсказки о количестве элементов списка и полей структуры
+  use std::fmt;
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use serde::de::{IgnoredAny, MapAccess, SeqAccess, Visitor};
+  use crate::Label;
+  use crate::ser::to_vec;
+  use crate::value::Value;
+  use super::{Deserializer, TAG_FIELD};
+
+  /// Подсказки о количестве элементов списка или полей структуры, сообщенные перед чтением
+  /// каждого элемента и после чтения всех элементов
+  #[derive(Debug, PartialEq)]
+  struct Hints(Vec<Option<usize>>);
+
+  /// Посетитель, читающий все элементы списка или поля структуры и собирающий подсказки
+  struct HintsVisitor;
+  impl<'de> Visitor<'de> for HintsVisitor {
+    type Value = Hints;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+      fmt.write_str("list or struct")
+    }
+    fn visit_seq<A>(self, mut seq: A) -> Result<Hints, A::Error>
+      where A: SeqAccess<'de>,
+    {
+      let mut hints = vec![seq.size_hint()];
+      while let Some(Value::Struct { .. }) = seq.next_element()? {
+        hints.push(seq.size_hint());
+      }
+      Ok(Hints(hints))
+    }
+    fn visit_map<A>(self, mut map: A) -> Result<Hints, A::Error>
+      where A: MapAccess<'de>,
+    {
+      let mut hints = vec![map.size_hint()];
+      while let Some((_, IgnoredAny)) = map.next_entry::<Label, IgnoredAny>()? {
+        hints.push(map.size_hint());
+      }
+      Ok(Hints(hints))
+    }
+  }
+
+  impl<'de> Deserialize<'de> for Hints {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+      where D: serde::Deserializer<'de>,
+    {
+      deserializer.deserialize_seq(HintsVisitor)
+    }
+  }
+
+  #[derive(Serialize)]
+  struct Item { value: u32 }
+
+  #[derive(Serialize)]
+  struct Storage { list: Vec<Item> }
+
+  #[derive(Debug, Deserialize)]
+  struct Hinted { list: Hints }
+
+  #[test]
+  fn remaining_items() {
+    let value = Storage { list: vec![Item { value: 1 }, Item { value: 2 }, Item { value: 3 }] };
+    let data = to_vec((*b"GFF ").into(), &value).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+
+    let hinted = Hinted::deserialize(&mut de).expect("can't deserialize value");
+    assert_eq!(hinted.list, Hints(vec![Some(3), Some(2), Some(1), Some(0)]));
+  }
+
+  #[test]
+  fn empty_list() {
+    let data = to_vec((*b"GFF ").into(), &Storage { list: vec![] }).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+
+    let hinted = Hinted::deserialize(&mut de).expect("can't deserialize value");
+    assert_eq!(hinted.list, Hints(vec![Some(0)]));
+  }
+
+  #[derive(Serialize)]
+  struct Struct { byte: u8, word: u16, dword: u32 }
+
+  fn deserializer() -> Deserializer<Cursor<Vec<u8>>> {
+    let data = to_vec((*b"GFF ").into(), &Struct { byte: 1, word: 2, dword: 3 }).expect("can't serialize value");
+    Deserializer::new(Cursor::new(data)).expect("can't read GFF header")
+  }
+
+  #[test]
+  fn remaining_fields() {
+    use serde::Deserializer as _;
+
+    let mut de = deserializer();
+    let hints = (&mut de).deserialize_map(HintsVisitor).expect("can't deserialize value");
+    assert_eq!(hints, Hints(vec![Some(3), Some(2), Some(1), Some(0)]));
+  }
+
+  #[test]
+  fn with_tag() {
+    use serde::Deserializer as _;
+
+    let mut de = deserializer();
+    let hints = (&mut de).deserialize_struct("Struct", &[TAG_FIELD, "byte"], HintsVisitor).expect("can't deserialize value");
+    assert_eq!(hints, Hints(vec![Some(4), Some(3), Some(2), Some(1), Some(0)]));
+  }
+}
+
+#[cfg(test)]
+mod byte_list {
+  //! Тестирование чтения двоичных данных из списков структур с единственным полем `Byte`
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use serde_bytes::ByteBuf;
+  use crate::error::Error;
+  use crate::ser::to_vec;
+  use super::Deserializer;
+
+  #[derive(Debug, Deserialize)]
+  struct Blob { data: ByteBuf }
+
+  fn deserialize<T: Serialize>(value: &T) -> Result<Blob, Error> {
+    let data = to_vec((*b"GFF ").into(), value).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    Blob::deserialize(&mut de)
+  }
+
+  #[test]
+  fn bytes() {
+    #[derive(Serialize)]
+    struct Item { byte: u8 }
+    #[derive(Serialize)]
+    struct List { data: Vec<Item> }
+
+    let list = List { data: vec![Item { byte: 41 }, Item { byte: 42 }, Item { byte: 43 }] };
+    assert_eq!(deserialize(&list).unwrap().data, ByteBuf::from(vec![41, 42, 43]));
+
+    let list = List { data: vec![] };
+    assert_eq!(deserialize(&list).unwrap().data, ByteBuf::from(vec![]));
+  }
+
+  #[test]
+  fn not_single_byte() {
+    #[derive(Serialize)]
+    struct Word { word: u16 }
+    #[derive(Serialize)]
+    struct Pair { first: u8, second: u8 }
+    #[derive(Serialize)]
+    struct List<T> { data: Vec<T> }
+
+    match deserialize(&List { data: vec![Word { word: 42 }] }) {
+      Err(Error::UnexpectedAt { expected: "Byte", .. }) => {},
+      result => panic!("expected Error::UnexpectedAt, but {:?} found", result),
+    }
+    match deserialize(&List { data: vec![Pair { first: 1, second: 2 }] }) {
+      Err(Error::UnexpectedAt { expected: "struct with single Byte field", .. }) => {},
+      result => panic!("expected Error::UnexpectedAt, but {:?} found", result),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tuples {
+  //! Тестирование разбора кортежей, записанных сериализатором
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use crate::error::Error;
+  use crate::ser::to_vec;
+  use super::Deserializer;
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Item1 { value: u32 }
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Item2 { value: String }
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Storage<T> { tuple: T }
+
+  fn deserializer<T: Serialize>(value: &T) -> Deserializer<Cursor<Vec<u8>>> {
+    let data = to_vec((*b"GFF ").into(), value).expect("can't serialize value");
+    Deserializer::new(Cursor::new(data)).expect("can't read GFF header")
+  }
+
+  #[test]
+  fn roundtrip() {
+    let value = Storage { tuple: (Item1 { value: 42 }, Item2 { value: "42".into() }) };
+    let mut de = deserializer(&value);
+    assert_eq!(Storage::deserialize(&mut de).expect("can't deserialize value"), value);
+  }
+
+  #[test]
+  fn tuple_struct() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Tuple(Item1, Item2);
+
+    let value = Storage { tuple: Tuple(Item1 { value: 42 }, Item2 { value: "42".into() }) };
+    let mut de = deserializer(&value);
+    assert_eq!(Storage::deserialize(&mut de).expect("can't deserialize value"), value);
+  }
+
+  #[test]
+  fn length_mismatch() {
+    let value = Storage { tuple: (Item1 { value: 1 }, Item1 { value: 2 }, Item1 { value: 3 }) };
+
+    let mut de = deserializer(&value);
+    let result = Storage::<(Item1, Item1)>::deserialize(&mut de);
+    assert!(matches!(result, Err(Error::Deserialize(_))), "{:?}", result);
+
+    let mut de = deserializer(&value);
+    let result = Storage::<(Item1, Item1, Item1, Item1)>::deserialize(&mut de);
+    assert!(matches!(result, Err(Error::Deserialize(_))), "{:?}", result);
+  }
+}
+
+#[cfg(test)]
+mod mismatch {
+  //! Тестирование сообщений об ошибках при несоответствии схемы данных содержимому файла
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use crate::ser::to_vec;
+  use super::Deserializer;
+
+  #[derive(Debug, Serialize, Deserialize)]
+  struct Item { value: u32 }
+
+  fn deserialize<T, U>(value: &T) -> String
+    where T: Serialize,
+          U: std::fmt::Debug + for<'de> Deserialize<'de>,
+  {
+    let data = to_vec((*b"GFF ").into(), value).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    U::deserialize(&mut de).unwrap_err().to_string()
+  }
+
+  #[test]
+  fn list_as_struct() {
+    #[derive(Debug, Serialize)]
+    struct Written { other: u8, field: Vec<Item> }
+    #[derive(Debug, Deserialize)]
+    struct Read { #[allow(dead_code)] other: u8, #[allow(dead_code)] field: Item }
+
+    let written = Written { other: 1, field: vec![Item { value: 1 }, Item { value: 2 }] };
+    assert_eq!(
+      deserialize::<_, Read>(&written),
+      "Field `field`: expected struct, but list with 2 elements found"
+    );
+  }
+
+  #[test]
+  fn struct_as_list() {
+    #[derive(Debug, Serialize)]
+    struct Written { field: Item }
+    #[derive(Debug, Deserialize)]
+    struct Read { #[allow(dead_code)] field: Vec<Item> }
+
+    assert_eq!(
+      deserialize::<_, Read>(&Written { field: Item { value: 1 } }),
+      "Field `field`: expected list, but struct found"
+    );
+  }
+
+  /// Проверяет, что ошибка содержит позицию в файле описания поля с неожиданным значением
+  #[test]
+  fn offset() {
+    use crate::error::Error;
+    use crate::header::Header;
+    use crate::parser::Token;
+    use crate::value::SimpleValueRef;
+
+    #[derive(Debug, Serialize)]
+    struct Written { other: u8, field: u32 }
+    #[derive(Debug, Deserialize)]
+    struct Read { #[allow(dead_code)] other: u8, #[allow(dead_code)] field: String }
+
+    let data = to_vec((*b"GFF ").into(), &Written { other: 1, field: 42 }).expect("can't serialize value");
+    let header = Header::read(&mut Cursor::new(&data)).expect("can't read GFF header");
+    // Описание каждого поля занимает 12 байт, ошибочное поле -- второе
+    let expected = header.fields.offset as u64 + 12;
+
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    let err = Read::deserialize(&mut de).unwrap_err();
+    assert_eq!(err.to_string(), format!("Expected String, ResRef, but Value(Dword(42)) found at offset {}", expected));
+    match err {
+      Error::UnexpectedAt { token: Token::Value(SimpleValueRef::Dword(42)), offset, .. } => assert_eq!(offset, expected),
+      err => panic!("expected Error::UnexpectedAt, but {:?} found", err),
+    }
+  }
+}
+
+#[cfg(test)]
+mod enums {
+  //! Тестирование разбора перечислений, записанных сериализатором
+  use std::fmt::Debug;
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use crate::ser::to_vec;
+  use super::Deserializer;
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Item { value: u32 }
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  enum E {
+    Unit,
+    Newtype1(u32),
+    Newtype2(Item),
+    Tuple(Item, Item),
+    Struct { value: u32 },
+  }
+
+  /// Сериализует значение и десериализует его обратно
+  fn roundtrip<T>(value: &T) -> T
+    where T: Serialize + for<'de> Deserialize<'de>,
+  {
+    let data = to_vec((*b"GFF ").into(), value).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    T::deserialize(&mut de).expect("can't deserialize value")
+  }
+
+  fn check<T>(value: T)
+    where T: Debug + PartialEq + Serialize + for<'de> Deserialize<'de>,
+  {
+    assert_eq!(roundtrip(&value), value);
+  }
+
+  #[test]
+  fn toplevel() {
+    check(E::Unit);
+    check(E::Newtype1(42));
+    check(E::Newtype2(Item { value: 42 }));
+    check(E::Tuple(Item { value: 1 }, Item { value: 2 }));
+    check(E::Struct { value: 42 });
+  }
+
+  #[test]
+  fn as_field() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Storage { value: E, other: u8 }
+
+    check(Storage { value: E::Unit, other: 1 });
+    check(Storage { value: E::Newtype1(42), other: 2 });
+    check(Storage { value: E::Newtype2(Item { value: 42 }), other: 3 });
+    check(Storage { value: E::Tuple(Item { value: 1 }, Item { value: 2 }), other: 4 });
+    check(Storage { value: E::Struct { value: 42 }, other: 5 });
+  }
+
+  #[test]
+  fn as_item() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Storage { list: Vec<E> }
+
+    check(Storage { list: vec![E::Newtype2(Item { value: 42 }), E::Struct { value: 1 }] });
+  }
+
+  /// Проверяет, что попытка прочитать список, как перечисление, приводит к ошибке, а не панике
+  #[test]
+  fn list_as_enum() {
+    use crate::error::Error;
+    use crate::parser::Token;
+
+    #[derive(Debug, Serialize)]
+    struct Source { value: Vec<Item> }
+    #[derive(Debug, Deserialize)]
+    struct Target { #[allow(dead_code)] value: E }
+
+    let data = to_vec((*b"GFF ").into(), &Source { value: vec![Item { value: 42 }] }).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    match Target::deserialize(&mut de) {
+      Err(Error::UnexpectedAt { token: Token::ListBegin(1), .. }) => {},
+      res => panic!("expected Error::UnexpectedAt with ListBegin token, but {:?} found", res),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tag {
+  //! Тестирование чтения идентификаторов типов структур через поле `$tag`
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use crate::raw::Gff;
+  use crate::ser::to_vec;
+  use super::Deserializer;
+
+  #[derive(Serialize)]
+  struct Item { value: u32 }
+  #[derive(Serialize)]
+  struct Storage { value: u8, list: Vec<Item> }
+
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct TaggedItem {
+    #[serde(rename = "$tag")]
+    tag: u32,
+    value: u32,
+  }
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct TaggedStorage {
+    #[serde(rename = "$tag")]
+    tag: u32,
+    value: u8,
+    list: Vec<TaggedItem>,
+  }
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct UntaggedStorage { value: u8, list: Vec<TaggedItem> }
+
+  /// Сериализует структуру и назначает ее корню и элементам списка идентификаторы типов
+  fn data() -> Vec<u8> {
+    let value = Storage { value: 1, list: vec![Item { value: 2 }, Item { value: 3 }] };
+    let data = to_vec((*b"GFF ").into(), &value).expect("can't serialize value");
+
+    let mut gff = Gff::read(&mut Cursor::new(data)).expect("can't read GFF");
+    for (i, s) in gff.structs.iter_mut().enumerate() {
+      s.tag = if i == 0 { 0xFFFF_FFFF } else { 4 + i as u32 };
+    }
+    let mut data = Vec::new();
+    gff.write(&mut data).expect("can't write GFF");
+    data
+  }
+
+  #[test]
+  fn tagged() {
+    let mut de = Deserializer::new(Cursor::new(data())).expect("can't read GFF header");
+    let value = TaggedStorage::deserialize(&mut de).expect("can't deserialize value");
+
+    assert_eq!(value, TaggedStorage {
+      tag: 0xFFFF_FFFF,
+      value: 1,
+      list: vec![
+        TaggedItem { tag: 5, value: 2 },
+        TaggedItem { tag: 6, value: 3 },
+      ],
+    });
+  }
+
+  #[test]
+  fn only_where_requested() {
+    let mut de = Deserializer::new(Cursor::new(data())).expect("can't read GFF header");
+    let value = UntaggedStorage::deserialize(&mut de).expect("can't deserialize value");
+
+    assert_eq!(value.value, 1);
+    assert_eq!(value.list, vec![
+      TaggedItem { tag: 5, value: 2 },
+      TaggedItem { tag: 6, value: 3 },
+    ]);
+  }
+
+  /// Проверяет, что идентификаторы типов сохраняются в дереве значений при любом способе
+  /// его построения и записываются обратно в файл
+  #[test]
+  fn value() {
+    use crate::parser::Parser;
+    use crate::value::{from_value, Value};
+
+    let mut de = Deserializer::new(Cursor::new(data())).expect("can't read GFF header");
+    let value = Value::deserialize(&mut de).expect("can't deserialize value");
+    let tag = |value: &Value| match *value {
+      Value::Struct { tag, .. } => tag,
+      ref value => panic!("expected struct, but {:?} found", value),
+    };
+    assert_eq!(tag(&value), 0xFFFF_FFFF);
+    let items: Vec<_> = value["list"].iter().map(|(_, item)| tag(item)).collect();
+    assert_eq!(items, vec![5, 6]);
+    assert_eq!(value["list"][0]["value"], Value::Dword(2));
+    assert_eq!(value.len(), Some(2));
+
+    let mut parser = Parser::new(Cursor::new(data())).expect("can't read GFF header");
+    assert_eq!(parser.read_all().expect("can't read GFF"), value);
+    let gff = Gff::read(&mut Cursor::new(data())).expect("can't read GFF");
+    assert_eq!(gff.to_value().expect("can't convert GFF to Value"), value);
+
+    let storage: TaggedStorage = from_value(value.clone()).expect("can't deserialize value");
+    assert_eq!(storage.tag, 0xFFFF_FFFF);
+    assert_eq!(storage.list[1].tag, 6);
+
+    let written = Gff::from_value((*b"GFF ").into(), &value).expect("can't convert Value to GFF");
+    assert_eq!(written.to_value().expect("can't convert GFF to Value"), value);
+  }
+
+  /// Проверяет, что идентификатор структуры, чтение которой завершилось ошибкой, не
+  /// сообщается при чтении следующей структуры
+  #[test]
+  fn cleared_on_error() {
+    use std::collections::HashMap;
+    use std::fmt;
+    use serde::de::{self, MapAccess, Visitor};
+    use crate::Label;
+
+    struct Failing;
+    impl<'de> Deserialize<'de> for Failing {
+      fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: de::Deserializer<'de>,
+      {
+        struct FailingVisitor;
+        impl<'de> Visitor<'de> for FailingVisitor {
+          type Value = Failing;
+
+          fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("nothing")
+          }
+          fn visit_map<A: MapAccess<'de>>(self, _map: A) -> Result<Failing, A::Error> {
+            Err(de::Error::custom("failed"))
+          }
+        }
+        deserializer.deserialize_struct("Failing", &["$tag"], FailingVisitor)
+      }
+    }
+
+    let mut de = Deserializer::new(Cursor::new(data())).expect("can't read GFF header");
+    assert!(Failing::deserialize(&mut de).is_err());
+
+    // Пропускаем поле `value` и начало списка, оставляя первый элемент
+    for _ in 0..4 {
+      de.next_token().expect("can't read token");
+    }
+    let item = HashMap::<Label, u32>::deserialize(&mut de).expect("can't deserialize item");
+    assert_eq!(item, vec![("value".parse().unwrap(), 2)].into_iter().collect());
+  }
+}
+
+#[cfg(test)]
+mod unmatchable {
+  //! Тестирование проверки имен полей, которые не могут быть метками GFF
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use crate::ser::to_vec;
+  use super::Deserializer;
+
+  #[derive(Serialize)]
+  struct Stored { value: u8 }
+
+  #[derive(Debug, Deserialize)]
+  struct Target {
+    value: u8,
+    #[serde(rename = "twenty_bytes_long_na", default)]
+    renamed: Option<u8>,
+  }
+
+  fn deserializer() -> Deserializer<Cursor<Vec<u8>>> {
+    let data = to_vec((*b"GFF ").into(), &Stored { value: 1 }).expect("can't serialize value");
+    Deserializer::new(Cursor::new(data)).expect("can't read GFF header")
+  }
+
+  #[test]
+  fn allowed_by_default() {
+    let mut de = deserializer();
+    let value = Target::deserialize(&mut de).expect("can't deserialize value");
+    assert_eq!(value.value, 1);
+    assert_eq!(value.renamed, None);
+  }
+
+  #[test]
+  fn denied() {
+    let mut de = deserializer();
+    de.deny_unmatchable_fields(true);
+    let err = Target::deserialize(&mut de).expect_err("long field name must be rejected");
+    assert_eq!(err.to_string(), "Field `twenty_bytes_long_na` of struct `Target` never matches a GFF label: label can contain up to 16 bytes, but field name contains 20 bytes in UTF-8");
+  }
+}
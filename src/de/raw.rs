@@ -0,0 +1,382 @@
+//! Содержит реализацию десериализатора, читающего данные непосредственно из структуры уже
+//! прочитанного GFF файла
+
+use std::slice;
+use byteorder::{ByteOrder, LE};
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, IgnoredAny, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor};
+
+use crate::Label;
+use crate::de::{TAG_FIELD, VALUE_TOKEN};
+use crate::error::{Error, Result};
+use crate::raw::{Field, FieldType, Gff, Nesting};
+use crate::value::Value;
+
+/// Узел файла, который может быть десериализован
+enum Node<'a> {
+  /// Структура с указанным индексом
+  Struct(u32),
+  /// Список, содержащий структуры с указанными индексами
+  List(&'a [u32]),
+  /// Значение простого поля
+  Value(Value),
+}
+
+/// Десериализатор узла уже прочитанного GFF файла. Структуры и списки разбираются по мере
+/// обращения к ним непосредственно из массивов структур, полей и индексов, в дерево значений
+/// [`Value`] читаются только данные отдельных простых полей
+///
+/// [`Value`]: ../../value/enum.Value.html
+pub(crate) struct GffDeserializer<'a, 'p> {
+  /// Файл, из которого читаются данные
+  gff: &'a Gff,
+  /// Десериализуемый узел
+  node: Node<'a>,
+  /// Глубина вложенности узла и структуры, в которые он вложен, для обнаружения циклов
+  nesting: &'p mut Nesting,
+}
+impl<'a, 'p> GffDeserializer<'a, 'p> {
+  /// Создает десериализатор структуры с указанным индексом
+  #[inline]
+  pub(crate) fn new(gff: &'a Gff, index: u32, nesting: &'p mut Nesting) -> Self {
+    GffDeserializer { gff, node: Node::Struct(index), nesting }
+  }
+  /// Создает десериализатор значения указанного поля
+  fn field(gff: &'a Gff, field: &Field, nesting: &'p mut Nesting) -> Result<Self> {
+    let data = LE::read_u32(&field.data);
+    let node = match gff.field_type(field)? {
+      FieldType::Struct => Node::Struct(data),
+      FieldType::List   => Node::List(gff.list_items(data)?),
+      _ => Node::Value(gff.field_value(field)?.into()),
+    };
+    Ok(GffDeserializer { gff, node, nesting })
+  }
+  /// Сообщает посетителю о структуре с указанным индексом в виде отображения. Если `tagged`
+  /// равен `true`, то первым ключом отображения сообщается [`TAG_FIELD`] с идентификатором
+  /// типа структуры
+  ///
+  /// [`TAG_FIELD`]: ../constant.TAG_FIELD.html
+  fn visit_struct<'de, V>(self, index: u32, tagged: bool, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    let (s, fields) = self.gff.struct_fields(index)?;
+
+    self.nesting.enter_struct(index)?;
+    let value = visitor.visit_map(StructAccess {
+      gff: self.gff,
+      nesting: &mut *self.nesting,
+      tag: if tagged { Some(s.tag) } else { None },
+      fields: fields.iter(),
+      value: None,
+    })?;
+    self.nesting.leave_struct(index);
+    Ok(value)
+  }
+}
+
+/// Реализует метод десериализатора, перенаправляя вызов десериализатору значения для
+/// простых полей и разбирая структуры и списки методом `deserialize_any`
+macro_rules! forward_to_node {
+  ($($method:ident($($arg:ident: $type:ty),*))*) => ($(
+    #[inline]
+    fn $method<V>(self, $($arg: $type,)* visitor: V) -> Result<V::Value>
+      where V: Visitor<'de>,
+    {
+      match self.node {
+        Node::Value(value) => value.into_deserializer().$method($($arg,)* visitor),
+        _ => self.deserialize_any(visitor),
+      }
+    }
+  )*);
+}
+
+impl<'de, 'a, 'p> Deserializer<'de> for GffDeserializer<'a, 'p> {
+  type Error = Error;
+
+  #[inline]
+  fn is_human_readable(&self) -> bool { false }
+
+  fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    match self.node {
+      Node::Struct(index) => self.visit_struct(index, false, visitor),
+      Node::List(items) => {
+        self.nesting.enter()?;
+        let value = visitor.visit_seq(ListAccess { gff: self.gff, nesting: &mut *self.nesting, items: items.iter() })?;
+        self.nesting.leave();
+        Ok(value)
+      },
+      Node::Value(value) => value.into_deserializer().deserialize_any(visitor),
+    }
+  }
+
+  /// Всегда разбирает любое значение, как `Some(...)`, так же, как и GFF десериализатор
+  #[inline]
+  fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    visitor.visit_some(self)
+  }
+  /// Десериализует любую структуру в `unit`, в остальных случаях выдает ошибку
+  fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    match self.node {
+      Node::Struct(_) => visitor.visit_unit(),
+      Node::Value(value) => value.into_deserializer().deserialize_unit(visitor),
+      Node::List(_) => self.deserialize_any(visitor),
+    }
+  }
+  #[inline]
+  fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    self.deserialize_unit(visitor)
+  }
+  /// Разбирает newtype-структуры так же, как десериализатор [`Value`]
+  ///
+  /// [`Value`]: ../../value/enum.Value.html
+  fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    match self.node {
+      Node::Value(value) => value.into_deserializer().deserialize_newtype_struct(name, visitor),
+      Node::Struct(index) if name == VALUE_TOKEN => {
+        let tag = self.gff.struct_fields(index)?.0.tag;
+        self.visit_struct(index, tag != 0, visitor)
+      },
+      _ if name == VALUE_TOKEN => self.deserialize_any(visitor),
+      _ => visitor.visit_newtype_struct(self),
+    }
+  }
+  /// Разбирает перечисление, записанное во внешне-тегированном представлении: структура с
+  /// единственным полем, метка которого является именем варианта, а значение -- его содержимым.
+  /// Unit-варианты также могут быть представлены строкой с именем варианта
+  fn deserialize_enum<V>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    match self.node {
+      Node::Value(value) => value.into_deserializer().deserialize_enum(name, variants, visitor),
+      Node::Struct(index) => {
+        let field = match self.gff.struct_fields(index)?.1 {
+          &[field] => field,
+          _ => return Err(de::Error::custom("expected struct with exactly one field for enum")),
+        };
+
+        self.nesting.enter_struct(index)?;
+        let value = visitor.visit_enum(EnumDeserializer { gff: self.gff, nesting: &mut *self.nesting, field })?;
+        self.nesting.leave_struct(index);
+        Ok(value)
+      },
+      Node::List(_) => Err(de::Error::custom("expected string or struct with one field for enum, but list found")),
+    }
+  }
+  /// Разбирает структуру так же, как и отображение. Если среди полей структуры есть поле
+  /// с именем [`TAG_FIELD`], то перед настоящими полями сообщается поле с идентификатором
+  /// типа структуры, так же, как это делает GFF десериализатор
+  ///
+  /// [`TAG_FIELD`]: ../constant.TAG_FIELD.html
+  fn deserialize_struct<V>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    match self.node {
+      Node::Struct(index) => self.visit_struct(index, fields.contains(&TAG_FIELD), visitor),
+      Node::Value(value) => value.into_deserializer().deserialize_struct(name, fields, visitor),
+      Node::List(_) => self.deserialize_any(visitor),
+    }
+  }
+
+  forward_to_node!(
+    deserialize_bool() deserialize_i8() deserialize_i16() deserialize_i32() deserialize_i64()
+    deserialize_u8() deserialize_u16() deserialize_u32() deserialize_u64()
+    deserialize_f32() deserialize_f64() deserialize_char()
+    deserialize_str() deserialize_string() deserialize_bytes() deserialize_byte_buf()
+    deserialize_seq()
+    deserialize_tuple(len: usize)
+    deserialize_tuple_struct(name: &'static str, len: usize)
+    deserialize_map() deserialize_identifier() deserialize_ignored_any()
+  );
+}
+
+/// Следующее значение, которое будет прочитано из структуры
+enum Next<'a> {
+  /// Идентификатор типа структуры, сообщаемый в поле [`TAG_FIELD`]
+  ///
+  /// [`TAG_FIELD`]: ../constant.TAG_FIELD.html
+  Tag(u32),
+  /// Значение поля структуры
+  Field(&'a Field),
+}
+
+/// Предоставляет доступ к полям структуры в виде отображения
+struct StructAccess<'a, 'p> {
+  /// Файл, из которого читаются данные
+  gff: &'a Gff,
+  /// Состояние обхода, включающее собственную структуру полей
+  nesting: &'p mut Nesting,
+  /// Идентификатор типа структуры, если его еще требуется сообщить
+  tag: Option<u32>,
+  /// Индексы еще не прочитанных полей структуры
+  fields: slice::Iter<'a, u32>,
+  /// Значение, ключ которого был прочитан последним
+  value: Option<Next<'a>>,
+}
+impl<'de, 'a, 'p> MapAccess<'de> for StructAccess<'a, 'p> {
+  type Error = Error;
+
+  fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where K: DeserializeSeed<'de>,
+  {
+    if let Some(tag) = self.tag.take() {
+      self.value = Some(Next::Tag(tag));
+      return seed.deserialize(TAG_FIELD.into_deserializer()).map(Some);
+    }
+    match self.fields.next() {
+      Some(&index) => {
+        let (label, field): (Label, _) = self.gff.labeled_field(index)?;
+        self.value = Some(Next::Field(field));
+        seed.deserialize(label.into_deserializer()).map(Some)
+      },
+      None => Ok(None),
+    }
+  }
+
+  fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where V: DeserializeSeed<'de>,
+  {
+    match self.value.take() {
+      Some(Next::Tag(tag)) => seed.deserialize(tag.into_deserializer()),
+      Some(Next::Field(field)) => seed.deserialize(GffDeserializer::field(self.gff, field, &mut *self.nesting)?),
+      None => Err(de::Error::custom("value is missing")),
+    }
+  }
+
+  #[inline]
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.fields.len() + self.tag.is_some() as usize)
+  }
+}
+
+/// Предоставляет доступ к элементам списка структур
+struct ListAccess<'a, 'p> {
+  /// Файл, из которого читаются данные
+  gff: &'a Gff,
+  /// Состояние обхода, включающее сам список
+  nesting: &'p mut Nesting,
+  /// Индексы еще не прочитанных структур списка
+  items: slice::Iter<'a, u32>,
+}
+impl<'de, 'a, 'p> SeqAccess<'de> for ListAccess<'a, 'p> {
+  type Error = Error;
+
+  fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where T: DeserializeSeed<'de>,
+  {
+    match self.items.next() {
+      Some(&index) => seed.deserialize(GffDeserializer::new(self.gff, index, &mut *self.nesting)).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  #[inline]
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.items.len())
+  }
+}
+
+/// Предоставляет доступ к варианту перечисления, представленному структурой с одним полем
+struct EnumDeserializer<'a, 'p> {
+  /// Файл, из которого читаются данные
+  gff: &'a Gff,
+  /// Состояние обхода, включающее собственную структуру поля
+  nesting: &'p mut Nesting,
+  /// Индекс поля, метка которого является именем варианта, а значение -- его содержимым
+  field: u32,
+}
+impl<'de, 'a, 'p> EnumAccess<'de> for EnumDeserializer<'a, 'p> {
+  type Error = Error;
+  type Variant = GffDeserializer<'a, 'p>;
+
+  fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where V: DeserializeSeed<'de>,
+  {
+    let (label, field): (Label, _) = self.gff.labeled_field(self.field)?;
+    let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(label))?;
+    Ok((variant, GffDeserializer::field(self.gff, field, self.nesting)?))
+  }
+}
+impl<'de, 'a, 'p> VariantAccess<'de> for GffDeserializer<'a, 'p> {
+  type Error = Error;
+
+  #[inline]
+  fn unit_variant(self) -> Result<()> {
+    IgnoredAny::deserialize(self)?;
+    Ok(())
+  }
+  #[inline]
+  fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where T: DeserializeSeed<'de>,
+  {
+    seed.deserialize(self)
+  }
+  #[inline]
+  fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    self.deserialize_seq(visitor)
+  }
+  #[inline]
+  fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where V: Visitor<'de>,
+  {
+    self.deserialize_map(visitor)
+  }
+}
+
+/// Реализует метод десериализатора, перенаправляя вызов десериализатору корневой структуры
+macro_rules! forward_to_root {
+  ($($method:ident($($arg:ident: $type:ty),*))*) => ($(
+    #[inline]
+    fn $method<V>(self, $($arg: $type,)* visitor: V) -> Result<V::Value>
+      where V: Visitor<'de>,
+    {
+      GffDeserializer::new(self, 0, &mut Nesting::new(self)).$method($($arg,)* visitor)
+    }
+  )*);
+}
+/// Позволяет десериализовать значения непосредственно из структуры уже прочитанного GFF
+/// файла, без его повторной записи и разбора. Структуры и списки разбираются по мере
+/// обращения к ним прямо из массивов структур, полей и индексов в памяти, без построения
+/// промежуточного дерева значений, поэтому операций ввода-вывода не выполняется. Значения
+/// разбираются так же, как при десериализации из [`Value`].
+///
+/// Проверяется согласованность только тех ссылок, которые встречаются при разборе. Глубина
+/// вложенности структур и списков ограничена так же, как и при чтении парсером, при ее
+/// превышении возвращается ошибка [`Error::DepthLimitExceeded`]
+///
+/// [`Value`]: ../value/enum.Value.html
+/// [`Error::DepthLimitExceeded`]: ../error/enum.Error.html#variant.DepthLimitExceeded
+impl<'de> Deserializer<'de> for &Gff {
+  type Error = Error;
+
+  #[inline]
+  fn is_human_readable(&self) -> bool { false }
+
+  forward_to_root!(
+    deserialize_any()
+    deserialize_bool() deserialize_i8() deserialize_i16() deserialize_i32() deserialize_i64()
+    deserialize_u8() deserialize_u16() deserialize_u32() deserialize_u64()
+    deserialize_f32() deserialize_f64() deserialize_char()
+    deserialize_str() deserialize_string() deserialize_bytes() deserialize_byte_buf()
+    deserialize_option() deserialize_unit()
+    deserialize_unit_struct(name: &'static str)
+    deserialize_newtype_struct(name: &'static str)
+    deserialize_seq()
+    deserialize_tuple(len: usize)
+    deserialize_tuple_struct(name: &'static str, len: usize)
+    deserialize_map()
+    deserialize_struct(name: &'static str, fields: &'static [&'static str])
+    deserialize_enum(name: &'static str, variants: &'static [&'static str])
+    deserialize_identifier() deserialize_ignored_any()
+  );
+}
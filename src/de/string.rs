@@ -1,12 +1,15 @@
 //! Содержит реализацию конвертирования типа GFF строки в десериализатор
 //! с помощью которого из него могут быть прочитаны другие совместимые типы.
 
+use std::collections::HashMap;
+use std::fmt;
 use std::marker::PhantomData;
 
 use serde::forward_to_deserialize_any;
-use serde::de::{Deserializer, Error, IntoDeserializer, Visitor};
+use serde::de::{Deserialize, Deserializer, Error, IntoDeserializer, MapAccess, Visitor};
 
-use crate::string::{GffString, StringKey};
+use crate::ser::{parse_loc_string, LOC_STRING_TOKEN};
+use crate::string::{GffString, LocString, StrRef, StringKey, SubString};
 
 impl<'de, E> IntoDeserializer<'de, E> for StringKey
   where E: Error,
@@ -103,3 +106,129 @@ impl<'de, E> Deserializer<'de> for GffStringDeserializer<E>
     tuple tuple_struct map struct enum identifier ignored_any
   );
 }
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Десериализует ключ из числа в том виде, в котором он хранится в GFF файле
+impl<'de> Deserialize<'de> for StringKey {
+  #[inline]
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>,
+  {
+    u32::deserialize(deserializer).map(StringKey)
+  }
+}
+
+/// Десериализует индекс в TLK файле из числа в том виде, в котором он хранится в GFF файле
+impl<'de> Deserialize<'de> for StrRef {
+  #[inline]
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>,
+  {
+    u32::deserialize(deserializer).map(StrRef)
+  }
+}
+
+/// Десериализует часть локализуемой строки из пары "ключ - текст"
+impl<'de> Deserialize<'de> for SubString {
+  #[inline]
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>,
+  {
+    <(StringKey, String)>::deserialize(deserializer).map(Into::into)
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Структура для конвертации данных в формате [`GffStringDeserializer`] в объект `GffString`
+///
+/// [`GffStringDeserializer`]: struct.GffStringDeserializer.html
+struct GffStringVisitor;
+
+impl<'de> Visitor<'de> for GffStringVisitor {
+  type Value = GffString;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a StrRef as u32 or map from string keys to strings")
+  }
+
+  #[inline]
+  fn visit_u32<E>(self, value: u32) -> Result<GffString, E>
+    where E: Error,
+  {
+    Ok(GffString::External(StrRef(value)))
+  }
+  fn visit_u64<E>(self, value: u64) -> Result<GffString, E>
+    where E: Error,
+  {
+    if value > u64::from(u32::MAX) {
+      return Err(E::custom(format_args!("StrRef {} is out of range of u32", value)));
+    }
+    self.visit_u32(value as u32)
+  }
+  fn visit_map<A>(self, mut map: A) -> Result<GffString, A::Error>
+    where A: MapAccess<'de>,
+  {
+    let mut strings = HashMap::new();
+    while let Some((key, string)) = map.next_entry::<StringKey, String>()? {
+      strings.insert(key, string);
+    }
+    Ok(GffString::Internal(strings))
+  }
+}
+
+/// Десериализует строку из числа (внешнее представление) или отображения из ключей на
+/// строки (внутреннее представление). Именно так GFF десериализатор сообщает о полях
+/// типа `LocString`
+impl<'de> Deserialize<'de> for GffString {
+  #[inline]
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>,
+  {
+    deserializer.deserialize_any(GffStringVisitor)
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Структура для конвертации массива байт с содержимым поля типа `LocString` в объект `LocString`
+struct LocStringVisitor;
+
+impl<'de> Visitor<'de> for LocStringVisitor {
+  type Value = LocString;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a LocString data as byte buffer")
+  }
+
+  #[inline]
+  fn visit_bytes<E>(self, value: &[u8]) -> Result<LocString, E>
+    where E: Error,
+  {
+    parse_loc_string(value).map_err(E::custom)
+  }
+  /// Вызывается форматами, которые не знают о [`LOC_STRING_TOKEN`] и просто передают себя
+  ///
+  /// [`LOC_STRING_TOKEN`]: ../../ser/constant.LOC_STRING_TOKEN.html
+  #[inline]
+  fn visit_newtype_struct<D>(self, deserializer: D) -> Result<LocString, D::Error>
+    where D: Deserializer<'de>,
+  {
+    deserializer.deserialize_byte_buf(self)
+  }
+}
+
+/// Десериализует локализуемую строку без потерь из поля типа `LocString`: порядок частей
+/// строки и несколько частей для одного языка и пола сохраняются. Запрашивает у десериализатора
+/// newtype-структуру с именем [`LOC_STRING_TOKEN`], в виде которой строка и сериализуется
+///
+/// [`LOC_STRING_TOKEN`]: ../../ser/constant.LOC_STRING_TOKEN.html
+impl<'de> Deserialize<'de> for LocString {
+  #[inline]
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>,
+  {
+    deserializer.deserialize_newtype_struct(LOC_STRING_TOKEN, LocStringVisitor)
+  }
+}
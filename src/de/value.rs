@@ -5,9 +5,13 @@ use std::fmt;
 use std::marker::PhantomData;
 use indexmap::IndexMap;
 use serde::forward_to_deserialize_any;
-use serde::de::{Deserialize, Deserializer, Error, IntoDeserializer, SeqAccess, MapAccess, Visitor};
+use serde::de::value::MapDeserializer;
+use serde::de::{Deserialize, DeserializeSeed, Deserializer, EnumAccess, Error, IgnoredAny, IntoDeserializer, SeqAccess, MapAccess, VariantAccess, Visitor};
 
-use crate::Label;
+use crate::{Label, ResRef};
+use crate::de::{RESREF_TOKEN, TAG_FIELD, VALUE_TOKEN};
+use crate::resref::MAX_LEN;
+use crate::ser::{LocStringData, LOC_STRING_TOKEN};
 use crate::string::{GffString, StringKey};
 use crate::value::Value;
 
@@ -28,6 +32,14 @@ enum Key {
   Label(Label),
   /// Ключ отображения является числом и соответствует элементу многоязыковой строки
   String(StringKey),
+  /// Ключ отображения равен [`RESREF_TOKEN`], а значение является содержимым `ResRef`
+  ///
+  /// [`RESREF_TOKEN`]: ../constant.RESREF_TOKEN.html
+  ResRef,
+  /// Ключ отображения равен [`TAG_FIELD`], а значение является идентификатором типа структуры
+  ///
+  /// [`TAG_FIELD`]: ../constant.TAG_FIELD.html
+  Tag,
 }
 /// Структура для конвертации событий десериализации от serde в объект `Key`
 struct KeyVisitor;
@@ -69,6 +81,12 @@ impl<'de> Visitor<'de> for KeyVisitor {
   {
     use crate::error::Error::TooLongLabel;
 
+    if value == RESREF_TOKEN.as_bytes() {
+      return Ok(Key::ResRef);
+    }
+    if value == TAG_FIELD.as_bytes() {
+      return Ok(Key::Tag);
+    }
     match Label::from_bytes(value) {
       Ok(label) => Ok(Key::Label(label)),
       Err(TooLongLabel(len)) => Err(E::invalid_length(len, &self)),
@@ -177,6 +195,114 @@ impl<'de, E> Deserializer<'de> for LabelDeserializer<E>
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 
+/// Структура для конвертации событий десериализации от serde в объект `ResRef`
+struct ResRefVisitor;
+
+impl<'de> Visitor<'de> for ResRefVisitor {
+  type Value = ResRef;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a string or byte buffer")
+  }
+
+  #[inline]
+  fn visit_str<E>(self, value: &str) -> Result<ResRef, E>
+    where E: Error,
+  {
+    self.visit_bytes(value.as_bytes())
+  }
+  #[inline]
+  fn visit_bytes<E>(self, value: &[u8]) -> Result<ResRef, E>
+    where E: Error,
+  {
+    Ok(ResRef(value.to_vec()))
+  }
+  #[inline]
+  fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<ResRef, E>
+    where E: Error,
+  {
+    Ok(ResRef(value))
+  }
+}
+
+/// Десериализует ссылку на ресурс из строки или массива байт. GFF десериализатор принимает
+/// поля типа `ResRef`, `String` и `Void`. Длина ссылки не должна превышать 16 байт
+impl<'de> Deserialize<'de> for ResRef {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>,
+  {
+    let value = deserializer.deserialize_any(ResRefVisitor)?;
+    if value.0.len() > MAX_LEN {
+      return Err(D::Error::invalid_length(value.0.len(), &"a ResRef with length <=16 bytes"));
+    }
+    Ok(value)
+  }
+}
+
+/// Обертка для чтения содержимого `ResRef`, переданного в виде отображения с ключом [`RESREF_TOKEN`]
+///
+/// [`RESREF_TOKEN`]: ../constant.RESREF_TOKEN.html
+struct ResRefData(ResRef);
+
+impl<'de> Deserialize<'de> for ResRefData {
+  #[inline]
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>,
+  {
+    deserializer.deserialize_byte_buf(ResRefVisitor).map(ResRefData)
+  }
+}
+
+/// Отображение из единственной записи с ключом [`RESREF_TOKEN`] и содержимым `ResRef` в
+/// качестве значения. В таком виде десериализаторы передают `ResRef` при чтении [`Value`],
+/// чтобы его можно было отличить от обычной строки
+///
+/// [`RESREF_TOKEN`]: ../constant.RESREF_TOKEN.html
+/// [`Value`]: ../../value/enum.Value.html
+pub(crate) struct ResRefAccess<E> {
+  /// Значение, еще не отданное посетителю
+  value: Option<ResRef>,
+  /// Признак того, что ключ записи уже был прочитан
+  key_read: bool,
+  /// Фиктивный элемент, для связывания типа ошибки `E`
+  marker: PhantomData<E>,
+}
+impl<E> ResRefAccess<E> {
+  #[inline]
+  pub(crate) fn new(value: ResRef) -> Self {
+    ResRefAccess { value: Some(value), key_read: false, marker: PhantomData }
+  }
+}
+impl<'de, E> MapAccess<'de> for ResRefAccess<E>
+  where E: Error,
+{
+  type Error = E;
+
+  fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, E>
+    where K: DeserializeSeed<'de>,
+  {
+    if self.key_read {
+      return Ok(None);
+    }
+    self.key_read = true;
+    seed.deserialize(RESREF_TOKEN.into_deserializer()).map(Some)
+  }
+
+  fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, E>
+    where V: DeserializeSeed<'de>,
+  {
+    match self.value.take() {
+      Some(value) => seed.deserialize(Value::Void(value.0).into_deserializer()),
+      None => Err(E::custom("value is missing")),
+    }
+  }
+
+  #[inline]
+  fn size_hint(&self) -> Option<usize> { Some(1) }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
 /// Макрос, создающий функцию конвертации события serde в один из вариантов GFF значения
 macro_rules! value_from_primitive {
   ($name:ident, $type:ty => $variant:ident) => (
@@ -187,6 +313,13 @@ macro_rules! value_from_primitive {
   );
 }
 
+/// Ограничивает подсказку о количестве элементов при предварительном выделении памяти,
+/// чтобы поврежденный файл с огромным счетчиком не приводил к исчерпанию памяти
+#[inline]
+fn cautious(hint: Option<usize>) -> usize {
+  hint.unwrap_or(0).min(4096)
+}
+
 /// Структура для конвертации событий десериализации от serde в объект `Value`
 struct ValueVisitor;
 
@@ -237,15 +370,24 @@ impl<'de> Visitor<'de> for ValueVisitor {
 
   #[inline]
   fn visit_unit<E>(self) -> Result<Value, E> {
-    Ok(Value::Struct(IndexMap::with_capacity(0)))
+    Ok(Value::Struct { tag: 0, fields: IndexMap::with_capacity(0) })
+  }
+  /// Вызывается в ответ на запрос [`VALUE_TOKEN`] десериализаторами, которые не знают о
+  /// нем и просто передают себя. Разбирает значение обычным образом
+  ///
+  /// [`VALUE_TOKEN`]: ../constant.VALUE_TOKEN.html
+  #[inline]
+  fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where D: Deserializer<'de>,
+  {
+    deserializer.deserialize_any(self)
   }
-  //visit_newtype_struct - не поддерживается
 
   #[inline]
   fn visit_seq<V>(self, mut seq: V) -> Result<Value, V::Error>
     where V: SeqAccess<'de>,
   {
-    let mut vec = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+    let mut vec = Vec::with_capacity(cautious(seq.size_hint()));
 
     while let Some(elem) = seq.next_element()? {
       vec.push(elem);
@@ -253,22 +395,38 @@ impl<'de> Visitor<'de> for ValueVisitor {
 
     Ok(Value::List(vec))
   }
+  /// Разбирает структуру, `ResRef` или многоязыковую строку в зависимости от вида первого
+  /// ключа. Если первым ключом является [`TAG_FIELD`], то его значение становится
+  /// идентификатором типа структуры
+  ///
+  /// [`TAG_FIELD`]: ../constant.TAG_FIELD.html
   fn visit_map<V>(self, mut map: V) -> Result<Value, V::Error>
     where V: MapAccess<'de>,
   {
-    let size = map.size_hint().unwrap_or(0);
+    let size = cautious(map.size_hint());
 
-    if let Some(key) = map.next_key()? {
+    let mut tag = 0;
+    let mut key = map.next_key()?;
+    if let Some(Key::Tag) = key {
+      tag = map.next_value()?;
+      key = map.next_key()?;
+    }
+    if let Some(key) = key {
       match key {
         Key::Label(label) => {
-          let mut values = IndexMap::with_capacity(size);
-          values.insert(label, map.next_value()?);
+          let mut fields = IndexMap::with_capacity(size);
+          fields.insert(label, map.next_value()?);
 
           while let Some((key, value)) = map.next_entry()? {
-            values.insert(key, value);
+            fields.insert(key, value);
           }
 
-          Ok(Value::Struct(values))
+          Ok(Value::Struct { tag, fields })
+        },
+        Key::Tag => Err(V::Error::duplicate_field(TAG_FIELD)),
+        Key::ResRef => {
+          let ResRefData(value) = map.next_value()?;
+          Ok(Value::ResRef(value))
         },
         Key::String(key) => {
           let mut values = HashMap::with_capacity(size);
@@ -282,18 +440,22 @@ impl<'de> Visitor<'de> for ValueVisitor {
         },
       }
     } else {
-      Ok(Value::Struct(IndexMap::with_capacity(0)))
+      Ok(Value::Struct { tag, fields: IndexMap::with_capacity(0) })
     }
   }
   //visit_enum - не поддерживается
 }
 
 impl<'de> Deserialize<'de> for Value {
+  /// Запрашивает у десериализатора newtype-структуру с именем [`VALUE_TOKEN`], что позволяет
+  /// GFF десериализатору сообщить о значениях `ResRef`, которые иначе были бы неотличимы от строк
+  ///
+  /// [`VALUE_TOKEN`]: ../constant.VALUE_TOKEN.html
   #[inline]
   fn deserialize<D>(deserializer: D) -> Result<Value, D::Error>
     where D: Deserializer<'de>,
   {
-    deserializer.deserialize_any(ValueVisitor)
+    deserializer.deserialize_newtype_struct(VALUE_TOKEN, ValueVisitor)
   }
 }
 
@@ -351,18 +513,169 @@ impl<'de, E> Deserializer<'de> for ValueDeserializer<E>
         value.into_deserializer().deserialize_any(visitor)
       },
       Void(val)      => visitor.visit_byte_buf(val),
-      Struct(val)    => {
+      Struct { fields, .. } => {
         //TODO: После мерджа https://github.com/bluss/indexmap/pull/87 можно заменить на into_deserializer()
         use serde::de::value::MapDeserializer;
-        MapDeserializer::new(val.into_iter()).deserialize_any(visitor)
+        MapDeserializer::new(fields.into_iter()).deserialize_any(visitor)
       },
       List(val)      => val.into_deserializer().deserialize_any(visitor),
     }
   }
 
+  /// Десериализует значение типа `Byte` в `bool`, считая любое ненулевое значение истиной
+  fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    match self.value {
+      Value::Byte(val) => visitor.visit_bool(val != 0),
+      value => ValueDeserializer { value, marker: PhantomData }.deserialize_any(visitor),
+    }
+  }
+  /// Всегда разбирает любое значение, как `Some(...)`, так же, как и GFF десериализатор
+  #[inline]
+  fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    visitor.visit_some(self)
+  }
+  /// Десериализует любую структуру в `unit`, в остальных случаях выдает ошибку
+  fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    match self.value {
+      Value::Struct {..} => visitor.visit_unit(),
+      value => ValueDeserializer { value, marker: PhantomData }.deserialize_any(visitor),
+    }
+  }
+  #[inline]
+  fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    self.deserialize_unit(visitor)
+  }
+  /// Сообщает о значении `ResRef` в виде отображения с ключом [`RESREF_TOKEN`], а о структуре
+  /// с ненулевым идентификатором типа -- в виде отображения с дополнительным первым ключом
+  /// [`TAG_FIELD`], если запрашивается newtype-структура с именем [`VALUE_TOKEN`], и о значении
+  /// `LocString` в виде массива байт, если запрашивается newtype-структура с именем
+  /// [`LOC_STRING_TOKEN`]. Для остальных newtype-структур разбирает нижележащее значение
+  ///
+  /// [`RESREF_TOKEN`]: ../constant.RESREF_TOKEN.html
+  /// [`TAG_FIELD`]: ../constant.TAG_FIELD.html
+  /// [`VALUE_TOKEN`]: ../constant.VALUE_TOKEN.html
+  /// [`LOC_STRING_TOKEN`]: ../../ser/constant.LOC_STRING_TOKEN.html
+  fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    match self.value {
+      Value::ResRef(val) if name == VALUE_TOKEN => visitor.visit_map(ResRefAccess::new(val)),
+      Value::Struct { tag, fields } if name == VALUE_TOKEN && tag != 0 => tagged(tag, fields)?.deserialize_any(visitor),
+      Value::LocString(val) if name == LOC_STRING_TOKEN => {
+        visitor.visit_byte_buf(LocStringData(&val).to_bytes().map_err(Error::custom)?)
+      },
+      value if name == VALUE_TOKEN => ValueDeserializer { value, marker: PhantomData }.deserialize_any(visitor),
+      value => visitor.visit_newtype_struct(ValueDeserializer { value, marker: PhantomData }),
+    }
+  }
+  /// Разбирает перечисление, записанное во внешне-тегированном представлении: структура с
+  /// единственным полем, метка которого является именем варианта, а значение -- его содержимым.
+  /// Unit-варианты также могут быть представлены строкой с именем варианта
+  fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    match self.value {
+      Value::String(val) => visitor.visit_enum(val.into_deserializer()),
+      Value::Struct { fields, .. } => {
+        let mut fields = fields.into_iter();
+        match (fields.next(), fields.next()) {
+          (Some((label, value)), None) => visitor.visit_enum(EnumDeserializer { label, value, marker: PhantomData }),
+          _ => Err(Error::custom("expected struct with exactly one field for enum")),
+        }
+      },
+      value => Err(Error::custom(format_args!("expected string or struct with one field for enum, but {:?} found", value))),
+    }
+  }
+
+  /// Разбирает структуру так же, как и отображение. Если среди полей структуры есть поле
+  /// с именем [`TAG_FIELD`], то перед настоящими полями сообщается поле с идентификатором
+  /// типа структуры, так же, как это делает GFF десериализатор
+  ///
+  /// [`TAG_FIELD`]: ../constant.TAG_FIELD.html
+  fn deserialize_struct<V>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    match self.value {
+      Value::Struct { tag, fields: values } if fields.contains(&TAG_FIELD) => tagged(tag, values)?.deserialize_any(visitor),
+      value => ValueDeserializer { value, marker: PhantomData }.deserialize_any(visitor),
+    }
+  }
+
   forward_to_deserialize_any!(
-    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str
-    string bytes byte_buf option unit unit_struct newtype_struct seq
-    tuple tuple_struct map struct enum identifier ignored_any
+    i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str
+    string bytes byte_buf seq
+    tuple tuple_struct map identifier ignored_any
   );
 }
+
+/// Создает десериализатор отображения из полей структуры, первым ключом которого является
+/// [`TAG_FIELD`] с идентификатором типа структуры в качестве значения
+///
+/// [`TAG_FIELD`]: ../constant.TAG_FIELD.html
+fn tagged<E>(tag: u32, fields: IndexMap<Label, Value>) -> Result<MapDeserializer<'static, impl Iterator<Item = (Label, Value)>, E>, E>
+  where E: Error,
+{
+  let label: Label = TAG_FIELD.parse().map_err(E::custom)?;
+  Ok(MapDeserializer::new(std::iter::once((label, Value::Dword(tag))).chain(fields)))
+}
+
+/// Предоставляет доступ к варианту перечисления, представленному структурой с одним полем
+struct EnumDeserializer<E> {
+  /// Метка поля, являющаяся именем варианта
+  label: Label,
+  /// Содержимое варианта
+  value: Value,
+  /// Фиктивный элемент, для связывания типа ошибки `E`
+  marker: PhantomData<E>,
+}
+impl<'de, E> EnumAccess<'de> for EnumDeserializer<E>
+  where E: Error,
+{
+  type Error = E;
+  type Variant = ValueDeserializer<E>;
+
+  #[inline]
+  fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where V: DeserializeSeed<'de>,
+  {
+    let variant = seed.deserialize(self.label.into_deserializer())?;
+    Ok((variant, self.value.into_deserializer()))
+  }
+}
+impl<'de, E> VariantAccess<'de> for ValueDeserializer<E>
+  where E: Error,
+{
+  type Error = E;
+
+  #[inline]
+  fn unit_variant(self) -> Result<(), Self::Error> {
+    IgnoredAny::deserialize(self)?;
+    Ok(())
+  }
+  #[inline]
+  fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where T: DeserializeSeed<'de>,
+  {
+    seed.deserialize(self)
+  }
+  #[inline]
+  fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    self.deserialize_seq(visitor)
+  }
+  #[inline]
+  fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de>,
+  {
+    self.deserialize_map(visitor)
+  }
+}
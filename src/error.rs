@@ -10,6 +10,7 @@ use std::string::FromUtf8Error;
 use serde::de;
 use serde::ser;
 
+use crate::index::StructIndex;
 use crate::parser::Token;
 use self::Error::*;
 
@@ -28,14 +29,76 @@ pub enum Error {
     /// Значение, которое было записано в файле для данного тега
     value: u32
   },
+  /// Заголовок файла описывает область, которая пересекается с самим заголовком или выходит
+  /// за пределы файла. Ошибка содержит название некорректной области
+  MalformedHeader(&'static str),
+  /// Внутренние ссылки GFF файла (на поля, метки, структуры, данные полей или списки)
+  /// указывают за пределы соответствующих областей. Ошибка содержит описание первой
+  /// найденной некорректной ссылки
+  Inconsistent(String),
+  /// Структура с указанным индексом через свои поля или элементы списков ссылается сама на
+  /// себя или на одну из структур, в которые она вложена. Данные GFF файла должны образовывать
+  /// дерево, поэтому такая ссылка означает, что файл поврежден.
+  ///
+  /// Это единственная ошибка о циклических ссылках: ее возвращают и потоковый [`Parser`], и
+  /// методы [`Gff`], обходящие дерево структур, и десериализаторы, построенные поверх них
+  ///
+  /// [`Parser`]: ../parser/struct.Parser.html
+  /// [`Gff`]: ../raw/struct.Gff.html
+  CyclicStructure {
+    /// Индекс структуры, которая встретилась повторно при разборе вложенных в нее данных
+    index: StructIndex,
+  },
+  /// Поле ссылается на метку с указанным индексом, но в файле нет метки с таким индексом
+  LabelIndexOutOfRange(u32),
+  /// Ключ многоязыковой строки содержит код языка, не соответствующий ни одному из известных
+  /// языков. Ошибка содержит этот код
+  UnknownLanguage(u32),
+  /// Идентификатор типа структуры не соответствует ни одному из известных типов игровых
+  /// объектов. Ошибка содержит этот идентификатор
+  UnknownObjectType(u32),
   /// Разбор уже завершен
   ParsingFinished,
+  /// Глубина вложенности структур и списков в файле превысила установленный в парсере предел.
+  /// Ошибка содержит этот предел
+  DepthLimitExceeded(usize),
+  /// Размер строки или двоичных данных, количество элементов списка или суммарный размер
+  /// прочитанных данных превысили ограничение, установленное в парсере
+  LimitExceeded {
+    /// Название превышенного ограничения -- имя поля структуры `Limits`
+    limit: &'static str,
+    /// Значение ограничения
+    max: usize,
+  },
   /// Некорректное значение для метки. Метка не должна превышать по длине 16 байт в UTF-8,
   /// но указанное значение больше. Ошибка содержит длину текста, который пытаются преобразовать
   TooLongLabel(usize),
+  /// Некорректное значение для ссылки на ресурс. Ссылка не должна превышать по длине 16 байт,
+  /// но указанное значение больше. Ошибка содержит длину текста, который пытаются преобразовать
+  TooLongResRef(usize),
+  /// Некорректная строка для сигнатуры файла. Сигнатура должна состоять из 3 или 4 ASCII
+  /// символов. Ошибка содержит текст, который пытаются преобразовать
+  InvalidSignature(String),
+  /// Некорректная строка для версии файла. Версия должна иметь формат `<major>.<minor>`
+  /// или `V<major>.<minor>`, где обе части -- одна десятичная цифра. Ошибка содержит текст,
+  /// который пытаются преобразовать
+  InvalidVersion(String),
   /// При десериализации был обнаружен указанный токен, хотя ожидался не он.
   /// Ожидаемые значения описаны в первом параметре
   Unexpected(&'static str, Token),
+  /// При разборе файла был обнаружен указанный токен, хотя ожидался не он. В отличие от
+  /// [`Unexpected`](#variant.Unexpected) содержит позицию в файле, в которой произошла ошибка
+  UnexpectedAt {
+    /// Описание ожидаемых токенов
+    expected: &'static str,
+    /// Обнаруженный токен
+    token: Token,
+    /// Смещение в байтах от начала файла записи, из которой был прочитан токен: записи поля
+    /// для меток и значений полей (в том числе начала вложенных структур и списков) и записи
+    /// структуры для начала корневой структуры и элементов списков. Для токенов окончания --
+    /// смещение записи последнего прочитанного перед ними токена
+    offset: u64,
+  },
   /// Ошибка, возникшая при десериализации
   Deserialize(String),
   /// Ошибка, возникшая при сериализации
@@ -50,15 +113,60 @@ impl fmt::Display for Error {
       Io(ref err) => err.fmt(fmt),
       Encoding(ref msg) => msg.fmt(fmt),
       UnknownValue { tag, value } => write!(fmt, "Unknown field value (tag: {}, value: {})", tag, value),
+      MalformedHeader(section) => write!(fmt, "Malformed GFF header: section `{}` overlaps the header or exceeds the file bounds", section),
+      Inconsistent(ref msg) => write!(fmt, "Inconsistent GFF data: {}", msg),
+      CyclicStructure { index } => write!(fmt, "Cyclic GFF data: struct {} contains itself", index.0),
+      LabelIndexOutOfRange(index) => write!(fmt, "Label index {} is out of range", index),
+      UnknownLanguage(code) => write!(fmt, "Unknown language code: {}", code),
+      UnknownObjectType(tag) => write!(fmt, "Unknown object type: {}", tag),
       ParsingFinished => write!(fmt, "Parsing finished"),
+      DepthLimitExceeded(limit) => write!(fmt, "Nesting depth limit ({}) exceeded", limit),
+      LimitExceeded { limit, max } => write!(fmt, "Limit `{}` ({}) exceeded", limit, max),
       TooLongLabel(len) => write!(fmt, "Too long label: label can contain up to 16 bytes, but string contains {} bytes in UTF-8", len),
+      TooLongResRef(len) => write!(fmt, "Too long resref: resref can contain up to 16 bytes, but string contains {} bytes", len),
+      InvalidSignature(ref sig) => write!(fmt, "Invalid signature {:?}: signature must contain 3 or 4 ASCII characters", sig),
+      InvalidVersion(ref ver) => write!(fmt, "Invalid version {:?}: version must have format `3.2` or `V3.2`", ver),
       Unexpected(ref expected, ref actual) => write!(fmt, "Expected {}, but {:?} found", expected, actual),
+      UnexpectedAt { ref expected, ref token, offset } => write!(fmt, "Expected {}, but {:?} found at offset {}", expected, token, offset),
       Deserialize(ref msg) => msg.fmt(fmt),
       Serialize(ref msg) => msg.fmt(fmt),
     }
   }
 }
 
+/// Ошибки сравниваются по виду и содержимому, ошибки ввода-вывода -- только по их виду
+/// ([`io::ErrorKind`]), т.к. сами они не сравнимы
+///
+/// [`io::ErrorKind`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html
+impl PartialEq for Error {
+  fn eq(&self, other: &Self) -> bool {
+    match (self, other) {
+      (Io(l), Io(r)) => l.kind() == r.kind(),
+      (Encoding(l), Encoding(r)) => l == r,
+      (UnknownValue { tag: lt, value: lv }, UnknownValue { tag: rt, value: rv }) => lt == rt && lv == rv,
+      (MalformedHeader(l), MalformedHeader(r)) => l == r,
+      (Inconsistent(l), Inconsistent(r)) => l == r,
+      (CyclicStructure { index: l }, CyclicStructure { index: r }) => l == r,
+      (LabelIndexOutOfRange(l), LabelIndexOutOfRange(r)) => l == r,
+      (UnknownLanguage(l), UnknownLanguage(r)) => l == r,
+      (UnknownObjectType(l), UnknownObjectType(r)) => l == r,
+      (ParsingFinished, ParsingFinished) => true,
+      (DepthLimitExceeded(l), DepthLimitExceeded(r)) => l == r,
+      (LimitExceeded { limit: ll, max: lm }, LimitExceeded { limit: rl, max: rm }) => ll == rl && lm == rm,
+      (TooLongLabel(l), TooLongLabel(r)) => l == r,
+      (TooLongResRef(l), TooLongResRef(r)) => l == r,
+      (InvalidSignature(l), InvalidSignature(r)) => l == r,
+      (InvalidVersion(l), InvalidVersion(r)) => l == r,
+      (Unexpected(le, lt), Unexpected(re, rt)) => le == re && lt == rt,
+      (UnexpectedAt { expected: le, token: lt, offset: lo },
+       UnexpectedAt { expected: re, token: rt, offset: ro }) => le == re && lt == rt && lo == ro,
+      (Deserialize(l), Deserialize(r)) => l == r,
+      (Serialize(l), Serialize(r)) => l == r,
+      _ => false,
+    }
+  }
+}
+
 impl error::Error for Error {
   fn source(&self) -> Option<&(dyn error::Error + 'static)> {
     match *self {
@@ -71,6 +179,18 @@ impl error::Error for Error {
 impl From<io::Error> for Error {
   fn from(value: io::Error) -> Self { Io(value) }
 }
+/// Ошибки ввода-вывода передаются как есть, остальные ошибки оборачиваются в ошибку
+/// вида [`ErrorKind::InvalidData`]
+///
+/// [`ErrorKind::InvalidData`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidData
+impl From<Error> for io::Error {
+  fn from(value: Error) -> Self {
+    match value {
+      Io(err) => err,
+      err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+  }
+}
 /// Реализация для конвертации из ошибок кодирования библиотеки `encodings`
 impl From<Cow<'static, str>> for Error {
   fn from(value: Cow<'static, str>) -> Self { Encoding(value) }
@@ -92,3 +212,43 @@ impl ser::Error for Error {
     Error::Serialize(msg.to_string())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::io;
+  use crate::label::Label;
+  use crate::parser::Token;
+  use super::Error;
+
+  #[test]
+  fn eq() {
+    assert_eq!("a_very_long_label".parse::<Label>().unwrap_err(), Error::TooLongLabel(17));
+    assert_ne!(Error::TooLongLabel(17), Error::TooLongResRef(17));
+    assert_ne!(Error::TooLongLabel(17), Error::TooLongLabel(18));
+    assert_eq!(Error::ParsingFinished, Error::ParsingFinished);
+    assert_eq!(Error::UnknownValue { tag: 1, value: 2 }, Error::UnknownValue { tag: 1, value: 2 });
+    assert_ne!(Error::UnknownValue { tag: 1, value: 2 }, Error::UnknownValue { tag: 2, value: 1 });
+    assert_eq!(Error::Unexpected("struct", Token::ListEnd), Error::Unexpected("struct", Token::ListEnd));
+    assert_ne!(Error::Unexpected("struct", Token::ListEnd), Error::Unexpected("struct", Token::ItemEnd));
+    assert_eq!(Error::Deserialize("msg".into()), Error::Deserialize("msg".into()));
+    assert_ne!(Error::Deserialize("msg".into()), Error::Serialize("msg".into()));
+
+    // Ошибки ввода-вывода сравниваются по виду
+    let eof = |msg| Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, msg));
+    assert_eq!(eof("first"), eof("second"));
+    assert_ne!(eof("first"), Error::Io(io::ErrorKind::InvalidData.into()));
+  }
+
+  #[test]
+  fn into_io_error() {
+    let err: io::Error = Error::TooLongLabel(20).into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), Error::TooLongLabel(20).to_string());
+    assert!(matches!(err.get_ref().and_then(|e| e.downcast_ref::<Error>()), Some(Error::TooLongLabel(20))));
+
+    // Ошибки ввода-вывода не оборачиваются повторно
+    let err: io::Error = Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "eof")).into();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(err.to_string(), "eof");
+  }
+}
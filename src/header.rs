@@ -1,9 +1,10 @@
 //! Содержит описания структур заголовка GFF файла
 
-use std::cmp::max;
 use std::io::{Read, Write, Result};
 use byteorder::{LE, ReadBytesExt, WriteBytesExt};
 
+use crate::error::Error;
+
 pub use crate::sig::*;
 pub use crate::ver::*;
 
@@ -25,6 +26,23 @@ impl Section {
       count:  reader.read_u32::<LE>()?,
     })
   }
+  /// Проверяет, что непустая область начинается после заголовка и целиком помещается в файл
+  ///
+  /// # Параметры
+  /// - `name`: Название области, используемое в сообщении об ошибке
+  /// - `record_size`: Размер одной записи области в байтах
+  /// - `file_len`: Полный размер файла в байтах
+  fn validate(&self, name: &'static str, record_size: u64, file_len: u64) -> crate::error::Result<()> {
+    if self.count == 0 {
+      return Ok(());
+    }
+    let start = self.offset as u64;
+    let end   = start + self.count as u64 * record_size;
+    if start < Header::HEADER_SIZE as u64 || end > file_len {
+      return Err(Error::MalformedHeader(name));
+    }
+    Ok(())
+  }
   /// Записывает описание области файла в поток
   #[inline]
   pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
@@ -76,6 +94,10 @@ pub struct Header {
 }
 
 impl Header {
+  /// Размер заголовка GFF файла в байтах: сигнатура, версия и описания 6 областей.
+  /// Данные файла располагаются сразу за заголовком
+  pub const HEADER_SIZE: u32 = 4 + 4 + 6 * 8;
+
   /// Создает заголовок для пустого файла с указанным типом
   #[inline]
   pub fn new(signature: Signature) -> Self {
@@ -96,8 +118,14 @@ impl Header {
     }
   }
   /// Читает значение GFF заголовка из потока
-  pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
-    Ok(Header {
+  ///
+  /// # Ошибки
+  /// Помимо ошибок чтения, возвращает [`Error::MalformedHeader`], если непустая область
+  /// структур, которая идет в файле первой, начинается внутри заголовка
+  ///
+  /// [`Error::MalformedHeader`]: ../error/enum.Error.html#variant.MalformedHeader
+  pub fn read<R: Read>(reader: &mut R) -> crate::error::Result<Self> {
+    let header = Header {
       signature:     Signature::read(reader)?,
       version:       Version::read(reader)?,
 
@@ -107,7 +135,12 @@ impl Header {
       field_data:    Section::read(reader)?,
       field_indices: Section::read(reader)?,
       list_indices:  Section::read(reader)?,
-    })
+    };
+    // Пустые области в реальных файлах могут иметь нулевое смещение
+    if header.structs.count != 0 && header.structs.offset < Self::HEADER_SIZE {
+      return Err(Error::MalformedHeader("structs"));
+    }
+    Ok(header)
   }
   /// Записывает значение GFF заголовка в поток
   pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
@@ -121,17 +154,110 @@ impl Header {
     self.field_indices.write(writer)?;
     self.list_indices.write(writer)
   }
+  /// Проверяет, что все области, описываемые заголовком, не пересекаются с ним и целиком
+  /// помещаются в файл указанного размера.
+  ///
+  /// # Параметры
+  /// - `file_len`: Полный размер файла в байтах, включая заголовок
+  ///
+  /// # Ошибки
+  /// Возвращает [`Error::MalformedHeader`] с названием первой некорректной области
+  ///
+  /// [`Error::MalformedHeader`]: ../error/enum.Error.html#variant.MalformedHeader
+  pub fn validate(&self, file_len: u64) -> crate::error::Result<()> {
+    for (name, section, record_size) in &self.sections() {
+      section.validate(name, *record_size, file_len)?;
+    }
+    Ok(())
+  }
+  /// Возвращает все области файла в порядке их расположения в заголовке вместе с их
+  /// названиями и размерами одной записи области в байтах
+  pub(crate) fn sections(&self) -> [(&'static str, &Section, u64); 6] {
+    [
+      ("structs",       &self.structs,       3 * 4),// 3 * u32
+      ("fields",        &self.fields,        3 * 4),// 3 * u32
+      ("labels",        &self.labels,          16),// 16 * u8
+      // Количество в следующих областях задается в байтах, а не элементах
+      ("field_data",    &self.field_data,        1),
+      ("field_indices", &self.field_indices,     1),
+      ("list_indices",  &self.list_indices,      1),
+    ]
+  }
+  /// Возвращает полный размер файла в байтах, описываемого данным заголовком: размер
+  /// самого заголовка и суммарный размер всех областей. Для файлов, в которых области
+  /// следуют друг за другом без промежутков (в том числе для всех файлов, создаваемых
+  /// сериализатором), совпадает с размером файла
+  pub fn total_size(&self) -> u64 {
+    self.sections().iter()
+      .map(|(_, section, record_size)| section.count as u64 * record_size)
+      .sum::<u64>() + Self::HEADER_SIZE as u64
+  }
   /// Возвращает нижнюю границу на количество токенов, которые может произвести
   /// данный файл
   #[inline]
   pub fn token_count(&self) -> usize {
-    // Для каждой структуры - токен начала и окончания
-    // Для каждого списка - токен начала и окончания
-    let size = (self.structs.count + self.list_indices.count)*2;
-
-    // Т.к. каждое поле может быть списком или структурой, то они уже подсчитываются
-    // в списках и структурах. Поэтому минимальное количество вычисляем, как максимум
-    // из того, что нам смогут дать поля или структуры со списками
-    max(size, self.fields.count) as usize
+    let structs = self.structs.count as usize;
+    let fields  = self.fields.count as usize;
+    // Для каждой структуры - токен начала и окончания, для каждого поля - токен метки.
+    // Кроме того, каждое поле, не являющееся структурой, производит хотя бы один токен
+    // значения или начала списка. Полями-структурами могут быть все структуры, кроме
+    // корневой. Метки и индексы полей токенов сами по себе не производят, а количество
+    // списков по заголовку неизвестно (область индексов списков задана в байтах)
+    structs * 2 + fields + (fields + 1).saturating_sub(structs)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs::File;
+  use std::io::Cursor;
+  use crate::error::Error;
+  use crate::parser::Parser;
+  use super::{Header, Section};
+
+  /// Записывает заголовок пустого файла с указанным смещением области структур
+  fn header(offset: u32) -> Vec<u8> {
+    let mut header = Header::new((*b"GFF ").into());
+    header.structs = Section { offset, count: 1 };
+
+    let mut data = Vec::new();
+    header.write(&mut data).unwrap();
+    assert_eq!(data.len(), Header::HEADER_SIZE as usize);
+    data
+  }
+
+  #[test]
+  fn structs_inside_header() {
+    match Header::read(&mut Cursor::new(header(10))) {
+      Err(Error::MalformedHeader(section)) => assert_eq!(section, "structs"),
+      result => panic!("expected Error::MalformedHeader, but {:?} found", result),
+    }
+    assert!(Header::read(&mut Cursor::new(header(Header::HEADER_SIZE))).is_ok());
+  }
+
+  #[test]
+  fn total_size() {
+    assert_eq!(Header::new((*b"GFF ").into()).total_size(), Header::HEADER_SIZE as u64);
+
+    let data = std::fs::read("test-data/all.gff").expect("test file not exist");
+    let header = Header::read(&mut Cursor::new(&data)).unwrap();
+    assert_eq!(header.total_size(), data.len() as u64);
+  }
+
+  /// Проверяет, что количество токенов в файле не меньше оценки по заголовку
+  #[test]
+  fn token_count() {
+    let parser = Parser::new(File::open("test-data/all.gff").expect("test file not exist")).unwrap();
+    let estimate = parser.header().token_count();
+    assert!(estimate <= parser.count());
+
+    // Корневая структура с единственным простым полем: начало, метка, значение, конец
+    let mut header = Header::new((*b"GFF ").into());
+    header.structs.count = 1;
+    header.fields.count = 1;
+    assert_eq!(header.token_count(), 4);
+    // Корневая структура с единственным полем-структурой без полей
+    header.structs.count = 2;
+    assert_eq!(header.token_count(), 5);
   }
 }
@@ -0,0 +1,184 @@
+//! Содержит реализацию преобразований дерева значений GFF в дерево значений JSON библиотеки
+//! `serde_json` и обратно. Доступно при включенной возможности `json`
+
+use std::convert::TryFrom;
+use indexmap::IndexMap;
+use serde_json::{Map, Number, Value as Json};
+
+use crate::{Label, LocString, StrRef, StringKey, SubString};
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+/// Имя единственного поля JSON объекта, которым представляется локализуемая строка
+const LOC_STRING_KEY: &str = "$locstring";
+
+/// Преобразует дерево значений GFF в дерево значений JSON. Преобразование теряет часть
+/// информации:
+/// - все числа становятся числами JSON, поэтому их исходный тип (например, `Byte` или `Dword`)
+///   не сохраняется. Значения `NaN` и бесконечности становятся `null`;
+/// - значения `ResRef` и `Void` записываются в виде строк в кодировке base64 и при обратном
+///   преобразовании становятся строками;
+/// - идентификаторы типов структур не сохраняются, при обратном преобразовании они равны `0`;
+/// - порядок полей структур сохраняется, только если `serde_json` сохраняет порядок ключей
+///   объектов (возможность `preserve_order`).
+///
+/// Локализуемые строки (`LocString`) записываются в виде объекта с единственным ключом
+/// `$locstring`, значением которого является объект с полями `str_ref` (число) и `strings`
+/// (массив объектов с полями `key` и `string`), и восстанавливаются без потерь
+impl From<Value> for Json {
+  fn from(value: Value) -> Json {
+    match value {
+      Value::Byte(val)      => val.into(),
+      Value::Char(val)      => val.into(),
+      Value::Word(val)      => val.into(),
+      Value::Short(val)     => val.into(),
+      Value::Dword(val)     => val.into(),
+      Value::Int(val)       => val.into(),
+      Value::Dword64(val)   => val.into(),
+      Value::Int64(val)     => val.into(),
+      Value::Float(val)     => Number::from_f64(val.into()).map_or(Json::Null, Json::Number),
+      Value::Double(val)    => Number::from_f64(val).map_or(Json::Null, Json::Number),
+      Value::String(val)    => Json::String(val),
+      Value::ResRef(val)    => Json::String(base64::encode(&val.0)),
+      Value::LocString(val) => {
+        let strings = val.strings.into_iter().map(|s| {
+          let mut map = Map::new();
+          map.insert("key".into(), s.key.0.into());
+          map.insert("string".into(), s.string.into());
+          Json::Object(map)
+        }).collect();
+
+        let mut inner = Map::new();
+        inner.insert("str_ref".into(), val.str_ref.0.into());
+        inner.insert("strings".into(), Json::Array(strings));
+
+        let mut map = Map::new();
+        map.insert(LOC_STRING_KEY.into(), Json::Object(inner));
+        Json::Object(map)
+      },
+      Value::Void(val)      => Json::String(base64::encode(&val)),
+      Value::Struct { fields, .. } => Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v.into())).collect()),
+      Value::List(val)      => Json::Array(val.into_iter().map(Json::from).collect()),
+    }
+  }
+}
+
+/// Преобразует дерево значений JSON в дерево значений GFF:
+/// - `true` и `false` становятся значениями `Byte` 1 и 0;
+/// - неотрицательные целые числа становятся `Dword64`, отрицательные -- `Int64`, а
+///   дробные -- `Double`;
+/// - строки становятся `String`, массивы -- `List`, объекты -- `Struct`;
+/// - объект с единственным ключом `$locstring` становится `LocString` (см. обратное
+///   преобразование).
+///
+/// # Ошибки
+/// Возвращает ошибку [`Error::Deserialize`], если значение является `null` (в GFF нет
+/// аналогичного значения) или объект локализуемой строки имеет неправильный формат, и ошибку
+/// [`Error::TooLongLabel`], если ключ объекта длиннее 16 байт
+///
+/// [`Error::Deserialize`]: error/enum.Error.html#variant.Deserialize
+/// [`Error::TooLongLabel`]: error/enum.Error.html#variant.TooLongLabel
+impl TryFrom<Json> for Value {
+  type Error = Error;
+
+  fn try_from(value: Json) -> Result<Value> {
+    Ok(match value {
+      Json::Null        => return Err(Error::Deserialize("null cannot be represented in GFF".into())),
+      Json::Bool(val)   => Value::Byte(val as u8),
+      Json::Number(val) => {
+        if let Some(val) = val.as_u64() {
+          Value::Dword64(val)
+        } else if let Some(val) = val.as_i64() {
+          Value::Int64(val)
+        } else {
+          Value::Double(val.as_f64().unwrap_or(f64::NAN))
+        }
+      },
+      Json::String(val) => Value::String(val),
+      Json::Array(val)  => Value::List(val.into_iter().map(Value::try_from).collect::<Result<_>>()?),
+      Json::Object(mut val) => {
+        if val.len() == 1 {
+          if let Some(inner) = val.remove(LOC_STRING_KEY) {
+            return Ok(Value::LocString(loc_string(inner)?));
+          }
+        }
+        let mut fields = IndexMap::with_capacity(val.len());
+        for (key, value) in val {
+          let label: Label = key.parse()?;
+          fields.insert(label, Value::try_from(value)?);
+        }
+        Value::Struct { tag: 0, fields }
+      },
+    })
+  }
+}
+
+/// Восстанавливает локализуемую строку из JSON представления, создаваемого при преобразовании
+/// `Value::LocString` в JSON
+fn loc_string(value: Json) -> Result<LocString> {
+  let invalid = || Error::Deserialize(format!("invalid `{}` object: expected `{{\"str_ref\": u32, \"strings\": [{{\"key\": u32, \"string\": string}}]}}`", LOC_STRING_KEY));
+  let as_u32 = |value: Option<&Json>| value.and_then(Json::as_u64).and_then(|v| u32::try_from(v).ok());
+
+  let str_ref = as_u32(value.get("str_ref")).ok_or_else(invalid)?;
+  let strings = value.get("strings").and_then(Json::as_array).ok_or_else(invalid)?;
+
+  let mut result = Vec::with_capacity(strings.len());
+  for s in strings {
+    let key    = as_u32(s.get("key")).ok_or_else(invalid)?;
+    let string = s.get("string").and_then(Json::as_str).ok_or_else(invalid)?;
+    result.push(SubString { key: StringKey(key), string: string.to_owned() });
+  }
+  Ok(LocString { str_ref: StrRef(str_ref), strings: result })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::convert::TryFrom;
+  use serde_json::{json, Value as Json};
+  use crate::{LocString, ResRef, StrRef, StringKey, SubString};
+  use crate::value::Value;
+
+  /// Проверяет, что значения, типы которых выбираются при обратном преобразовании, не
+  /// изменяются после преобразования в JSON и обратно
+  #[test]
+  fn round_trip() {
+    let loc = LocString {
+      str_ref: StrRef(42),
+      strings: vec![SubString { key: StringKey(1), string: "Текст".into() }],
+    };
+    let value: Value = vec![
+      ("u64".parse().unwrap(), Value::Dword64(u64::MAX)),
+      ("i64".parse().unwrap(), Value::Int64(-5)),
+      ("f64".parse().unwrap(), Value::Double(0.5)),
+      ("string".parse().unwrap(), Value::String("String".into())),
+      ("loc".parse().unwrap(), Value::LocString(loc)),
+      ("list".parse().unwrap(), vec![Value::default(), Value::Int64(-1)].into_iter().collect()),
+    ].into_iter().collect();
+
+    let json = Json::from(value.clone());
+    assert_eq!(json["loc"], json!({ "$locstring": { "str_ref": 42, "strings": [{ "key": 1, "string": "Текст" }] } }));
+    assert_eq!(Value::try_from(json).expect("can't convert JSON to Value"), value);
+  }
+
+  /// Проверяет преобразования, теряющие информацию о типе
+  #[test]
+  fn lossy() {
+    assert_eq!(Json::from(Value::Byte(1)), json!(1));
+    assert_eq!(Value::try_from(json!(1)).unwrap(), Value::Dword64(1));
+    assert_eq!(Value::try_from(json!(true)).unwrap(), Value::Byte(1));
+    assert_eq!(Json::from(Value::Float(f32::NAN)), Json::Null);
+
+    let resref = Json::from(Value::ResRef(ResRef(b"nw_it_gold".to_vec())));
+    assert_eq!(resref, json!("bndfaXRfZ29sZA=="));
+    assert_eq!(Json::from(Value::Void(vec![0, 1, 2])), json!("AAEC"));
+    assert_eq!(Value::try_from(resref).unwrap(), Value::String("bndfaXRfZ29sZA==".into()));
+  }
+
+  #[test]
+  fn errors() {
+    assert!(Value::try_from(Json::Null).is_err());
+    assert!(Value::try_from(json!({ "a_very_long_label": 1 })).is_err());
+    assert!(Value::try_from(json!({ "$locstring": { "str_ref": -1, "strings": [] } })).is_err());
+    assert!(Value::try_from(json!({ "$locstring": { "str_ref": 1 } })).is_err());
+  }
+}
@@ -24,6 +24,15 @@ impl Label {
     return from_utf8(&self.0);
   }
 
+  /// Пытается создать метку из указанной строки. Аналог `value.parse()`.
+  ///
+  /// # Ошибки
+  /// В случае, если строка в UTF-8 занимает более 16 байт, возвращается ошибка
+  /// [`Error::TooLongLabel`](./error/enum.Error.html#variant.TooLongLabel)
+  #[inline]
+  pub fn new(value: &str) -> Result<Self, Error> {
+    Self::from_bytes(value.as_bytes())
+  }
   /// Пытается создать метку из указанного массива байт.
   ///
   /// # Ошибки
@@ -80,6 +89,12 @@ impl FromStr for Label {
 mod tests {
   use super::Label;
 
+  #[test]
+  fn label_new() {
+    assert_eq!(Label::new("short").unwrap(), "short".parse().unwrap());
+    assert!(Label::new("more_then_16_char").is_err());
+  }
+
   #[test]
   fn label_constructs_from_str() {
     assert_eq!(Label::from(*b"short\0\0\0\0\0\0\0\0\0\0\0"), "short".parse().unwrap());
@@ -5,6 +5,8 @@
 //! - элементами верхнего уровня могут быть только структуры или перечисления Rust в unit или struct варианте
 //! - имена полей структур не должны быть длиннее 16 байт в UTF-8. При нарушении при сериализации будет ошибка
 //! - то же самое касается ключей карт. Кроме того, ключами могут быть только строки (`&str` или `String`)
+//! - имя поля `$tag` зарезервировано: в нем хранится идентификатор типа структуры, который
+//!   читается и записывается вместо обычного поля (см. [`de::TAG_FIELD`](de/constant.TAG_FIELD.html))
 //!
 //! # Пример
 //! ```rust
@@ -81,14 +83,19 @@ pub mod index;
 pub mod value;
 pub mod error;
 pub mod raw;
+pub mod view;
+pub mod resref;
+pub mod locstring;
+pub mod prelude;
+#[cfg(feature = "json")]
+mod json;
 
 // Модули, чье содержимое реэкспортируется, разделено для удобства сопровождения
 mod label;
-mod resref;
 mod string;
 
 pub use crate::label::*;
-pub use crate::resref::*;
+pub use crate::resref::{ResRef, ResRefCI};
 pub use crate::string::*;
 
 // Модули для поддержки инфраструктуры serde
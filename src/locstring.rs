@@ -0,0 +1,91 @@
+//! Содержит функции для использования в атрибуте `#[serde(with = "serde_gff::locstring")]`
+//! на полях типа [`GffString`], позволяющие читать и записывать их как поля типа `LocString`
+//! напрямую, без промежуточного представления в виде числа или отображения:
+//!
+//! ```rust
+//! use serde::{Serialize, Deserialize};
+//! use serde_gff::GffString;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! #[allow(non_snake_case)]
+//! struct Creature {
+//!   #[serde(with = "serde_gff::locstring")]
+//!   FirstName: GffString,
+//! }
+//! ```
+//!
+//! [`GffString`]: ../enum.GffString.html
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::string::{GffString, LocString};
+
+/// Сериализует строку как поле типа `LocString`. Внутреннее представление строки
+/// записывается со `StrRef(0xFFFFFFFF)`, внешнее -- без строк
+#[inline]
+pub fn serialize<S>(value: &GffString, serializer: S) -> Result<S::Ok, S::Error>
+  where S: Serializer,
+{
+  LocString::from(value.clone()).serialize(serializer)
+}
+
+/// Десериализует строку из поля типа `LocString`. Для полей других типов GFF десериализатор
+/// возвращает ошибку
+#[inline]
+pub fn deserialize<'de, D>(deserializer: D) -> Result<GffString, D::Error>
+  where D: Deserializer<'de>,
+{
+  LocString::deserialize(deserializer).map(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use std::io::Cursor;
+  use serde::{Deserialize, Serialize};
+  use crate::de::Deserializer;
+  use crate::error::Error;
+  use crate::ser::to_vec;
+  use crate::string::{Gender, GffString, Language, StrRef, StringKey};
+  use crate::value::{from_value, to_value};
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Test {
+    #[serde(with = "super")]
+    name: GffString,
+  }
+
+  fn internal() -> Test {
+    let mut strings = HashMap::new();
+    strings.insert(StringKey::from((Language::English, Gender::Male)), "Aribeth".to_owned());
+    strings.insert(StringKey::from((Language::French, Gender::Female)), "Aribeth de Tylmarande".to_owned());
+    strings.insert(StringKey::from((Language::German, Gender::Male)), "".to_owned());
+    Test { name: GffString::Internal(strings) }
+  }
+
+  #[test]
+  fn round_trip() {
+    for value in [internal(), Test { name: GffString::External(StrRef(42)) }] {
+      let data = to_vec((*b"GFF ").into(), &value).expect("can't serialize value");
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      assert_eq!(Test::deserialize(&mut de).expect("can't deserialize value"), value);
+
+      // Значение также переживает преобразование в `Value` и обратно
+      let converted = to_value(&value).expect("can't convert to Value");
+      assert_eq!(from_value::<Test>(converted).expect("can't convert from Value"), value);
+    }
+  }
+
+  #[test]
+  fn wrong_field_type() {
+    #[derive(Serialize)]
+    struct Other { name: String }
+
+    let data = to_vec((*b"GFF ").into(), &Other { name: "Aribeth".into() }).expect("can't serialize value");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    match Test::deserialize(&mut de) {
+      Err(Error::UnexpectedAt { expected: "LocString", .. }) => {},
+      result => panic!("expected Error::UnexpectedAt, but {:?} found", result),
+    }
+  }
+}
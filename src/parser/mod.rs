@@ -1,17 +1,22 @@
 //! Реализация потокового парсера GFF файла. См. описание структуры [`Parser`](struct.Parser.html)
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::iter::FusedIterator;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{BufReader, Cursor, ErrorKind, Read, Seek, SeekFrom};
+use std::str;
 use byteorder::{LE, ReadBytesExt};
 use encoding::{EncodingRef, DecoderTrap};
 use encoding::all::UTF_8;
+use indexmap::IndexMap;
 
 use crate::{Label, SubString, ResRef, StrRef};
 use crate::error::{Error, Result};
-use crate::header::Header;
-use crate::index::{Index, LabelIndex, U64Index, I64Index, F64Index, StringIndex, ResRefIndex, LocStringIndex, BinaryIndex};
-use crate::string::{LocString, StringKey};
-use crate::value::{SimpleValue, SimpleValueRef};
+use crate::header::{Header, Signature, Version};
+use crate::raw::{read_bytes, FieldType, Gff};
+use crate::index::{Index, FieldIndex, LabelIndex, U64Index, I64Index, F64Index, StringIndex, ResRefIndex, LocStringIndex, BinaryIndex};
+use crate::string::{Game, Language, LocString, StringKey};
+use crate::value::{SimpleValue, SimpleValueRef, Value};
 
 mod token;
 mod states;
@@ -19,9 +24,131 @@ mod states;
 use self::states::State;
 pub use self::token::Token;
 
+/// Максимальная глубина вложенности структурных элементов файла, используемая по умолчанию
+const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Ограничения на размеры данных, читаемых парсером, и на глубину вложенности структурных
+/// элементов файла. По умолчанию ограничена только глубина вложенности, см. [`Parser::set_limits`].
+/// Ограничение глубины вложенности также применяется при обработке уже прочитанного файла,
+/// см. [`Gff::limits`]
+///
+/// [`Parser::set_limits`]: struct.Parser.html#method.set_limits
+/// [`Gff::limits`]: ../raw/struct.Gff.html#structfield.limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+  /// Максимальный размер в байтах одной строки, ссылки на ресурс, части локализуемой строки
+  /// или двоичных данных
+  pub max_string_len: usize,
+  /// Максимальное количество элементов одного списка или частей одной локализуемой строки
+  pub max_list_len: usize,
+  /// Максимальный суммарный размер в байтах всех строк, ссылок на ресурсы и двоичных данных,
+  /// прочитанных парсером, включая области данных полей и меток, прочитанные целиком
+  pub max_allocated: usize,
+  /// Максимальная глубина вложенности структур, списков и элементов списков,
+  /// см. [`Parser::set_max_depth`]
+  ///
+  /// [`Parser::set_max_depth`]: struct.Parser.html#method.set_max_depth
+  pub max_depth: usize,
+}
+impl Limits {
+  /// Проверяет, что данные указанного размера можно прочитать, если уже прочитано `allocated` байт
+  fn check_size(&self, size: usize, allocated: usize) -> Result<()> {
+    if size > self.max_string_len {
+      return Err(Error::LimitExceeded { limit: "max_string_len", max: self.max_string_len });
+    }
+    self.check_allocated(size, allocated)
+  }
+  /// Проверяет, что можно выделить еще `size` байт, если уже прочитано `allocated` байт
+  fn check_allocated(&self, size: usize, allocated: usize) -> Result<()> {
+    if size > self.max_allocated.saturating_sub(allocated) {
+      return Err(Error::LimitExceeded { limit: "max_allocated", max: self.max_allocated });
+    }
+    Ok(())
+  }
+}
+impl Default for Limits {
+  fn default() -> Self {
+    Limits {
+      max_string_len: usize::MAX,
+      max_list_len:   usize::MAX,
+      max_allocated:  usize::MAX,
+      max_depth:      DEFAULT_MAX_DEPTH,
+    }
+  }
+}
+
 /// Уникальный идентификатор типа структуры, хранимой в GFF-файле
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Tag(u32);
+pub struct Tag(pub(crate) u32);
+
+impl Tag {
+  /// Значение идентификатора в том виде, в котором оно хранится в GFF файле
+  #[inline]
+  pub fn raw(self) -> u32 { self.0 }
+  /// Значение идентификатора. Синоним для [`raw`](#method.raw)
+  #[inline]
+  pub fn get(self) -> u32 { self.0 }
+  /// Интерпретирует идентификатор, как тип игрового объекта, хранимого в элементе списка
+  /// экземпляров объектов области (`.git` файл). Для неизвестных значений возвращает `None`
+  #[inline]
+  pub fn object_type(self) -> Option<ObjectType> {
+    ObjectType::try_from(self.0).ok()
+  }
+}
+
+/// Типы игровых объектов, экземпляры которых хранятся в списках `.git` файлов. Значение
+/// перечисления совпадает с идентификатором типа ([`Tag`]) структур-элементов этих списков
+///
+/// [`Tag`]: struct.Tag.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ObjectType {
+  /// Предмет, лежащий в области (список `List`)
+  Item = 0,
+  /// Триггер (список `TriggerList`)
+  Trigger = 1,
+  /// Существо (список `Creature List`)
+  Creature = 4,
+  /// Путевая точка (список `WaypointList`)
+  Waypoint = 5,
+  /// Источник звука (список `SoundList`)
+  Sound = 6,
+  /// Область появления существ (список `Encounter List`)
+  Encounter = 7,
+  /// Дверь (список `Door List`)
+  Door = 8,
+  /// Размещаемый объект (список `Placeable List`)
+  Placeable = 9,
+  /// Магазин (список `StoreList`)
+  Store = 11,
+}
+
+/// Преобразует идентификатор типа структуры в тип игрового объекта.
+///
+/// # Ошибки
+/// Для неизвестных идентификаторов возвращает ошибку [`Error::UnknownObjectType`]
+///
+/// [`Error::UnknownObjectType`]: ../error/enum.Error.html#variant.UnknownObjectType
+impl TryFrom<u32> for ObjectType {
+  type Error = Error;
+
+  fn try_from(value: u32) -> Result<Self> {
+    use self::ObjectType::*;
+
+    Ok(match value {
+       0 => Item,
+       1 => Trigger,
+       4 => Creature,
+       5 => Waypoint,
+       6 => Sound,
+       7 => Encounter,
+       8 => Door,
+       9 => Placeable,
+      11 => Store,
+      _ => return Err(Error::UnknownObjectType(value)),
+    })
+  }
+}
 
 /// Реализует потоковый (наподобие SAX) парсер GFF файла. Парсер реализует интерфейс
 /// итератора по [токенам]. Каждый вызов метода [`next_token`] возвращает следующий токен
@@ -163,9 +290,33 @@ pub struct Parser<R: Read + Seek> {
   trap: DecoderTrap,
   /// Текущее состояние разбора
   state: State,
+  /// Заранее прочитанные метки всех полей файла. Если `None`, метки читаются из файла
+  /// по требованию, см. [`preload_labels`](#method.preload_labels)
+  labels: Option<Vec<Label>>,
+  /// Заранее прочитанная область данных полей. Если `None`, данные комплексных полей читаются
+  /// из файла по требованию, см. [`preload_field_data`](#method.preload_field_data)
+  field_data: Option<Vec<u8>>,
+  /// Кодировки, используемые для декодирования частей локализуемых строк на конкретных языках.
+  /// Для языков, отсутствующих в списке, используется кодировка `encoding`
+  languages: HashMap<Language, EncodingRef>,
+  /// Текущая глубина вложенности: количество начатых, но еще не завершенных структур,
+  /// списков и элементов списков
+  depth: usize,
+  /// Ограничения на размеры читаемых данных и глубину вложенности, см. [`set_limits`](#method.set_limits)
+  limits: Limits,
+  /// Суммарный размер в байтах всех строк и двоичных данных, прочитанных парсером
+  allocated: usize,
+  /// Идентификаторы типов читаемых в данный момент структур, от корневой до самой вложенной
+  tags: Vec<Tag>,
+  /// Смещение от начала файла записи (структуры, поля или списка), из которой был прочитан
+  /// последний токен. Используется для указания места ошибки в файле
+  record: u64,
 }
 
 impl<R: Read + Seek> Parser<R> {
+  /// Максимальная глубина вложенности структурных элементов файла, используемая по умолчанию
+  pub const DEFAULT_MAX_DEPTH: usize = DEFAULT_MAX_DEPTH;
+
   /// Создает парсер для чтения GFF файла из указанного источника данных с использованием
   /// кодировки `UTF-8` для декодирования строк и генерацией ошибки в случае, если декодировать
   /// набор байт, как строку в этой кодировке, не удалось.
@@ -183,18 +334,136 @@ impl<R: Read + Seek> Parser<R> {
   /// - `encoding`: Кодировка для декодирования символов в строках
   /// - `trap`: Способ обработки символов в строках, которые не удалось декодировать с
   ///   использованием выбранной кодировки
+  ///
+  /// # Ошибки
+  /// В случае, если не удалось прочитать заголовок GFF файла или если области, описываемые
+  /// заголовком, выходят за пределы файла (ошибка [`Error::MalformedHeader`])
+  ///
+  /// [`Error::MalformedHeader`]: ../error/enum.Error.html#variant.MalformedHeader
   pub fn with_encoding(mut reader: R, encoding: EncodingRef, trap: DecoderTrap) -> Result<Self> {
     let header = Header::read(&mut reader)?;
 
-    Ok(Parser { header, reader, encoding, trap, state: State::default() })
+    let position = reader.stream_position()?;
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    header.validate(file_len)?;
+    reader.seek(SeekFrom::Start(position))?;
+
+    Ok(Parser {
+      header,
+      reader,
+      encoding,
+      trap,
+      state: State::default(),
+      labels: None,
+      field_data: None,
+      languages: HashMap::new(),
+      depth: 0,
+      limits: Limits::default(),
+      allocated: 0,
+      tags: Vec::new(),
+      record: 0,
+    })
+  }
+  /// Создает парсер для чтения GFF файла из указанного источника данных с кодировкой `UTF-8`
+  /// для декодирования строк (см. [`new`]) и указанными ограничениями на размеры читаемых данных
+  ///
+  /// # Параметры
+  /// - `reader`: Источник данных для чтения файла
+  /// - `limits`: Ограничения на размеры данных, см. [`set_limits`]
+  ///
+  /// [`new`]: #method.new
+  /// [`set_limits`]: #method.set_limits
+  pub fn with_limits(reader: R, limits: Limits) -> Result<Self> {
+    let mut parser = Self::new(reader)?;
+    parser.set_limits(limits);
+    Ok(parser)
+  }
+  /// Устанавливает ограничения на размеры данных, которые может прочитать парсер. Ограничения
+  /// защищают от специально созданных файлов, содержащих огромные длины строк или количество
+  /// элементов списков, чтение которых привело бы к выделению большого количества памяти.
+  /// При превышении ограничения на размер данных чтение значения или [`next_token`] возвращают
+  /// ошибку [`Error::LimitExceeded`], при превышении глубины вложенности -- ошибку
+  /// [`Error::DepthLimitExceeded`]
+  ///
+  /// [`next_token`]: #method.next_token
+  /// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+  /// [`Error::DepthLimitExceeded`]: ../error/enum.Error.html#variant.DepthLimitExceeded
+  #[inline]
+  pub fn set_limits(&mut self, limits: Limits) {
+    self.limits = limits;
+  }
+  /// Возвращает идентификатор типа самой вложенной из структур, разбираемых в данный момент,
+  /// т.е. структуры, к которой относятся читаемые поля. Возвращает `None` до начала и после
+  /// окончания разбора корневой структуры
+  #[inline]
+  pub fn current_tag(&self) -> Option<Tag> {
+    self.tags.last().cloned()
+  }
+  /// Устанавливает максимальную глубину вложенности структур, списков и элементов списков
+  /// друг в друга. При ее превышении [`next_token`] возвращает ошибку [`Error::DepthLimitExceeded`].
+  /// Ограничение защищает от переполнения стека при рекурсивной обработке специально
+  /// созданных файлов с очень глубокой вложенностью. По умолчанию равна [`DEFAULT_MAX_DEPTH`]
+  ///
+  /// [`next_token`]: #method.next_token
+  /// [`DEFAULT_MAX_DEPTH`]: #associatedconstant.DEFAULT_MAX_DEPTH
+  /// [`Error::DepthLimitExceeded`]: ../error/enum.Error.html#variant.DepthLimitExceeded
+  #[inline]
+  pub fn set_max_depth(&mut self, max_depth: usize) {
+    self.limits.max_depth = max_depth;
   }
   /// Возвращает следующий токен или ошибку, если данных не осталось или при их чтении возникли
   /// проблемы.
+  ///
+  /// # Ошибки
+  /// Если структура через свои поля или элементы списков ссылается на одну из структур, в
+  /// которые она вложена, возвращает ошибку [`Error::CyclicStructure`] с индексом этой
+  /// структуры вместо бесконечного разбора цикла
+  ///
+  /// [`Error::CyclicStructure`]: ../error/enum.Error.html#variant.CyclicStructure
   pub fn next_token(&mut self) -> Result<Token> {
     let (token, next) = self.state.clone().next(self)?;
+    if let Token::ListBegin(count) = token {
+      if count as usize > self.limits.max_list_len {
+        return Err(Error::LimitExceeded { limit: "max_list_len", max: self.limits.max_list_len });
+      }
+    }
+    if token.is_begin() {
+      if self.depth >= self.limits.max_depth {
+        return Err(Error::DepthLimitExceeded(self.limits.max_depth));
+      }
+      self.depth += 1;
+    } else if token.is_end() {
+      self.depth = self.depth.saturating_sub(1);
+    }
+    match token {
+      Token::RootBegin { tag, .. } |
+      Token::StructBegin { tag, .. } |
+      Token::ItemBegin { tag, .. } => self.tags.push(tag),
+      Token::RootEnd | Token::StructEnd | Token::ItemEnd => { self.tags.pop(); },
+      _ => {},
+    }
     self.state = next;
     Ok(token)
   }
+  /// Читает все оставшиеся токены файла, передавая каждый из них в указанную функцию.
+  /// Чтение завершается, когда файл закончился, при ошибке разбора или когда функция вернет
+  /// ошибку. Ошибка [`Error::ParsingFinished`] в конце файла не считается ошибкой
+  ///
+  /// # Параметры
+  /// - `f`: Функция, вызываемая для каждого прочитанного токена
+  ///
+  /// [`Error::ParsingFinished`]: ../error/enum.Error.html#variant.ParsingFinished
+  pub fn drive<F>(&mut self, mut f: F) -> Result<()>
+    where F: FnMut(&Token) -> Result<()>,
+  {
+    loop {
+      match self.next_token() {
+        Ok(token) => f(&token)?,
+        Err(Error::ParsingFinished) => return Ok(()),
+        Err(err) => return Err(err),
+      }
+    }
+  }
   /// Быстро пропускает всю внутреннюю структуру, переводя парсер в состояние, при котором
   /// вызов [`next_token`] вернет следующий структурный элемент после пропущенного (следующее
   /// поле структуры или элемент списка).
@@ -205,14 +474,183 @@ impl<R: Read + Seek> Parser<R> {
   /// [`next_token`]: #method.next_token
   #[inline]
   pub fn skip_next(&mut self, token: Token) {
+    // Завершающий токен пропускаемого элемента не будет получен, поэтому уменьшаем глубину сразу
+    if token.is_begin() {
+      self.depth = self.depth.saturating_sub(1);
+    }
+    if let Token::RootBegin { .. } | Token::StructBegin { .. } | Token::ItemBegin { .. } = token {
+      self.tags.pop();
+    }
     self.state = self.state.clone().skip(token);
   }
+  /// Читает всю область данных полей файла за один раз и в дальнейшем читает значения
+  /// комплексных полей (строк, 64-битных чисел, двоичных данных и т.п.) из памяти, а не из
+  /// файла. После вызова чтение таких значений не меняет позицию чтения в файле.
+  ///
+  /// Требует памяти по размеру области данных полей. Не меняет позицию чтения в файле
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку [`Error::LimitExceeded`], если размер области превышает оставшуюся
+  /// часть ограничения [`Limits::max_allocated`]
+  ///
+  /// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+  /// [`Limits::max_allocated`]: struct.Limits.html#structfield.max_allocated
+  pub fn preload_field_data(&mut self) -> Result<()> {
+    let size = self.header.field_data.count as usize;
+    self.limits.check_allocated(size, self.allocated)?;
+
+    let old = self.offset()?;
+    self.reader.seek(SeekFrom::Start(self.header.field_data.offset as u64))?;
+    let data = read_bytes(&mut self.reader, size)?;
+    self.reader.seek(old)?;
+
+    self.allocated += size;
+    self.field_data = Some(data);
+    Ok(())
+  }
+  /// Читает всю область меток файла за один раз и в дальнейшем использует прочитанные
+  /// значения в [`read_label`] вместо чтения меток из файла при каждом обращении.
+  ///
+  /// При десериализации в структуры одни и те же метки читаются многократно, поэтому
+  /// предварительное чтение позволяет избавиться от лишних перемещений по файлу ценой
+  /// небольшого количества памяти (16 байт на метку). Рекомендуется вызывать сразу после
+  /// создания парсера. Не меняет позицию чтения в файле
+  ///
+  /// [`read_label`]: #method.read_label
+  pub fn preload_labels(&mut self) -> Result<()> {
+    let labels = self.labels()?;
+    self.labels = Some(labels);
+    Ok(())
+  }
+  /// Возвращает все метки файла в порядке их хранения, читая область меток целиком, без
+  /// обхода структур файла. Если метки были предварительно прочитаны методом [`preload_labels`],
+  /// возвращает их копию. Не меняет позицию чтения в файле
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку [`Error::LimitExceeded`], если размер области меток (16 байт на метку)
+  /// превышает оставшуюся часть ограничения [`Limits::max_allocated`]
+  ///
+  /// [`preload_labels`]: #method.preload_labels
+  /// [`Error::LimitExceeded`]: ../error/enum.Error.html#variant.LimitExceeded
+  /// [`Limits::max_allocated`]: struct.Limits.html#structfield.max_allocated
+  pub fn labels(&mut self) -> Result<Vec<Label>> {
+    if let Some(ref labels) = self.labels {
+      return Ok(labels.clone());
+    }
+    let count = self.header.labels.count as usize;
+    let size = count.saturating_mul(16);
+    self.limits.check_allocated(size, self.allocated)?;
+
+    let old = self.offset()?;
+    self.seek(LabelIndex(0))?;
+
+    let mut labels = Vec::with_capacity(count);
+    for _ in 0..count {
+      let mut label = [0u8; 16];
+      self.reader.read_exact(&mut label)?;
+      labels.push(label.into());
+    }
+
+    self.reader.seek(old)?;
+    self.allocated += size;
+    Ok(labels)
+  }
+  /// Создает парсер для чтения GFF файла указанной игры из указанного источника данных.
+  /// Строки декодируются в кодировке, в которой их хранит игра (см. [`Game::encoding`]), а для
+  /// игр, хранящих части локализуемых строк в кодировках их языков, дополнительно вызывается
+  /// [`use_language_encodings`]. Строки, которые не удалось декодировать, приводят к ошибке.
+  ///
+  /// # Параметры
+  /// - `reader`: Источник данных для чтения файла
+  /// - `game`: Игра, создавшая файл
+  ///
+  /// # Ошибки
+  /// Те же, что и у [`with_encoding`]
+  ///
+  /// [`Game::encoding`]: ../enum.Game.html#method.encoding
+  /// [`use_language_encodings`]: #method.use_language_encodings
+  /// [`with_encoding`]: #method.with_encoding
+  pub fn for_game(reader: R, game: Game) -> Result<Self> {
+    let mut parser = Self::with_encoding(reader, game.encoding(), DecoderTrap::Strict)?;
+    if game.uses_language_encodings() {
+      parser.use_language_encodings();
+    }
+    Ok(parser)
+  }
+  /// Устанавливает кодировку, которая будет использоваться для декодирования частей
+  /// локализуемых строк на указанном языке вместо кодировки, переданной при создании парсера
+  ///
+  /// # Параметры
+  /// - `language`: Язык, для которого меняется кодировка
+  /// - `encoding`: Кодировка для декодирования текста на этом языке
+  #[inline]
+  pub fn set_language_encoding(&mut self, language: Language, encoding: EncodingRef) {
+    self.languages.insert(language, encoding);
+  }
+  /// Устанавливает для всех известных языков кодировки, в которых движок Aurora хранит
+  /// текст на этих языках (см. [`Language::encoding`]). Используется для чтения оригинальных
+  /// файлов игры, в которых текст на разных языках хранится в разных кодовых страницах.
+  /// Части строк с неизвестным кодом языка по-прежнему декодируются кодировкой парсера.
+  ///
+  /// Отдельные кодировки после вызова могут быть переопределены методом [`set_language_encoding`]
+  ///
+  /// [`Language::encoding`]: ../enum.Language.html#method.encoding
+  /// [`set_language_encoding`]: #method.set_language_encoding
+  pub fn use_language_encodings(&mut self) {
+    for &language in Language::ALL.iter() {
+      self.languages.insert(language, language.encoding());
+    }
+  }
+  /// Возвращает заголовок читаемого файла, прочитанный при создании парсера. Позволяет
+  /// узнать сигнатуру и версию файла, не перечитывая его
+  #[inline]
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+  /// Уничтожает парсер и возвращает нижележащий источник данных. Позиция чтения в нем не
+  /// определена: парсер перемещается по областям файла не по порядку, а данные, прочитанные
+  /// заранее в память, читает, не обращаясь к источнику. Единственное исключение -- парсер,
+  /// только что созданный методом [`new`], который оставляет позицию сразу за заголовком.
+  ///
+  /// Чтобы продолжить чтение данных, следующих за GFF файлом, переместитесь на позицию
+  /// [`Header::total_size`] от начала файла
+  ///
+  /// [`new`]: #method.new
+  /// [`Header::total_size`]: ../header/struct.Header.html#method.total_size
+  #[inline]
+  pub fn into_inner(self) -> R {
+    self.reader
+  }
+  /// Возвращает текущую позицию чтения в источнике данных в байтах от его начала. Вместе с
+  /// [`Header::total_size`] позволяет оценить прогресс разбора файла.
+  ///
+  /// Позиция отражает последнее перемещение по файлу, поэтому она не обязательно растет
+  /// монотонно: чтение значений полей по запросу перемещает позицию в другие области файла
+  ///
+  /// [`Header::total_size`]: ../header/struct.Header.html#method.total_size
+  #[inline]
+  pub fn position(&mut self) -> Result<u64> {
+    Ok(self.reader.stream_position()?)
+  }
 //-------------------------------------------------------------------------------------------------
 // Завершение чтения комплексных данных
 //-------------------------------------------------------------------------------------------------
-  /// Читает из файла значение метки по указанному индексу.
+  /// Читает из файла значение метки по указанному индексу. Если метки были предварительно
+  /// прочитаны методом [`preload_labels`], обращения к файлу не происходит.
   /// Не меняет позицию чтения в файле
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку [`Error::LabelIndexOutOfRange`], если в файле нет метки с указанным индексом
+  ///
+  /// [`preload_labels`]: #method.preload_labels
+  /// [`Error::LabelIndexOutOfRange`]: ../error/enum.Error.html#variant.LabelIndexOutOfRange
   pub fn read_label(&mut self, index: LabelIndex) -> Result<Label> {
+    if index.0 >= self.header.labels.count {
+      return Err(Error::LabelIndexOutOfRange(index.0));
+    }
+    if let Some(label) = self.labels.as_ref().and_then(|l| l.get(index.0 as usize)) {
+      return Ok(*label);
+    }
     let old = self.offset()?;
     self.seek(index)?;
 
@@ -222,59 +660,154 @@ impl<R: Read + Seek> Parser<R> {
     self.reader.seek(old)?;
     Ok(label.into())
   }
-  /// Читает из файла значение поля по указанному индексу. Побочный эффект -- переход по указанному адресу
-  pub fn read_u64(&mut self, index: U64Index) -> Result<u64> {
+  /// Читает из файла индекс метки, привязанной к полю с указанным индексом, не читая саму
+  /// метку. Позволяет сравнивать метки полей по индексам без декодирования строк.
+  /// Не меняет позицию чтения в файле
+  pub fn field_label_index(&mut self, index: FieldIndex) -> Result<u32> {
+    let old = self.offset()?;
     self.seek(index)?;
-    self.reader.read_u64::<LE>().map_err(Into::into)
+
+    // Метка хранится после 4 байт идентификатора типа поля
+    self.reader.seek(SeekFrom::Current(4))?;
+    let label = self.read_u32()?;
+
+    self.reader.seek(old)?;
+    Ok(label)
+  }
+  /// Читает значение поля по указанному индексу. Если область данных полей не прочитана в память
+  /// методом [`preload_field_data`], переходит по указанному адресу, иначе позиция чтения не меняется
+  ///
+  /// [`preload_field_data`]: #method.preload_field_data
+  pub fn read_u64(&mut self, index: U64Index) -> Result<u64> {
+    self.data(index)?.read_u64::<LE>().map_err(Into::into)
   }
-  /// Читает из файла значение поля по указанному индексу. Побочный эффект -- переход по указанному адресу
+  /// Читает значение поля по указанному индексу. Если область данных полей не прочитана в память
+  /// методом [`preload_field_data`], переходит по указанному адресу, иначе позиция чтения не меняется
+  ///
+  /// [`preload_field_data`]: #method.preload_field_data
   pub fn read_i64(&mut self, index: I64Index) -> Result<i64> {
-    self.seek(index)?;
-    self.reader.read_i64::<LE>().map_err(Into::into)
+    self.data(index)?.read_i64::<LE>().map_err(Into::into)
   }
-  /// Читает из файла значение поля по указанному индексу. Побочный эффект -- переход по указанному адресу
+  /// Читает значение поля по указанному индексу. Если область данных полей не прочитана в память
+  /// методом [`preload_field_data`], переходит по указанному адресу, иначе позиция чтения не меняется
+  ///
+  /// [`preload_field_data`]: #method.preload_field_data
   pub fn read_f64(&mut self, index: F64Index) -> Result<f64> {
-    self.seek(index)?;
-    self.reader.read_f64::<LE>().map_err(Into::into)
+    self.data(index)?.read_f64::<LE>().map_err(Into::into)
   }
   /// Читает 4 байта длины и следующие за ними байты строки, интерпретирует их в соответствии с
-  /// кодировкой декодера и возвращает полученную строку. Побочный эффект -- переход по указанному адресу
+  /// кодировкой декодера и возвращает полученную строку. Если область данных полей не прочитана в память
+  /// методом [`preload_field_data`], переходит по указанному адресу, иначе позиция чтения не меняется
+  ///
+  /// [`preload_field_data`]: #method.preload_field_data
   pub fn read_string(&mut self, index: StringIndex) -> Result<String> {
-    self.seek(index)?;
-    self.read_string_impl()
+    let (limits, allocated) = (self.limits, self.allocated);
+    let bytes = read_sized(&mut self.data(index)?, &limits, allocated)?;
+    self.allocated += bytes.len();
+    Ok(self.encoding.decode(&bytes, self.trap)?)
+  }
+  /// Интерпретирует байт в соответствии с кодировкой декодера и возвращает полученный символ.
+  /// Используется для чтения символов, хранимых в полях типа `Byte` и `Char`
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку [`Error::Encoding`], если байт не удалось декодировать или он
+  /// не является отдельным символом в кодировке декодера (например, байты `>= 128` в `UTF-8`)
+  ///
+  /// [`Error::Encoding`]: ../error/enum.Error.html#variant.Encoding
+  pub fn decode_char(&self, byte: u8) -> Result<char> {
+    let string = self.encoding.decode(&[byte], self.trap)?;
+    let mut chars = string.chars();
+    match (chars.next(), chars.next()) {
+      (Some(ch), None) => Ok(ch),
+      _ => Err(Error::Encoding(format!("byte {} is not a single character in encoding `{}`", byte, self.encoding.name()).into())),
+    }
   }
   /// Читает 1 байт длины и следующие за ними байты массива, возвращает прочитанный массив,
-  /// обернутый в `ResRef`. Побочный эффект -- переход по указанному адресу
+  /// обернутый в `ResRef`. Если область данных полей не прочитана в память
+  /// методом [`preload_field_data`], переходит по указанному адресу, иначе позиция чтения не меняется
+  ///
+  /// [`preload_field_data`]: #method.preload_field_data
   pub fn read_resref(&mut self, index: ResRefIndex) -> Result<ResRef> {
-    self.seek(index)?;
+    let (limits, allocated) = (self.limits, self.allocated);
+    let mut data = self.data(index)?;
 
-    let size = self.reader.read_u8()? as usize;
-    let mut bytes = Vec::with_capacity(size);
-    unsafe { bytes.set_len(size); }
-
-    self.reader.read_exact(&mut bytes)?;
+    let size = data.read_u8()? as usize;
+    limits.check_size(size, allocated)?;
+    let bytes = read_bytes(&mut data, size)?;
+    self.allocated += bytes.len();
     Ok(ResRef(bytes))
   }
-  /// Читает из файла значение поля по указанному индексу. Побочный эффект -- переход по указанному адресу
+  /// Читает значение поля по указанному индексу. Если область данных полей не прочитана в память
+  /// методом [`preload_field_data`], переходит по указанному адресу, иначе позиция чтения не меняется
+  ///
+  /// [`preload_field_data`]: #method.preload_field_data
   pub fn read_loc_string(&mut self, index: LocStringIndex) -> Result<LocString> {
-    self.seek(index)?;
+    let (limits, mut allocated) = (self.limits, self.allocated);
+    let mut data = self.data(index)?;
 
-    let _total_size = self.read_u32()?;
-    let str_ref     = StrRef(self.read_u32()?);
-    let count       = self.read_u32()?;
+    let _total_size = data.read_u32::<LE>()?;
+    let str_ref     = StrRef(data.read_u32::<LE>()?);
+    let count       = data.read_u32::<LE>()?;
+    if count as usize > limits.max_list_len {
+      return Err(Error::LimitExceeded { limit: "max_list_len", max: limits.max_list_len });
+    }
 
-    let mut strings = Vec::with_capacity(count as usize);
+    // Части строк декодируются после чтения, т.к. кодировки хранятся в самом парсере
+    let mut parts = Vec::new();
     for _i in 0..count {
-      strings.push(self.read_substring()?);
+      let key = StringKey(data.read_u32::<LE>()?);
+      let bytes = read_sized(&mut data, &limits, allocated)?;
+      allocated += bytes.len();
+      parts.push((key, bytes));
     }
+    self.allocated = allocated;
 
+    let mut strings = Vec::with_capacity(parts.len());
+    for (key, bytes) in parts {
+      strings.push(self.decode_substring(key, &bytes)?);
+    }
     Ok(LocString { str_ref, strings })
   }
   /// Читает 4 байта длины и следующие за ними байты массива, возвращает прочитанный массив.
-  /// Побочный эффект -- переход по указанному адресу
+  /// Если область данных полей не прочитана в память
+  /// методом [`preload_field_data`], переходит по указанному адресу, иначе позиция чтения не меняется
+  ///
+  /// [`preload_field_data`]: #method.preload_field_data
   pub fn read_byte_buf(&mut self, index: BinaryIndex) -> Result<Vec<u8>> {
-    self.seek(index)?;
-    self.read_bytes()
+    let (limits, allocated) = (self.limits, self.allocated);
+    let bytes = read_sized(&mut self.data(index)?, &limits, allocated)?;
+    self.allocated += bytes.len();
+    Ok(bytes)
+  }
+  /// Читает бинарные данные так же, как [`read_byte_buf`], и, если они являются вложенным
+  /// GFF файлом, разбирает его. Данные считаются GFF файлом, если они начинаются с сигнатуры
+  /// из ASCII символов и версии вида `V<major>.<minor>`, а все области, описываемые заголовком,
+  /// помещаются в данные. Позиция чтения меняется так же, как в [`read_byte_buf`]
+  ///
+  /// # Возвращаемое значение
+  /// Разобранный вложенный файл или `None`, если данные не начинаются с заголовка GFF файла
+  ///
+  /// # Ошибки
+  /// Помимо ошибок чтения самих данных, возвращает ошибку, если данные начинаются с корректного
+  /// заголовка, но файл не удалось прочитать
+  ///
+  /// [`read_byte_buf`]: #method.read_byte_buf
+  pub fn read_embedded(&mut self, index: BinaryIndex) -> Result<Option<Gff>> {
+    let data = self.read_byte_buf(index)?;
+    if !is_gff(&data) {
+      return Ok(None);
+    }
+    Ok(Some(Gff::read(&mut Cursor::new(data))?))
+  }
+  /// Возвращает тип поля, значение которого представлено токеном [`Value`]. Позволяет узнать
+  /// тип значения, не читая его данные, хранящиеся в других областях файла. Эквивалентно
+  /// вызову [`SimpleValueRef::field_type`]
+  ///
+  /// [`Value`]: enum.Token.html#variant.Value
+  /// [`SimpleValueRef::field_type`]: ../value/enum.SimpleValueRef.html#method.field_type
+  #[inline]
+  pub fn field_type_of(&self, value: &SimpleValueRef) -> FieldType {
+    value.field_type()
   }
   /// Если `value` содержит еще не прочитанные поля (т.е. содержащие [индексы]), читает их.
   /// В противном случае просто преобразует тип значения в `SimpleValue`.
@@ -306,55 +839,120 @@ impl<R: Read + Seek> Parser<R> {
       Void(val)     => SimpleValue::Void(self.read_byte_buf(val)?),
     })
   }
+  /// Читает весь файл до конца и собирает из него дерево значений. Должен вызываться до
+  /// чтения первого токена, т.к. ожидает, что следующим токеном будет [`RootBegin`].
+  ///
+  /// В отличие от десериализации [`Value`] через [`Deserializer`], значения всех полей
+  /// (в том числе локализуемые строки) читаются методом [`read_value`] в том виде, в каком
+  /// они хранятся в файле.
+  ///
+  /// Возвращает [`Value::Struct`], описывающую корневую структуру файла.
+  ///
+  /// [`RootBegin`]: enum.Token.html#variant.RootBegin
+  /// [`Value`]: ../value/enum.Value.html
+  /// [`Value::Struct`]: ../value/enum.Value.html#variant.Struct
+  /// [`Deserializer`]: ../de/struct.Deserializer.html
+  /// [`read_value`]: #method.read_value
+  pub fn read_all(&mut self) -> Result<Value> {
+    match self.next_token()? {
+      Token::RootBegin { tag, .. } => self.read_struct_fields(tag.raw()),
+      token => Err(self.unexpected("RootBegin", token)),
+    }
+  }
 //-------------------------------------------------------------------------------------------------
+  /// Читает поля структуры с идентификатором типа `tag`, токен начала которой уже прочитан,
+  /// вплоть до токена ее окончания
+  fn read_struct_fields(&mut self, tag: u32) -> Result<Value> {
+    let mut fields = IndexMap::new();
+    loop {
+      match self.next_token()? {
+        Token::Label(index) => {
+          let label = self.read_label(index)?;
+          let value = self.read_field_value()?;
+          fields.insert(label, value);
+        },
+        Token::RootEnd | Token::StructEnd | Token::ItemEnd => return Ok(Value::Struct { tag, fields }),
+        token => return Err(self.unexpected("Label, RootEnd, StructEnd, ItemEnd", token)),
+      }
+    }
+  }
+  /// Читает значение поля, метка которого уже прочитана
+  fn read_field_value(&mut self) -> Result<Value> {
+    match self.next_token()? {
+      Token::Value(value) => Ok(self.read_value(value)?.into()),
+      Token::StructBegin { tag, .. } => self.read_struct_fields(tag.raw()),
+      Token::ListBegin(_) => {
+        // Количество элементов не используется для резервирования памяти, т.к. в
+        // поврежденном файле оно может быть сколь угодно большим
+        let mut items = Vec::new();
+        loop {
+          match self.next_token()? {
+            Token::ItemBegin { tag, .. } => items.push(self.read_struct_fields(tag.raw())?),
+            Token::ListEnd => return Ok(Value::List(items)),
+            token => return Err(self.unexpected("ItemBegin, ListEnd", token)),
+          }
+        }
+      },
+      token => Err(self.unexpected("Value, StructBegin, ListBegin", token)),
+    }
+  }
   /// Позиционирует нижележащий считыватель в место, указуемое данным индексом данных GFF.
   /// Возвращает старую позицию в файле, для того, чтобы можно было затем вернуться в нее.
   #[inline]
-  fn seek<I: Index>(&mut self, index: I) -> Result<()> {
+  pub(crate) fn seek<I: Index>(&mut self, index: I) -> Result<()> {
     let offset = index.offset(&self.header);
     self.reader.seek(SeekFrom::Start(offset))?;
     Ok(())
   }
+  /// Запоминает смещение записи, из которой читается очередной токен, для сообщений об ошибках
+  #[inline]
+  pub(crate) fn mark_record<I: Index>(&mut self, index: I) {
+    self.record = index.offset(&self.header);
+  }
   /// Получает текущую позицию в файле
   #[inline]
   fn offset(&mut self) -> Result<SeekFrom> {
     Ok(SeekFrom::Start(self.reader.seek(SeekFrom::Current(0))?))
   }
+  /// Формирует ошибку о неожиданном токене, дополняя ее смещением записи, из которой был
+  /// прочитан последний токен
+  #[inline]
+  pub(crate) fn unexpected(&self, expected: &'static str, token: Token) -> Error {
+    Error::UnexpectedAt { expected, token, offset: self.record }
+  }
 //-------------------------------------------------------------------------------------------------
 // Чтение вспомогательных данных
 //-------------------------------------------------------------------------------------------------
   /// Читает 4 байта из текущей позиции и интерпретирует их, как беззнаковое целое
   #[inline]
-  fn read_u32(&mut self) -> Result<u32> {
+  pub(crate) fn read_u32(&mut self) -> Result<u32> {
     Ok(self.reader.read_u32::<LE>()?)
   }
 //-------------------------------------------------------------------------------------------------
 // Чтение значений
 //-------------------------------------------------------------------------------------------------
-  /// Читает 4 байта длины и следующие за ними байты массива, возвращает прочитанный массив
-  #[inline]
-  fn read_bytes(&mut self) -> Result<Vec<u8>> {
-    let size = self.read_u32()? as usize;
-    let mut bytes = Vec::with_capacity(size);
-    unsafe { bytes.set_len(size); }
-
-    self.reader.read_exact(&mut bytes)?;
-    Ok(bytes)
+  /// Возвращает источник для чтения данных комплексного поля по указанному индексу: область
+  /// данных полей в памяти, если она была предварительно прочитана, или сам файл, позиция
+  /// чтения в котором устанавливается на данные поля
+  fn data<I: Index>(&mut self, index: I) -> Result<DataReader<'_, R>> {
+    let offset = index.offset(&self.header);
+    if let Some(ref data) = self.field_data {
+      let mut cursor = Cursor::new(&data[..]);
+      cursor.set_position(offset - self.header.field_data.offset as u64);
+      return Ok(DataReader::Memory(cursor));
+    }
+    self.reader.seek(SeekFrom::Start(offset))?;
+    Ok(DataReader::File(&mut self.reader))
   }
-  /// Читает 4 байта длины и следующие за ними байты строки, интерпретирует их в соответствии с
-  /// кодировкой декодера и возвращает полученную строку
+  /// Декодирует текст части локализуемой строки кодировкой, назначенной для ее языка
   #[inline]
-  fn read_string_impl(&mut self) -> Result<String> {
-    let bytes = self.read_bytes()?;
+  fn decode_substring(&self, key: StringKey, bytes: &[u8]) -> Result<SubString> {
+    let encoding = key.language().ok()
+      .and_then(|language| self.languages.get(&language))
+      .unwrap_or(&self.encoding);
+    let string = encoding.decode(bytes, self.trap)?;
 
-    Ok(self.encoding.decode(&bytes, self.trap)?)
-  }
-  #[inline]
-  fn read_substring(&mut self) -> Result<SubString> {
-    Ok(SubString {
-      key   : StringKey(self.read_u32()?),
-      string: self.read_string_impl()?,
-    })
+    Ok(SubString { key, string })
   }
   /// Читает из потока примитивное значение в соответствии с указанным тегом
   ///
@@ -365,29 +963,37 @@ impl<R: Read + Seek> Parser<R> {
   /// # Возвращаемое значение
   /// Возвращает лениво читаемое значение. Если данные хранятся непосредственно за тегом, то
   /// они будут уже прочитаны, в противном случае читается только адрес их местонахождения в файле.
-  /// Таким образом, если данные не нужны, лишних чтений не будет
+  /// Таким образом, если данные не нужны, лишних чтений не будет, а прочитать их можно позже
+  /// методом [`read_value`].
+  ///
+  /// Данные поля читаются с текущей позиции потока: метод предполагает, что поток
+  /// расположен на 4 байтах данных записи поля, следующих за ее тегом и индексом метки. Именно
+  /// так его использует сам парсер при формировании токена [`Token::Value`]
   ///
-  /// [`Error::UnknownValue`]: ../../error/enum.Error.html#variant.UnknownValue
-  fn read_value_ref(&mut self, tag: u32) -> Result<SimpleValueRef> {
+  /// [`Error::UnknownValue`]: ../error/enum.Error.html#variant.UnknownValue
+  /// [`read_value`]: #method.read_value
+  /// [`Token::Value`]: enum.Token.html#variant.Value
+  pub fn read_value_ref(&mut self, tag: u32) -> Result<SimpleValueRef> {
     use self::SimpleValueRef::*;
 
-    let value = match tag {
-      0 => Byte (self.reader.read_u8()?),
-      1 => Char (self.reader.read_i8()?),
-      2 => Word (self.reader.read_u16::<LE>()?),
-      3 => Short(self.reader.read_i16::<LE>()?),
-      4 => Dword(self.reader.read_u32::<LE>()?),
-      5 => Int  (self.reader.read_i32::<LE>()?),
-      8 => Float(self.reader.read_f32::<LE>()?),
-
-      6 => Dword64   (U64Index(self.read_u32()?)),
-      7 => Int64     (I64Index(self.read_u32()?)),
-      9 => Double    (F64Index(self.read_u32()?)),
-      10 => String   (StringIndex(self.read_u32()?)),
-      11 => ResRef   (ResRefIndex(self.read_u32()?)),
-      12 => LocString(LocStringIndex(self.read_u32()?)),
-      13 => Void     (BinaryIndex(self.read_u32()?)),
-      tag => return Err(Error::UnknownValue { tag, value: self.read_u32()? }),
+    let value = match FieldType::from_u32(tag) {
+      Some(FieldType::Byte)  => Byte (self.reader.read_u8()?),
+      Some(FieldType::Char)  => Char (self.reader.read_i8()?),
+      Some(FieldType::Word)  => Word (self.reader.read_u16::<LE>()?),
+      Some(FieldType::Short) => Short(self.reader.read_i16::<LE>()?),
+      Some(FieldType::Dword) => Dword(self.reader.read_u32::<LE>()?),
+      Some(FieldType::Int)   => Int  (self.reader.read_i32::<LE>()?),
+      Some(FieldType::Float) => Float(self.reader.read_f32::<LE>()?),
+
+      Some(FieldType::Dword64)   => Dword64  (U64Index(self.read_u32()?)),
+      Some(FieldType::Int64)     => Int64    (I64Index(self.read_u32()?)),
+      Some(FieldType::Double)    => Double   (F64Index(self.read_u32()?)),
+      Some(FieldType::String)    => String   (StringIndex(self.read_u32()?)),
+      Some(FieldType::ResRef)    => ResRef   (ResRefIndex(self.read_u32()?)),
+      Some(FieldType::LocString) => LocString(LocStringIndex(self.read_u32()?)),
+      Some(FieldType::Void)      => Void     (BinaryIndex(self.read_u32()?)),
+      // Структуры и списки не являются простыми значениями
+      _ => return Err(Error::UnknownValue { tag, value: self.read_u32()? }),
     };
     Ok(value)
   }
@@ -414,3 +1020,869 @@ impl<R: Read + Seek> Iterator for Parser<R> {
 }
 
 impl<R: Read + Seek> FusedIterator for Parser<R> {}
+
+impl<R: Read + Seek> Parser<BufReader<R>> {
+  /// Создает парсер, читающий файл через буфер, с кодировкой `UTF-8` для декодирования строк
+  /// (см. [`new`]) и сразу читает в память метки и данные полей (см. [`preload_labels`] и
+  /// [`preload_field_data`]). Предназначен для чтения из источников без собственной
+  /// буферизации, например, [`File`].
+  ///
+  /// После создания парсера метки и значения полей читаются из памяти, а чтение из файла
+  /// требуется только для записей структур, полей и списков. Для файла `test-data/all.gff`
+  /// построение дерева значений методом [`read_all`] таким парсером выполняет 42 чтения из
+  /// файла и 48 перемещений по нему вместо 116 чтений и 95 перемещений у парсера, созданного
+  /// методом [`new`]
+  ///
+  /// Ограничения на размеры данных не устанавливаются, поэтому объем читаемой в память
+  /// информации ограничен только размером файла, с которым сверяется заголовок. Для чтения
+  /// файлов из недоверенных источников создайте парсер методом [`with_limits`] и вызовите
+  /// методы предварительного чтения самостоятельно
+  ///
+  /// [`new`]: #method.new
+  /// [`with_limits`]: #method.with_limits
+  /// [`preload_labels`]: #method.preload_labels
+  /// [`preload_field_data`]: #method.preload_field_data
+  /// [`read_all`]: #method.read_all
+  /// [`File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+  pub fn new_buffered(reader: R) -> Result<Self> {
+    let mut parser = Parser::new(BufReader::new(reader))?;
+    parser.preload_labels()?;
+    parser.preload_field_data()?;
+    Ok(parser)
+  }
+}
+
+impl<'a> Parser<Cursor<&'a [u8]>> {
+  /// Читает 4 байта длины и возвращает ссылку на следующие за ними байты строки прямо в
+  /// буфере, из которого читается файл, без копирования. Побочный эффект -- переход по
+  /// указанному адресу, сразу за конец строки, даже если область данных полей была прочитана
+  /// в память методом [`preload_field_data`].
+  ///
+  /// В отличие от [`read_string`], кодировка парсера не используется: байты строки только
+  /// проверяются на корректность в `UTF-8` (и, как следствие, в `ASCII`). Для строк в других
+  /// кодировках возвращается ошибка [`Error::Encoding`] и следует использовать [`read_string`].
+  ///
+  /// [`read_string`]: #method.read_string
+  /// [`preload_field_data`]: #method.preload_field_data
+  /// [`Error::Encoding`]: ../error/enum.Error.html#variant.Encoding
+  pub fn read_str_borrowed(&mut self, index: StringIndex) -> Result<&'a str> {
+    self.seek(index)?;
+    let size = self.read_u32()? as usize;
+    self.limits.check_size(size, self.allocated)?;
+
+    let data: &'a [u8] = self.reader.get_ref();
+    let start = self.reader.position() as usize;
+    let bytes = start.checked_add(size)
+      .and_then(|end| data.get(start..end))
+      .ok_or_else(|| {
+        let available = data.len().saturating_sub(start);
+        let msg = format!("expected {} bytes, but only {} available", size, available);
+        std::io::Error::new(ErrorKind::UnexpectedEof, msg)
+      })?;
+    let value = str::from_utf8(bytes)?;
+    self.reader.set_position((start + size) as u64);
+    self.allocated += size;
+
+    Ok(value)
+  }
+}
+
+/// Источник данных комплексных полей, возвращаемый методом `Parser::data`
+enum DataReader<'a, R> {
+  /// Данные читаются из файла, позиция в котором уже установлена на начало данных
+  File(&'a mut R),
+  /// Данные читаются из предварительно прочитанной области данных полей
+  Memory(Cursor<&'a [u8]>),
+}
+impl<'a, R: Read> Read for DataReader<'a, R> {
+  #[inline]
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    match self {
+      DataReader::File(reader) => reader.read(buf),
+      DataReader::Memory(cursor) => cursor.read(buf),
+    }
+  }
+}
+
+/// Читает 4 байта длины и следующие за ними байты массива, возвращает прочитанный массив.
+/// Перед чтением байт проверяет, что их количество не превышает ограничения `limits` с учетом
+/// уже прочитанных парсером `allocated` байт
+#[inline]
+fn read_sized<R: Read>(reader: &mut R, limits: &Limits, allocated: usize) -> Result<Vec<u8>> {
+  let size = reader.read_u32::<LE>()? as usize;
+  limits.check_size(size, allocated)?;
+  Ok(read_bytes(reader, size)?)
+}
+
+/// Проверяет, что данные начинаются с заголовка GFF файла, все области которого помещаются
+/// в данные
+fn is_gff(data: &[u8]) -> bool {
+  if data.len() < Header::HEADER_SIZE as usize {
+    return false;
+  }
+  let sig_valid = str::from_utf8(&data[..4]).map_or(false, |sig| Signature::try_from(sig).is_ok());
+  let ver_valid = str::from_utf8(&data[4..8]).map_or(false, |ver| ver.parse::<Version>().is_ok());
+  if !sig_valid || !ver_valid {
+    return false;
+  }
+  match Header::read(&mut Cursor::new(data)) {
+    Ok(header) => header.validate(data.len() as u64).is_ok(),
+    Err(_) => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::Cell;
+  use std::fs::File;
+  use std::io::{Cursor, ErrorKind, Read, Result, Seek, SeekFrom};
+  use std::rc::Rc;
+  use byteorder::{LE, WriteBytesExt};
+  use serde::Serialize;
+  use serde_bytes::ByteBuf;
+  use crate::error::Error;
+  use crate::header::Header;
+  use crate::raw::Gff;
+  use crate::ser::to_vec;
+  use crate::value::SimpleValueRef;
+  use super::{Parser, Tag, Token};
+
+  /// Обертка над источником данных, подсчитывающая количество перемещений по нему
+  struct CountingReader<R> {
+    inner: R,
+    seeks: Rc<Cell<usize>>,
+  }
+  impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> { self.inner.read(buf) }
+  }
+  impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+      self.seeks.set(self.seeks.get() + 1);
+      self.inner.seek(pos)
+    }
+  }
+
+  /// Открывает тестовый файл и возвращает парсер для него и счетчик перемещений по файлу
+  fn open() -> (Parser<CountingReader<File>>, Rc<Cell<usize>>) {
+    let file = File::open("test-data/all.gff").expect("test file not exist");
+    let seeks = Rc::new(Cell::new(0));
+    let reader = CountingReader { inner: file, seeks: seeks.clone() };
+    (Parser::new(reader).expect("can't read GFF header"), seeks)
+  }
+
+  /// Проверяет, что после предварительного чтения меток их чтение не приводит к перемещениям по файлу
+  #[test]
+  fn preloaded_labels_do_not_seek() {
+    let (mut lazy, _) = open();
+    let (mut eager, seeks) = open();
+    eager.preload_labels().expect("can't preload labels");
+
+    let mut labels = 0;
+    while let Some(token) = lazy.next() {
+      // Токены обоих парсеров должны совпадать, но метки читаются только из памяти
+      let other = eager.next().expect("eager parser finished too early");
+      if let (Token::Label(index), Token::Label(other)) = (token, other) {
+        let expected = lazy.read_label(index).expect("can't read label");
+
+        let before = seeks.get();
+        for _ in 0..100 {
+          assert_eq!(eager.read_label(other).expect("can't read label"), expected);
+        }
+        assert_eq!(seeks.get(), before);
+        labels += 1;
+      }
+    }
+    assert!(labels > 0);
+  }
+
+  /// Проверяет чтение всех меток файла без обхода его структур
+  #[test]
+  fn labels() {
+    let gff = Gff::read(&mut File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF");
+    let (mut parser, _) = open();
+
+    let labels = parser.labels().expect("can't read labels");
+    assert_eq!(labels, gff.labels());
+    assert!(labels.iter().any(|l| l.as_str() == Ok("Struct")));
+    // Позиция чтения не изменилась, и разбор начинается с начала
+    assert!(matches!(parser.next_token().unwrap(), Token::RootBegin { .. }));
+
+    parser.preload_labels().unwrap();
+    assert_eq!(parser.labels().unwrap(), labels);
+  }
+
+  /// Проверяет, что позиция чтения продвигается по мере разбора токенов
+  #[test]
+  fn position() {
+    let (mut parser, _) = open();
+    let total = parser.header().total_size();
+    let start = parser.position().unwrap();
+    assert_eq!(start, Header::HEADER_SIZE as u64);
+
+    let mut max = start;
+    while let Some(_) = parser.next() {
+      let position = parser.position().unwrap();
+      assert!(position <= total, "position {} is beyond file size {}", position, total);
+      max = max.max(position);
+    }
+    assert!(max > start);
+  }
+
+  /// Проверяет, что ссылка поля на несуществующую метку приводит к ошибке
+  #[test]
+  fn label_out_of_range() {
+    #[derive(Serialize)]
+    struct Single { value: u32 }
+
+    let mut data = to_vec((*b"GFF ").into(), &Single { value: 42 }).unwrap();
+    let header = Header::read(&mut Cursor::new(&data)).unwrap();
+    assert_eq!(header.labels.count, 1);
+    // Индекс метки хранится после 4 байт идентификатора типа поля
+    let offset = header.fields.offset as usize + 4;
+    (&mut data[offset..offset + 4]).write_u32::<LE>(5).unwrap();
+
+    let mut parser = Parser::new(Cursor::new(&data)).unwrap();
+    assert!(matches!(parser.next_token().unwrap(), Token::RootBegin { .. }));
+    let index = match parser.next_token().unwrap() {
+      Token::Label(index) => index,
+      token => panic!("expected Token::Label, but {:?} found", token),
+    };
+    assert_eq!(parser.read_label(index), Err(Error::LabelIndexOutOfRange(5)));
+
+    // Предварительное чтение меток не отменяет проверку
+    parser.preload_labels().unwrap();
+    assert_eq!(parser.read_label(index), Err(Error::LabelIndexOutOfRange(5)));
+
+    let mut parser = Parser::new(Cursor::new(&data)).unwrap();
+    assert_eq!(parser.read_all().unwrap_err(), Error::LabelIndexOutOfRange(5));
+  }
+
+  /// Проверяет, что источник данных только что созданного парсера возвращается
+  /// позиционированным сразу за заголовком
+  #[test]
+  fn into_inner() {
+    let mut data = Vec::new();
+    Header::new((*b"GFF ").into()).write(&mut data).unwrap();
+    data.extend_from_slice(b"next");
+
+    let parser = Parser::new(Cursor::new(data)).expect("can't read GFF header");
+    let mut reader = parser.into_inner();
+    assert_eq!(reader.position(), Header::HEADER_SIZE as u64);
+
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"next");
+  }
+
+  /// Проверяет, что индексы меток полей, прочитанные парсером, совпадают с хранящимися в файле
+  #[test]
+  fn field_label_index() {
+    let gff = Gff::read(&mut File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF");
+    let (mut parser, _) = open();
+
+    let (mut other, _) = open();
+    // Чтение индексов в середине разбора не должно сбивать позицию чтения
+    assert_eq!(format!("{:?}", parser.next_token()), format!("{:?}", other.next_token()));
+    for i in 0..gff.fields.len() {
+      let label = parser.field_label_index((i as u32).into()).expect("can't read label index");
+      assert_eq!(label, gff.field_label_index(i));
+    }
+    assert_eq!(format!("{:?}", parser.next_token()), format!("{:?}", other.next_token()));
+  }
+
+  /// Проверяет, что огромная длина бинарных данных в поврежденном файле приводит к ошибке
+  /// ввода-вывода, а не к попытке выделить память под все данные
+  #[test]
+  fn truncated_binary_data() {
+    #[derive(Serialize)]
+    struct Storage { data: ByteBuf }
+
+    let mut data = to_vec((*b"GFF ").into(), &Storage { data: ByteBuf::from(vec![1, 2, 3]) }).unwrap();
+    let header = Header::read(&mut Cursor::new(&data)).unwrap();
+
+    // Данные поля единственные в секции, поэтому их длина записана в ее начале
+    let offset = header.field_data.offset as usize;
+    (&mut data[offset..offset + 4]).write_u32::<LE>(0xFFFF_FFF0).unwrap();
+
+    let mut parser = Parser::new(Cursor::new(data.clone())).unwrap();
+    let mut value = None;
+    for token in parser.by_ref() {
+      if let Token::Value(v @ SimpleValueRef::Void(_)) = token {
+        value = Some(v);
+        break;
+      }
+    }
+    match parser.read_value(value.expect("Void value not found")) {
+      Err(Error::Io(err)) => assert_eq!(err.kind(), ErrorKind::UnexpectedEof),
+      result => panic!("expected Error::Io, but {:?} found", result),
+    }
+
+    // Чтение сырой структуры файла с некорректным размером секции также завершается ошибкой
+    let mut header = header;
+    header.field_data.count = 0xFFFF_FFF0;
+    header.write(&mut &mut data[..]).unwrap();
+    let err = Gff::read(&mut Cursor::new(data)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+  }
+
+  /// Проверяет, что некорректные смещения областей в заголовке обнаруживаются сразу при
+  /// создании парсера
+  #[test]
+  fn corrupt_section_offset() {
+    let data = std::fs::read("test-data/all.gff").expect("test file not exist");
+    assert!(Parser::new(Cursor::new(&data)).is_ok());
+
+    let sections = ["structs", "fields", "labels", "field_data", "field_indices", "list_indices"];
+    for (i, name) in sections.iter().enumerate() {
+      // Смещение области располагается после сигнатуры и версии файла
+      let offset = 8 + i * 8;
+      for &corrupt in &[0, 0x7FFF_FFFF, 0xFFFF_FFFF] {
+        let mut data = data.clone();
+        (&mut data[offset..offset + 4]).write_u32::<LE>(corrupt).unwrap();
+
+        match Parser::new(Cursor::new(data)) {
+          Err(Error::MalformedHeader(section)) => assert_eq!(section, *name),
+          Err(err) => panic!("expected Error::MalformedHeader, but {:?} found", err),
+          Ok(_) => panic!("expected Error::MalformedHeader for section `{}`", name),
+        }
+      }
+    }
+  }
+
+  /// Проверяет, что функция, переданная в `drive`, вызывается для каждого токена файла,
+  /// а ее ошибка прерывает разбор
+  #[test]
+  fn drive() {
+    let (parser, _) = open();
+    let expected = parser.count();
+
+    let (mut parser, _) = open();
+    let mut count = 0;
+    let mut labels = 0;
+    parser.drive(|token| {
+      count += 1;
+      if let Token::Label(_) = token { labels += 1; }
+      Ok(())
+    }).expect("can't parse file");
+    assert_eq!(count, expected);
+    assert!(labels > 0);
+    // Повторный вызов ничего не делает, т.к. файл уже прочитан
+    parser.drive(|_| panic!("file already parsed")).unwrap();
+
+    let (mut parser, _) = open();
+    let mut count = 0;
+    let err = parser.drive(|token| {
+      count += 1;
+      if token.is_value() { return Err(Error::Deserialize("stop".into())); }
+      Ok(())
+    });
+    match err {
+      Err(Error::Deserialize(ref msg)) => assert_eq!(msg, "stop"),
+      res => panic!("expected Error::Deserialize, but {:?} found", res),
+    }
+    assert!(count < expected);
+  }
+
+  /// Проверяет, что тип значения, определенный по токену, совпадает с типом поля в файле
+  #[test]
+  fn field_type() {
+    let gff = Gff::read(&mut File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF");
+    let (mut parser, _) = open();
+
+    let mut label = None;
+    let mut values = 0;
+    while let Some(token) = parser.next() {
+      match token {
+        Token::Label(index) => label = Some(parser.read_label(index).unwrap()),
+        Token::Value(ref value) => {
+          let label = label.take().expect("value without label");
+          // Поля с одинаковыми метками в тестовом файле имеют одинаковый тип
+          let index = gff.label_index(&label).unwrap();
+          let field = gff.fields.iter().find(|f| f.label == index).unwrap();
+          assert_eq!(value.field_type() as u32, field.tag, "field {}", label);
+          assert_eq!(parser.field_type_of(value), value.field_type(), "field {}", label);
+          values += 1;
+        },
+        _ => {},
+      }
+    }
+    assert!(values > 0);
+  }
+
+  /// Проверяет построение дерева значений из всего файла
+  #[test]
+  fn read_all() {
+    use crate::value::Value;
+
+    let (mut parser, _) = open();
+    let value = parser.read_all().expect("can't read 'all.gff'");
+
+    assert_eq!(value["u16"], Value::Word(1));
+    assert_eq!(value["string"], Value::String("String".into()));
+    assert_eq!(value["Struct"]["f64"], Value::Double(std::f64::consts::E));
+    assert_eq!(value["list"][1]["i8"], Value::Char(-10));
+    // Весь файл прочитан
+    assert!(parser.next().is_none());
+    // Повторное чтение невозможно
+    assert!(parser.read_all().is_err());
+  }
+
+  /// Проверяет, что парсер с буферизацией и предварительно прочитанными данными читает то же
+  /// самое, что и обычный, но обращается к файлу реже
+  #[test]
+  fn buffered() {
+    /// Обертка над файлом, подсчитывающая количество чтений и перемещений
+    struct Counting {
+      inner: File,
+      reads: Rc<Cell<usize>>,
+      seeks: Rc<Cell<usize>>,
+    }
+    impl Read for Counting {
+      fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.reads.set(self.reads.get() + 1);
+        self.inner.read(buf)
+      }
+    }
+    impl Seek for Counting {
+      fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.seeks.set(self.seeks.get() + 1);
+        self.inner.seek(pos)
+      }
+    }
+    fn open() -> (Counting, Rc<Cell<usize>>, Rc<Cell<usize>>) {
+      let inner = File::open("test-data/all.gff").expect("test file not exist");
+      let reads = Rc::new(Cell::new(0));
+      let seeks = Rc::new(Cell::new(0));
+      (Counting { inner, reads: reads.clone(), seeks: seeks.clone() }, reads, seeks)
+    }
+
+    let (file, reads, seeks) = open();
+    let expected = Parser::new(file).unwrap().read_all().expect("can't read 'all.gff'");
+    let (plain_reads, plain_seeks) = (reads.get(), seeks.get());
+
+    let (file, reads, seeks) = open();
+    let value = Parser::new_buffered(file).unwrap().read_all().expect("can't read 'all.gff'");
+    assert_eq!(value, expected);
+    assert!(reads.get() < plain_reads);
+    assert!(seeks.get() < plain_seeks);
+
+    // Значения, прочитанные из памяти, совпадают с прочитанными из файла
+    let (file, _, _) = open();
+    let mut parser = Parser::new(file).unwrap();
+    parser.preload_field_data().unwrap();
+    assert_eq!(parser.read_all().expect("can't read 'all.gff'"), expected);
+  }
+
+  /// Проверяет, что ссылка структуры на одну из структур, в которые она вложена, приводит
+  /// к ошибке, а не к бесконечному разбору
+  #[test]
+  fn cyclic_structure() {
+    use crate::index::StructIndex;
+    use crate::raw::FieldType;
+
+    #[derive(Serialize)]
+    struct Inner { value: u32 }
+    #[derive(Serialize)]
+    struct Root { first: Inner, second: Inner }
+
+    let root = Root { first: Inner { value: 1 }, second: Inner { value: 2 } };
+    let data = to_vec((*b"GFF ").into(), &root).unwrap();
+
+    // Повторное чтение одной и той же структуры, не вложенной в саму себя, допустимо
+    let mut gff = Gff::read(&mut Cursor::new(data)).unwrap();
+    let second = gff.fields.iter().filter(|f| f.tag == FieldType::Struct as u32).nth(1).unwrap().data;
+    gff.fields.iter_mut().filter(|f| f.tag == FieldType::Struct as u32).for_each(|f| f.data = second);
+    let mut shared = Vec::new();
+    gff.write(&mut shared).unwrap();
+    assert!(Parser::new(Cursor::new(shared)).unwrap().all(|_| true));
+
+    // Поле вложенной структуры ссылается на корневую структуру
+    let value = gff.fields.iter_mut().rev().find(|f| f.tag == FieldType::Dword as u32).unwrap();
+    value.tag = FieldType::Struct as u32;
+    value.data = [0; 4];
+    let mut data = Vec::new();
+    gff.write(&mut data).unwrap();
+
+    let mut parser = Parser::new(Cursor::new(data)).unwrap();
+    let err = loop {
+      match parser.next_token() {
+        Ok(Token::RootEnd) => panic!("expected Error::CyclicStructure, but parsing finished"),
+        Ok(_) => {},
+        Err(err) => break err,
+      }
+    };
+    match err {
+      Error::CyclicStructure { index } => assert_eq!(index, StructIndex(0)),
+      err => panic!("expected Error::CyclicStructure, but {:?} found", err),
+    }
+  }
+
+  /// Проверяет, что парсер помнит идентификаторы типов структур, в которых он находится
+  #[test]
+  fn current_tag() {
+    let (mut parser, _) = open();
+    assert_eq!(parser.current_tag(), None);
+
+    let mut root = None;
+    let mut checked = false;
+    while let Some(token) = parser.next() {
+      match token {
+        Token::RootBegin { tag, .. } => root = Some(tag),
+        Token::StructBegin { tag, .. } => {
+          assert_eq!(parser.current_tag(), Some(tag));
+          assert_eq!(parser.current_tag().map(Tag::get), Some(tag.raw()));
+          // Значения структуры прочитываются целиком, после чего снова находимся в корневой
+          while let Some(Token::Label(_)) | Some(Token::Value(_)) = parser.next() {}
+          assert_eq!(parser.current_tag(), root);
+          checked = true;
+        },
+        Token::ItemBegin { tag, .. } => assert_eq!(parser.current_tag(), Some(tag)),
+        Token::Value(_) => assert!(parser.current_tag().is_some()),
+        _ => {},
+      }
+    }
+    assert!(checked);
+    assert_eq!(parser.current_tag(), None);
+
+    // Пропуск структуры также возвращает к идентификатору внешней структуры
+    let (mut parser, _) = open();
+    while let Some(token) = parser.next() {
+      if let Token::StructBegin { .. } = token {
+        parser.skip_next(token);
+        assert_eq!(parser.current_tag(), root);
+      }
+    }
+  }
+
+  /// Проверяет, что поле корневой структуры, ссылающееся на саму корневую структуру,
+  /// обнаруживается еще до чтения ее полей повторно
+  #[test]
+  fn self_reference() {
+    use crate::index::StructIndex;
+    use crate::raw::FieldType;
+
+    #[derive(Serialize)]
+    struct Root { value: u32 }
+
+    let data = to_vec((*b"GFF ").into(), &Root { value: 42 }).unwrap();
+    let mut gff = Gff::read(&mut Cursor::new(data)).unwrap();
+    // Единственное поле корневой структуры превращаем в ссылку на нее же
+    gff.fields[0].tag = FieldType::Struct as u32;
+    gff.fields[0].data = [0; 4];
+    let mut data = Vec::new();
+    gff.write(&mut data).unwrap();
+
+    let mut parser = Parser::new(Cursor::new(data)).unwrap();
+    assert!(matches!(parser.next_token().unwrap(), Token::RootBegin { .. }));
+    assert!(matches!(parser.next_token().unwrap(), Token::Label(_)));
+    match parser.next_token() {
+      Err(Error::CyclicStructure { index }) => assert_eq!(index, StructIndex(0)),
+      res => panic!("expected Error::CyclicStructure, but {:?} found", res),
+    }
+  }
+
+  /// Проверяет, что слишком глубокая вложенность структур приводит к ошибке, а не к
+  /// переполнению стека при ее рекурсивной обработке
+  #[test]
+  fn depth_limit() {
+    use indexmap::IndexMap;
+    use crate::value::Value;
+
+    /// Строит цепочку из `depth` вложенных друг в друга структур
+    fn nested(depth: usize) -> Value {
+      let mut value = Value::Dword(42);
+      for _ in 0..depth {
+        let mut fields = IndexMap::new();
+        fields.insert("value".parse().unwrap(), Value::Dword(42));
+        fields.insert("inner".parse().unwrap(), value);
+        value = Value::Struct { tag: 0, fields };
+      }
+      value
+    }
+    /// Разбирает файл до конца и возвращает максимальную достигнутую глубину или ошибку
+    fn parse(data: &[u8], max_depth: usize) -> std::result::Result<usize, Error> {
+      let mut parser = Parser::new(Cursor::new(data)).unwrap();
+      parser.set_max_depth(max_depth);
+      let mut depth = 0;
+      let mut max = 0;
+      loop {
+        match parser.next_token()? {
+          Token::RootEnd => return Ok(max),
+          Token::RootBegin { .. } | Token::StructBegin { .. } => depth += 1,
+          Token::StructEnd => depth -= 1,
+          _ => {},
+        }
+        max = max.max(depth);
+      }
+    }
+
+    // Корневая структура и 9 вложенных в нее
+    let data = to_vec((*b"GFF ").into(), &nested(10)).unwrap();
+    assert_eq!(parse(&data, 10).unwrap(), 10);
+    match parse(&data, 9) {
+      Err(Error::DepthLimitExceeded(9)) => {},
+      res => panic!("expected Error::DepthLimitExceeded(9), but {:?} found", res),
+    }
+
+    // Пропущенные структуры не учитываются в глубине
+    let mut parser = Parser::new(Cursor::new(&data)).unwrap();
+    parser.set_max_depth(2);
+    assert!(matches!(parser.next_token().unwrap(), Token::RootBegin { .. }));
+    assert!(matches!(parser.next_token().unwrap(), Token::Label(_)));
+    assert!(matches!(parser.next_token().unwrap(), Token::Value(_)));
+    assert!(matches!(parser.next_token().unwrap(), Token::Label(_)));
+    let token = parser.next_token().unwrap();
+    assert!(matches!(token, Token::StructBegin { .. }));
+    parser.skip_next(token);
+    assert!(matches!(parser.next_token().unwrap(), Token::RootEnd));
+
+    // Огромная вложенность отвергается с пределом по умолчанию
+    let limit = Parser::<Cursor<&[u8]>>::DEFAULT_MAX_DEPTH;
+    let data = to_vec((*b"GFF ").into(), &nested(limit + 1)).unwrap();
+    match Parser::new(Cursor::new(&data)).unwrap().read_all() {
+      Err(Error::DepthLimitExceeded(max)) => assert_eq!(max, limit),
+      res => panic!("expected Error::DepthLimitExceeded, but {:?} found", res),
+    }
+  }
+
+  /// Проверяет, что тип объекта элементов списка доступен непосредственно из токенов
+  #[test]
+  fn item_object_type() {
+    use std::convert::TryFrom;
+    use super::ObjectType;
+
+    #[derive(Serialize)]
+    struct Item { value: u32 }
+    #[derive(Serialize)]
+    struct Area { list: Vec<Item> }
+
+    let area = Area { list: vec![Item { value: 1 }, Item { value: 2 }, Item { value: 3 }] };
+    let data = to_vec((*b"GIT ").into(), &area).unwrap();
+
+    // Сериализатор всегда записывает тег 0, поэтому проставляем теги вручную
+    let mut gff = Gff::read(&mut Cursor::new(data)).unwrap();
+    gff.structs[1].tag = ObjectType::Creature as u32;
+    gff.structs[2].tag = ObjectType::Door as u32;
+    gff.structs[3].tag = 42;
+    let mut data = Vec::new();
+    gff.write(&mut data).unwrap();
+
+    let parser = Parser::new(Cursor::new(data)).unwrap();
+    let types: Vec<_> = parser
+      .filter(|token| matches!(token, Token::ItemBegin { .. }))
+      .map(|token| token.object_type())
+      .collect();
+    assert_eq!(types, vec![Some(ObjectType::Creature), Some(ObjectType::Door), None]);
+
+    assert_eq!(ObjectType::try_from(11).unwrap(), ObjectType::Store);
+    match ObjectType::try_from(42) {
+      Err(Error::UnknownObjectType(tag)) => assert_eq!(tag, 42),
+      res => panic!("expected Error::UnknownObjectType, but {:?} found", res),
+    }
+  }
+
+  /// Проверяет, что части локализуемой строки декодируются кодировкой, назначенной их языку
+  #[test]
+  fn language_encoding() {
+    use encoding::{EncoderTrap, Encoding};
+    use encoding::all::WINDOWS_1251;
+    use crate::string::{Gender, Language, LocString, StrRef, StringKey, SubString};
+    use crate::value::SimpleValue;
+
+    #[derive(Serialize)]
+    struct Storage { text: LocString }
+
+    // Русские локализации хранят текст в слотах английского языка в кодировке windows-1251.
+    // Сериализатор пишет текст в UTF-8, поэтому заменяем его в готовом файле
+    let placeholder = "XXXXXX";
+    let russian = WINDOWS_1251.encode("Привет", EncoderTrap::Strict).unwrap();
+    assert_eq!(russian.len(), placeholder.len());
+
+    let text = LocString {
+      str_ref: StrRef(0xFFFF_FFFF),
+      strings: vec![
+        SubString { key: (Language::English, Gender::Male).into(), string: placeholder.into() },
+        SubString { key: StringKey(200 << 1), string: "Hello".into() },
+      ],
+    };
+    let mut data = to_vec((*b"GFF ").into(), &Storage { text }).unwrap();
+    let pos = data.windows(placeholder.len()).position(|w| w == placeholder.as_bytes()).unwrap();
+    data[pos..pos + russian.len()].copy_from_slice(&russian);
+
+    let mut parser = Parser::new(Cursor::new(data)).unwrap();
+    parser.set_language_encoding(Language::English, WINDOWS_1251);
+
+    let mut value = None;
+    for token in parser.by_ref() {
+      if let Token::Value(v @ SimpleValueRef::LocString(_)) = token {
+        value = Some(v);
+        break;
+      }
+    }
+    match parser.read_value(value.expect("LocString value not found")).unwrap() {
+      SimpleValue::LocString(LocString { strings, .. }) => {
+        assert_eq!(strings[0].string, "Привет");
+        // Неизвестный язык декодируется кодировкой парсера
+        assert_eq!(strings[1].string, "Hello");
+      },
+      value => panic!("expected LocString, but {:?} found", value),
+    }
+  }
+
+  /// Проверяет выбор кодировок строк по игре, создавшей файл
+  #[test]
+  fn for_game() {
+    use encoding::EncoderTrap;
+    use encoding::all::WINDOWS_1252;
+    use serde::Deserialize;
+    use crate::de::Deserializer;
+    use crate::ser::SerializerBuilder;
+    use crate::string::Game;
+
+    assert_eq!(Game::Nwn1.encoding().name(), "windows-1252");
+    assert_eq!(Game::Nwn2.encoding().name(), "utf-8");
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Storage { name: String }
+
+    let storage = Storage { name: "Café".into() };
+    let data = SerializerBuilder::new((*b"GFF ").into())
+      .encoding(WINDOWS_1252, EncoderTrap::Strict)
+      .serialize(&storage)
+      .unwrap();
+
+    let mut de = Deserializer::for_game(Cursor::new(&data), Game::Nwn1).unwrap();
+    assert_eq!(Storage::deserialize(&mut de).unwrap(), storage);
+
+    let mut de = Deserializer::for_game(Cursor::new(&data), Game::Nwn2).unwrap();
+    match Storage::deserialize(&mut de) {
+      Err(Error::Encoding(_)) => {},
+      res => panic!("expected Error::Encoding, but {:?} found", res),
+    }
+  }
+
+  /// Проверяет разбор GFF файла, вложенного в бинарное поле
+  #[test]
+  fn read_embedded() {
+    use crate::value::Value;
+
+    #[derive(Serialize)]
+    struct Inner { name: String }
+    #[derive(Serialize)]
+    struct Storage { embedded: ByteBuf, truncated: ByteBuf, other: ByteBuf }
+
+    let inner = to_vec((*b"UTI ").into(), &Inner { name: "Sword".into() }).unwrap();
+    let storage = Storage {
+      embedded:  ByteBuf::from(inner.clone()),
+      truncated: ByteBuf::from(inner[..inner.len() - 1].to_vec()),
+      other:     ByteBuf::from(b"not a GFF file".to_vec()),
+    };
+    let data = to_vec((*b"GFF ").into(), &storage).unwrap();
+
+    let mut parser = Parser::new(Cursor::new(data)).unwrap();
+    let mut values = Vec::new();
+    for token in parser.by_ref() {
+      if let Token::Value(SimpleValueRef::Void(index)) = token {
+        values.push(index);
+      }
+    }
+    assert_eq!(values.len(), 3);
+
+    let gff = parser.read_embedded(values[0]).unwrap().expect("embedded GFF not found");
+    assert_eq!(gff.header.signature, (*b"UTI ").into());
+    let value = gff.to_value().unwrap();
+    assert_eq!(value.get("name"), Some(&Value::String("Sword".into())));
+
+    assert!(parser.read_embedded(values[1]).unwrap().is_none());
+    assert!(parser.read_embedded(values[2]).unwrap().is_none());
+  }
+
+  /// Проверяет, что строка, прочитанная из буфера в памяти, ссылается на сам буфер
+  #[test]
+  fn borrowed_string() {
+    #[derive(Serialize)]
+    struct Storage { text: String, invalid: String }
+
+    let mut data = to_vec((*b"GFF ").into(), &Storage { text: "Hello".into(), invalid: "XX".into() }).unwrap();
+    let pos = data.windows(2).position(|w| w == b"XX").unwrap();
+    data[pos] = 0xFF;
+
+    let mut parser = Parser::new(Cursor::new(&data[..])).unwrap();
+    let mut values = Vec::new();
+    for token in parser.by_ref() {
+      if let Token::Value(SimpleValueRef::String(index)) = token {
+        values.push(index);
+      }
+    }
+
+    let text = parser.read_str_borrowed(values[0]).unwrap();
+    assert_eq!(text, "Hello");
+    let range = data.as_ptr_range();
+    assert!(range.contains(&text.as_ptr()));
+
+    match parser.read_str_borrowed(values[1]) {
+      Err(Error::Encoding(_)) => {},
+      result => panic!("expected Error::Encoding, but {:?} found", result),
+    }
+  }
+
+  /// Проверяет, что при превышении каждого из ограничений чтение завершается ошибкой
+  #[test]
+  fn limits() {
+    use super::Limits;
+
+    fn read(limits: Limits) -> std::result::Result<(), Error> {
+      let file = File::open("test-data/all.gff").expect("test file not exist");
+      Parser::with_limits(file, limits)?.read_all().map(|_| ())
+    }
+    fn exceeded(limits: Limits, limit: &'static str, max: usize) {
+      match read(limits) {
+        Err(Error::LimitExceeded { limit: l, max: m }) if l == limit && m == max => {},
+        res => panic!("expected Error::LimitExceeded {{ limit: {:?}, max: {} }}, but {:?} found", limit, max, res),
+      }
+    }
+
+    assert!(read(Limits::default()).is_ok());
+
+    // Самые длинные данные в файле -- 7 байт `Vec<u8>`
+    assert!(read(Limits { max_string_len: 7, ..Limits::default() }).is_ok());
+    exceeded(Limits { max_string_len: 6, ..Limits::default() }, "max_string_len", 6);
+
+    // Список `list` содержит 2 элемента
+    assert!(read(Limits { max_list_len: 2, ..Limits::default() }).is_ok());
+    exceeded(Limits { max_list_len: 1, ..Limits::default() }, "max_list_len", 1);
+
+    // Строка `String` (6 байт) и двоичные данные `Vec<u8>` (7 байт)
+    assert!(read(Limits { max_allocated: 13, ..Limits::default() }).is_ok());
+    exceeded(Limits { max_allocated: 12, ..Limits::default() }, "max_allocated", 12);
+
+    match read(Limits { max_depth: 1, ..Limits::default() }) {
+      Err(Error::DepthLimitExceeded(1)) => {},
+      res => panic!("expected Error::DepthLimitExceeded(1), but {:?} found", res),
+    }
+
+    // Области данных полей и меток, прочитанные целиком, также учитываются
+    let open = |max_allocated| {
+      let file = File::open("test-data/all.gff").expect("test file not exist");
+      Parser::with_limits(file, Limits { max_allocated, ..Limits::default() }).expect("can't read GFF header")
+    };
+    let parser = open(usize::MAX);
+    let field_data = parser.header().field_data.count as usize;
+    let labels = parser.header().labels.count as usize * 16;
+
+    let mut parser = open(field_data - 1);
+    match parser.preload_field_data() {
+      Err(Error::LimitExceeded { limit: "max_allocated", max }) => assert_eq!(max, field_data - 1),
+      res => panic!("expected Error::LimitExceeded, but {:?} found", res),
+    }
+    let mut parser = open(labels - 1);
+    match parser.preload_labels() {
+      Err(Error::LimitExceeded { limit: "max_allocated", max }) => assert_eq!(max, labels - 1),
+      res => panic!("expected Error::LimitExceeded, but {:?} found", res),
+    }
+
+    let mut parser = open(field_data + labels);
+    parser.preload_field_data().expect("can't preload field data");
+    parser.preload_labels().expect("can't preload labels");
+  }
+}
@@ -4,6 +4,7 @@ use std::io::{Read, Seek};
 use crate::index::{FieldIndex, FieldIndicesIndex, LabelIndex, ListIndicesIndex, StructIndex};
 use crate::error::{Error, Result};
 use crate::parser::{Parser, Token, Tag};
+use crate::raw::FieldType;
 use self::State::*;
 
 /// Возможные состояния, в которых может находиться парсер
@@ -80,6 +81,10 @@ impl State {
   ///
   /// [`next`]: #method.next
   pub fn skip(self, token: Token) -> State {
+    // Простое значение уже прочитано, пропускать нечего
+    if let Token::Value(..) = token {
+      return self;
+    }
     match self {
       Start(state)      => state.skip(),
       ReadLabel(state)  => state.skip(),
@@ -92,6 +97,29 @@ impl State {
       Finish => unreachable!(),
     }
   }
+  /// Проверяет, читается ли в данный момент структура с указанным индексом, т.е. является
+  /// ли она одной из структур, в которые вложен текущий читаемый элемент. Такие структуры
+  /// ожидают своего завершения в цепочке состояний, в которые нужно вернуться
+  fn is_reading(&self, index: StructIndex) -> bool {
+    let mut state = self;
+    loop {
+      state = match *state {
+        EndRoot(ref s)   if s.index == index => return true,
+        EndItem(ref s)   if s.index == index => return true,
+        EndStruct(ref s) if s.index == index => return true,
+
+        Start(ref s)      => &s.state,
+        ReadLabel(ref s)  => &s.state,
+        ReadField(ref s)  => &s.state,
+        ReadFields(ref s) => &s.state,
+        ReadItems(ref s)  => &s.state,
+        EndRoot(ref s)    => &s.state,
+        EndItem(ref s)    => &s.state,
+        EndStruct(ref s)  => &s.state,
+        Finish => return false,
+      };
+    }
+  }
 }
 impl Default for State {
   fn default() -> Self {
@@ -110,7 +138,11 @@ pub trait TokenEmitter {
   fn end(&self) -> Token;
   /// Возвращает завершающее состояние, в которое необходимо перейти после испускания
   /// последнего токена
-  fn next(self, state: Box<State>) -> State;
+  ///
+  /// # Параметры
+  /// - `index`: Индекс читаемой структуры
+  /// - `state`: Состояние, в которое нужно вернуться после завершения структуры
+  fn next(self, index: StructIndex, state: Box<State>) -> State;
 }
 
 /// Корневая структура, представляющая весь GFF-документ
@@ -121,8 +153,9 @@ impl TokenEmitter for Root {
     Token::RootBegin { tag, count }
   }
   fn end(&self) -> Token { Token::RootEnd }
-  fn next(self, state: Box<State>) -> State {
+  fn next(self, index: StructIndex, state: Box<State>) -> State {
     State::EndRoot(EndStruct::<Self> {
+      index,
       state: state,
       data:  self,
     })
@@ -137,8 +170,9 @@ impl TokenEmitter for Struct {
     Token::StructBegin { tag, count }
   }
   fn end(&self) -> Token { Token::StructEnd }
-  fn next(self, state: Box<State>) -> State {
+  fn next(self, index: StructIndex, state: Box<State>) -> State {
     State::EndStruct(EndStruct::<Self> {
+      index,
       state: state,
       data:  self,
     })
@@ -156,8 +190,9 @@ impl TokenEmitter for Item {
     Token::ItemBegin { tag, count, index: self.index }
   }
   fn end(&self) -> Token { Token::ItemEnd }
-  fn next(self, state: Box<State>) -> State {
+  fn next(self, index: StructIndex, state: Box<State>) -> State {
     State::EndItem(EndStruct::<Self> {
+      index,
       state: state,
       data:  self,
     })
@@ -182,6 +217,10 @@ impl<Data: TokenEmitter> ReadStruct<Data> {
   /// # Возвращаемое значение
   /// Возвращает генерируемый в процессе разбора токен и новое состояние парсера
   fn next<R: Read + Seek>(self, parser: &mut Parser<R>) -> Result<(Token, State)> {
+    // Структура, уже читаемая выше по дереву, приведет к бесконечному разбору
+    if self.state.is_reading(self.index) {
+      return Err(Error::CyclicStructure { index: self.index });
+    }
     // Переходим к структуре в списке структур и читаем его
     parser.seek(self.index)?;
     let tag   = parser.read_u32()?;
@@ -189,7 +228,11 @@ impl<Data: TokenEmitter> ReadStruct<Data> {
     let count = parser.read_u32()?;
 
     let token = self.data.begin(Tag(tag), count);
-    let next  = self.data.next(self.state);
+    // Начало структуры-значения поля относится к записи этого поля
+    if !matches!(token, Token::StructBegin { .. }) {
+      parser.mark_record(self.index);
+    }
+    let next  = self.data.next(self.index, self.state);
     let state = match count {
       0 => next,
       1 => State::ReadLabel(ReadLabel { index: FieldIndex(index), state: next.into() }),
@@ -213,6 +256,8 @@ impl Default for ReadStruct<Root> {
 //--------------------------------------------------------------------------------------------------
 #[derive(Debug, Clone)]
 pub struct EndStruct<Data: TokenEmitter> {
+  /// Индекс завершаемой структуры
+  index: StructIndex,
   /// Состояние, в которое нужно вернуться
   state: Box<State>,
   /// Дополнительные данные
@@ -240,6 +285,7 @@ impl ReadLabel {
   /// Возвращает генерируемый в процессе разбора токен и новое состояние парсера
   fn next<R: Read + Seek>(self, parser: &mut Parser<R>) -> Result<(Token, State)> {
     // Переходим к полю в списке полей и читаем его
+    parser.mark_record(self.index);
     parser.seek(self.index)?;
     let tag   = parser.read_u32()?;
     let label = LabelIndex(parser.read_u32()?);
@@ -265,8 +311,8 @@ impl ReadField {
   /// # Возвращаемое значение
   /// Возвращает генерируемый в процессе разбора токен и новое состояние парсера
   fn next<R: Read + Seek>(self, parser: &mut Parser<R>) -> Result<(Token, State)> {
-    match self.tag {
-      14 => {// Структура
+    match FieldType::from_u32(self.tag) {
+      Some(FieldType::Struct) => {// Структура
         let next = ReadStruct::<Struct> {
           index: StructIndex(parser.read_u32()?),
           state: self.state,
@@ -274,7 +320,7 @@ impl ReadField {
         };
         next.next(parser)
       },
-      15 => {// Список элементов
+      Some(FieldType::List) => {// Список элементов
         let next = ReadList {
           index: ListIndicesIndex(parser.read_u32()?, 0),
           state: self.state,
@@ -325,12 +371,8 @@ impl ReadFields {
 
     state.next(parser)
   }
-  fn skip(self, token: Token) -> State {
-    match token {
-      Token::Value(..) => State::ReadFields(self),
-      _ => self.state.skip(token),
-    }
-  }
+  #[inline]
+  fn skip(self, token: Token) -> State { self.state.skip(token) }
 }
 //--------------------------------------------------------------------------------------------------
 /// Псевдо-состояние для чтения указанного списка элементов.
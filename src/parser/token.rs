@@ -1,10 +1,12 @@
+use std::fmt;
+
 use crate::index::LabelIndex;
 use crate::value::SimpleValueRef;
-use super::Tag;
+use super::{ObjectType, Tag};
 
 /// Возможные виды событий, которые могут возникнуть при чтении GFF файла. Отражают
 /// появление в потоке значений из файла и структурных единиц (списков, структур, ...)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
   /// Событие о начале разбора GFF-читателем структуры с индексом 0 в GFF файле.
   ///
@@ -81,3 +83,99 @@ pub enum Token {
   /// Поле, представляющее примитивное (не имеющее структуры) значение, хранимое в файле
   Value(SimpleValueRef),
 }
+
+impl Token {
+  /// Возвращает тип игрового объекта, хранимого в элементе списка, если токен является
+  /// токеном [`ItemBegin`] и его тег соответствует одному из известных типов объектов.
+  /// Позволяет выбрать способ обработки элемента списка экземпляров `.git` файла без
+  /// дополнительного чтения его полей
+  ///
+  /// [`ItemBegin`]: #variant.ItemBegin
+  #[inline]
+  pub fn object_type(&self) -> Option<ObjectType> {
+    match *self {
+      Token::ItemBegin { tag, .. } => tag.object_type(),
+      _ => None,
+    }
+  }
+  /// Возвращает `true`, если токен открывает структурный элемент: корневую структуру,
+  /// структуру, список или элемент списка
+  #[inline]
+  pub fn is_begin(&self) -> bool {
+    matches!(*self,
+      Token::RootBegin { .. } | Token::StructBegin { .. } |
+      Token::ListBegin(_) | Token::ItemBegin { .. }
+    )
+  }
+  /// Возвращает `true`, если токен закрывает структурный элемент: корневую структуру,
+  /// структуру, список или элемент списка
+  #[inline]
+  pub fn is_end(&self) -> bool {
+    matches!(*self, Token::RootEnd | Token::StructEnd | Token::ListEnd | Token::ItemEnd)
+  }
+  /// Возвращает `true`, если токен является простым значением поля
+  #[inline]
+  pub fn is_value(&self) -> bool {
+    matches!(*self, Token::Value(_))
+  }
+}
+
+/// Выводит токен в компактной форме, например, `struct(tag: 0, fields: 3)`, `label #2`
+/// или `end list`. Значения выводятся в отладочном представлении
+impl fmt::Display for Token {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Token::RootBegin { tag, count } => write!(f, "root(tag: {}, fields: {})", tag.0, count),
+      Token::RootEnd => write!(f, "end root"),
+      Token::Label(index) => write!(f, "label #{}", index.0),
+      Token::StructBegin { tag, count } => write!(f, "struct(tag: {}, fields: {})", tag.0, count),
+      Token::StructEnd => write!(f, "end struct"),
+      Token::ListBegin(count) => write!(f, "list(items: {})", count),
+      Token::ListEnd => write!(f, "end list"),
+      Token::ItemBegin { tag, count, index } => write!(f, "item #{}(tag: {}, fields: {})", index, tag.0, count),
+      Token::ItemEnd => write!(f, "end item"),
+      Token::Value(ref value) => write!(f, "{:?}", value),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::index::LabelIndex;
+  use crate::value::SimpleValueRef;
+  use super::super::Tag;
+  use super::Token;
+
+  /// Возвращает токены всех видов, их ожидаемое текстовое представление и ожидаемые
+  /// результаты `is_begin`, `is_end` и `is_value`
+  fn tokens() -> Vec<(Token, &'static str, bool, bool, bool)> {
+    vec![
+      (Token::RootBegin { tag: Tag(0xFFFF_FFFF), count: 2 }, "root(tag: 4294967295, fields: 2)", true, false, false),
+      (Token::RootEnd, "end root", false, true, false),
+      (Token::Label(LabelIndex(3)), "label #3", false, false, false),
+      (Token::StructBegin { tag: Tag(5), count: 1 }, "struct(tag: 5, fields: 1)", true, false, false),
+      (Token::StructEnd, "end struct", false, true, false),
+      (Token::ListBegin(4), "list(items: 4)", true, false, false),
+      (Token::ListEnd, "end list", false, true, false),
+      (Token::ItemBegin { tag: Tag(6), count: 0, index: 1 }, "item #1(tag: 6, fields: 0)", true, false, false),
+      (Token::ItemEnd, "end item", false, true, false),
+      (Token::Value(SimpleValueRef::Dword(42)), "Dword(42)", false, false, true),
+    ]
+  }
+
+  #[test]
+  fn predicates() {
+    for (token, _, begin, end, value) in tokens() {
+      assert_eq!(token.is_begin(), begin, "{:?}", token);
+      assert_eq!(token.is_end(), end, "{:?}", token);
+      assert_eq!(token.is_value(), value, "{:?}", token);
+    }
+  }
+
+  #[test]
+  fn display() {
+    for (token, expected, ..) in tokens() {
+      assert_eq!(token.to_string(), expected);
+    }
+  }
+}
@@ -0,0 +1,35 @@
+//! Реэкспортирует типы и функции, необходимые в большинстве случаев использования библиотеки,
+//! чтобы их можно было подключить одной строкой.
+//!
+//! # Пример
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_gff::prelude::*;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Item {
+//!   #[serde(rename = "Tag")]
+//!   tag: String,
+//!   #[serde(rename = "StackSize")]
+//!   stack: u16,
+//! }
+//!
+//! let item = Item { tag: "NW_IT_GOLD001".into(), stack: 10 };
+//! let data = to_vec(Signature::from(*b"UTI "), &item).expect("can't serialize item");
+//!
+//! let value: Value = from_slice(&data).expect("can't read GFF");
+//! assert_eq!(value["StackSize"], Value::Word(10));
+//! assert_eq!(from_value::<Item>(value).expect("can't convert value"), item);
+//!
+//! let label: Label = "StackSize".parse().expect("too long label");
+//! assert_eq!(label.as_str(), Ok("StackSize"));
+//! let resref: ResRef = "nw_it_gold001".into();
+//! assert_eq!(resref.as_str(), Ok("nw_it_gold001"));
+//! assert_eq!(Version::V3_2.to_string(), "3.2");
+//! ```
+
+pub use crate::{Label, LocString, ResRef, StrRef};
+pub use crate::de::{from_reader, from_slice, Deserializer};
+pub use crate::header::{Signature, Version};
+pub use crate::ser::{to_vec, to_writer, Serializer};
+pub use crate::value::{from_value, to_value, Value};
@@ -1,12 +1,24 @@
 //! Вспомогательный модуль, содержащий описание структур, непосредственно хранимых
 //! в GFF файле на диске. Обычно нет необходимости использовать данный модуль -- он
 //! может понадобиться только при отладке
+use std::convert::TryFrom;
 use std::fmt;
-use std::io::{Cursor, Read, Seek, SeekFrom, Write, Result};
-use byteorder::{LE, ReadBytesExt, WriteBytesExt};
+use std::io::{Cursor, Error, ErrorKind, Read, Seek, SeekFrom, Write, Result};
+use std::slice;
+use byteorder::{ByteOrder, LE, ReadBytesExt, WriteBytesExt};
+use encoding::{DecoderTrap, EncodingRef};
+use encoding::all::UTF_8;
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
-use crate::header::Header;
-use crate::Label;
+use crate::de::GffDeserializer;
+use crate::header::{Header, Section, Signature, Version};
+use crate::index::StructIndex;
+use crate::parser::Limits;
+use crate::ser::{parse_loc_string, LocStringData, Serializer};
+use crate::value::{SimpleValue, Value};
+use crate::{Label, ResRef};
 
 /// Типы полей, которые возможно встретить в GFF файле
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -75,9 +87,52 @@ impl FieldType {
   pub fn is_simple(&self) -> bool {
     !self.is_complex() && *self != FieldType::Struct && *self != FieldType::List
   }
+  /// Возвращает количество байт, которые значение поля указанного типа занимает внутри
+  /// структуры [`Field`]. Для типов, данные которых хранятся в других областях файла,
+  /// возвращает 4 -- размер хранимого в поле смещения на эти данные
+  ///
+  /// [`Field`]: struct.Field.html
+  #[inline]
+  pub fn size(&self) -> usize {
+    use self::FieldType::*;
+
+    match *self {
+      Byte | Char  => 1,
+      Word | Short  => 2,
+      _ => 4,
+    }
+  }
+  /// Возвращает название типа, совпадающее с названием варианта перечисления
+  pub fn name(&self) -> &'static str {
+    use self::FieldType::*;
+
+    match *self {
+      Byte      => "Byte",
+      Char      => "Char",
+      Word      => "Word",
+      Short     => "Short",
+      Dword     => "Dword",
+      Int       => "Int",
+      Dword64   => "Dword64",
+      Int64     => "Int64",
+      Float     => "Float",
+      Double    => "Double",
+      String    => "String",
+      ResRef    => "ResRef",
+      LocString => "LocString",
+      Void      => "Void",
+      Struct    => "Struct",
+      List      => "List",
+    }
+  }
+  /// Возвращает итератор по всем типам полей в порядке возрастания их идентификаторов
+  #[inline]
+  pub fn all() -> impl Iterator<Item = FieldType> {
+    (0..16).filter_map(FieldType::from_u32)
+  }
   //TODO: После стабилизации https://github.com/rust-lang/rust/issues/33417 полностью перенести в TryFrom
   #[inline]
-  fn from_u32(value: u32) -> Option<Self> {
+  pub(crate) fn from_u32(value: u32) -> Option<Self> {
     use self::FieldType::*;
 
     Some(match value {
@@ -274,12 +329,29 @@ pub struct Gff {
   /// или `[3, 0, 2, 4, 2, 1, 3]` в зависимости от порядка записи списков. Каждый подсписок начинается
   /// с числа, указывающего его размер: в данном примере `[2| 1, 3]` и `[3| 0, 2, 4]`
   pub list_indices:  Vec<u32>,
+  /// Ограничения, применяемые при обработке файла в памяти. Методы [`to_value`] и
+  /// [`deserialize_struct_at`], а также десериализатор `&Gff` учитывают максимальную глубину
+  /// вложенности [`Limits::max_depth`] так же, как [`Parser`], и при ее превышении возвращают
+  /// ошибку [`Error::DepthLimitExceeded`]. Метод [`walk`] обходит структуры без рекурсии и
+  /// глубину не ограничивает. По умолчанию используются ограничения [`Limits::default`]
+  ///
+  /// [`to_value`]: #method.to_value
+  /// [`deserialize_struct_at`]: #method.deserialize_struct_at
+  /// [`walk`]: #method.walk
+  /// [`Limits::max_depth`]: ../parser/struct.Limits.html#structfield.max_depth
+  /// [`Limits::default`]: ../parser/struct.Limits.html#impl-Default
+  /// [`Parser`]: ../parser/struct.Parser.html
+  /// [`Error::DepthLimitExceeded`]: ../error/enum.Error.html#variant.DepthLimitExceeded
+  pub limits:        Limits,
 }
 
+/// Читает все записи области. Память выделяется по мере чтения записей, а не заранее по
+/// количеству из заголовка, поэтому некорректный заголовок не приводит к попытке выделить
+/// гигантский буфер (см. [`read_bytes`])
 macro_rules! read_exact {
   ($reader:expr, $section:expr, $type:ident) => ({
     $reader.seek(SeekFrom::Start($section.offset as u64))?;
-    let mut vec = Vec::with_capacity($section.count as usize);
+    let mut vec = Vec::new();
     for _ in 0..$section.count {
       vec.push($type::read($reader)?);
     }
@@ -290,13 +362,30 @@ macro_rules! read_exact {
 macro_rules! read_into {
   ($reader:expr, $section:expr) => ({
     $reader.seek(SeekFrom::Start($section.offset as u64))?;
-    let mut vec = Vec::with_capacity($section.count as usize);
-    unsafe { vec.set_len(($section.count / 4) as usize); }
-    $reader.read_u32_into::<LE>(&mut vec[..])?;
-    vec
+    let bytes = read_bytes($reader, ($section.count / 4 * 4) as usize)?;
+    bytes.chunks_exact(4).map(LE::read_u32).collect::<Vec<_>>()
   });
 }
 
+/// Читает из потока ровно `size` байт. В отличие от предварительного выделения буфера
+/// указанного размера, память выделяется по мере чтения данных, поэтому некорректная длина,
+/// прочитанная из поврежденного файла, не приводит к попытке выделить гигантский буфер.
+///
+/// Если поток закончился раньше, чем было прочитано `size` байт, возвращает ошибку
+/// с видом [`ErrorKind::UnexpectedEof`].
+///
+/// [`ErrorKind::UnexpectedEof`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.UnexpectedEof
+pub(crate) fn read_bytes<R: Read>(reader: &mut R, size: usize) -> Result<Vec<u8>> {
+  let mut bytes = Vec::new();
+  reader.take(size as u64).read_to_end(&mut bytes)?;
+
+  if bytes.len() != size {
+    let msg = format!("expected {} bytes, but only {} available", size, bytes.len());
+    return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+  }
+  Ok(bytes)
+}
+
 macro_rules! write_all {
   ($writer:expr, $list:expr) => (
     for elem in &$list {
@@ -310,15 +399,72 @@ macro_rules! write_all {
   );
 }
 
+/// Состояние обхода дерева структур файла в памяти: текущая глубина вложенности и множество
+/// структур, в которые вложен обрабатываемый элемент. Глубина считается так же, как в
+/// [`Parser`]: каждая структура, список и элемент списка увеличивают ее на единицу
+///
+/// [`Parser`]: ../parser/struct.Parser.html
+pub(crate) struct Nesting {
+  /// Для каждой структуры файла признак того, что она является одной из объемлющих
+  active: Vec<bool>,
+  /// Текущая глубина вложенности
+  depth: usize,
+  /// Максимальная глубина вложенности
+  max_depth: usize,
+}
+impl Nesting {
+  /// Создает состояние для обхода указанного файла с его ограничениями
+  pub(crate) fn new(gff: &Gff) -> Self {
+    Nesting { active: vec![false; gff.structs.len()], depth: 0, max_depth: gff.limits.max_depth }
+  }
+  /// Увеличивает глубину вложенности при входе в список, или возвращает ошибку
+  /// [`Error::DepthLimitExceeded`], если это превысит максимальную глубину
+  ///
+  /// [`Error::DepthLimitExceeded`]: ../error/enum.Error.html#variant.DepthLimitExceeded
+  pub(crate) fn enter(&mut self) -> crate::error::Result<()> {
+    if self.depth >= self.max_depth {
+      return Err(crate::error::Error::DepthLimitExceeded(self.max_depth));
+    }
+    self.depth += 1;
+    Ok(())
+  }
+  /// Уменьшает глубину вложенности при выходе из списка
+  #[inline]
+  pub(crate) fn leave(&mut self) {
+    self.depth -= 1;
+  }
+  /// Входит в структуру с указанным индексом, который должен быть корректным. Возвращает
+  /// ошибку [`Error::CyclicStructure`], если структура уже является одной из объемлющих
+  ///
+  /// [`Error::CyclicStructure`]: ../error/enum.Error.html#variant.CyclicStructure
+  pub(crate) fn enter_struct(&mut self, index: u32) -> crate::error::Result<()> {
+    if self.active[index as usize] {
+      return Err(crate::error::Error::CyclicStructure { index: index.into() });
+    }
+    self.enter()?;
+    self.active[index as usize] = true;
+    Ok(())
+  }
+  /// Выходит из структуры с указанным индексом
+  #[inline]
+  pub(crate) fn leave_struct(&mut self, index: u32) {
+    self.active[index as usize] = false;
+    self.leave();
+  }
+}
+
 impl Gff {
-  /// Осуществляет чтение GFF формата из указанного источника данных
+  /// Осуществляет чтение GFF формата из указанного источника данных. Поле [`limits`]
+  /// прочитанного файла содержит ограничения по умолчанию
+  ///
+  /// [`limits`]: #structfield.limits
   pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Gff> {
     let header  = Header::read(reader)?;
     let structs = read_exact!(reader, header.structs, Struct);
     let fields  = read_exact!(reader, header.fields , Field);
 
     reader.seek(SeekFrom::Start(header.labels.offset as u64))?;
-    let mut labels = Vec::with_capacity(header.labels.count as usize);
+    let mut labels = Vec::new();
     for _ in 0..header.labels.count {
       let mut label = [0u8; 16];
       reader.read_exact(&mut label)?;
@@ -326,14 +472,12 @@ impl Gff {
     }
 
     reader.seek(SeekFrom::Start(header.field_data.offset as u64))?;
-    let mut field_data = Vec::with_capacity(header.field_data.count as usize);
-    unsafe { field_data.set_len(header.field_data.count as usize); }
-    reader.read_exact(&mut field_data[..])?;
+    let field_data = read_bytes(reader, header.field_data.count as usize)?;
 
     let field_indices = read_into!(reader, header.field_indices);
     let list_indices  = read_into!(reader, header.list_indices);
 
-    Ok(Gff { header, structs, fields, labels, field_data, field_indices, list_indices })
+    Ok(Gff { header, structs, fields, labels, field_data, field_indices, list_indices, limits: Limits::default() })
   }
   /// Записывает всю GFF структуру в указанный поток
   pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
@@ -349,6 +493,633 @@ impl Gff {
     Ok(())
   }
 
+  /// Проверяет согласованность внутренних ссылок файла: ссылки структур на поля, ссылки полей
+  /// на метки, данные, структуры и списки, а также ссылки элементов списков на структуры.
+  ///
+  /// # Ошибки
+  /// Возвращает [`Error::Inconsistent`] с описанием первой найденной некорректной ссылки
+  ///
+  /// [`Error::Inconsistent`]: ../error/enum.Error.html#variant.Inconsistent
+  pub fn validate(&self) -> crate::error::Result<()> {
+    use crate::error::Error::Inconsistent;
+
+    for (i, s) in self.structs.iter().enumerate() {
+      match s.fields {
+        0 => {},
+        1 if s.offset as usize >= self.fields.len() => {
+          return Err(Inconsistent(format!("struct {} references field {}, but only {} fields exist", i, s.offset, self.fields.len())));
+        },
+        1 => {},
+        count => {
+          let start = s.offset as usize / 4;
+          let indices = self.field_indices.get(start..start + count as usize);
+          let indices = match indices {
+            Some(indices) if s.offset & 3 == 0 => indices,
+            _ => return Err(Inconsistent(format!("struct {} references {} field indices at byte offset {}, but field indices contain only {} bytes", i, count, s.offset, self.field_indices.len() * 4))),
+          };
+          if let Some(index) = indices.iter().find(|&&index| index as usize >= self.fields.len()) {
+            return Err(Inconsistent(format!("struct {} references field {}, but only {} fields exist", i, index, self.fields.len())));
+          }
+        },
+      }
+    }
+    for (i, f) in self.fields.iter().enumerate() {
+      if f.label as usize >= self.labels.len() {
+        return Err(Inconsistent(format!("field {} references label {}, but only {} labels exist", i, f.label, self.labels.len())));
+      }
+      let type_ = FieldType::from_u32(f.tag).ok_or_else(|| Inconsistent(format!("field {} has unknown type {}", i, f.tag)))?;
+      let data = LE::read_u32(&f.data);
+      match type_ {
+        FieldType::Struct if data as usize >= self.structs.len() => {
+          return Err(Inconsistent(format!("field {} references struct {}, but only {} structs exist", i, data, self.structs.len())));
+        },
+        FieldType::List => self.validate_list(i, data)?,
+        _ if type_.is_complex() && data as usize >= self.field_data.len() => {
+          return Err(Inconsistent(format!("field {} references data at byte offset {}, but field data contain only {} bytes", i, data, self.field_data.len())));
+        },
+        _ => {},
+      }
+    }
+    Ok(())
+  }
+  /// Проверяет, что список, на который ссылается поле, целиком помещается в массив индексов
+  /// списков и состоит из существующих структур
+  ///
+  /// # Параметры
+  /// - `field`: Индекс поля, ссылающегося на список, для сообщения об ошибке
+  /// - `offset`: Байтовое смещение списка в массиве индексов списков
+  fn validate_list(&self, field: usize, offset: u32) -> crate::error::Result<()> {
+    use crate::error::Error::Inconsistent;
+
+    let start = offset as usize / 4;
+    let count = match self.list_indices.get(start) {
+      Some(&count) if offset & 3 == 0 => count as usize,
+      _ => return Err(Inconsistent(format!("field {} references list at byte offset {}, but list indices contain only {} bytes", field, offset, self.list_indices.len() * 4))),
+    };
+    let items = match self.list_indices.get(start + 1..start + 1 + count) {
+      Some(items) => items,
+      None => return Err(Inconsistent(format!("field {} references list of {} elements at byte offset {}, but list indices contain only {} bytes", field, count, offset, self.list_indices.len() * 4))),
+    };
+    if let Some(index) = items.iter().find(|&&index| index as usize >= self.structs.len()) {
+      return Err(Inconsistent(format!("list at byte offset {} references struct {}, but only {} structs exist", offset, index, self.structs.len())));
+    }
+    Ok(())
+  }
+  /// Возвращает диапазоны байт файла, не занятые ни заголовком, ни одной из областей,
+  /// описываемых заголовком, в порядке возрастания смещений. Каждый диапазон задается парой
+  /// `(начало, конец)`, конец в диапазон не входит. Такие диапазоны могут быть выравниванием
+  /// между областями или скрытыми данными, в том числе дописанными в конец файла.
+  ///
+  /// # Параметры
+  /// - `file_len`: Полный размер файла в байтах, включая заголовок
+  pub fn gaps(&self, file_len: u64) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<_> = self.header.sections().iter()
+      .filter(|(_, section, _)| section.count != 0)
+      .map(|(_, section, record_size)| {
+        let start = section.offset as u64;
+        (start, start + section.count as u64 * record_size)
+      })
+      .collect();
+    ranges.sort();
+
+    let mut gaps = Vec::new();
+    let mut pos = Header::HEADER_SIZE as u64;
+    for (start, end) in ranges {
+      if start > pos {
+        gaps.push((pos, start));
+      }
+      pos = pos.max(end);
+    }
+    if file_len > pos {
+      gaps.push((pos, file_len));
+    }
+    gaps
+  }
+  /// Возвращает все метки файла в порядке их хранения
+  #[inline]
+  pub fn labels(&self) -> &[Label] {
+    &self.labels
+  }
+  /// Возвращает индекс метки, привязанной к полю с указанным номером, не читая саму метку.
+  /// Вместе с [`label_index`] позволяет искать поля, сравнивая индексы, а не строки меток
+  ///
+  /// # Паника
+  /// Паникует, если поля с указанным номером не существует
+  ///
+  /// [`label_index`]: #method.label_index
+  #[inline]
+  pub fn field_label_index(&self, field_index: usize) -> u32 {
+    self.fields[field_index].label
+  }
+  /// Возвращает индекс указанной метки в массиве меток или `None`, если в файле такой метки нет
+  pub fn label_index(&self, label: &Label) -> Option<u32> {
+    self.labels.iter().position(|l| l == label).map(|i| i as u32)
+  }
+  /// Возвращает все ссылки на ресурсы (поля типа `ResRef`) вместе с метками полей, в которых
+  /// они хранятся, в порядке следования полей в файле. Так как поля всех структур файла хранятся
+  /// в общем массиве, в результат попадают ссылки из всех вложенных структур и элементов
+  /// списков, что позволяет получить полный набор ресурсов, от которых зависит файл.
+  ///
+  /// Поля, ссылающиеся на несуществующие метки или данные, пропускаются. Для их обнаружения
+  /// используйте метод [`validate`](#method.validate).
+  ///
+  /// Метод не возвращает `Result`: ссылки на ресурсы хранятся в виде байт и не декодируются,
+  /// поэтому, в отличие от [`strings`](#method.strings), их чтение не может завершиться ошибкой
+  pub fn resrefs(&self) -> Vec<(Label, ResRef)> {
+    self.fields.iter()
+      .filter(|f| f.tag == FieldType::ResRef as u32)
+      .filter_map(|f| {
+        let label = *self.labels.get(f.label as usize)?;
+        // Данные ссылки на ресурс состоят из байта длины и самих символов ссылки
+        let data = self.field_data.get(LE::read_u32(&f.data) as usize..)?;
+        let (&len, data) = data.split_first()?;
+        let resref = ResRef(data.get(..len as usize)?.to_vec());
+        Some((label, resref))
+      })
+      .collect()
+  }
+  /// Возвращает метки и значения всех полей типа `String` файла, декодируя строки в
+  /// кодировке `UTF-8`, см. [`strings_with_encoding`].
+  ///
+  /// Как и [`resrefs`], метод возвращает значения вместе с метками полей, в которых они
+  /// хранятся, чтобы при аудите было видно, откуда взята каждая строка. Если метки не нужны,
+  /// их можно отбросить: `gff.strings()?.into_iter().map(|(_, s)| s)`
+  ///
+  /// [`strings_with_encoding`]: #method.strings_with_encoding
+  /// [`resrefs`]: #method.resrefs
+  #[inline]
+  pub fn strings(&self) -> crate::error::Result<Vec<(Label, String)>> {
+    self.strings_with_encoding(UTF_8, DecoderTrap::Strict)
+  }
+  /// Возвращает метки и значения всех полей типа `String` файла в порядке следования полей
+  /// в массиве полей, не строя дерево значений. Как и для [`resrefs`], в результат попадают
+  /// строки из всех вложенных структур и элементов списков.
+  ///
+  /// Поля, ссылающиеся на несуществующие метки или данные, пропускаются. Для их обнаружения
+  /// используйте метод [`validate`]
+  ///
+  /// # Параметры
+  /// - `encoding`: Кодировка для декодирования символов в строках
+  /// - `trap`: Способ обработки символов в строках, которые не удалось декодировать с
+  ///   использованием выбранной кодировки
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку, если строку не удалось декодировать
+  ///
+  /// [`resrefs`]: #method.resrefs
+  /// [`validate`]: #method.validate
+  pub fn strings_with_encoding(&self, encoding: EncodingRef, trap: DecoderTrap) -> crate::error::Result<Vec<(Label, String)>> {
+    let mut result = Vec::new();
+    for f in self.fields.iter().filter(|f| f.tag == FieldType::String as u32) {
+      let bytes = self.labels.get(f.label as usize).and_then(|&label| {
+        // Данные строки состоят из 4 байт длины и самих байт строки
+        let data = self.field_data.get(LE::read_u32(&f.data) as usize..)?;
+        let len = LE::read_u32(data.get(..4)?) as usize;
+        Some((label, data[4..].get(..len)?))
+      });
+      if let Some((label, bytes)) = bytes {
+        result.push((label, encoding.decode(bytes, trap)?));
+      }
+    }
+    Ok(result)
+  }
+
+  /// Строит дерево значений непосредственно из прочитанных массивов, без повторного разбора
+  /// файла. Строки декодируются, как `UTF-8`. Идентификаторы типов структур (теги)
+  /// сохраняются в поле `tag` значений [`Value::Struct`].
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку, если внутренние ссылки файла несогласованы (см. [`validate`]), файл
+  /// не содержит корневой структуры, структура содержит саму себя, вложенность превышает
+  /// ограничение [`limits`] или данные поля не удалось прочитать
+  ///
+  /// [`Value::Struct`]: ../value/enum.Value.html#variant.Struct
+  /// [`validate`]: #method.validate
+  /// [`limits`]: #structfield.limits
+  pub fn to_value(&self) -> crate::error::Result<Value> {
+    self.validate()?;
+    if self.structs.is_empty() {
+      return Err(crate::error::Error::Inconsistent("file contains no root struct".into()));
+    }
+    self.struct_value(0, &mut Nesting::new(self))
+  }
+  /// Десериализует значение из структуры с указанным индексом и всех вложенных в нее
+  /// структур и списков, не затрагивая остальные данные файла. Позволяет извлечь из большого
+  /// файла только интересующую часть, если ее индекс известен. Значение разбирается так же,
+  /// как при десериализации из самого файла в памяти
+  ///
+  /// Проверяется согласованность только тех ссылок, которые встречаются при разборе выбранной
+  /// структуры, поэтому время работы не зависит от размера остальной части файла.
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку [`Error::Inconsistent`], если структуры с указанным индексом не
+  /// существует или ссылки внутри нее и вложенных в нее данных несогласованы, ошибку
+  /// [`Error::CyclicStructure`], если структура содержит саму себя, или ошибку, если
+  /// значение не удалось десериализовать
+  ///
+  /// [`Error::Inconsistent`]: ../error/enum.Error.html#variant.Inconsistent
+  /// [`Error::CyclicStructure`]: ../error/enum.Error.html#variant.CyclicStructure
+  pub fn deserialize_struct_at<T>(&self, index: StructIndex) -> crate::error::Result<T>
+    where T: DeserializeOwned,
+  {
+    T::deserialize(GffDeserializer::new(self, index.0, &mut Nesting::new(self)))
+  }
+  /// Возвращает итератор по всем структурам файла вместе с их индексами в порядке их
+  /// расположения в файле, без учета вложенности
+  pub fn iter_structs(&self) -> impl Iterator<Item = (StructIndex, &Struct)> + '_ {
+    self.structs.iter().enumerate().map(|(i, s)| (StructIndex(i as u32), s))
+  }
+  /// Обходит в глубину все структуры, достижимые из корневой, следуя ссылкам полей-структур
+  /// и полей-списков в порядке следования полей. Для каждой структуры вызывает функцию `f`,
+  /// передавая ей индекс структуры, саму структуру и глубину ее вложенности (`0` для корневой).
+  ///
+  /// Каждая структура посещается не более одного раза, поэтому циклические ссылки в
+  /// поврежденном файле не приводят к зацикливанию, а структуры, на которые ссылаются
+  /// несколько полей, посещаются только при первой встрече. Обход выполняется без рекурсии,
+  /// поэтому глубина вложенности структур не ограничена
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку, если внутренние ссылки файла несогласованы (см. [`validate`]) или
+  /// поле имеет неизвестный тип
+  ///
+  /// [`validate`]: #method.validate
+  pub fn walk<F>(&self, mut f: F) -> crate::error::Result<()>
+    where F: FnMut(StructIndex, &Struct, usize),
+  {
+    self.validate()?;
+    if self.structs.is_empty() {
+      return Ok(());
+    }
+    let mut visited = vec![false; self.structs.len()];
+    // Структуры, ожидающие посещения, вместе с глубиной их вложенности. Дочерние структуры
+    // помещаются в стек в обратном порядке, чтобы извлекаться в порядке следования полей
+    let mut stack = vec![(0, 0)];
+    let mut children = Vec::new();
+    while let Some((index, depth)) = stack.pop() {
+      let (s, fields) = self.struct_fields(index)?;
+      if visited[index as usize] {
+        continue;
+      }
+      visited[index as usize] = true;
+
+      f(StructIndex(index), s, depth);
+      for &field in fields {
+        let field = self.field_at(field)?;
+        let data = LE::read_u32(&field.data);
+        match self.field_type(field)? {
+          FieldType::Struct => children.push(data),
+          FieldType::List   => children.extend_from_slice(self.list_items(data)?),
+          _ => {},
+        }
+      }
+      stack.extend(children.drain(..).rev().map(|item| (item, depth + 1)));
+    }
+    Ok(())
+  }
+  /// Возвращает тип указанного поля
+  ///
+  /// # Ошибки
+  /// Возвращает [`Error::UnknownValue`], если поле имеет неизвестный тип
+  ///
+  /// [`Error::UnknownValue`]: ../error/enum.Error.html#variant.UnknownValue
+  pub fn field_type(&self, field: &Field) -> crate::error::Result<FieldType> {
+    FieldType::from_u32(field.tag).ok_or(crate::error::Error::UnknownValue {
+      tag: field.tag,
+      value: LE::read_u32(&field.data),
+    })
+  }
+  /// Читает значение указанного поля, получая данные из массива данных полей, если они
+  /// не помещаются в само поле. Строки читаются в кодировке UTF-8
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку, если поле имеет неизвестный тип, его данные выходят за пределы
+  /// массива данных полей или не являются корректной строкой UTF-8, а также ошибку
+  /// [`Error::Deserialize`], если поле является структурой или списком. Их значения можно
+  /// получить методом [`to_value`]
+  ///
+  /// [`Error::Deserialize`]: ../error/enum.Error.html#variant.Deserialize
+  /// [`to_value`]: #method.to_value
+  pub fn field_value(&self, field: &Field) -> crate::error::Result<SimpleValue> {
+    let data = LE::read_u32(&field.data);
+    let mut cursor = Cursor::new(self.field_data.get(data as usize..).unwrap_or(&[]));
+
+    Ok(match self.field_type(field)? {
+      FieldType::Byte    => SimpleValue::Byte(field.data[0]),
+      FieldType::Char    => SimpleValue::Char(field.data[0] as i8),
+      FieldType::Word    => SimpleValue::Word(LE::read_u16(&field.data)),
+      FieldType::Short   => SimpleValue::Short(LE::read_i16(&field.data)),
+      FieldType::Dword   => SimpleValue::Dword(data),
+      FieldType::Int     => SimpleValue::Int(data as i32),
+      FieldType::Dword64 => SimpleValue::Dword64(cursor.read_u64::<LE>()?),
+      FieldType::Int64   => SimpleValue::Int64(cursor.read_i64::<LE>()?),
+      FieldType::Float   => SimpleValue::Float(LE::read_f32(&field.data)),
+      FieldType::Double  => SimpleValue::Double(cursor.read_f64::<LE>()?),
+      FieldType::String  => {
+        let size = cursor.read_u32::<LE>()? as usize;
+        SimpleValue::String(String::from_utf8(read_bytes(&mut cursor, size)?)?)
+      },
+      FieldType::ResRef  => {
+        let size = cursor.read_u8()? as usize;
+        SimpleValue::ResRef(ResRef(read_bytes(&mut cursor, size)?))
+      },
+      FieldType::LocString => SimpleValue::LocString(parse_loc_string(cursor.get_ref())?),
+      FieldType::Void    => {
+        let size = cursor.read_u32::<LE>()? as usize;
+        SimpleValue::Void(read_bytes(&mut cursor, size)?)
+      },
+      type_ @ FieldType::Struct |
+      type_ @ FieldType::List => return Err(crate::error::Error::Deserialize(format!(
+        "field of type `{}` has no simple value", type_.name()
+      ))),
+    })
+  }
+  /// Создает структуру файла из дерева значений. Значение должно быть структурой, которая
+  /// станет корневой структурой файла. Создаваемый файл имеет версию `V3.2`. Идентификаторы
+  /// типов структур (теги) берутся из поля `tag` значений [`Value::Struct`]
+  ///
+  /// [`Value::Struct`]: ../value/enum.Value.html#variant.Struct
+  pub fn from_value(signature: Signature, value: &Value) -> crate::error::Result<Gff> {
+    let mut ser = Serializer::default();
+    value.serialize(&mut ser)?;
+    ser.to_gff(signature, Version::V3_2)
+  }
+
+  /// Пересчитывает заголовок файла по содержимому массивов, располагая области друг за другом
+  /// сразу после заголовка в том порядке, в котором их записывает метод [`write`]. Вызывается
+  /// всеми методами редактирования; вызов вручную нужен только после прямого изменения массивов
+  ///
+  /// [`write`]: #method.write
+  pub fn update_header(&mut self) {
+    let mut offset = Header::HEADER_SIZE;
+    let mut section = |count: usize, size: u32| {
+      let section = Section { offset, count: count as u32 };
+      offset += section.count * size;
+      section
+    };
+    self.header.structs       = section(self.structs.len(), 3 * 4);
+    self.header.fields        = section(self.fields.len(), 3 * 4);
+    self.header.labels        = section(self.labels.len(), 16);
+    self.header.field_data    = section(self.field_data.len(), 1);
+    // Размер областей индексов задается в байтах, а не в элементах
+    self.header.field_indices = section(self.field_indices.len() * 4, 1);
+    self.header.list_indices  = section(self.list_indices.len() * 4, 1);
+  }
+  /// Возвращает индекс указанной метки, добавляя ее в конец массива меток, если в файле
+  /// такой метки еще нет
+  pub fn add_label(&mut self, label: Label) -> u32 {
+    if let Some(index) = self.label_index(&label) {
+      return index;
+    }
+    self.labels.push(label);
+    self.update_header();
+    (self.labels.len() - 1) as u32
+  }
+  /// Добавляет в файл новую структуру без полей с указанным идентификатором типа. Созданная
+  /// структура ни на что не ссылается -- чтобы она стала частью дерева, ее необходимо сделать
+  /// значением поля (см. [`add_struct_field`]) или элементом списка (см. [`add_list_field`]).
+  /// Поля добавляются методом [`add_field`]
+  ///
+  /// [`add_struct_field`]: #method.add_struct_field
+  /// [`add_list_field`]: #method.add_list_field
+  /// [`add_field`]: #method.add_field
+  pub fn add_struct(&mut self, tag: u32) -> StructIndex {
+    self.structs.push(Struct { tag, offset: 0, fields: 0 });
+    self.update_header();
+    ((self.structs.len() - 1) as u32).into()
+  }
+  /// Добавляет в конец указанной структуры поле с указанными меткой и значением. Значения,
+  /// не помещающиеся в поле, дописываются в конец области данных полей.
+  ///
+  /// Метка не проверяется на уникальность внутри структуры: при наличии в ней поля с такой
+  /// же меткой при чтении файла будет использовано значение последнего из них.
+  ///
+  /// # Ошибки
+  /// Возвращает [`Error::TooLongResRef`], если значение является ссылкой на ресурс длиннее
+  /// 16 байт. В этом случае файл не изменяется
+  ///
+  /// # Паника
+  /// Паникует, если структуры с указанным индексом не существует
+  ///
+  /// [`Error::TooLongResRef`]: ../error/enum.Error.html#variant.TooLongResRef
+  pub fn add_field(&mut self, index: StructIndex, label: Label, value: &SimpleValue) -> crate::error::Result<crate::index::FieldIndex> {
+    assert!((index.0 as usize) < self.structs.len(), "struct {} not exist", index.0);
+    let (type_, data) = self.push_value(value)?;
+    Ok(self.push_field(index, label, type_, data))
+  }
+  /// Добавляет в конец указанной структуры поле с указанной меткой, значением которого
+  /// является новая пустая структура с указанным идентификатором типа. Возвращает индекс
+  /// созданной структуры, в которую можно добавлять поля
+  ///
+  /// # Паника
+  /// Паникует, если структуры с указанным индексом не существует
+  pub fn add_struct_field(&mut self, index: StructIndex, label: Label, tag: u32) -> StructIndex {
+    assert!((index.0 as usize) < self.structs.len(), "struct {} not exist", index.0);
+    let child = self.add_struct(tag);
+    self.push_field(index, label, FieldType::Struct, child.0.to_le_bytes());
+    child
+  }
+  /// Добавляет в конец указанной структуры поле с указанной меткой, значением которого
+  /// является список из указанных структур
+  ///
+  /// # Паника
+  /// Паникует, если структуры с указанным индексом или одной из структур списка не существует
+  pub fn add_list_field(&mut self, index: StructIndex, label: Label, items: &[StructIndex]) -> crate::index::FieldIndex {
+    assert!((index.0 as usize) < self.structs.len(), "struct {} not exist", index.0);
+    if let Some(item) = items.iter().find(|item| item.0 as usize >= self.structs.len()) {
+      panic!("struct {} not exist", item.0);
+    }
+    let offset = (self.list_indices.len() * 4) as u32;
+    self.list_indices.push(items.len() as u32);
+    self.list_indices.extend(items.iter().map(|item| item.0));
+    self.push_field(index, label, FieldType::List, offset.to_le_bytes())
+  }
+  /// Заменяет значение указанного поля, в том числе его тип. Старые данные поля остаются
+  /// в области данных полей, т.к. могут использоваться другими полями, а новые, если они
+  /// не помещаются в поле, дописываются в ее конец. Если поле было структурой или списком,
+  /// то они перестают быть частью дерева, но из файла не удаляются
+  ///
+  /// # Ошибки
+  /// Возвращает [`Error::TooLongResRef`], если значение является ссылкой на ресурс длиннее
+  /// 16 байт. В этом случае файл не изменяется
+  ///
+  /// # Паника
+  /// Паникует, если поля с указанным индексом не существует
+  ///
+  /// [`Error::TooLongResRef`]: ../error/enum.Error.html#variant.TooLongResRef
+  pub fn set_field_data(&mut self, index: crate::index::FieldIndex, value: &SimpleValue) -> crate::error::Result<()> {
+    assert!((index.0 as usize) < self.fields.len(), "field {} not exist", index.0);
+    let (type_, data) = self.push_value(value)?;
+    let field = &mut self.fields[index.0 as usize];
+    field.tag = type_ as u32;
+    field.data = data;
+    self.update_header();
+    Ok(())
+  }
+  /// Кодирует значение в представление, хранимое в поле, дописывая данные, не помещающиеся
+  /// в поле, в конец области данных полей
+  fn push_value(&mut self, value: &SimpleValue) -> crate::error::Result<(FieldType, [u8; 4])> {
+    use self::SimpleValue::*;
+
+    let mut data = [0u8; 4];
+    let offset = (self.field_data.len() as u32).to_le_bytes();
+    let type_ = match value {
+      Byte(val)  => { data[0] = *val;       FieldType::Byte  },
+      Char(val)  => { data[0] = *val as u8; FieldType::Char  },
+      Word(val)  => { LE::write_u16(&mut data[..2], *val); FieldType::Word  },
+      Short(val) => { LE::write_i16(&mut data[..2], *val); FieldType::Short },
+      Dword(val) => { LE::write_u32(&mut data, *val); FieldType::Dword },
+      Int(val)   => { LE::write_i32(&mut data, *val); FieldType::Int   },
+      Float(val) => { LE::write_f32(&mut data, *val); FieldType::Float },
+      Dword64(val) => { self.field_data.write_u64::<LE>(*val)?; data = offset; FieldType::Dword64 },
+      Int64(val)   => { self.field_data.write_i64::<LE>(*val)?; data = offset; FieldType::Int64   },
+      Double(val)  => { self.field_data.write_f64::<LE>(*val)?; data = offset; FieldType::Double  },
+      String(val) => {
+        self.field_data.write_u32::<LE>(val.len() as u32)?;
+        self.field_data.extend_from_slice(val.as_bytes());
+        data = offset;
+        FieldType::String
+      },
+      ResRef(val) => {
+        if val.0.len() > 16 {
+          return Err(crate::error::Error::TooLongResRef(val.0.len()));
+        }
+        self.field_data.push(val.0.len() as u8);
+        self.field_data.extend_from_slice(&val.0);
+        data = offset;
+        FieldType::ResRef
+      },
+      LocString(val) => {
+        self.field_data.extend_from_slice(&LocStringData(val).to_bytes()?);
+        data = offset;
+        FieldType::LocString
+      },
+      Void(val) => {
+        self.field_data.write_u32::<LE>(val.len() as u32)?;
+        self.field_data.extend_from_slice(val);
+        data = offset;
+        FieldType::Void
+      },
+    };
+    Ok((type_, data))
+  }
+  /// Добавляет поле с уже закодированным значением в конец указанной структуры, обновляя
+  /// ссылки структуры на поля и смещения списков полей остальных структур
+  fn push_field(&mut self, index: StructIndex, label: Label, type_: FieldType, data: [u8; 4]) -> crate::index::FieldIndex {
+    let label = self.add_label(label);
+    let field = self.fields.len() as u32;
+    self.fields.push(Field { tag: type_ as u32, label, data });
+
+    let s = &mut self.structs[index.0 as usize];
+    match s.fields {
+      0 => s.offset = field,
+      // Единственное поле хранится в самой структуре, поэтому для двух полей создается новый
+      // список индексов полей в конце массива
+      1 => {
+        let offset = (self.field_indices.len() * 4) as u32;
+        self.field_indices.push(s.offset);
+        self.field_indices.push(field);
+        s.offset = offset;
+      },
+      // Индекс вставляется сразу после последнего поля структуры, поэтому списки полей
+      // остальных структур, расположенные после него, сдвигаются на 4 байта
+      count => {
+        let end = s.offset + count * 4;
+        self.field_indices.insert(end as usize / 4, field);
+        for other in &mut self.structs {
+          if other.fields > 1 && other.offset >= end {
+            other.offset += 4;
+          }
+        }
+      },
+    }
+    self.structs[index.0 as usize].fields += 1;
+    self.update_header();
+    field.into()
+  }
+  /// Возвращает структуру с указанным индексом и индексы ее полей
+  pub(crate) fn struct_fields(&self, index: u32) -> crate::error::Result<(&Struct, &[u32])> {
+    use crate::error::Error::Inconsistent;
+
+    let s = match self.structs.get(index as usize) {
+      Some(s) => s,
+      None => return Err(Inconsistent(format!("struct {} requested, but only {} structs exist", index, self.structs.len()))),
+    };
+    let fields = match s.fields {
+      0 => &[],
+      1 => slice::from_ref(&s.offset),
+      count => {
+        let start = s.offset as usize / 4;
+        match self.field_indices.get(start..start + count as usize) {
+          Some(indices) if s.offset & 3 == 0 => indices,
+          _ => return Err(Inconsistent(format!("struct {} references {} field indices at byte offset {}, but field indices contain only {} bytes", index, count, s.offset, self.field_indices.len() * 4))),
+        }
+      },
+    };
+    Ok((s, fields))
+  }
+  /// Возвращает поле с указанным индексом
+  pub(crate) fn field_at(&self, index: u32) -> crate::error::Result<&Field> {
+    self.fields.get(index as usize).ok_or_else(|| crate::error::Error::Inconsistent(
+      format!("field {} requested, but only {} fields exist", index, self.fields.len())
+    ))
+  }
+  /// Возвращает поле с указанным индексом вместе с его меткой
+  pub(crate) fn labeled_field(&self, index: u32) -> crate::error::Result<(Label, &Field)> {
+    let field = self.field_at(index)?;
+    match self.labels.get(field.label as usize) {
+      Some(label) => Ok((*label, field)),
+      None => Err(crate::error::Error::Inconsistent(format!("field {} references label {}, but only {} labels exist", index, field.label, self.labels.len()))),
+    }
+  }
+  /// Возвращает индексы структур списка, начинающегося с указанного смещения в массиве
+  /// индексов списков
+  pub(crate) fn list_items(&self, offset: u32) -> crate::error::Result<&[u32]> {
+    use crate::error::Error::Inconsistent;
+
+    let start = offset as usize / 4;
+    let count = match self.list_indices.get(start) {
+      Some(&count) if offset & 3 == 0 => count as usize,
+      _ => return Err(Inconsistent(format!("list at byte offset {} requested, but list indices contain only {} bytes", offset, self.list_indices.len() * 4))),
+    };
+    match self.list_indices.get(start + 1..start + 1 + count) {
+      Some(items) => Ok(items),
+      None => Err(Inconsistent(format!("list of {} elements at byte offset {} requested, but list indices contain only {} bytes", count, offset, self.list_indices.len() * 4))),
+    }
+  }
+  /// Строит значение структуры с указанным индексом. Параметр `nesting` содержит состояние
+  /// обхода для обнаружения циклов и ограничения глубины вложенности
+  fn struct_value(&self, index: u32, nesting: &mut Nesting) -> crate::error::Result<Value> {
+    let (s, fields) = self.struct_fields(index)?;
+    nesting.enter_struct(index)?;
+
+    let mut map = IndexMap::with_capacity(fields.len());
+    for &index in fields {
+      let (label, field) = self.labeled_field(index)?;
+      map.insert(label, self.field_tree(field, nesting)?);
+    }
+
+    nesting.leave_struct(index);
+    Ok(Value::Struct { tag: s.tag, fields: map })
+  }
+  /// Строит значение поля вместе со всеми вложенными в него структурами
+  fn field_tree(&self, field: &Field, nesting: &mut Nesting) -> crate::error::Result<Value> {
+    let data = LE::read_u32(&field.data);
+    Ok(match self.field_type(field)? {
+      FieldType::Struct  => self.struct_value(data, nesting)?,
+      FieldType::List    => {
+        let items = self.list_items(data)?;
+        let mut list = Vec::with_capacity(items.len());
+        nesting.enter()?;
+        for &index in items {
+          list.push(self.struct_value(index, nesting)?);
+        }
+        nesting.leave();
+        Value::List(list)
+      },
+      _ => self.field_value(field)?.into(),
+    })
+  }
+
   /// Разделяет плоский список с данными полей на массив, содержащий по порции данных на
   /// каждое поле. Вспомогательный массив `offsets` содержит смещения внутри массива с данными
   /// для каждого поля
@@ -389,6 +1160,18 @@ impl Gff {
   }
 }
 
+/// Строит дерево значений из структуры файла, см. [`Gff::to_value`]
+///
+/// [`Gff::to_value`]: struct.Gff.html#method.to_value
+impl TryFrom<&Gff> for Value {
+  type Error = crate::error::Error;
+
+  #[inline]
+  fn try_from(gff: &Gff) -> crate::error::Result<Value> {
+    gff.to_value()
+  }
+}
+
 impl fmt::Debug for Gff {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let data_offsets: Vec<_> = self.fields.iter()
@@ -411,6 +1194,478 @@ impl fmt::Debug for Gff {
       .field("field_data",    &Self::split_data(&self.field_data, &data_offsets))
       .field("field_indices", &Self::split_fields(&self.field_indices, &field_offsets))
       .field("list_indices",  &Self::split_lists(&self.list_indices))
+      .field("limits",        &self.limits)
       .finish()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+  use super::{Field, FieldType, Gff, Struct};
+
+  /// Проверяет, что преобразование файла в дерево значений и обратно не теряет данных
+  #[test]
+  fn value_round_trip() {
+    use std::convert::TryFrom;
+    use std::fs::File;
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    let gff = Gff::read(&mut File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF");
+    let value = Value::try_from(&gff).expect("can't convert GFF to Value");
+
+    let mut parser = Parser::new(File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF header");
+    assert_eq!(value, parser.read_all().expect("can't read GFF"));
+
+    let converted = Gff::from_value(gff.header.signature, &value).expect("can't convert Value to GFF");
+    assert_eq!(converted.header.signature, gff.header.signature);
+    assert_eq!(converted.to_value().expect("can't convert GFF to Value"), value);
+
+    let mut data = Vec::new();
+    converted.write(&mut data).expect("can't write GFF");
+    let reread = Gff::read(&mut Cursor::new(data)).expect("can't read GFF");
+    assert_eq!(reread.to_value().expect("can't convert GFF to Value"), value);
+
+    // Файл не содержит ссылок на ресурсы и локализуемых строк, поэтому проверяем их отдельно
+    let mut value = value;
+    if let Value::Struct { fields, .. } = &mut value {
+      fields.insert("resref".parse().unwrap(), Value::ResRef("nw_chicken".into()));
+    }
+    value.insert_loc("locstring", crate::GffString::External(crate::StrRef(42))).unwrap();
+    let converted = Gff::from_value(gff.header.signature, &value).expect("can't convert Value to GFF");
+    assert_eq!(converted.to_value().expect("can't convert GFF to Value"), value);
+  }
+
+  /// Читает файл и возвращает его структуру вместе с полным размером файла
+  fn read(data: &[u8]) -> (Gff, u64) {
+    let gff = Gff::read(&mut Cursor::new(data)).expect("can't read GFF");
+    (gff, data.len() as u64)
+  }
+
+  #[test]
+  fn no_gaps() {
+    let data = include_bytes!("../test-data/all.gff");
+    let (gff, len) = read(data);
+    assert_eq!(gff.gaps(len), vec![]);
+  }
+
+  #[test]
+  fn padding_and_trailing_data() {
+    let (mut gff, _) = read(include_bytes!("../test-data/all.gff"));
+    // Сдвигаем область с данными полей и все последующие на 4 байта
+    let padding = gff.header.field_data.offset as u64;
+    gff.header.field_data.offset    += 4;
+    gff.header.field_indices.offset += 4;
+    gff.header.list_indices.offset  += 4;
+
+    // Записанные подряд области дополняем выравниванием перед данными полей
+    let mut data = Vec::new();
+    gff.write(&mut data).unwrap();
+    data.splice(padding as usize..padding as usize, vec![0; 4]);
+    data.extend_from_slice(b"hidden");
+
+    let (gff, len) = read(&data);
+    assert_eq!(gff.gaps(len), vec![(padding, padding + 4), (len - 6, len)]);
+  }
+
+  #[test]
+  fn field_label_index() {
+    let (gff, _) = read(include_bytes!("../test-data/all.gff"));
+    for (i, field) in gff.fields.iter().enumerate() {
+      assert_eq!(gff.field_label_index(i), field.label);
+    }
+    let label = gff.labels[gff.field_label_index(0) as usize];
+    assert_eq!(gff.label_index(&label), Some(gff.field_label_index(0)));
+    assert_eq!(gff.label_index(&"not exist".parse().unwrap()), None);
+  }
+
+  #[test]
+  fn consistent() {
+    let (gff, _) = read(include_bytes!("../test-data/all.gff"));
+    gff.validate().expect("all.gff must be consistent");
+  }
+
+  /// Строит цепочку из `depth` вложенных друг в друга структур
+  fn nested(depth: usize) -> Gff {
+    use indexmap::IndexMap;
+    use crate::value::Value;
+
+    let mut value = Value::Dword(42);
+    for _ in 0..depth {
+      let mut fields = IndexMap::new();
+      fields.insert("inner".parse().unwrap(), value);
+      value = Value::Struct { tag: 0, fields };
+    }
+    Gff::from_value((*b"GFF ").into(), &value).expect("can't convert Value to GFF")
+  }
+
+  /// Проверяет, что слишком глубокая вложенность структур приводит к ошибке, а не к
+  /// переполнению стека при построении дерева значений
+  #[test]
+  fn depth_limit() {
+    use serde::Deserialize;
+    use crate::error::Error;
+    use crate::index::StructIndex;
+    use crate::parser::Limits;
+    use crate::value::Value;
+
+    let limit = Limits::default().max_depth;
+    nested(limit).to_value().expect("can't convert GFF to Value");
+    match nested(limit + 1).to_value() {
+      Err(Error::DepthLimitExceeded(max)) => assert_eq!(max, limit),
+      res => panic!("expected Error::DepthLimitExceeded, but {:?} found", res),
+    }
+
+    // Ограничение файла учитывается всеми способами его обработки, кроме обхода
+    let mut gff = nested(10);
+    gff.limits.max_depth = 9;
+    match gff.to_value() {
+      Err(Error::DepthLimitExceeded(9)) => {},
+      res => panic!("expected Error::DepthLimitExceeded(9), but {:?} found", res),
+    }
+    match Value::deserialize(&gff) {
+      Err(Error::DepthLimitExceeded(9)) => {},
+      res => panic!("expected Error::DepthLimitExceeded(9), but {:?} found", res),
+    }
+    match gff.deserialize_struct_at::<Value>(StructIndex(0)) {
+      Err(Error::DepthLimitExceeded(9)) => {},
+      res => panic!("expected Error::DepthLimitExceeded(9), but {:?} found", res),
+    }
+    let mut count = 0;
+    gff.walk(|_, _, _| count += 1).expect("can't walk GFF");
+    assert_eq!(count, 10);
+
+    gff.limits = Limits { max_depth: 10, ..Limits::default() };
+    assert_eq!(Value::deserialize(&gff).unwrap(), gff.to_value().unwrap());
+  }
+
+  #[test]
+  fn corrupted_field_indices() {
+    let (mut gff, _) = read(include_bytes!("../test-data/all.gff"));
+    let fields = gff.fields.len() as u32;
+    gff.field_indices[0] = fields;
+
+    let err = gff.validate().expect_err("corrupted field index must be found");
+    assert_eq!(err.to_string(), format!("Inconsistent GFF data: struct 0 references field {0}, but only {0} fields exist", fields));
+  }
+
+  #[test]
+  fn corrupted_label() {
+    let (mut gff, _) = read(include_bytes!("../test-data/all.gff"));
+    let labels = gff.labels.len() as u32;
+    gff.fields[1].label = labels;
+
+    let err = gff.validate().expect_err("corrupted label index must be found");
+    assert_eq!(err.to_string(), format!("Inconsistent GFF data: field 1 references label {0}, but only {0} labels exist", labels));
+  }
+
+  /// Проверяет, что количество записей из поврежденного заголовка не приводит к попытке
+  /// выделить под них память до чтения данных
+  #[test]
+  fn huge_section_count() {
+    use std::io::ErrorKind;
+    use crate::header::Header;
+
+    let mut header = Header::new((*b"GFF ").into());
+    header.structs.offset = Header::HEADER_SIZE;
+    header.structs.count  = u32::MAX;
+    let mut data = Vec::new();
+    header.write(&mut data).unwrap();
+    assert_eq!(data.len(), Header::HEADER_SIZE as usize);
+
+    match Gff::read(&mut Cursor::new(data)) {
+      Err(err) => assert_eq!(err.kind(), ErrorKind::UnexpectedEof),
+      Ok(gff) => panic!("expected error, but {:?} found", gff),
+    }
+  }
+
+  #[test]
+  fn corrupted_list() {
+    let (mut gff, _) = read(include_bytes!("../test-data/all.gff"));
+    let structs = gff.structs.len() as u32;
+    gff.list_indices[1] = structs;
+
+    assert!(gff.validate().is_err());
+  }
+
+  #[test]
+  fn resrefs() {
+    use serde::Serialize;
+    use crate::ResRef;
+    use crate::ser::to_vec;
+    use super::FieldType;
+
+    #[derive(Serialize)]
+    #[allow(non_snake_case)]
+    struct Script {
+      #[serde(with = "crate::resref")]
+      OnUsed: String,
+    }
+    #[derive(Serialize)]
+    #[allow(non_snake_case)]
+    struct Item {
+      #[serde(with = "crate::resref")]
+      InventoryRes: String,
+      Tag: String,
+    }
+    #[derive(Serialize)]
+    #[allow(non_snake_case)]
+    struct Blueprint {
+      #[serde(with = "crate::resref")]
+      TemplateResRef: String,
+      Scripts: Script,
+      ItemList: Vec<Item>,
+    }
+
+    let item = |resref: &str, tag: &str| Item { InventoryRes: resref.into(), Tag: tag.into() };
+    let value = Blueprint {
+      TemplateResRef: "bow".into(),
+      Scripts: Script { OnUsed: "axe".into() },
+      ItemList: vec![item("use", "first"), item("utp", "second")],
+    };
+    let data = to_vec((*b"UTP ").into(), &value).unwrap();
+    let (mut gff, _) = read(&data);
+
+    let resrefs: Vec<_> = gff.resrefs().into_iter()
+      .map(|(label, resref)| (label.to_string(), resref))
+      .collect();
+    let expected: Vec<(String, ResRef)> = vec![
+      ("TemplateResRef".into(), "bow".into()),
+      ("OnUsed".into(), "axe".into()),
+      ("InventoryRes".into(), "use".into()),
+      ("InventoryRes".into(), "utp".into()),
+    ];
+    assert_eq!(resrefs, expected);
+
+    // Поле с некорректным смещением данных пропускается
+    let field = gff.fields.iter_mut().find(|f| f.tag == FieldType::ResRef as u32).unwrap();
+    field.data = [0xFF; 4];
+    assert_eq!(gff.resrefs().len(), 3);
+  }
+
+  /// Проверяет, что обход посещает все структуры файла ровно один раз и не зацикливается
+  #[test]
+  fn walk() {
+    use byteorder::{ByteOrder, LE};
+    use crate::index::StructIndex;
+
+    let (mut gff, _) = read(include_bytes!("../test-data/all.gff"));
+    assert_eq!(gff.iter_structs().count(), gff.header.structs.count as usize);
+    assert!(gff.iter_structs().all(|(i, s)| std::ptr::eq(s, &gff.structs[i.0 as usize])));
+
+    let mut visited = Vec::new();
+    gff.walk(|index, _, depth| visited.push((index, depth))).expect("can't walk GFF");
+    assert_eq!(visited.len(), gff.header.structs.count as usize);
+    assert_eq!(visited[0], (StructIndex(0), 0));
+    assert!(visited[1..].iter().all(|&(_, depth)| depth > 0));
+
+    // Зацикливаем поле-структуру на корневую структуру
+    let field = gff.fields.iter_mut().find(|f| f.tag == FieldType::Struct as u32).unwrap();
+    LE::write_u32(&mut field.data, 0);
+    let mut count = 0;
+    gff.walk(|_, _, _| count += 1).expect("can't walk GFF");
+    assert!(count < gff.structs.len());
+
+    // Очень глубокая вложенность обходится без переполнения стека
+    let depth = 200_000;
+    let mut gff = nested(1);
+    gff.structs = (0..depth).map(|i| Struct { tag: 0, offset: i, fields: (i + 1 < depth) as u32 }).collect();
+    gff.fields = (1..depth).map(|i| Field { tag: FieldType::Struct as u32, label: 0, data: i.to_le_bytes() }).collect();
+    let mut max = 0;
+    gff.walk(|_, _, depth| max = max.max(depth)).expect("can't walk GFF");
+    assert_eq!(max, depth as usize - 1);
+  }
+
+  /// Проверяет извлечение отдельной вложенной структуры по ее индексу
+  #[test]
+  fn deserialize_struct_at() {
+    use std::fs::File;
+    use byteorder::{ByteOrder, LE};
+    use serde::Deserialize;
+    use crate::error::Error;
+    use crate::index::StructIndex;
+    use super::FieldType;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Struct { f32: f32, f64: f64 }
+
+    let gff = Gff::read(&mut File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF");
+    // Единственное поле-структура файла -- поле `Struct` корневой структуры
+    let field = gff.fields.iter().find(|f| f.tag == FieldType::Struct as u32).unwrap();
+    let index = StructIndex(LE::read_u32(&field.data));
+
+    let value: Struct = gff.deserialize_struct_at(index).expect("can't deserialize struct");
+    assert_eq!(value, Struct { f32: std::f32::consts::PI, f64: std::f64::consts::E });
+
+    match gff.deserialize_struct_at::<Struct>(StructIndex(gff.structs.len() as u32)) {
+      Err(Error::Inconsistent(_)) => {},
+      res => panic!("expected Error::Inconsistent, but {:?} found", res),
+    }
+
+    // Поврежденные данные вне извлекаемой структуры не проверяются
+    let mut gff = gff;
+    let (_, fields) = gff.struct_fields(index.0).unwrap();
+    let inner = fields[0] as usize;
+    let outer = (0..gff.fields.len()).find(|&i| !fields.contains(&(i as u32))).unwrap();
+    gff.fields[outer].label = u32::MAX;
+    assert_eq!(gff.deserialize_struct_at::<Struct>(index).unwrap(), value);
+
+    // Поврежденные данные внутри нее приводят к ошибке, а не к панике
+    gff.fields[inner].label = u32::MAX;
+    match gff.deserialize_struct_at::<Struct>(index) {
+      Err(Error::Inconsistent(_)) => {},
+      res => panic!("expected Error::Inconsistent, but {:?} found", res),
+    }
+  }
+
+  #[test]
+  fn field_type() {
+    use super::FieldType;
+
+    assert_eq!(FieldType::Byte.size(), 1);
+    assert_eq!(FieldType::Short.size(), 2);
+    assert_eq!(FieldType::Float.size(), 4);
+    assert_eq!(FieldType::Dword64.size(), 4);
+    assert_eq!(FieldType::List.size(), 4);
+    assert_eq!(FieldType::LocString.name(), "LocString");
+
+    let all: Vec<_> = FieldType::all().collect();
+    assert_eq!(all.len(), 16);
+    for (i, type_) in all.iter().enumerate() {
+      assert_eq!(*type_ as usize, i);
+      assert_eq!(type_.name(), format!("{:?}", type_));
+    }
+  }
+
+  #[test]
+  fn strings() {
+    use std::fs::File;
+    use encoding::{DecoderTrap, EncoderTrap};
+    use encoding::all::WINDOWS_1251;
+    use serde::Serialize;
+    use crate::error::Error;
+    use crate::ser::SerializerBuilder;
+
+    let gff = Gff::read(&mut File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF");
+    let strings = gff.strings().expect("can't read strings");
+    assert_eq!(strings, vec![("string".parse().unwrap(), "String".to_owned())]);
+
+    // Строки во вложенных структурах и в другой кодировке
+    #[derive(Serialize)]
+    struct Item { text: String }
+    #[derive(Serialize)]
+    struct Root { name: String, items: Vec<Item> }
+
+    let root = Root { name: "Имя".into(), items: vec![Item { text: "Строка".into() }] };
+    let data = SerializerBuilder::new((*b"GFF ").into())
+      .encoding(WINDOWS_1251, EncoderTrap::Strict)
+      .serialize(&root)
+      .unwrap();
+    let gff = Gff::read(&mut Cursor::new(data)).unwrap();
+
+    let strings = gff.strings_with_encoding(WINDOWS_1251, DecoderTrap::Strict).expect("can't read strings");
+    assert_eq!(strings, vec![
+      ("name".parse().unwrap(), "Имя".to_owned()),
+      ("text".parse().unwrap(), "Строка".to_owned()),
+    ]);
+    match gff.strings() {
+      Err(Error::Encoding(_)) => {},
+      res => panic!("expected Error::Encoding, but {:?} found", res),
+    }
+  }
+
+  /// Проверяет чтение значений отдельных полей
+  #[test]
+  fn field_value() {
+    use std::fs::File;
+    use crate::error::Error;
+    use crate::value::Value;
+
+    let gff = Gff::read(&mut File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF");
+    let value = gff.to_value().expect("can't convert GFF to Value");
+
+    for field in &gff.fields {
+      let type_ = gff.field_type(field).expect("field has unknown type");
+      let nested = type_ == FieldType::Struct || type_ == FieldType::List;
+      match gff.field_value(field) {
+        Ok(_) => assert!(!nested, "{:?} field has value", type_),
+        Err(Error::Deserialize(_)) => assert!(nested, "{:?} field has no value", type_),
+        Err(err) => panic!("can't read {:?} field: {}", type_, err),
+      }
+    }
+    // Значения полей корневой структуры совпадают со значениями в дереве
+    let root = &gff.structs[0];
+    let start = root.offset as usize / 4;
+    for &index in &gff.field_indices[start..start + root.fields as usize] {
+      let field = &gff.fields[index as usize];
+      if let Ok(simple) = gff.field_value(field) {
+        let label = gff.labels[field.label as usize].to_string();
+        assert_eq!(Some(&Value::from(simple)), value.get(&label), "field `{}`", label);
+      }
+    }
+
+    let unknown = Field { tag: 42, label: 0, data: [1, 0, 0, 0] };
+    assert_eq!(gff.field_type(&unknown), Err(Error::UnknownValue { tag: 42, value: 1 }));
+  }
+
+  /// Проверяет, что отредактированный файл записывается и читается обратно без потерь
+  #[test]
+  fn edit() {
+    use std::fs::File;
+    use crate::{Label, LocString, ResRef, StrRef};
+    use crate::error::Error;
+    use crate::index::StructIndex;
+    use crate::value::{SimpleValue, Value};
+
+    let mut gff = Gff::read(&mut File::open("test-data/all.gff").expect("test file not exist")).expect("can't read GFF");
+    let (tag, mut expected) = match gff.to_value().expect("can't convert GFF to Value") {
+      Value::Struct { tag, fields } => (tag, fields),
+      value => panic!("expected struct, but {:?} found", value),
+    };
+    let label = |name: &str| name.parse::<Label>().unwrap();
+    let root = StructIndex(0);
+
+    // Замена значения существующего поля корневой структуры
+    let first = gff.field_indices[gff.structs[0].offset as usize / 4];
+    gff.set_field_data(first.into(), &SimpleValue::String("replaced".into())).unwrap();
+    *expected.get_index_mut(0).unwrap().1 = Value::String("replaced".into());
+
+    // Добавление полей в структуру, поля которой находятся в середине списка индексов полей
+    gff.add_field(root, label("new_int"), &SimpleValue::Int(-42)).unwrap();
+    gff.add_field(root, label("new_loc"), &SimpleValue::LocString(LocString { str_ref: StrRef(7), strings: vec![] })).unwrap();
+    expected.insert(label("new_int"), Value::Int(-42));
+    expected.insert(label("new_loc"), Value::LocString(LocString { str_ref: StrRef(7), strings: vec![] }));
+
+    // Вложенная структура, в которую поля добавляются по одному
+    let child = gff.add_struct_field(root, label("child"), 1);
+    gff.add_field(child, label("a"), &SimpleValue::Byte(1)).unwrap();
+    gff.add_field(child, label("b"), &SimpleValue::Dword64(u64::MAX)).unwrap();
+    expected.insert(label("child"), Value::Struct { tag: 1, fields: vec![
+      (label("a"), Value::Byte(1)),
+      (label("b"), Value::Dword64(u64::MAX)),
+    ].into_iter().collect() });
+
+    // Список из пустой структуры и структуры с одним полем
+    let item1 = gff.add_struct(2);
+    let item2 = gff.add_struct(3);
+    gff.add_field(item2, label("new_int"), &SimpleValue::Int(5)).unwrap();
+    gff.add_list_field(root, label("new_list"), &[item1, item2]);
+    expected.insert(label("new_list"), vec![
+      Value::Struct { tag: 2, fields: Default::default() },
+      Value::Struct { tag: 3, fields: vec![(label("new_int"), Value::Int(5))].into_iter().collect() },
+    ].into_iter().collect());
+
+    let long = SimpleValue::ResRef(ResRef(vec![b'a'; 17]));
+    assert_eq!(gff.add_field(root, label("resref"), &long), Err(Error::TooLongResRef(17)));
+
+    let mut data = Vec::new();
+    gff.write(&mut data).expect("can't write GFF");
+    assert_eq!(data.len() as u64, gff.header.total_size());
+
+    let (gff, len) = read(&data);
+    assert_eq!(gff.validate(), Ok(()));
+    assert_eq!(gff.gaps(len), vec![]);
+    assert_eq!(gff.to_value().expect("can't convert GFF to Value"), Value::Struct { tag, fields: expected });
+  }
+}
@@ -1,15 +1,72 @@
 //! Содержит реализацию структуры, описывающей ссылку на ресурс и реализацию типажей для
-//! конвертации других типов данных в ссылку и обратно
+//! конвертации других типов данных в ссылку и обратно.
+//!
+//! Также содержит функции для использования в атрибуте `#[serde(with = "serde_gff::resref")]`
+//! на строковых полях, позволяющие записывать их в GFF файл как поля типа `ResRef`, а не `String`:
+//!
+//! ```rust
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! #[allow(non_snake_case)]
+//! struct Creature {
+//!   #[serde(with = "serde_gff::resref")]
+//!   TemplateResRef: String,
+//! }
+//! ```
 
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::str::{self, FromStr, Utf8Error};
 use std::string::FromUtf8Error;
+use serde::{Deserializer, Serializer};
+use serde::de::{self, Visitor};
+use serde::ser::{Error as _, Serialize};
+
+use crate::de::RESREF_TOKEN;
+use crate::error::Error;
+
+/// Максимальная длина ссылки на ресурс в байтах
+pub(crate) const MAX_LEN: usize = 16;
 
 /// Представляет ссылку на игровой ресурс, которым может быть шаблон объекта
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ResRef(pub(crate) Vec<u8>);
 
 impl ResRef {
+  /// Создает ссылку на ресурс из указанной строки, проверяя ее длину.
+  ///
+  /// # Ошибки
+  /// В случае, если строка занимает более 16 байт, возвращается ошибка
+  /// [`Error::TooLongResRef`](../error/enum.Error.html#variant.TooLongResRef)
+  pub fn new(value: &str) -> Result<Self, Error> {
+    if value.len() > MAX_LEN {
+      return Err(Error::TooLongResRef(value.len()));
+    }
+    Ok(value.into())
+  }
+  /// Создает ссылку на ресурс из указанной строки, приводя ее к нижнему регистру. Игра не
+  /// различает регистр символов в ссылках на ресурсы, поэтому такая ссылка указывает на тот же
+  /// ресурс, но может сравниваться с другими ссылками, созданными этим методом, напрямую.
+  ///
+  /// # Ошибки
+  /// В случае, если строка занимает более 16 байт, возвращается ошибка
+  /// [`Error::TooLongResRef`](../error/enum.Error.html#variant.TooLongResRef)
+  #[inline]
+  pub fn new_lowercase(value: &str) -> Result<Self, Error> {
+    Self::new(&value.to_lowercase())
+  }
+  /// Сравнивает ссылки на ресурсы без учета регистра ASCII символов, так же, как это делает игра
+  #[inline]
+  pub fn eq_ignore_case(&self, other: &ResRef) -> bool {
+    self.0.eq_ignore_ascii_case(&other.0)
+  }
+  /// Возвращает копию ссылки на ресурс, в которой все ASCII символы приведены к нижнему регистру.
+  /// В таком виде ссылки хранит игра
+  #[inline]
+  pub fn to_lowercase(&self) -> ResRef {
+    ResRef(self.0.to_ascii_lowercase())
+  }
   /// Возвращает представление данной ссылки на ресурс как строки, если она представлена в виде `UTF-8` строки
   #[inline]
   pub fn as_str(&self) -> Result<&str, Utf8Error> {
@@ -53,14 +110,173 @@ impl<'a> Into<&'a str> for &'a ResRef {
   }
 }
 
+/// Обертка над ссылкой на ресурс, сравнивающая и хеширующая ее без учета регистра ASCII символов.
+/// Предназначена для использования в качестве ключа карт, в которых ресурсы ищутся так же,
+/// как это делает игра
+#[derive(Clone, Debug)]
+pub struct ResRefCI(pub ResRef);
+
+impl PartialEq for ResRefCI {
+  #[inline]
+  fn eq(&self, other: &Self) -> bool {
+    self.0.eq_ignore_case(&other.0)
+  }
+}
+impl Eq for ResRefCI {}
+
+impl Hash for ResRefCI {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    state.write_usize(self.0 .0.len());
+    for b in &self.0 .0 {
+      state.write_u8(b.to_ascii_lowercase());
+    }
+  }
+}
+
+impl From<ResRef> for ResRefCI {
+  #[inline]
+  fn from(value: ResRef) -> Self { ResRefCI(value) }
+}
+
+/// Создает ссылку на ресурс без проверки длины. Для проверки используйте [`ResRef::new`]
+///
+/// [`ResRef::new`]: struct.ResRef.html#method.new
 impl<'a> From<&'a str> for ResRef {
   #[inline]
   fn from(str: &'a str) -> Self { ResRef(str.as_bytes().to_owned()) }
 }
 
+/// Создает ссылку на ресурс без проверки длины. Для проверки используйте [`ResRef::new`]
+///
+/// [`ResRef::new`]: struct.ResRef.html#method.new
 impl FromStr for ResRef {
   type Err = ();
 
   #[inline]
   fn from_str(str: &str) -> Result<Self, Self::Err> { Ok(str.into()) }
 }
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Содержимое ссылки на ресурс, сериализуемое как массив байт внутри newtype-структуры
+/// с именем [`RESREF_TOKEN`]
+///
+/// [`RESREF_TOKEN`]: ../de/constant.RESREF_TOKEN.html
+pub(crate) struct ResRefData<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Serialize for ResRefData<'a> {
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+  {
+    serializer.serialize_bytes(self.0)
+  }
+}
+
+/// Сериализует строку как ссылку на ресурс. GFF сериализатор записывает такое значение как
+/// поле типа `ResRef`, другие форматы получают обычный массив байт.
+///
+/// Длина ссылки на ресурс ограничена 16 байтами, для более длинных строк возвращается ошибка
+pub fn serialize<S>(value: &str, serializer: S) -> Result<S::Ok, S::Error>
+  where S: Serializer,
+{
+  if value.len() > MAX_LEN {
+    return Err(S::Error::custom(format!(
+      "ResRef `{}` has length {} bytes, but only {} bytes allowed", value, value.len(), MAX_LEN
+    )));
+  }
+  serializer.serialize_newtype_struct(RESREF_TOKEN, &ResRefData(value.as_bytes()))
+}
+
+/// Структура для чтения ссылки на ресурс в виде строки
+struct StringVisitor;
+
+impl<'de> Visitor<'de> for StringVisitor {
+  type Value = String;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a ResRef as string or byte buffer in UTF-8")
+  }
+
+  #[inline]
+  fn visit_str<E>(self, value: &str) -> Result<String, E>
+    where E: de::Error,
+  {
+    Ok(value.to_owned())
+  }
+  #[inline]
+  fn visit_string<E>(self, value: String) -> Result<String, E>
+    where E: de::Error,
+  {
+    Ok(value)
+  }
+  #[inline]
+  fn visit_bytes<E>(self, value: &[u8]) -> Result<String, E>
+    where E: de::Error,
+  {
+    str::from_utf8(value).map(Into::into).map_err(E::custom)
+  }
+  #[inline]
+  fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<String, E>
+    where E: de::Error,
+  {
+    String::from_utf8(value).map_err(E::custom)
+  }
+}
+
+/// Десериализует ссылку на ресурс в строку. GFF десериализатор принимает только поля
+/// типа `ResRef` и `Void`, содержимое которых должно быть корректной `UTF-8` строкой
+#[inline]
+pub fn deserialize<'de, D>(deserializer: D) -> Result<String, D::Error>
+  where D: Deserializer<'de>,
+{
+  deserializer.deserialize_byte_buf(StringVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::error::Error;
+  use super::{ResRef, ResRefCI};
+
+  #[test]
+  fn new() {
+    assert_eq!(ResRef::new("nw_chicken").unwrap(), "nw_chicken".into());
+    assert_eq!(ResRef::new("exact_16_chars__").unwrap(), "exact_16_chars__".into());
+    match ResRef::new("more_then_16_char") {
+      Err(Error::TooLongResRef(17)) => {},
+      res => panic!("expected Error::TooLongResRef(17), but {:?} found", res),
+    }
+  }
+
+  #[test]
+  fn new_lowercase() {
+    assert_eq!(ResRef::new_lowercase("NW_Chicken").unwrap(), "nw_chicken".into());
+    assert!(ResRef::new_lowercase("MORE_THEN_16_CHAR").is_err());
+  }
+
+  #[test]
+  fn ignore_case() {
+    let mixed: ResRef = "NW_Chicken".into();
+    let lower: ResRef = "nw_chicken".into();
+
+    assert_ne!(mixed, lower);
+    assert!(mixed.eq_ignore_case(&lower));
+    assert!(lower.eq_ignore_case(&mixed));
+    assert!(!mixed.eq_ignore_case(&"nw_chicken2".into()));
+    assert_eq!(mixed.to_lowercase(), lower);
+  }
+
+  #[test]
+  fn case_insensitive_key() {
+    use std::collections::HashMap;
+
+    let mut map = HashMap::new();
+    map.insert(ResRefCI("NW_Chicken".into()), 1);
+    assert_eq!(map.get(&ResRefCI("nw_chicken".into())), Some(&1));
+    assert_eq!(map.get(&ResRefCI("nw_chicken2".into())), None);
+
+    map.insert(ResRefCI("nw_CHICKEN".into()), 2);
+    assert_eq!(map.len(), 1);
+    assert_eq!(map[&ResRefCI("Nw_Chicken".into())], 2);
+  }
+}
@@ -1,21 +1,38 @@
 //! Сериализатор для формата Bioware GFF (Generic File Format)
 
-use std::io::Write;
-use byteorder::{LE, WriteBytesExt};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use byteorder::{ByteOrder, LE, WriteBytesExt};
+use encoding::{EncoderTrap, EncodingRef};
+use encoding::all::UTF_8;
 use indexmap::IndexSet;
 use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeSeq,
                  SerializeStruct, SerializeTuple, SerializeTupleStruct,
                  SerializeTupleVariant, SerializeStructVariant};
 
 use crate::Label;
+use crate::de::{RESREF_TOKEN, TAG_FIELD};
 use crate::error::{Error, Result};
 use crate::header::{Header, Section, Signature, Version};
 use crate::index::LabelIndex;
-use crate::value::SimpleValueRef;
+use crate::parser::Limits;
+use crate::resref::MAX_LEN;
+use crate::value::{SimpleValueRef, Value};
 use crate::raw::{self, FieldType};
 
 mod value;
 
+pub(crate) use self::value::{LocStringData, ValueSerializer, parse_loc_string};
+
+/// Имя newtype-структуры, используемое для передачи сериализатору уже закодированных данных
+/// локализуемой строки. Встретив newtype-структуру с таким именем, сериализатор поля записывает
+/// ее содержимое в виде поля типа `LocString`, а не `Void`
+pub(crate) const LOC_STRING_TOKEN: &str = "$serde_gff::private::LocString";
+
 /// Вспомогательная структура, описывающая индекс структуры, для типобезопасности
 #[derive(Debug, Copy, Clone)]
 struct StructIndex(usize);
@@ -32,15 +49,18 @@ struct ListIndex(usize);
 
 /// Промежуточное представление сериализуемых структур. Содержит данные, которые после
 /// небольшого преобразования, возможного только после окончания сериализации, могут
-/// быть записаны в файл
+/// быть записаны в файл. Каждый вариант хранит идентификатор типа структуры, задаваемый
+/// полем [`TAG_FIELD`]
+///
+/// [`TAG_FIELD`]: ../de/constant.TAG_FIELD.html
 #[derive(Debug)]
 enum Struct {
   /// Структура без полей
-  NoFields,
+  NoFields { tag: u32 },
   /// Структура, состоящая только из одного поля, содержит индекс этого поля
-  OneField(usize),
+  OneField { tag: u32, field: usize },
   /// Структура, состоящая из двух и более полей. Содержит индекс списка и количество полей
-  MultiField { list: FieldListIndex, fields: u32 }
+  MultiField { tag: u32, list: FieldListIndex, fields: u32 }
 }
 impl Struct {
   /// Преобразует промежуточное представление в окончательное, которое может быть записано в файл
@@ -49,9 +69,9 @@ impl Struct {
     use self::Struct::*;
 
     match *self {
-      NoFields                    => raw::Struct { tag: 0, offset: 0,               fields: 0 },
-      OneField(index)             => raw::Struct { tag: 0, offset: index as u32,    fields: 1 },
-      MultiField { list, fields } => raw::Struct { tag: 0, offset: offsets[list.0], fields },
+      NoFields { tag }                 => raw::Struct { tag, offset: 0,               fields: 0 },
+      OneField { tag, field }          => raw::Struct { tag, offset: field as u32,    fields: 1 },
+      MultiField { tag, list, fields } => raw::Struct { tag, offset: offsets[list.0], fields },
     }
   }
 }
@@ -95,7 +115,12 @@ impl Field {
 }
 
 impl SimpleValueRef {
-  /// Конвертирует возможно ссылочное значение в структуру, которая может быть записана в файл
+  /// Конвертирует возможно ссылочное значение в структуру, которая может быть записана в файл.
+  ///
+  /// Данные комплексных значений (64-битных чисел, строк, `ResRef`, `LocString` и `Void`) к
+  /// этому моменту уже записаны в область данных полей методом `add_field_data` при
+  /// сериализации самого значения, поэтому для них в поле записывается только смещение этих
+  /// данных, хранящееся в индексе
   ///
   /// # Параметры
   /// - `label`: индекс метки для поля
@@ -127,6 +152,83 @@ impl SimpleValueRef {
   }
 }
 
+/// Способ записи отсутствующего значения (`None`) в поле структуры или значение карты
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoneRepresentation {
+  /// Поле не записывается вовсе. При десериализации отсутствующее поле типа `Option<T>`
+  /// становится `None`
+  Absent,
+  /// Поле записывается в виде строки (поля типа `String`) нулевой длины. Так как тип значения,
+  /// которое могло бы быть внутри `Option`, неизвестен, используется именно строка. Такое
+  /// представление требуется для движков, ожидающих, что поле в структуре присутствует всегда
+  Empty,
+}
+impl Default for NoneRepresentation {
+  #[inline]
+  fn default() -> Self { NoneRepresentation::Absent }
+}
+
+/// Способ дополнения файла нулевыми байтами после последней секции. Смещения секций при
+/// этом не меняются, добавленные байты не принадлежат ни одной из них
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+  /// Файл не дополняется, его размер равен суммарному размеру заголовка и всех секций
+  None,
+  /// Размер файла дополняется до кратного указанному числу байт, например, `Align(4)`
+  /// выравнивает его на границу 4 байт. Значения `0` и `1` означают отсутствие дополнения
+  Align(u32),
+  /// Файл дополняется до указанного размера. Если данные файла в него не помещаются,
+  /// запись завершается ошибкой
+  Size(u64),
+}
+impl Default for Padding {
+  #[inline]
+  fn default() -> Self { Padding::None }
+}
+
+/// Порядок расположения данных полей в области данных полей файла
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldDataOrder {
+  /// Данные располагаются в том порядке, в котором сериализовались поля
+  Serialization,
+  /// Данные упорядочиваются по их содержимому, а ссылки полей на данные корректируются.
+  /// Область данных полей двух документов с одинаковым содержимым в этом режиме совпадает
+  /// независимо от порядка сериализации полей, что упрощает сравнение файлов
+  Canonical,
+}
+impl Default for FieldDataOrder {
+  #[inline]
+  fn default() -> Self { FieldDataOrder::Serialization }
+}
+
+/// Кодировка, используемая для записи строк (полей типа `String`), вместе со способом
+/// обработки символов, которые в ней не представимы
+#[derive(Clone, Copy)]
+struct StringEncoding {
+  /// Кодировка для записи символов строк
+  encoding: EncodingRef,
+  /// Способ обработки символов, которые не удалось закодировать
+  trap: EncoderTrap,
+}
+impl StringEncoding {
+  /// Кодирует строку в соответствии с настройками
+  #[inline]
+  fn encode(&self, value: &str) -> Result<Vec<u8>> {
+    Ok(self.encoding.encode(value, self.trap)?)
+  }
+}
+impl Default for StringEncoding {
+  #[inline]
+  fn default() -> Self {
+    StringEncoding { encoding: UTF_8, trap: EncoderTrap::Strict }
+  }
+}
+impl fmt::Debug for StringEncoding {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "StringEncoding {{ encoding: {:?} }}", self.encoding.name())
+  }
+}
+
 /// Структура для сериализации значения Rust в Bioware GFF.
 ///
 /// Формат поддерживает непосредственную сериализацию только структур, перечислений и отображений.
@@ -142,6 +244,10 @@ pub struct Serializer {
   labels: IndexSet<Label>,
   /// Массив, содержащий данные комплексных полей
   field_data: Vec<u8>,
+  /// Уже записанные данные комплексных полей, сгруппированные по хешу типа поля и данных.
+  /// Для каждого хеша хранятся тип поля, смещение и размер данных в массиве `field_data`.
+  /// Позволяет не записывать одинаковые данные повторно, не храня их копию
+  data_offsets: HashMap<u64, Vec<(FieldType, u32, u32)>>,
   /// Массив списков с индексами полей структур. Каждый элемент массива описывает набор
   /// полей одной структуры, которая содержит более одного поля
   field_indices: Vec<Vec<u32>>,
@@ -149,9 +255,69 @@ pub struct Serializer {
   /// массива описывает набор структур, содержащихся в списке. Общее количество полей-списков
   /// равно размеру массива.
   list_indices: Vec<Vec<u32>>,
+  /// Способ записи отсутствующих значений в полях структур
+  none: NoneRepresentation,
+  /// Способ дополнения файла после последней секции
+  padding: Padding,
+  /// Порядок расположения данных полей при записи
+  data_order: FieldDataOrder,
+  /// Кодировка, в которой записываются строки
+  encoding: StringEncoding,
 }
 
 impl Serializer {
+  /// Создает сериализатор, записывающий строки в указанной кодировке. Аналог
+  /// [`Deserializer::with_encoding`] для записи. См. [`set_encoding`]
+  ///
+  /// [`Deserializer::with_encoding`]: ../de/struct.Deserializer.html#method.with_encoding
+  /// [`set_encoding`]: #method.set_encoding
+  #[inline]
+  pub fn with_encoding(encoding: EncodingRef, trap: EncoderTrap) -> Self {
+    let mut ser = Self::default();
+    ser.set_encoding(encoding, trap);
+    ser
+  }
+  /// Устанавливает способ записи отсутствующих значений (`None`) в полях структур и значениях
+  /// карт. По умолчанию такие поля не записываются ([`NoneRepresentation::Absent`]).
+  ///
+  /// Отсутствующее значение на верхнем уровне и в элементах списков всегда записывается как
+  /// структура без полей, так как в этих местах GFF не допускает ничего, кроме структур.
+  ///
+  /// [`NoneRepresentation::Absent`]: enum.NoneRepresentation.html#variant.Absent
+  #[inline]
+  pub fn set_none_representation(&mut self, none: NoneRepresentation) {
+    self.none = none;
+  }
+  /// Устанавливает способ дополнения записываемого файла нулевыми байтами, что требуется
+  /// для инструментов, проверяющих выравнивание или точный размер файла. По умолчанию файл
+  /// не дополняется ([`Padding::None`]).
+  ///
+  /// [`Padding::None`]: enum.Padding.html#variant.None
+  #[inline]
+  pub fn set_padding(&mut self, padding: Padding) {
+    self.padding = padding;
+  }
+  /// Устанавливает порядок расположения данных полей в записываемом файле. По умолчанию
+  /// данные располагаются в порядке сериализации полей ([`FieldDataOrder::Serialization`]).
+  ///
+  /// [`FieldDataOrder::Serialization`]: enum.FieldDataOrder.html#variant.Serialization
+  #[inline]
+  pub fn set_field_data_order(&mut self, order: FieldDataOrder) {
+    self.data_order = order;
+  }
+  /// Устанавливает кодировку, в которой записываются строки (поля типа `String`).
+  /// По умолчанию строки записываются в `UTF-8`, а невозможность закодировать символ
+  /// является ошибкой.
+  ///
+  /// Метки полей и части локализуемых строк записываются без учета этой настройки
+  ///
+  /// # Параметры
+  /// - `encoding`: Кодировка для записи символов строк
+  /// - `trap`: Способ обработки символов, которые не удалось закодировать в выбранной кодировке
+  #[inline]
+  pub fn set_encoding(&mut self, encoding: EncodingRef, trap: EncoderTrap) {
+    self.encoding = StringEncoding { encoding, trap };
+  }
   /// Добавляет в список известных названий полей для сериализации указанное и возвращает
   /// его индекс в этом списке. Если такое поле уже имеется в индексе, не добавляет его
   /// повторно.
@@ -159,11 +325,37 @@ impl Serializer {
   /// В случае, если метка содержит более 16 байт в UTF-8 представлении, метод завершается
   /// с ошибкой.
   fn add_label(&mut self, label: &str) -> Result<LabelIndex> {
-    let label = label.parse()?;
-    self.labels.insert(label);
-    // Мы только что вставили значение, ошибка может быть только в случае переполнения, что вряд ли случится
-    let (index, _) = self.labels.get_full(&label).unwrap();
-    Ok(LabelIndex(index as u32))
+    Ok(self.insert_label(label.parse()?))
+  }
+  /// Добавляет в область данных полей данные комплексного поля указанного типа и возвращает
+  /// их смещение. Если точно такие же данные поля того же типа уже были записаны, повторно
+  /// они не добавляются, а возвращается смещение уже записанных
+  fn add_field_data(&mut self, tag: FieldType, data: &[u8]) -> u32 {
+    let field_data = &mut self.field_data;
+    let written = self.data_offsets.entry(Self::data_hash(tag, data)).or_default();
+    // При совпадении хешей сравниваем сами данные, так как хеши разных данных могут совпасть
+    let found = written.iter().find(|&&(t, offset, len)| {
+      t == tag && field_data[offset as usize..][..len as usize] == *data
+    });
+    if let Some(&(_, offset, _)) = found {
+      return offset;
+    }
+    let offset = field_data.len() as u32;
+    field_data.extend_from_slice(data);
+    written.push((tag, offset, data.len() as u32));
+    offset
+  }
+  /// Вычисляет хеш данных комплексного поля указанного типа для поиска в `data_offsets`
+  fn data_hash(tag: FieldType, data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tag.hash(&mut hasher);
+    data.hash(&mut hasher);
+    hasher.finish()
+  }
+  /// Добавляет в список меток уже сконструированную метку и возвращает ее индекс
+  fn insert_label(&mut self, label: Label) -> LabelIndex {
+    let (index, _) = self.labels.insert_full(label);
+    LabelIndex(index as u32)
   }
   /// Добавляет в список структур новую структуру с указанным количеством полей.
   /// Корректная ссылка на данные еще не заполнена, ее нужно будет скорректировать
@@ -179,12 +371,12 @@ impl Serializer {
     let list  = FieldListIndex(self.field_indices.len());
 
     match fields {
-      0 => self.structs.push(Struct::NoFields),
+      0 => self.structs.push(Struct::NoFields { tag: 0 }),
       // Для структуры с одним полем записываем placeholder, он будет перезаписан после записи поля
-      1 => self.structs.push(Struct::OneField(0)),
+      1 => self.structs.push(Struct::OneField { tag: 0, field: 0 }),
       _ => {
         self.field_indices.push(Vec::with_capacity(fields));
-        self.structs.push(Struct::MultiField { list, fields: fields as u32 })
+        self.structs.push(Struct::MultiField { tag: 0, list, fields: fields as u32 })
       }
     }
     (index, list)
@@ -205,8 +397,8 @@ impl Serializer {
   }
   fn add_tuple_variant(&mut self, struct_index: StructIndex, variant: &'static str, len: usize) -> Result<ListSerializer> {
     // Обновляем ссылки из записи о структуре
-    if let Struct::OneField(ref mut index) = self.structs[struct_index.0] {
-      *index = self.fields.len();
+    if let Struct::OneField { ref mut field, .. } = self.structs[struct_index.0] {
+      *field = self.fields.len();
     }
     // Добавляем запись о метке
     let label = self.add_label(variant)?;
@@ -216,8 +408,8 @@ impl Serializer {
   }
   fn add_struct_variant(&mut self, struct_index: StructIndex, variant: &'static str, len: usize) -> Result<StructSerializer> {
     // Обновляем ссылки из записи о структуре
-    if let Struct::OneField(ref mut index) = self.structs[struct_index.0] {
-      *index = self.fields.len();
+    if let Struct::OneField { ref mut field, .. } = self.structs[struct_index.0] {
+      *field = self.fields.len();
     }
 
     let (struct_index, fields_index) = self.add_struct(len);
@@ -236,8 +428,7 @@ impl Serializer {
     impl Builder {
       #[inline]
       fn new() -> Self {
-        // Версия, сигнатура и 6 секций
-        Builder { offset: 4 + 4 + 8 * 6 }
+        Builder { offset: Header::HEADER_SIZE }
       }
       #[inline]
       fn add_section(&mut self, count: usize, size: u32) -> Section {
@@ -270,14 +461,22 @@ impl Serializer {
       list_indices:  builder.lists(&self.list_indices),
     }
   }
-  /// Записывает в поток все собранные данные
+  /// Записывает в поток все собранные данные, дополняя их в соответствии с настройкой
+  /// [`set_padding`]
+  ///
+  /// [`set_padding`]: #method.set_padding
   pub fn write<W: Write>(&self, writer: &mut W, signature: Signature, version: Version) -> Result<()> {
-    self.make_header(signature, version).write(writer)?;
+    let header = self.make_header(signature, version);
+    let padding = self.padding_size(&header)?;
+    header.write(writer)?;
 
     self.write_structs(writer)?;
-    self.write_fields(writer)?;
+    let (fields, field_data) = self.raw_fields()?;
+    for e in fields.iter() {
+      e.write(writer)?;
+    }
     self.write_labels(writer)?;
-    writer.write_all(&self.field_data)?;
+    writer.write_all(&field_data)?;
     for ref list in &self.field_indices {
       self.write_indices(writer, list)?;
     }
@@ -285,8 +484,47 @@ impl Serializer {
       writer.write_u32::<LE>(list.len() as u32)?;
       self.write_indices(writer, list)?;
     }
+    io::copy(&mut io::repeat(0).take(padding), writer)?;
     Ok(())
   }
+  /// Преобразует все собранные данные в структуру файла в памяти, без записи в поток
+  pub(crate) fn to_gff(&self, signature: Signature, version: Version) -> Result<raw::Gff> {
+    let offsets = self.calc_field_offsets();
+    let structs = self.structs.iter().map(|e| e.into_raw(&offsets)).collect();
+    let (fields, field_data) = self.raw_fields()?;
+
+    let mut list_indices = Vec::new();
+    for list in &self.list_indices {
+      list_indices.push(list.len() as u32);
+      list_indices.extend_from_slice(list);
+    }
+    Ok(raw::Gff {
+      header:        self.make_header(signature, version),
+      structs,
+      fields,
+      labels:        self.labels.iter().cloned().collect(),
+      field_data:    field_data.into_owned(),
+      field_indices: self.field_indices.concat(),
+      list_indices,
+      limits:        Limits::default(),
+    })
+  }
+  /// Вычисляет количество нулевых байт, которые нужно записать после последней секции файла
+  fn padding_size(&self, header: &Header) -> Result<u64> {
+    // Секция списков структур записывается последней
+    let len = header.list_indices.offset as u64 + header.list_indices.count as u64;
+    Ok(match self.padding {
+      Padding::Align(align) if align > 1 => {
+        let align = align as u64;
+        (align - len % align) % align
+      },
+      Padding::None | Padding::Align(_) => 0,
+      Padding::Size(size) if size >= len => size - len,
+      Padding::Size(size) => return Err(Error::Serialize(format!(
+        "GFF data occupies {} bytes and can't be padded to {} bytes", len, size
+      ))),
+    })
+  }
   /// Записывает в поток информацию о структурах файла
   #[inline]
   fn write_structs<W: Write>(&self, writer: &mut W) -> Result<()> {
@@ -296,14 +534,44 @@ impl Serializer {
     }
     Ok(())
   }
-  /// Записывает в поток информацию о полях файла
-  #[inline]
-  fn write_fields<W: Write>(&self, writer: &mut W) -> Result<()> {
+  /// Формирует окончательное представление полей файла и область данных полей, упорядочивая
+  /// данные в соответствии с настройкой [`set_field_data_order`]
+  ///
+  /// [`set_field_data_order`]: #method.set_field_data_order
+  fn raw_fields(&self) -> Result<(Vec<raw::Field>, Cow<'_, [u8]>)> {
     let offsets = self.calc_list_offsets();
-    for e in self.fields.iter() {
-      e.into_raw(&offsets)?.write(writer)?;
+    let mut fields = self.fields.iter().map(|e| e.into_raw(&offsets)).collect::<Result<Vec<_>>>()?;
+
+    if self.data_order == FieldDataOrder::Serialization {
+      return Ok((fields, Cow::Borrowed(&self.field_data)));
     }
-    Ok(())
+    let is_complex = |f: &raw::Field| FieldType::from_u32(f.tag).is_some_and(|t| t.is_complex());
+
+    // Данные полей записываются друг за другом, поэтому данные каждого поля продолжаются
+    // до начала данных следующего
+    let mut starts: Vec<_> = fields.iter()
+      .filter(|f| is_complex(f))
+      .map(|f| LE::read_u32(&f.data) as usize)
+      .collect();
+    starts.sort();
+    starts.dedup();
+    let mut chunks: Vec<_> = starts.iter().enumerate().map(|(i, &start)| {
+      let end = starts.get(i + 1).cloned().unwrap_or(self.field_data.len());
+      (start, &self.field_data[start..end])
+    }).collect();
+    chunks.sort_by_key(|&(_, chunk)| chunk);
+
+    let mut data = Vec::with_capacity(self.field_data.len());
+    let mut remap = HashMap::with_capacity(chunks.len());
+    for (start, chunk) in chunks {
+      remap.insert(start as u32, data.len() as u32);
+      data.extend_from_slice(chunk);
+    }
+    for field in fields.iter_mut().filter(|f| is_complex(f)) {
+      let offset = remap[&LE::read_u32(&field.data)];
+      LE::write_u32(&mut field.data, offset);
+    }
+    Ok((fields, Cow::Owned(data)))
   }
   /// Записывает в поток информацию о метках файла
   #[inline]
@@ -344,6 +612,90 @@ impl Serializer {
   }
 }
 
+/// Построитель сериализатора, позволяющий в одном месте задать тип и версию записываемого
+/// файла и кодировку строк.
+///
+/// # Пример
+/// ```rust
+/// use encoding::EncoderTrap;
+/// use encoding::all::WINDOWS_1251;
+/// use serde::Serialize;
+/// use serde_gff::header::Version;
+/// use serde_gff::ser::SerializerBuilder;
+///
+/// #[derive(Serialize)]
+/// struct Item { name: String }
+///
+/// let data = SerializerBuilder::new((*b"UTI ").into())
+///   .version(Version::V3_2)
+///   .encoding(WINDOWS_1251, EncoderTrap::Strict)
+///   .serialize(&Item { name: "Меч".into() })
+///   .expect("can't write data");
+/// assert_eq!(&data[..8], b"UTI V3.2");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SerializerBuilder {
+  /// Тип записываемого файла
+  signature: Signature,
+  /// Версия записываемого файла
+  version: Version,
+  /// Кодировка, в которой записываются строки
+  encoding: StringEncoding,
+}
+impl SerializerBuilder {
+  /// Создает построитель для записи файла указанного типа версии `V3.2` со строками в `UTF-8`
+  #[inline]
+  pub fn new(signature: Signature) -> Self {
+    SerializerBuilder { signature, version: Version::V3_2, encoding: StringEncoding::default() }
+  }
+  /// Устанавливает тип записываемого файла
+  #[inline]
+  pub fn signature(mut self, signature: Signature) -> Self {
+    self.signature = signature;
+    self
+  }
+  /// Устанавливает версию записываемого файла
+  #[inline]
+  pub fn version(mut self, version: Version) -> Self {
+    self.version = version;
+    self
+  }
+  /// Устанавливает кодировку строк. См. [`Serializer::set_encoding`]
+  ///
+  /// [`Serializer::set_encoding`]: struct.Serializer.html#method.set_encoding
+  #[inline]
+  pub fn encoding(mut self, encoding: EncodingRef, trap: EncoderTrap) -> Self {
+    self.encoding = StringEncoding { encoding, trap };
+    self
+  }
+  /// Создает сериализатор с настроенной кодировкой строк. Тип и версию файла необходимо
+  /// передать в метод [`Serializer::write`] после сериализации значения
+  ///
+  /// [`Serializer::write`]: struct.Serializer.html#method.write
+  pub fn build(&self) -> Serializer {
+    Serializer::with_encoding(self.encoding.encoding, self.encoding.trap)
+  }
+  /// Сериализует значение в поток с настроенными типом, версией и кодировкой.
+  /// Значение должно являться Rust структурой или перечислением
+  pub fn serialize_to<W, T>(&self, writer: &mut W, value: &T) -> Result<()>
+    where W: Write,
+          T: Serialize + ?Sized,
+  {
+    let mut s = self.build();
+    value.serialize(&mut s)?;
+    s.write(writer, self.signature, self.version)
+  }
+  /// Сериализует значение в массив с настроенными типом, версией и кодировкой.
+  /// Значение должно являться Rust структурой или перечислением
+  pub fn serialize<T>(&self, value: &T) -> Result<Vec<u8>>
+    where T: Serialize + ?Sized,
+  {
+    let mut vec = Vec::new();
+    self.serialize_to(&mut vec, value)?;
+    Ok(vec)
+  }
+}
+
 /// Сериализует значение в произвольный поток. Значение должно являться Rust структурой или перечислением
 #[inline]
 pub fn to_writer<W, T>(writer: &mut W, signature: Signature, value: &T) -> Result<()>
@@ -458,19 +810,17 @@ impl<'a> ser::Serializer for &'a mut Serializer {
   unsupported!(serialize_seq(Option<usize>) -> Self::SerializeSeq);
   fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
     let ser = self.serialize_struct("", len.unwrap_or(0))?;
-    Ok(MapSerializer { ser, label: None })
+    Ok(MapSerializer { labels: ser.ser.labels.len(), ser, label: None, tag: false })
   }
   //-----------------------------------------------------------------------------------------------
   // Сериализация компонентов перечисления
   //-----------------------------------------------------------------------------------------------
-  /// Всегда возвращает ошибку сериализации `Error::Serialize`, т.к. unit-варианты перечисления
-  /// должны сериализоваться, как строки `variant`, а сериализация строк на верхнем уровне невозможна
-  #[inline]
-  fn serialize_unit_variant(self, name: &'static str, index: u32, variant: &'static str) -> Result<Self::Ok> {
-    Err(Error::Serialize(format!(
-      "`serialize_unit_variant(name: {}, index: {}, variant: {})` can't be implemented in GFF format. Wrap value to the struct and serialize struct",
-      name, index, variant
-    )))
+  /// Сериализует unit-вариант как структуру с одним строковым полем с именем `variant`
+  /// и значением `variant`, т.к. сериализация строк на верхнем уровне невозможна
+  fn serialize_unit_variant(self, name: &'static str, _index: u32, variant: &'static str) -> Result<Self::Ok> {
+    let mut ser = self.serialize_struct(name, 1)?;
+    SerializeStruct::serialize_field(&mut ser, variant, variant)?;
+    SerializeStruct::end(ser)
   }
   /// Сериализует `value` как структуру с одним полем с именем `variant` и значением `value`
   fn serialize_newtype_variant<T>(self, name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<Self::Ok>
@@ -520,6 +870,25 @@ impl<'a> FieldSerializer<'a> {
     });
     Ok((struct_index, fields_index))
   }
+  /// Записывает в область данных полей 1 байт длины и байты ссылки на ресурс, добавляя
+  /// поле типа `ResRef`
+  fn add_resref(self, data: &[u8]) -> Result<()> {
+    if data.len() > MAX_LEN {
+      return Err(Error::Serialize(format!(
+        "ResRef has length {} bytes, but only {} bytes allowed", data.len(), MAX_LEN
+      )));
+    }
+    let mut bytes = Vec::with_capacity(data.len() + 1);
+    bytes.write_u8(data.len() as u8)?;
+    bytes.write_all(data)?;
+    let offset = self.ser.add_field_data(FieldType::ResRef, &bytes);
+
+    self.ser.fields.push(Field::Simple {
+      label: self.label,
+      value: SimpleValueRef::ResRef(offset.into())
+    });
+    Ok(())
+  }
 }
 /// Записывает значения поля, чей размер не превышает 4 байта
 macro_rules! primitive {
@@ -539,9 +908,10 @@ macro_rules! complex {
   ($ser_method:ident, $type:ty, $tag:ident, $write_method:ident) => (
     #[inline]
     fn $ser_method(self, v: $type) -> Result<Self::Ok> {
-      let offset = self.ser.field_data.len() as u32;
       // Записываем данные поля в сторонке
-      self.ser.field_data.$write_method::<LE>(v)?;
+      let mut data = Vec::with_capacity(8);
+      data.$write_method::<LE>(v)?;
+      let offset = self.ser.add_field_data(FieldType::$tag, &data);
 
       // Добавляем само поле
       self.ser.fields.push(Field::Simple {
@@ -554,10 +924,11 @@ macro_rules! complex {
   ($ser_method:ident, $type:ty, $tag:ident) => (
     #[inline]
     fn $ser_method(self, v: $type) -> Result<Self::Ok> {
-      let offset = self.ser.field_data.len() as u32;
       // Записываем данные поля в сторонке
-      self.ser.field_data.write_u32::<LE>(v.len() as u32)?;
-      self.ser.field_data.write_all(v.as_ref())?;
+      let mut data = Vec::with_capacity(v.len() + 4);
+      data.write_u32::<LE>(v.len() as u32)?;
+      data.write_all(v.as_ref())?;
+      let offset = self.ser.add_field_data(FieldType::$tag, &data);
 
       // Добавляем само поле
       self.ser.fields.push(Field::Simple {
@@ -606,12 +977,32 @@ impl<'a> ser::Serializer for FieldSerializer<'a> {
     self.serialize_str(v.encode_utf8(&mut data))
   }
 
-  complex!(serialize_str  , &str , String);
+  fn serialize_str(self, v: &str) -> Result<Self::Ok> {
+    let string = self.ser.encoding.encode(v)?;
+
+    let mut data = Vec::with_capacity(string.len() + 4);
+    data.write_u32::<LE>(string.len() as u32)?;
+    data.write_all(&string)?;
+    let offset = self.ser.add_field_data(FieldType::String, &data);
+
+    self.ser.fields.push(Field::Simple {
+      label: self.label,
+      value: SimpleValueRef::String(offset.into())
+    });
+    Ok(())
+  }
   complex!(serialize_bytes, &[u8], Void);
 
+  /// В зависимости от настройки [`Serializer::set_none_representation`] либо не записывает
+  /// поле вовсе, либо записывает его как пустую строку
+  ///
+  /// [`Serializer::set_none_representation`]: struct.Serializer.html#method.set_none_representation
   #[inline]
   fn serialize_none(self) -> Result<Self::Ok> {
-    self.serialize_unit()
+    match self.ser.none {
+      NoneRepresentation::Absent => Ok(()),
+      NoneRepresentation::Empty  => self.serialize_str(""),
+    }
   }
   #[inline]
   fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
@@ -631,10 +1022,25 @@ impl<'a> ser::Serializer for FieldSerializer<'a> {
   fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
     self.serialize_unit()
   }
+  /// Разбирает в newtype структуру нижележащее значение. Если имя структуры совпадает с
+  /// [`LOC_STRING_TOKEN`], то значение записывается, как поле типа `LocString`, а если с
+  /// [`RESREF_TOKEN`] -- как поле типа `ResRef`
+  ///
+  /// [`LOC_STRING_TOKEN`]: constant.LOC_STRING_TOKEN.html
+  /// [`RESREF_TOKEN`]: ../de/constant.RESREF_TOKEN.html
   #[inline]
-  fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+  fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where T: ?Sized + Serialize,
   {
+    if name == LOC_STRING_TOKEN {
+      return value.serialize(LocStringSerializer(self));
+    }
+    if name == RESREF_TOKEN {
+      return match value.serialize(ValueSerializer::new(self.ser.none))? {
+        Value::Void(data) => self.add_resref(&data),
+        _ => Err(Error::Serialize("ResRef data expected as byte array".into())),
+      };
+    }
     value.serialize(self)
   }
   #[inline]
@@ -661,7 +1067,7 @@ impl<'a> ser::Serializer for FieldSerializer<'a> {
   #[inline]
   fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
     let ser = self.serialize_struct("", len.unwrap_or(0))?;
-    Ok(MapSerializer { ser, label: None })
+    Ok(MapSerializer { labels: ser.ser.labels.len(), ser, label: None, tag: false })
   }
   //-----------------------------------------------------------------------------------------------
   // Сериализация компонентов перечисления
@@ -706,22 +1112,31 @@ pub struct StructSerializer<'a> {
   fields_index: FieldListIndex,
 }
 impl<'a> StructSerializer<'a> {
-  /// Сериализует значение, обновляя поле с указанным индексом
+  /// Сериализует значение, обновляя поле с указанным индексом. Если значение не было
+  /// записано (отсутствующее значение при [`NoneRepresentation::Absent`]), то уменьшает
+  /// количество полей структуры и удаляет метки, добавленные после `labels` первых
+  ///
+  /// [`NoneRepresentation::Absent`]: enum.NoneRepresentation.html#variant.Absent
   #[inline]
-  fn serialize_value<T>(&mut self, label: LabelIndex, value: &T) -> Result<()>
+  fn serialize_value<T>(&mut self, label: LabelIndex, labels: usize, value: &T) -> Result<()>
     where T: ?Sized + Serialize,
   {
     use self::Struct::*;
 
     let index = self.ser.fields.len();
     value.serialize(FieldSerializer { ser: self.ser, label })?;
+    if index == self.ser.fields.len() {
+      self.ser.labels.truncate(labels);
+      self.skip_field();
+      return Ok(());
+    }
     // Обновляем ссылки из записи о структуре
     let struct_ = &mut self.ser.structs[self.struct_index.0];
     match struct_ {
       // Если полей нет, ничего делать не нужно
-      NoFields => {},
+      NoFields {..} => {},
       // Если поле одно, то структура хранит ссылку на само поле
-      OneField(ref mut idx) => *idx = index,
+      OneField { ref mut field, .. } => *field = index,
       MultiField {..} => {
         // Если полей несколько, то структура содержит ссылку на список с полями. Добавляем
         // индекс этого поля в нее
@@ -731,6 +1146,43 @@ impl<'a> StructSerializer<'a> {
     };
     Ok(())
   }
+  /// Записывает идентификатор типа в сериализуемую структуру. Так как поле [`TAG_FIELD`]
+  /// было учтено в количестве полей структуры при ее создании, но не записывается в файл,
+  /// количество полей структуры уменьшается на единицу
+  ///
+  /// [`TAG_FIELD`]: ../de/constant.TAG_FIELD.html
+  fn serialize_tag<T>(&mut self, value: &T) -> Result<()>
+    where T: ?Sized + Serialize,
+  {
+    use self::Struct::*;
+
+    let value = value.serialize(TagSerializer)?;
+    self.skip_field();
+    match self.ser.structs[self.struct_index.0] {
+      NoFields { ref mut tag } |
+      OneField { ref mut tag, .. } |
+      MultiField { ref mut tag, .. } => *tag = value,
+    }
+    Ok(())
+  }
+  /// Уменьшает на единицу количество полей сериализуемой структуры, если одно из
+  /// объявленных при ее создании полей не было записано в файл
+  fn skip_field(&mut self) {
+    use self::Struct::*;
+
+    let struct_ = &mut self.ser.structs[self.struct_index.0];
+    *struct_ = match *struct_ {
+      NoFields { tag } => NoFields { tag },
+      OneField { tag, .. } => NoFields { tag },
+      MultiField { tag, list, fields: 2 } => {
+        // Если одно из полей уже было записано, структура должна ссылаться на него напрямую
+        let fields = &mut self.ser.field_indices[list.0];
+        let field = fields.pop().unwrap_or(0) as usize;
+        OneField { tag, field }
+      },
+      MultiField { tag, list, fields } => MultiField { tag, list, fields: fields - 1 },
+    };
+  }
 }
 impl<'a> SerializeStruct for StructSerializer<'a> {
   type Ok = ();
@@ -740,9 +1192,13 @@ impl<'a> SerializeStruct for StructSerializer<'a> {
   fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<Self::Ok>
     where T: ?Sized + Serialize,
   {
+    if key == TAG_FIELD {
+      return self.serialize_tag(value);
+    }
     // Добавляем запись о метке
+    let labels = self.ser.labels.len();
     let label = self.ser.add_label(key)?;
-    self.serialize_value(label, value)
+    self.serialize_value(label, labels, value)
   }
 
   #[inline]
@@ -762,6 +1218,93 @@ impl<'a> SerializeStructVariant for StructSerializer<'a> {
   fn end(self) -> Result<()> { <Self as SerializeStruct>::end(self) }
 }
 
+/// Сериализатор значения поля [`TAG_FIELD`] в идентификатор типа структуры. Допускает
+/// только беззнаковые целые, помещающиеся в `u32`
+///
+/// [`TAG_FIELD`]: ../de/constant.TAG_FIELD.html
+pub(crate) struct TagSerializer;
+
+/// Реализует метод, возвращающий ошибку при попытке сериализовать в качестве идентификатора
+/// типа структуры что-либо, кроме беззнакового целого
+macro_rules! not_tag {
+  ($ser_method:ident ( $($type:ty),* ) ) => (
+    not_tag!($ser_method($($type),*) -> Self::Ok);
+  );
+  ($ser_method:ident ( $($type:ty),* ) -> $result:ty) => (
+    fn $ser_method(self, $(_: $type),*) -> Result<$result> {
+      Err(Error::Serialize(concat!(
+        "`", stringify!($ser_method), "` can't be used as struct tag, only unsigned integer up to `u32` expected"
+      ).into()))
+    }
+  );
+}
+
+impl ser::Serializer for TagSerializer {
+  type Ok = u32;
+  type Error = Error;
+
+  type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+  type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+  type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+  type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+  type SerializeMap = Impossible<Self::Ok, Self::Error>;
+  type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+  type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+  #[inline]
+  fn serialize_u8(self, v: u8) -> Result<Self::Ok> { Ok(v.into()) }
+  #[inline]
+  fn serialize_u16(self, v: u16) -> Result<Self::Ok> { Ok(v.into()) }
+  #[inline]
+  fn serialize_u32(self, v: u32) -> Result<Self::Ok> { Ok(v) }
+
+  not_tag!(serialize_i8(i8));
+  not_tag!(serialize_i16(i16));
+  not_tag!(serialize_i32(i32));
+  not_tag!(serialize_i64(i64));
+  not_tag!(serialize_u64(u64));
+
+  not_tag!(serialize_f32(f32));
+  not_tag!(serialize_f64(f64));
+
+  not_tag!(serialize_bool(bool));
+  not_tag!(serialize_char(char));
+  not_tag!(serialize_str(&str));
+  not_tag!(serialize_bytes(&[u8]));
+
+  not_tag!(serialize_none());
+  #[inline]
+  fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
+    where T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+  not_tag!(serialize_unit());
+  not_tag!(serialize_unit_struct(&'static str));
+  #[inline]
+  fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    where T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+  not_tag!(serialize_tuple(usize) -> Self::SerializeTuple);
+  not_tag!(serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct);
+  not_tag!(serialize_struct(&'static str, usize) -> Self::SerializeStruct);
+  not_tag!(serialize_seq(Option<usize>) -> Self::SerializeSeq);
+  not_tag!(serialize_map(Option<usize>) -> Self::SerializeMap);
+  not_tag!(serialize_unit_variant(&'static str, u32, &'static str));
+  fn serialize_newtype_variant<T>(self, name: &'static str, _index: u32, variant: &'static str, _value: &T) -> Result<Self::Ok>
+    where T: ?Sized + Serialize,
+  {
+    Err(Error::Serialize(format!(
+      "`serialize_newtype_variant(name: {}, variant: {})` can't be used as struct tag, only unsigned integer up to `u32` expected",
+      name, variant
+    )))
+  }
+  not_tag!(serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant);
+  not_tag!(serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant);
+}
+
 /// Сериализует все поля списка или кортежа, заполняя массив с индексами элементов списка
 pub struct ListSerializer<'a> {
   /// Хранилище записываемых данных
@@ -840,9 +1383,21 @@ pub struct MapSerializer<'a> {
   ser: StructSerializer<'a>,
   /// Индекс поля в массиве `ser.fields`, которое будет отражать сериализованную запись
   label: Option<LabelIndex>,
+  /// Количество меток до сериализации ключа. Если значение не будет записано, то метки,
+  /// добавленные ключом, удаляются
+  labels: usize,
+  /// Признак того, что последний сериализованный ключ равен [`TAG_FIELD`], и значение
+  /// записи является идентификатором типа структуры
+  ///
+  /// [`TAG_FIELD`]: ../de/constant.TAG_FIELD.html
+  tag: bool,
 }
+/// Сериализует ключ карты в индекс метки. Для ключа [`TAG_FIELD`] метка не добавляется
+/// и возвращается `None`
+///
+/// [`TAG_FIELD`]: ../de/constant.TAG_FIELD.html
 impl<'a, 'b> ser::Serializer for &'b mut MapSerializer<'a> {
-  type Ok = LabelIndex;
+  type Ok = Option<LabelIndex>;
   type Error = Error;
 
   type SerializeSeq = Impossible<Self::Ok, Self::Error>;
@@ -870,10 +1425,19 @@ impl<'a, 'b> ser::Serializer for &'b mut MapSerializer<'a> {
 
   #[inline]
   fn serialize_str(self, value: &str) -> Result<Self::Ok> {
+    if value == TAG_FIELD {
+      return Ok(None);
+    }
     // Добавляем запись о метке
-    self.ser.ser.add_label(value)
+    self.ser.ser.add_label(value).map(Some)
+  }
+  /// Массив байт используется для сериализации меток, которые могут быть некорректными
+  /// строками UTF-8, поэтому он также допустим в качестве ключа
+  #[inline]
+  fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok> {
+    let label = Label::from_bytes(value)?;
+    Ok(Some(self.ser.ser.insert_label(label)))
   }
-  unsupported!(serialize_bytes(&[u8]));
 
   unsupported!(serialize_none());
   fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
@@ -910,7 +1474,10 @@ impl<'a, 'b> ser::Serializer for &'b mut MapSerializer<'a> {
   fn serialize_newtype_variant<T>(self, name: &'static str, index: u32, variant: &'static str, _value: &T) -> Result<Self::Ok>
     where T: ?Sized + Serialize,
   {
-    unimplemented!("`serialize_newtype_variant(name: {}, index: {}, variant: {})`", name, index, variant);
+    Err(Error::Serialize(format!(
+      "`serialize_newtype_variant(name: {}, index: {}, variant: {})` can't be used as a map key in GFF format. Only strings and unit variants are allowed",
+      name, index, variant
+    )))
   }
   unsupported!(serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant);
   unsupported!(serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant);
@@ -924,7 +1491,9 @@ impl<'a> SerializeMap for MapSerializer<'a> {
   fn serialize_key<T>(&mut self, key: &T) -> Result<()>
     where T: ?Sized + Serialize,
   {
-    self.label = Some(key.serialize(&mut *self)?);
+    self.labels = self.ser.ser.labels.len();
+    self.label = key.serialize(&mut *self)?;
+    self.tag = self.label.is_none();
     Ok(())
   }
 
@@ -932,14 +1501,106 @@ impl<'a> SerializeMap for MapSerializer<'a> {
   fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where T: ?Sized + Serialize,
   {
+    if self.tag {
+      self.tag = false;
+      return self.ser.serialize_tag(value);
+    }
     let error = || Error::Serialize("`SerializeMap::serialize_key` must be called before `SerializeMap::serialize_value`".into());
-    self.ser.serialize_value(self.label.ok_or_else(error)?, value)
+    self.ser.serialize_value(self.label.ok_or_else(error)?, self.labels, value)
   }
 
   #[inline]
   fn end(self) -> Result<Self::Ok> { Ok(()) }
 }
 
+/// Сериализатор данных локализуемой строки. Принимает только массив байт, содержащий
+/// закодированное представление `LocString` (без учета заголовка поля), и записывает его
+/// в область данных полей, добавляя поле типа `LocString`
+struct LocStringSerializer<'a>(FieldSerializer<'a>);
+
+/// Реализует метод, возвращающий ошибку при попытке сериализовать в качестве локализуемой
+/// строки что-либо, кроме массива байт
+macro_rules! not_loc_string {
+  ($ser_method:ident ( $($type:ty),* ) ) => (
+    not_loc_string!($ser_method($($type),*) -> Self::Ok);
+  );
+  ($ser_method:ident ( $($type:ty),* ) -> $result:ty) => (
+    fn $ser_method(self, $(_: $type),*) -> Result<$result> {
+      Err(Error::Serialize(concat!(
+        "`", stringify!($ser_method), "` can't be used for LocString serialization, only `serialize_bytes` expected"
+      ).into()))
+    }
+  );
+}
+
+impl<'a> ser::Serializer for LocStringSerializer<'a> {
+  type Ok = ();
+  type Error = Error;
+
+  type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+  type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+  type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+  type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+  type SerializeMap = Impossible<Self::Ok, Self::Error>;
+  type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+  type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+  not_loc_string!(serialize_i8(i8));
+  not_loc_string!(serialize_u8(u8));
+  not_loc_string!(serialize_i16(i16));
+  not_loc_string!(serialize_u16(u16));
+  not_loc_string!(serialize_i32(i32));
+  not_loc_string!(serialize_u32(u32));
+  not_loc_string!(serialize_i64(i64));
+  not_loc_string!(serialize_u64(u64));
+
+  not_loc_string!(serialize_f32(f32));
+  not_loc_string!(serialize_f64(f64));
+
+  not_loc_string!(serialize_bool(bool));
+  not_loc_string!(serialize_char(char));
+  not_loc_string!(serialize_str(&str));
+
+  fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
+    let ser = self.0.ser;
+    // Данные уже содержат общий размер, StrRef, количество строк и сами строки
+    let offset = ser.add_field_data(FieldType::LocString, v);
+
+    ser.fields.push(Field::Simple {
+      label: self.0.label,
+      value: SimpleValueRef::LocString(offset.into())
+    });
+    Ok(())
+  }
+
+  not_loc_string!(serialize_none());
+  fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
+    where T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+  not_loc_string!(serialize_unit());
+  not_loc_string!(serialize_unit_struct(&'static str));
+  fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    where T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+  not_loc_string!(serialize_tuple(usize) -> Self::SerializeTuple);
+  not_loc_string!(serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct);
+  not_loc_string!(serialize_struct(&'static str, usize) -> Self::SerializeStruct);
+  not_loc_string!(serialize_seq(Option<usize>) -> Self::SerializeSeq);
+  not_loc_string!(serialize_map(Option<usize>) -> Self::SerializeMap);
+  not_loc_string!(serialize_unit_variant(&'static str, u32, &'static str));
+  fn serialize_newtype_variant<T>(self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T) -> Result<Self::Ok>
+    where T: ?Sized + Serialize,
+  {
+    Err(Error::Serialize("`serialize_newtype_variant` can't be used for LocString serialization, only `serialize_bytes` expected".into()))
+  }
+  not_loc_string!(serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant);
+  not_loc_string!(serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant);
+}
+
 #[cfg(test)]
 mod tests {
   use std::collections::BTreeMap;
@@ -1114,6 +1775,14 @@ mod tests {
           2u32 => 2u32,
         ];
         assert!(is_err(map));
+
+        // Ключами также не могут быть newtype-варианты перечислений
+        #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+        enum Key { Newtype(u32) }
+        let map = map![
+          Key::Newtype(1) => 1u32,
+        ];
+        assert!(is_err(map));
       }
     );
   }
@@ -1332,9 +2001,15 @@ mod tests {
     );
 
     (externally toplevel $E:ident) => (
-      // Сериализация unit-варианта на верхнем уровне невозможна
+      // На верхнем уровне unit-вариант записывается, как структура с одним строковым полем
+      #[derive(Serialize)]
+      #[allow(non_snake_case)]
+      struct Unit {
+        Unit: &'static str,
+      }
       let u = $E::Unit;
-      assert!(is_err(u));
+      let expected = to_vec(Unit { Unit: "Unit" });
+      assert_eq!(to_vec(u), expected);
     );
     (externally as_field $E:ident) => (
       let u = $E::Unit;
@@ -1364,7 +2039,7 @@ mod tests {
   mod toplevel {
     //! Тестирует сериализацию различных значений, когда они не включены ни в какую структуру
     use super::*;
-    use crate::error::Result;
+    use crate::error::{Error, Result};
 
     #[inline]
     fn to_result<T>(value: T) -> Result<Vec<u8>>
@@ -1479,6 +2154,15 @@ mod tests {
       to_result(ByteBuf::from(array.as_ref())).unwrap();
     }
 
+    /// Проверяет, что ошибка содержит описание причины, а не приводит к панике
+    #[test]
+    fn test_error_message() {
+      match to_result(true) {
+        Err(Error::Serialize(msg)) => assert_eq!(msg, "`serialize_bool` can't be implemented in GFF format. Wrap value to the struct and serialize struct"),
+        res => panic!("expected Error::Serialize, but {:?} found", res),
+      }
+    }
+
     /// Тестирует запись отсутствующего опционального значения
     #[test]
     fn test_none() {
@@ -1637,6 +2321,28 @@ mod tests {
       newtype_test!(Struct = Struct { field1: 42, field2: 42.0 });
     }
 
+    /// Тестирует запись варианта перечисления, обернутого в новый тип. Он записывается, как
+    /// структура с единственным полем, поэтому ошибки при записи этого поля должны
+    /// возвращаться, а не приводить к панике
+    #[test]
+    fn test_newtype_variant() {
+      use std::collections::BTreeMap;
+
+      #[derive(Serialize)]
+      #[allow(non_snake_case)]
+      struct Struct { Variant: u32 }
+      #[derive(Serialize)]
+      enum Enum {
+        Variant(u32),
+        Map(BTreeMap<u32, u32>),
+        VariantWithTooLongName(u32),
+      }
+
+      assert_eq!(to_vec(Enum::Variant(42)), to_vec(Struct { Variant: 42 }));
+      assert!(is_err(Enum::Map(vec![(1, 2)].into_iter().collect())));
+      assert!(is_err(Enum::VariantWithTooLongName(42)));
+    }
+
     /// Тестирует запись структуры с более чем одним полем
     #[test]
     fn test_struct() {
@@ -1896,13 +2602,40 @@ mod tests {
       assert_eq!(to_vec_((*b"GFF ").into(), &storage).expect("Serialization fail"), expected);
     }
 
-    /// Тестирует запись отсутствующего опционального значения
+    /// Тестирует запись локализуемых строк
+    #[test]
+    fn test_loc_string() {
+      use crate::{LocString, StrRef, StringKey, SubString};
+      use crate::value::Value;
+
+      let value = Value::LocString(LocString {
+        str_ref: StrRef(0xFFFFFFFF),
+        strings: vec![
+          SubString { key: StringKey(0), string: "en".into() },
+          SubString { key: StringKey(3), string: "fr".into() },
+        ],
+      });
+      let expected = complex_wrapped![12;
+        28,0,0,0,// Размер данных без учета этого поля: 4+4 + (4+4+2)*2
+        0xFF,0xFF,0xFF,0xFF,// StrRef
+        2,0,0,0,// Количество строк
+        0,0,0,0, 2,0,0,0, b'e',b'n',// English, Male
+        3,0,0,0, 2,0,0,0, b'f',b'r' // French, Female
+      ];
+      assert_eq!(to_vec(value), expected);
+    }
+
+    /// Тестирует запись отсутствующего опционального значения: по умолчанию поле не записывается
     #[test]
     fn test_none() {
+      #[derive(Serialize)]
+      struct Empty {}
+      let empty = to_vec_((*b"GFF ").into(), &Empty {}).expect("Serialization fail");
+
       macro_rules! none_test {
         ($type:ty) => (
           let none: Option<$type> = None;
-          assert_eq!(to_vec(none), unit!());
+          assert_eq!(to_vec(none), empty);
         );
       }
       none_test!(u8);
@@ -2314,4 +3047,377 @@ mod tests {
     map_tests!();
     enum_tests!(as_field);
   }
+
+  mod tag {
+    //! Тестирование записи идентификаторов типов структур из поля `$tag`
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::header::Header;
+    use crate::raw::Gff;
+    use super::to_vec_;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Empty {
+      #[serde(rename = "$tag")]
+      tag: u32,
+    }
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct One {
+      value: u8,
+      #[serde(rename = "$tag")]
+      tag: u16,
+    }
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Many {
+      #[serde(rename = "$tag")]
+      tag: u32,
+      value: u8,
+      empty: Empty,
+      list: Vec<One>,
+    }
+
+    fn value() -> Many {
+      Many {
+        tag: 0xFFFF_FFFF,
+        value: 1,
+        empty: Empty { tag: 3 },
+        list: vec![One { value: 2, tag: 4 }],
+      }
+    }
+
+    #[test]
+    fn struct_records() {
+      let data = to_vec_((*b"GFF ").into(), &value()).expect("Serialization fail");
+      // Тег первой структуры хранится сразу после заголовка
+      let start = Header::HEADER_SIZE as usize;
+      assert_eq!(&data[start..start + 12], &[0xFF,0xFF,0xFF,0xFF,  0,0,0,0,  3,0,0,0]);
+
+      let gff = Gff::read(&mut Cursor::new(data)).expect("can't read GFF");
+      let structs: Vec<_> = gff.structs.iter().map(|s| (s.tag, s.fields)).collect();
+      assert_eq!(structs, vec![(0xFFFF_FFFF, 3), (3, 0), (4, 1)]);
+      assert_eq!(gff.fields.len(), 4);
+      assert!(gff.labels.iter().all(|l| l.as_str() != Ok("$tag")));
+    }
+
+    #[test]
+    fn roundtrip() {
+      let data = to_vec_((*b"GFF ").into(), &value()).expect("Serialization fail");
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      assert_eq!(Many::deserialize(&mut de).expect("can't deserialize value"), value());
+    }
+
+    #[test]
+    fn not_unsigned() {
+      #[derive(Serialize)]
+      struct Signed {
+        #[serde(rename = "$tag")]
+        tag: i32,
+      }
+      assert!(to_vec_((*b"GFF ").into(), &Signed { tag: 1 }).is_err());
+    }
+  }
+
+  mod none {
+    //! Тестирование различных способов записи отсутствующих значений
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::header::Version;
+    use crate::value::{SimpleValue, Value};
+    use super::super::{NoneRepresentation, Serializer};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Test {
+      first: u8,
+      value: Option<String>,
+      last: u8,
+    }
+
+    fn serialize(none: NoneRepresentation) -> Vec<u8> {
+      let mut ser = Serializer::default();
+      ser.set_none_representation(none);
+      Test { first: 1, value: None, last: 2 }.serialize(&mut ser).expect("Serialization fail");
+
+      let mut data = Vec::new();
+      ser.write(&mut data, (*b"GFF ").into(), Version::V3_2).expect("can't write data");
+      data
+    }
+    fn labels(data: &[u8]) -> Vec<String> {
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      match Value::deserialize(&mut de).expect("can't deserialize data") {
+        Value::Struct { fields, .. } => fields.keys().map(|l| l.to_string()).collect(),
+        value => panic!("expected struct, but {:?} found", value),
+      }
+    }
+
+    #[test]
+    fn absent() {
+      let data = serialize(NoneRepresentation::Absent);
+      assert_eq!(labels(&data), vec!["first", "last"]);
+
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      let value = Test::deserialize(&mut de).expect("can't deserialize data");
+      assert_eq!(value, Test { first: 1, value: None, last: 2 });
+    }
+
+    #[test]
+    fn empty() {
+      let data = serialize(NoneRepresentation::Empty);
+      assert_eq!(labels(&data), vec!["first", "value", "last"]);
+
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      let value = Value::deserialize(&mut de).expect("can't deserialize data");
+      assert_eq!(value["value"], Value::from(SimpleValue::String("".into())));
+    }
+  }
+
+  mod field_data_order {
+    //! Тестирование упорядочивания области данных полей
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::header::Version;
+    use crate::raw::Gff;
+    use super::super::{FieldDataOrder, Serializer};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Forward { string: String, dword64: u64, double: f64 }
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Backward { double: f64, dword64: u64, string: String }
+
+    fn serialize<T: Serialize>(value: &T, order: FieldDataOrder) -> Vec<u8> {
+      let mut ser = Serializer::default();
+      ser.set_field_data_order(order);
+      value.serialize(&mut ser).expect("Serialization fail");
+
+      let mut data = Vec::new();
+      ser.write(&mut data, (*b"GFF ").into(), Version::V3_2).expect("can't write data");
+      data
+    }
+    fn field_data(data: &[u8]) -> Vec<u8> {
+      Gff::read(&mut Cursor::new(data)).expect("can't read GFF").field_data
+    }
+
+    #[test]
+    fn canonical() {
+      let forward = Forward { string: "string".into(), dword64: 42, double: 3.5 };
+      let backward = Backward { double: 3.5, dword64: 42, string: "string".into() };
+
+      let f = serialize(&forward, FieldDataOrder::Serialization);
+      let b = serialize(&backward, FieldDataOrder::Serialization);
+      assert_ne!(field_data(&f), field_data(&b));
+
+      let f = serialize(&forward, FieldDataOrder::Canonical);
+      let b = serialize(&backward, FieldDataOrder::Canonical);
+      assert_eq!(field_data(&f), field_data(&b));
+
+      let mut de = Deserializer::new(Cursor::new(f)).expect("can't read GFF header");
+      assert_eq!(Forward::deserialize(&mut de).expect("can't deserialize data"), forward);
+      let mut de = Deserializer::new(Cursor::new(b)).expect("can't read GFF header");
+      assert_eq!(Backward::deserialize(&mut de).expect("can't deserialize data"), backward);
+    }
+  }
+
+  mod dedup {
+    //! Тестирование повторного использования одинаковых данных полей
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::raw::Gff;
+    use crate::ser::to_vec;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Dialog { tag: String, speaker: String, other: String }
+
+    #[test]
+    fn same_string() {
+      let dialog = Dialog { tag: "shared".into(), speaker: "shared".into(), other: "other".into() };
+      let data = to_vec((*b"GFF ").into(), &dialog).expect("can't write data");
+
+      let gff = Gff::read(&mut Cursor::new(&data)).expect("can't read GFF");
+      assert_eq!(gff.field_data, b"\x06\0\0\0shared\x05\0\0\0other");
+      assert_eq!(gff.fields[0].data, gff.fields[1].data);
+
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      assert_eq!(Dialog::deserialize(&mut de).expect("can't deserialize data"), dialog);
+    }
+
+    /// Проверяет, что данные разных типов и разные данные с одинаковым хешем не объединяются
+    #[test]
+    fn collision() {
+      use crate::raw::FieldType;
+      use crate::ser::Serializer;
+
+      let mut ser = Serializer::default();
+      assert_eq!(ser.add_field_data(FieldType::String, b"one"), 0);
+      assert_eq!(ser.add_field_data(FieldType::Void, b"one"), 3);
+      assert_eq!(ser.add_field_data(FieldType::String, b"one"), 0);
+
+      // Имитируем совпадение хеша новых данных с хешем уже записанных
+      let hash = Serializer::data_hash(FieldType::String, b"two");
+      ser.data_offsets.entry(hash).or_default().push((FieldType::String, 0, 3));
+      assert_eq!(ser.add_field_data(FieldType::String, b"two"), 6);
+      assert_eq!(ser.add_field_data(FieldType::String, b"two"), 6);
+      assert_eq!(ser.field_data, b"oneonetwo");
+    }
+  }
+
+  mod encoding {
+    //! Тестирование записи строк в кодировке, отличной от `UTF-8`
+    use std::io::Cursor;
+    use encoding::{DecoderTrap, EncoderTrap};
+    use encoding::all::{ASCII, WINDOWS_1251};
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::error::Error;
+    use crate::header::Version;
+    use crate::raw::Gff;
+    use super::super::{Serializer, SerializerBuilder};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item { name: String }
+
+    #[test]
+    fn windows_1251() {
+      let item = Item { name: "Меч".into() };
+      let data = SerializerBuilder::new((*b"UTI ").into())
+        .version(Version::V3_2)
+        .encoding(WINDOWS_1251, EncoderTrap::Strict)
+        .serialize(&item)
+        .expect("can't write data");
+
+      let gff = Gff::read(&mut Cursor::new(&data)).expect("can't read GFF");
+      assert_eq!(gff.header.signature, (*b"UTI ").into());
+      assert_eq!(gff.field_data, b"\x03\0\0\0\xCC\xE5\xF7");
+
+      let mut de = Deserializer::with_encoding(Cursor::new(data), WINDOWS_1251, DecoderTrap::Strict).expect("can't read GFF header");
+      assert_eq!(Item::deserialize(&mut de).expect("can't deserialize data"), item);
+    }
+
+    /// Проверяет, что строка записывается в указанной кодировке, а не в `UTF-8`
+    #[test]
+    fn serializer_encoding() {
+      let item = Item { name: "юникод".into() };
+      let write = |mut ser: Serializer| {
+        item.serialize(&mut ser).expect("Serialization fail");
+        let mut data = Vec::new();
+        ser.write(&mut data, (*b"UTI ").into(), Version::V3_2).expect("can't write data");
+        Gff::read(&mut Cursor::new(data)).expect("can't read GFF").field_data
+      };
+
+      let utf8 = write(Serializer::default());
+      let cp1251 = write(Serializer::with_encoding(WINDOWS_1251, EncoderTrap::Strict));
+      assert_eq!(utf8, [&b"\x0C\0\0\0"[..], "юникод".as_bytes()].concat());
+      assert_eq!(cp1251, b"\x06\0\0\0\xFE\xED\xE8\xEA\xEE\xE4");
+      assert_ne!(utf8, cp1251);
+    }
+
+    #[test]
+    fn unmappable() {
+      let item = Item { name: "Меч".into() };
+      let builder = SerializerBuilder::new((*b"UTI ").into());
+
+      match builder.encoding(ASCII, EncoderTrap::Strict).serialize(&item) {
+        Err(Error::Encoding(_)) => {},
+        res => panic!("expected Error::Encoding, but {:?} found", res),
+      }
+      let data = builder.encoding(ASCII, EncoderTrap::Replace).serialize(&item).expect("can't write data");
+      let gff = Gff::read(&mut Cursor::new(&data)).expect("can't read GFF");
+      assert_eq!(gff.field_data, b"\x03\0\0\0???");
+    }
+  }
+
+  mod padding {
+    //! Тестирование дополнения файла нулевыми байтами
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::error::{Error, Result};
+    use crate::header::Version;
+    use super::super::{Padding, Serializer};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Test { string: String }
+
+    fn serialize(padding: Padding) -> Result<Vec<u8>> {
+      let mut ser = Serializer::default();
+      ser.set_padding(padding);
+      // Строка нечетной длины нарушает выравнивание области данных полей
+      Test { string: "odd".into() }.serialize(&mut ser).expect("Serialization fail");
+
+      let mut data = Vec::new();
+      ser.write(&mut data, (*b"GFF ").into(), Version::V3_2)?;
+      Ok(data)
+    }
+    fn deserialize(data: Vec<u8>) -> Test {
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      Test::deserialize(&mut de).expect("can't deserialize data")
+    }
+
+    #[test]
+    fn align() {
+      let unpadded = serialize(Padding::None).unwrap();
+      assert_ne!(unpadded.len() % 4, 0);
+
+      let data = serialize(Padding::Align(4)).unwrap();
+      assert_eq!(data.len() % 4, 0);
+      assert!(data.len() - unpadded.len() < 4);
+      assert_eq!(&data[..unpadded.len()], &unpadded[..]);
+      assert!(data[unpadded.len()..].iter().all(|b| *b == 0));
+      assert_eq!(deserialize(data), Test { string: "odd".into() });
+    }
+
+    #[test]
+    fn size() {
+      let data = serialize(Padding::Size(1024)).unwrap();
+      assert_eq!(data.len(), 1024);
+      assert_eq!(deserialize(data), Test { string: "odd".into() });
+
+      match serialize(Padding::Size(16)) {
+        Err(Error::Serialize(_)) => {},
+        result => panic!("expected Error::Serialize, but {:?} found", result),
+      }
+    }
+  }
+
+  mod resref {
+    //! Тестирование записи строковых полей как ссылок на ресурсы
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::error::Error;
+    use crate::raw::{FieldType, Gff};
+    use super::super::to_vec;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Test {
+      #[serde(with = "crate::resref")]
+      resref: String,
+      string: String,
+    }
+
+    #[test]
+    fn field_type() {
+      let value = Test { resref: "nw_chicken".into(), string: "nw_chicken".into() };
+      let data = to_vec((*b"GFF ").into(), &value).expect("Serialization fail");
+
+      let gff = Gff::read(&mut Cursor::new(&data)).expect("can't read GFF");
+      assert_eq!(gff.fields[0].tag, FieldType::ResRef as u32);
+      assert_eq!(gff.fields[0].tag, 11);
+      assert_eq!(gff.fields[1].tag, FieldType::String as u32);
+
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      assert_eq!(Test::deserialize(&mut de).expect("can't deserialize data"), value);
+    }
+
+    #[test]
+    fn too_long() {
+      let value = Test { resref: "x".repeat(17), string: "".into() };
+      match to_vec((*b"GFF ").into(), &value) {
+        Err(Error::Serialize(_)) => {},
+        result => panic!("expected Error::Serialize, but {:?} found", result),
+      }
+    }
+  }
 }
@@ -1,9 +1,17 @@
-//! Содержит реализацию типажа `Serialize` для сериализации типа `Value`
+//! Содержит реализацию типажа `Serialize` для сериализации типа `Value` и других типов значений GFF
 
-use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::cell::Cell;
+use std::io::Cursor;
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
+use indexmap::IndexMap;
+use serde::ser::{self, Error as _, Impossible, Serialize, SerializeMap, Serializer};
 
-use crate::Label;
-use crate::value::Value;
+use crate::{GffString, Label, LocString, ResRef, StrRef, StringKey, SubString};
+use crate::de::{RESREF_TOKEN, TAG_FIELD};
+use crate::resref::ResRefData;
+use crate::error::Error;
+use crate::ser::{LOC_STRING_TOKEN, NoneRepresentation, TagSerializer};
+use crate::value::{SimpleValue, Value};
 
 impl Serialize for Label {
   #[inline]
@@ -14,6 +22,141 @@ impl Serialize for Label {
   }
 }
 
+/// Обертка над локализуемой строкой, сериализующая ее в тот вид, в котором она хранится
+/// в области данных полей GFF файла: общий размер данных (без учета самого поля размера),
+/// `StrRef`, количество строк и для каждой строки ее ключ и текст с предваряющей его длиной
+pub(crate) struct LocStringData<'a>(pub(crate) &'a LocString);
+
+impl<'a> LocStringData<'a> {
+  /// Формирует массив байт с представлением локализуемой строки
+  pub(crate) fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+    let value = self.0;
+    // StrRef, количество строк и по ключу и длине на каждую строку
+    let size = value.strings.iter().fold(4 + 4, |sum, s| sum + 4 + 4 + s.string.len());
+
+    let mut data = Vec::with_capacity(4 + size);
+    data.write_u32::<LE>(size as u32)?;
+    data.write_u32::<LE>(value.str_ref.0)?;
+    data.write_u32::<LE>(value.strings.len() as u32)?;
+    for s in &value.strings {
+      data.write_u32::<LE>(s.key.0)?;
+      data.write_u32::<LE>(s.string.len() as u32)?;
+      data.extend_from_slice(s.string.as_bytes());
+    }
+    Ok(data)
+  }
+}
+
+/// Разбирает данные локализуемой строки, сформированные методом `LocStringData::to_bytes`
+pub(crate) fn parse_loc_string(data: &[u8]) -> std::io::Result<LocString> {
+  let mut cursor = Cursor::new(data);
+  let _size   = cursor.read_u32::<LE>()?;
+  let str_ref = StrRef(cursor.read_u32::<LE>()?);
+  let count   = cursor.read_u32::<LE>()?;
+
+  let mut strings = Vec::new();
+  for _ in 0..count {
+    let key = StringKey(cursor.read_u32::<LE>()?);
+    let len = cursor.read_u32::<LE>()? as usize;
+    let start = cursor.position() as usize;
+    let bytes = data.get(start..start + len).ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+    let string = String::from_utf8(bytes.to_vec()).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    cursor.set_position((start + len) as u64);
+    strings.push(SubString { key, string });
+  }
+  Ok(LocString { str_ref, strings })
+}
+
+impl<'a> Serialize for LocStringData<'a> {
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+  {
+    let data = self.to_bytes().map_err(S::Error::custom)?;
+    serializer.serialize_bytes(&data)
+  }
+}
+
+impl Serialize for LocString {
+  /// Сериализует локализуемую строку как newtype-структуру со специальным именем, которую
+  /// GFF сериализатор распознает и записывает как поле типа `LocString`
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+  {
+    serializer.serialize_newtype_struct(LOC_STRING_TOKEN, &LocStringData(self))
+  }
+}
+
+/// Сериализует ключ как число в том виде, в котором он хранится в GFF файле
+impl Serialize for StringKey {
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+  {
+    serializer.serialize_u32(self.0)
+  }
+}
+
+/// Сериализует индекс в TLK файле как число в том виде, в котором он хранится в GFF файле
+impl Serialize for StrRef {
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+  {
+    serializer.serialize_u32(self.0)
+  }
+}
+
+impl Serialize for GffString {
+  /// Сериализует внешнее представление строки как число, являющееся StrRef индексом, а
+  /// внутреннее -- как отображение из ключа (комбинированного значения языка и пола) на
+  /// текст строки. Ключи записываются в порядке возрастания. Это представление симметрично
+  /// десериализации `GffString`.
+  ///
+  /// Так как ключами GFF структур могут быть только строки, GFF сериализатор не может записать
+  /// внутреннее представление непосредственно. Для записи строки в поле типа `LocString`
+  /// используйте модуль [`locstring`](../locstring/index.html)
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+  {
+    match self {
+      GffString::External(str_ref) => str_ref.serialize(serializer),
+      GffString::Internal(strings) => {
+        let mut strings: Vec<_> = strings.iter().collect();
+        strings.sort_by_key(|(k, _)| k.0);
+        serializer.collect_map(strings)
+      },
+    }
+  }
+}
+
+impl Serialize for SimpleValue {
+  #[inline]
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+  {
+    use self::SimpleValue::*;
+
+    match *self {
+      Byte(val)       => serializer.serialize_u8(val),
+      Char(val)       => serializer.serialize_i8(val),
+      Word(val)       => serializer.serialize_u16(val),
+      Short(val)      => serializer.serialize_i16(val),
+      Dword(val)      => serializer.serialize_u32(val),
+      Int(val)        => serializer.serialize_i32(val),
+      Dword64(val)    => serializer.serialize_u64(val),
+      Int64(val)      => serializer.serialize_i64(val),
+      Float(val)      => serializer.serialize_f32(val),
+      Double(val)     => serializer.serialize_f64(val),
+      String(ref val) => serializer.serialize_str(val),
+      ResRef(ref val) => serializer.serialize_newtype_struct(RESREF_TOKEN, &ResRefData(&val.0)),
+      LocString(ref val) => val.serialize(serializer),
+      Void(ref val)   => serializer.serialize_bytes(val),
+    }
+  }
+}
+
 impl Serialize for Value {
   #[inline]
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -33,13 +176,16 @@ impl Serialize for Value {
       Float(val)      => serializer.serialize_f32(val),
       Double(val)     => serializer.serialize_f64(val),
       String(ref val) => serializer.serialize_str(&val),
-      ResRef(ref val) => serializer.serialize_bytes(&val.0),
-      //TODO: реализовать сериализацию LocString
-      LocString(ref _val) => unimplemented!("serialization of LocString not yet implemented"),
+      ResRef(ref val) => serializer.serialize_newtype_struct(RESREF_TOKEN, &ResRefData(&val.0)),
+      LocString(ref val) => val.serialize(serializer),
       Void(ref val)   => serializer.serialize_bytes(&val),
-      Struct(ref val) => {
-        let mut map = serializer.serialize_map(Some(val.len()))?;
-        for (k, v) in val {
+      // Ненулевой идентификатор типа передается в виде первой записи с ключом `TAG_FIELD`
+      Struct { tag, ref fields } => {
+        let mut map = serializer.serialize_map(Some(fields.len() + (tag != 0) as usize))?;
+        if tag != 0 {
+          map.serialize_entry(TAG_FIELD, &tag)?;
+        }
+        for (k, v) in fields {
           map.serialize_key(k)?;
           map.serialize_value(v)?;
         }
@@ -49,3 +195,564 @@ impl Serialize for Value {
     }
   }
 }
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Реализует метод, возвращающий ошибку при попытке сериализовать в `Value` тип, не имеющий
+/// представления в GFF
+macro_rules! unsupported {
+  ($ser_method:ident ( $($type:ty),* ) -> $result:ty) => (
+    fn $ser_method(self, $(_: $type),*) -> Result<$result, Error> {
+      Err(Error::Serialize(concat!(
+        "`", stringify!($ser_method), "` can't be represented as GFF value"
+      ).into()))
+    }
+  );
+}
+
+/// Сериализатор, строящий дерево [`Value`] по тем же правилам, по которым GFF сериализатор
+/// записывает значения полей структур
+///
+/// [`Value`]: ../../value/enum.Value.html
+pub(crate) struct ValueSerializer<'a> {
+  /// Способ представления отсутствующих значений в полях структур и значениях карт
+  none: NoneRepresentation,
+  /// Если сериализуется значение поля структуры, то флаг, устанавливаемый в случае, когда
+  /// поле не должно попасть в структуру (отсутствующее значение при [`NoneRepresentation::Absent`])
+  ///
+  /// [`NoneRepresentation::Absent`]: ../enum.NoneRepresentation.html#variant.Absent
+  absent: Option<&'a Cell<bool>>,
+}
+impl ValueSerializer<'static> {
+  /// Создает сериализатор значения верхнего уровня или элемента списка, в которых отсутствующее
+  /// значение всегда представляется структурой без полей
+  #[inline]
+  pub(crate) fn new(none: NoneRepresentation) -> Self {
+    ValueSerializer { none, absent: None }
+  }
+}
+impl<'a> ValueSerializer<'a> {
+  /// Создает сериализатор значения поля структуры. Если значение отсутствует и должно быть
+  /// пропущено, устанавливает флаг `absent`
+  #[inline]
+  fn field(none: NoneRepresentation, absent: &'a Cell<bool>) -> Self {
+    ValueSerializer { none, absent: Some(absent) }
+  }
+}
+
+impl<'a> ser::Serializer for ValueSerializer<'a> {
+  type Ok = Value;
+  type Error = Error;
+
+  type SerializeSeq = SerializeList;
+  type SerializeTuple = SerializeList;
+  type SerializeTupleStruct = SerializeList;
+  type SerializeTupleVariant = SerializeVariant<SerializeList>;
+  type SerializeMap = SerializeStruct;
+  type SerializeStruct = SerializeStruct;
+  type SerializeStructVariant = SerializeVariant<SerializeStruct>;
+
+  #[inline] fn serialize_u8 (self, v: u8 ) -> Result<Value, Error> { Ok(Value::Byte(v)) }
+  #[inline] fn serialize_i8 (self, v: i8 ) -> Result<Value, Error> { Ok(Value::Char(v)) }
+  #[inline] fn serialize_u16(self, v: u16) -> Result<Value, Error> { Ok(Value::Word(v)) }
+  #[inline] fn serialize_i16(self, v: i16) -> Result<Value, Error> { Ok(Value::Short(v)) }
+  #[inline] fn serialize_u32(self, v: u32) -> Result<Value, Error> { Ok(Value::Dword(v)) }
+  #[inline] fn serialize_i32(self, v: i32) -> Result<Value, Error> { Ok(Value::Int(v)) }
+  #[inline] fn serialize_u64(self, v: u64) -> Result<Value, Error> { Ok(Value::Dword64(v)) }
+  #[inline] fn serialize_i64(self, v: i64) -> Result<Value, Error> { Ok(Value::Int64(v)) }
+
+  #[inline] fn serialize_f32(self, v: f32) -> Result<Value, Error> { Ok(Value::Float(v)) }
+  #[inline] fn serialize_f64(self, v: f64) -> Result<Value, Error> { Ok(Value::Double(v)) }
+
+  /// Булевы значения представляются в виде `Byte`, как и в GFF сериализаторе
+  #[inline]
+  fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+    self.serialize_u8(if v { 1 } else { 0 })
+  }
+  /// Символы представляются в виде строки из одного символа, как и в GFF сериализаторе
+  #[inline]
+  fn serialize_char(self, v: char) -> Result<Value, Error> {
+    Ok(Value::String(v.to_string()))
+  }
+  #[inline]
+  fn serialize_str(self, v: &str) -> Result<Value, Error> {
+    Ok(Value::String(v.to_owned()))
+  }
+  #[inline]
+  fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+    Ok(Value::Void(v.to_vec()))
+  }
+
+  /// В значении поля структуры, как и GFF сериализатор, в зависимости от настройки
+  /// [`NoneRepresentation`] либо пропускает поле, либо представляет отсутствующее значение
+  /// пустой строкой. В остальных местах возвращает структуру без полей
+  ///
+  /// [`NoneRepresentation`]: ../enum.NoneRepresentation.html
+  fn serialize_none(self) -> Result<Value, Error> {
+    match (self.absent, self.none) {
+      (Some(absent), NoneRepresentation::Absent) => {
+        absent.set(true);
+        self.serialize_unit()
+      },
+      (Some(_), NoneRepresentation::Empty) => self.serialize_str(""),
+      (None, _) => self.serialize_unit(),
+    }
+  }
+  #[inline]
+  fn serialize_some<T>(self, value: &T) -> Result<Value, Error>
+    where T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+  //-----------------------------------------------------------------------------------------------
+  // Сериализация структурных элементов
+  //-----------------------------------------------------------------------------------------------
+  #[inline]
+  fn serialize_unit(self) -> Result<Value, Error> {
+    Ok(Value::default())
+  }
+  #[inline]
+  fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+    self.serialize_unit()
+  }
+  /// Разбирает в newtype структуру нижележащее значение. Если имя структуры совпадает с
+  /// [`LOC_STRING_TOKEN`], то значение превращается в `Value::LocString`, а если с
+  /// [`RESREF_TOKEN`] -- в `Value::ResRef`
+  ///
+  /// [`LOC_STRING_TOKEN`]: ../constant.LOC_STRING_TOKEN.html
+  /// [`RESREF_TOKEN`]: ../../de/constant.RESREF_TOKEN.html
+  fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Value, Error>
+    where T: ?Sized + Serialize,
+  {
+    let value = value.serialize(self)?;
+    if name == LOC_STRING_TOKEN {
+      if let Value::Void(data) = value {
+        return Ok(Value::LocString(parse_loc_string(&data)?));
+      }
+      return Err(Error::Serialize("LocString data expected as byte array".into()));
+    }
+    if name == RESREF_TOKEN {
+      if let Value::Void(data) = value {
+        return Ok(Value::ResRef(ResRef(data)));
+      }
+      return Err(Error::Serialize("ResRef data expected as byte array".into()));
+    }
+    Ok(value)
+  }
+  #[inline]
+  fn serialize_tuple(self, len: usize) -> Result<SerializeList, Error> {
+    Ok(SerializeList { none: self.none, items: Vec::with_capacity(len) })
+  }
+  #[inline]
+  fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeList, Error> {
+    self.serialize_tuple(len)
+  }
+  #[inline]
+  fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeStruct, Error> {
+    Ok(SerializeStruct { none: self.none, tag: 0, fields: IndexMap::with_capacity(len), label: None })
+  }
+  //-----------------------------------------------------------------------------------------------
+  // Сериализация последовательностей и отображений
+  //-----------------------------------------------------------------------------------------------
+  #[inline]
+  fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, Error> {
+    self.serialize_tuple(len.unwrap_or(0))
+  }
+  #[inline]
+  fn serialize_map(self, len: Option<usize>) -> Result<SerializeStruct, Error> {
+    self.serialize_struct("", len.unwrap_or(0))
+  }
+  //-----------------------------------------------------------------------------------------------
+  // Сериализация компонентов перечисления
+  //-----------------------------------------------------------------------------------------------
+  /// Сериализует вариант перечисления, как строку `variant`
+  #[inline]
+  fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Value, Error> {
+    self.serialize_str(variant)
+  }
+  /// Сериализует `value` как структуру с одним полем с именем `variant` и значением `value`
+  fn serialize_newtype_variant<T>(self, name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<Value, Error>
+    where T: ?Sized + Serialize,
+  {
+    let mut ser = self.serialize_struct(name, 1)?;
+    ser::SerializeStruct::serialize_field(&mut ser, variant, value)?;
+    ser::SerializeStruct::end(ser)
+  }
+  #[inline]
+  fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeTupleVariant, Error> {
+    Ok(SerializeVariant { label: variant.parse()?, inner: self.serialize_tuple(len)? })
+  }
+  #[inline]
+  fn serialize_struct_variant(self, name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeStructVariant, Error> {
+    Ok(SerializeVariant { label: variant.parse()?, inner: self.serialize_struct(name, len)? })
+  }
+}
+
+/// Собирает элементы последовательности в `Value::List`
+pub(crate) struct SerializeList {
+  /// Способ представления отсутствующих значений в полях структур элементов
+  none: NoneRepresentation,
+  /// Уже сериализованные элементы
+  items: Vec<Value>,
+}
+
+impl ser::SerializeSeq for SerializeList {
+  type Ok = Value;
+  type Error = Error;
+
+  #[inline]
+  fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    self.items.push(value.serialize(ValueSerializer::new(self.none))?);
+    Ok(())
+  }
+  #[inline]
+  fn end(self) -> Result<Value, Error> { Ok(Value::List(self.items)) }
+}
+impl ser::SerializeTuple for SerializeList {
+  type Ok = Value;
+  type Error = Error;
+
+  #[inline]
+  fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    ser::SerializeSeq::serialize_element(self, value)
+  }
+  #[inline]
+  fn end(self) -> Result<Value, Error> { ser::SerializeSeq::end(self) }
+}
+impl ser::SerializeTupleStruct for SerializeList {
+  type Ok = Value;
+  type Error = Error;
+
+  #[inline]
+  fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    ser::SerializeSeq::serialize_element(self, value)
+  }
+  #[inline]
+  fn end(self) -> Result<Value, Error> { ser::SerializeSeq::end(self) }
+}
+
+/// Собирает поля структуры или записи отображения в `Value::Struct`. Значение поля или записи
+/// с ключом [`TAG_FIELD`] становится идентификатором типа структуры
+///
+/// [`TAG_FIELD`]: ../../de/constant.TAG_FIELD.html
+pub(crate) struct SerializeStruct {
+  /// Способ представления отсутствующих значений в полях структуры
+  none: NoneRepresentation,
+  /// Идентификатор типа структуры
+  tag: u32,
+  /// Уже сериализованные поля
+  fields: IndexMap<Label, Value>,
+  /// Метка, полученная последним вызовом `serialize_key`
+  label: Option<Label>,
+}
+
+impl ser::SerializeStruct for SerializeStruct {
+  type Ok = Value;
+  type Error = Error;
+
+  #[inline]
+  fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    if key == TAG_FIELD {
+      self.tag = value.serialize(TagSerializer)?;
+      return Ok(());
+    }
+    let label = key.parse()?;
+    self.insert(label, value)
+  }
+  #[inline]
+  fn end(self) -> Result<Value, Error> { Ok(Value::Struct { tag: self.tag, fields: self.fields }) }
+}
+impl ser::SerializeMap for SerializeStruct {
+  type Ok = Value;
+  type Error = Error;
+
+  #[inline]
+  fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    self.label = Some(key.serialize(LabelSerializer)?);
+    Ok(())
+  }
+  #[inline]
+  fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    let error = || Error::Serialize("`SerializeMap::serialize_key` must be called before `SerializeMap::serialize_value`".into());
+    let label = self.label.take().ok_or_else(error)?;
+    if label.as_ref() == TAG_FIELD.as_bytes() {
+      self.tag = value.serialize(TagSerializer)?;
+      return Ok(());
+    }
+    self.insert(label, value)
+  }
+  #[inline]
+  fn end(self) -> Result<Value, Error> { Ok(Value::Struct { tag: self.tag, fields: self.fields }) }
+}
+impl SerializeStruct {
+  /// Сериализует значение поля и добавляет его в структуру, если оно не должно быть пропущено
+  fn insert<T>(&mut self, label: Label, value: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    let absent = Cell::new(false);
+    let value = value.serialize(ValueSerializer::field(self.none, &absent))?;
+    if !absent.get() {
+      self.fields.insert(label, value);
+    }
+    Ok(())
+  }
+}
+
+/// Оборачивает содержимое варианта перечисления в структуру с единственным полем,
+/// метка которого равна имени варианта
+pub(crate) struct SerializeVariant<S> {
+  /// Метка поля, соответствующая имени варианта
+  label: Label,
+  /// Сериализатор содержимого варианта
+  inner: S,
+}
+/// Создает структуру с единственным полем `label`, хранящим значение `value`
+#[inline]
+fn variant(label: Label, value: Value) -> Value {
+  let mut fields = IndexMap::with_capacity(1);
+  fields.insert(label, value);
+  Value::Struct { tag: 0, fields }
+}
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeList> {
+  type Ok = Value;
+  type Error = Error;
+
+  #[inline]
+  fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    ser::SerializeSeq::serialize_element(&mut self.inner, value)
+  }
+  #[inline]
+  fn end(self) -> Result<Value, Error> {
+    Ok(variant(self.label, ser::SerializeSeq::end(self.inner)?))
+  }
+}
+impl ser::SerializeStructVariant for SerializeVariant<SerializeStruct> {
+  type Ok = Value;
+  type Error = Error;
+
+  #[inline]
+  fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where T: ?Sized + Serialize,
+  {
+    ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+  }
+  #[inline]
+  fn end(self) -> Result<Value, Error> {
+    Ok(variant(self.label, ser::SerializeStruct::end(self.inner)?))
+  }
+}
+
+/// Сериализатор ключей отображения в метки полей
+struct LabelSerializer;
+
+impl ser::Serializer for LabelSerializer {
+  type Ok = Label;
+  type Error = Error;
+
+  type SerializeSeq = Impossible<Label, Error>;
+  type SerializeTuple = Impossible<Label, Error>;
+  type SerializeTupleStruct = Impossible<Label, Error>;
+  type SerializeTupleVariant = Impossible<Label, Error>;
+  type SerializeMap = Impossible<Label, Error>;
+  type SerializeStruct = Impossible<Label, Error>;
+  type SerializeStructVariant = Impossible<Label, Error>;
+
+  unsupported!(serialize_i8(i8) -> Label);
+  unsupported!(serialize_u8(u8) -> Label);
+  unsupported!(serialize_i16(i16) -> Label);
+  unsupported!(serialize_u16(u16) -> Label);
+  unsupported!(serialize_i32(i32) -> Label);
+  unsupported!(serialize_u32(u32) -> Label);
+  unsupported!(serialize_i64(i64) -> Label);
+  unsupported!(serialize_u64(u64) -> Label);
+
+  unsupported!(serialize_f32(f32) -> Label);
+  unsupported!(serialize_f64(f64) -> Label);
+
+  unsupported!(serialize_bool(bool) -> Label);
+  #[inline]
+  fn serialize_char(self, v: char) -> Result<Label, Error> {
+    self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+  }
+  #[inline]
+  fn serialize_str(self, v: &str) -> Result<Label, Error> {
+    v.parse()
+  }
+  #[inline]
+  fn serialize_bytes(self, v: &[u8]) -> Result<Label, Error> {
+    Label::from_bytes(v)
+  }
+
+  unsupported!(serialize_none() -> Label);
+  #[inline]
+  fn serialize_some<T>(self, value: &T) -> Result<Label, Error>
+    where T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+  unsupported!(serialize_unit() -> Label);
+  unsupported!(serialize_unit_struct(&'static str) -> Label);
+  #[inline]
+  fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Label, Error>
+    where T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+  unsupported!(serialize_tuple(usize) -> Self::SerializeTuple);
+  unsupported!(serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct);
+  unsupported!(serialize_struct(&'static str, usize) -> Self::SerializeStruct);
+  unsupported!(serialize_seq(Option<usize>) -> Self::SerializeSeq);
+  unsupported!(serialize_map(Option<usize>) -> Self::SerializeMap);
+  #[inline]
+  fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Label, Error> {
+    self.serialize_str(variant)
+  }
+  fn serialize_newtype_variant<T>(self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T) -> Result<Label, Error>
+    where T: ?Sized + Serialize,
+  {
+    Err(Error::Serialize("`serialize_newtype_variant` can't be represented as GFF value".into()))
+  }
+  unsupported!(serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant);
+  unsupported!(serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use serde::Serialize;
+  use crate::{GffString, Language, Gender, LocStringBuilder, StrRef};
+  use crate::value::{SimpleValue, Value};
+  use crate::ser::NoneRepresentation;
+  use super::ValueSerializer;
+
+  /// Проверяет, что каждый вариант простого значения сериализуется так же, как аналогичный
+  /// вариант `Value`
+  #[test]
+  fn simple_value() {
+    let values = vec![
+      SimpleValue::Byte(1),
+      SimpleValue::Char(-2),
+      SimpleValue::Word(3),
+      SimpleValue::Short(-4),
+      SimpleValue::Dword(5),
+      SimpleValue::Int(-6),
+      SimpleValue::Dword64(7),
+      SimpleValue::Int64(-8),
+      SimpleValue::Float(9.5),
+      SimpleValue::Double(-10.5),
+      SimpleValue::String("string".into()),
+      SimpleValue::ResRef("resref".into()),
+      SimpleValue::LocString(LocStringBuilder::new().add(Language::English, Gender::Male, "text").build()),
+      SimpleValue::Void(vec![1, 2, 3]),
+    ];
+    for value in values {
+      let expected: Value = value.clone().into();
+      assert_eq!(value.serialize(ValueSerializer::new(NoneRepresentation::Absent)).expect("can't serialize value"), expected);
+    }
+  }
+
+  /// Проверяет, что отсутствующие значения полей обрабатываются так же, как GFF сериализатором,
+  /// и результат разбирается обратно в исходное значение
+  #[test]
+  fn none() {
+    use serde::Deserialize;
+    use crate::value::{from_value, to_value, to_value_with_none};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Test { first: u8, value: Option<u8>, last: u8 }
+
+    let test = Test { first: 1, value: None, last: 2 };
+    let value = to_value(&test).expect("can't serialize value");
+    assert_eq!(value.get("value"), None);
+    assert_eq!(from_value::<Test>(value).expect("can't deserialize value"), test);
+
+    let value = to_value_with_none(&test, NoneRepresentation::Empty).expect("can't serialize value");
+    assert_eq!(value["value"], Value::String("".into()));
+
+    // На верхнем уровне отсутствующее значение представляется структурой без полей
+    assert_eq!(to_value(&None::<u8>).expect("can't serialize value"), Value::default());
+  }
+
+  #[test]
+  fn gff_string_external() {
+    let value = GffString::External(StrRef(42));
+    assert_eq!(value.serialize(ValueSerializer::new(NoneRepresentation::Absent)).expect("can't serialize value"), Value::Dword(42));
+  }
+
+  /// Строка во внутреннем представлении с ключами, добавленными не в порядке возрастания
+  fn internal() -> GffString {
+    let mut strings = HashMap::new();
+    strings.insert((Language::French, Gender::Male).into(), "Bonjour".to_owned());
+    strings.insert((Language::English, Gender::Female).into(), "Hello, lady".to_owned());
+    GffString::Internal(strings)
+  }
+
+  /// Проверяет, что внутреннее представление сериализуется в отображение с числовыми ключами
+  /// в порядке их возрастания. Ключами GFF структур могут быть только строки, поэтому
+  /// результат проверяется с помощью JSON
+  #[cfg(feature = "json")]
+  #[test]
+  fn gff_string_internal() {
+    let json = serde_json::to_string(&internal()).expect("can't serialize value");
+    assert_eq!(json, r#"{"1":"Hello, lady","2":"Bonjour"}"#);
+  }
+
+  /// Проверяет, что внутреннее представление не может быть записано в GFF напрямую, так как
+  /// ключами отображения являются числа, а при использовании модуля `locstring` записывается
+  /// в поле типа `LocString`
+  #[test]
+  fn gff_string_internal_gff() {
+    use crate::error::Error;
+    use crate::ser::to_vec;
+    use crate::value::to_value;
+
+    match to_value(&internal()) {
+      Err(Error::Serialize(msg)) => assert_eq!(msg, "`serialize_u32` can't be represented as GFF value"),
+      res => panic!("expected Error::Serialize, but {:?} found", res),
+    }
+
+    #[derive(Serialize)]
+    struct Plain { text: GffString }
+    assert!(to_vec((*b"GFF ").into(), &Plain { text: internal() }).is_err());
+
+    #[derive(Serialize)]
+    struct Helper {
+      #[serde(with = "crate::locstring")]
+      text: GffString,
+    }
+    let value = to_value(&Helper { text: internal() }).expect("can't serialize value");
+    match value["text"].clone() {
+      Value::LocString(string) => {
+        assert_eq!(string.str_ref, StrRef::invalid());
+        assert_eq!(GffString::from(string), internal());
+      },
+      value => panic!("expected Value::LocString, but {:?} found", value),
+    }
+  }
+
+  /// Проверяет, что отображение, в которое сериализуется внутреннее представление,
+  /// десериализуется обратно в ту же строку
+  #[test]
+  fn gff_string_internal_symmetric() {
+    use serde::de::Deserialize;
+    use serde::de::value::{Error as DeError, MapDeserializer};
+
+    let pairs = vec![(1u32, "Hello, lady".to_owned()), (2, "Bonjour".to_owned())];
+    let de = MapDeserializer::<_, DeError>::new(pairs.into_iter());
+    assert_eq!(GffString::deserialize(de).expect("can't deserialize value"), internal());
+  }
+}
@@ -1,7 +1,12 @@
 //! Содержит реализацию структуры, описывающей сигнатуру GFF файла, реализацию типажей для
 //! конвертации других типов данных в сигнатуру и обратно и известные форматы файлов
 
+use std::convert::TryFrom;
 use std::io::{Read, Write, Result};
+use std::result;
+use std::str::FromStr;
+
+use crate::error::Error;
 
 /// Определяет назначение файла. Сигнатура записана в первых 4-х байтах файла на диске
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -69,6 +74,75 @@ impl Signature {
   pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
     writer.write_all(self.as_ref())
   }
+  /// Возвращает расширение, с которым принято хранить файлы данного вида на диске, без
+  /// точки. Для неизвестных видов файлов (`Other`) возвращает пустую строку
+  pub fn extension(&self) -> &'static str {
+    use self::Signature::*;
+
+    match *self {
+      IFO => "ifo",
+
+      ARE => "are",
+      GIT => "git",
+      GIC => "gic",
+
+      UTC => "utc",
+      UTD => "utd",
+      UTE => "ute",
+      UTI => "uti",
+      UTP => "utp",
+      UTS => "uts",
+      UTM => "utm",
+      UTT => "utt",
+      UTW => "utw",
+
+      DLG => "dlg",
+      JRL => "jrl",
+      FAC => "fac",
+      ITP => "itp",
+
+      PTM => "ptm",
+      PTT => "ptt",
+
+      BIC => "bic",
+
+      Other(_) => "",
+    }
+  }
+  /// Возвращает человекочитаемое описание назначения файла данного вида
+  pub fn description(&self) -> &'static str {
+    use self::Signature::*;
+
+    match *self {
+      IFO => "Информация о модуле",
+
+      ARE => "Описание области",
+      GIT => "Инстанции игровых объектов и динамические свойства области",
+      GIC => "Комментарий к области",
+
+      UTC => "Шаблон (blueprint) существа",
+      UTD => "Шаблон (blueprint) двери",
+      UTE => "Шаблон (blueprint) схватки (encounter)",
+      UTI => "Шаблон (blueprint) предмета",
+      UTP => "Шаблон (blueprint) размещаемого объекта окружения (placeable)",
+      UTS => "Шаблон (blueprint) звука",
+      UTM => "Шаблон (blueprint) магазина",
+      UTT => "Шаблон (blueprint) триггера",
+      UTW => "Шаблон (blueprint) навигационной точки (waypoint)",
+
+      DLG => "Диалог",
+      JRL => "Журнал заданий",
+      FAC => "Описания фракций",
+      ITP => "Палитра",
+
+      PTM => "Файл мастера сценариев: plot instance/plot manager file",
+      PTT => "Файл мастера сценариев: plot wizard blueprint",
+
+      BIC => "Параметры существа или игрового персонажа, создаваемые игрой",
+
+      Other(_) => "Прочие виды файлов",
+    }
+  }
 }
 
 impl From<[u8; 4]> for Signature {
@@ -107,6 +181,30 @@ impl From<[u8; 4]> for Signature {
   }
 }
 
+/// Создает сигнатуру из 3 или 4 ASCII символов. Сигнатура из 3 символов дополняется
+/// пробелом до 4 байт, поэтому `"UTC"` и `"UTC "` дают одну и ту же сигнатуру
+impl FromStr for Signature {
+  type Err = Error;
+
+  fn from_str(value: &str) -> result::Result<Self, Error> {
+    let bytes = value.as_bytes();
+    if !value.is_ascii() || bytes.len() < 3 || bytes.len() > 4 {
+      return Err(Error::InvalidSignature(value.to_owned()));
+    }
+    let mut sig = [b' '; 4];
+    sig[..bytes.len()].copy_from_slice(bytes);
+    Ok(sig.into())
+  }
+}
+impl<'a> TryFrom<&'a str> for Signature {
+  type Error = Error;
+
+  #[inline]
+  fn try_from(value: &'a str) -> result::Result<Self, Error> {
+    value.parse()
+  }
+}
+
 impl AsRef<[u8]> for Signature {
   fn as_ref(&self) -> &[u8] {
     use self::Signature::*;
@@ -142,3 +240,45 @@ impl AsRef<[u8]> for Signature {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::Signature;
+
+  #[test]
+  fn extension() {
+    assert_eq!(Signature::UTC.extension(), "utc");
+    assert_eq!(Signature::DLG.extension(), "dlg");
+    assert_eq!(Signature::from(*b"BIC ").extension(), "bic");
+    assert_eq!(Signature::Other(*b"GFF ").extension(), "");
+
+    // Расширение совпадает с сигнатурой в нижнем регистре
+    let sig = Signature::UTP;
+    assert_eq!(sig.extension().as_bytes(), &sig.as_ref()[..3].to_ascii_lowercase()[..]);
+  }
+
+  #[test]
+  fn from_str() {
+    use std::convert::TryFrom;
+    use crate::error::Error;
+
+    assert_eq!("UTC".parse::<Signature>().unwrap(), Signature::UTC);
+    assert_eq!("UTC ".parse::<Signature>().unwrap(), Signature::UTC);
+    assert_eq!("GFF".parse::<Signature>().unwrap(), Signature::Other(*b"GFF "));
+    assert_eq!(Signature::try_from("DLG").unwrap(), Signature::DLG);
+
+    for sig in &["TOOLONG", "UT", "", "ЮТС"] {
+      match sig.parse::<Signature>() {
+        Err(Error::InvalidSignature(ref s)) if s == sig => {},
+        res => panic!("expected Error::InvalidSignature, but {:?} found", res),
+      }
+    }
+  }
+
+  #[test]
+  fn description() {
+    assert_eq!(Signature::UTI.description(), "Шаблон (blueprint) предмета");
+    assert_eq!(Signature::JRL.description(), "Журнал заданий");
+    assert_eq!(Signature::Other(*b"GFF ").description(), "Прочие виды файлов");
+  }
+}
@@ -1,7 +1,11 @@
 //! Содержит реализации структур, описывающих строки, хранящиеся в GFF файле
 use std::fmt;
-use std::mem::transmute;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use encoding::EncodingRef;
+use encoding::all::{BIG5_2003, GBK, UTF_8, WINDOWS_1250, WINDOWS_1252, WINDOWS_31J, WINDOWS_949};
+
+use crate::error::{Error, Result};
 
 /// Маска, определяющая идентификатор строки
 const USER_TLK_MASK: u32 = 0x8000_0000;
@@ -11,6 +15,26 @@ const USER_TLK_MASK: u32 = 0x8000_0000;
 pub struct StrRef(pub(crate) u32);
 
 impl StrRef {
+  /// Создает ссылку на строку с указанным индексом в основном TLK файле игры или, если `user`
+  /// равен `true`, в TLK файле модуля. Старший бит индекса игнорируется
+  #[inline]
+  pub fn new(code: u32, user: bool) -> Self {
+    StrRef(code & !USER_TLK_MASK).with_user(user)
+  }
+  /// Создает ссылку, не указывающую ни на какую строку (`0xFFFFFFFF`). Такая ссылка хранится
+  /// в локализуемых строках, текст которых содержится в самом GFF файле
+  #[inline]
+  pub fn invalid() -> Self { StrRef(0xFFFFFFFF) }
+  /// Возвращает ссылку на строку с тем же индексом в TLK файле модуля, если `user` равен `true`,
+  /// или в основном TLK файле игры в противном случае
+  #[inline]
+  pub fn with_user(self, user: bool) -> Self {
+    if user {
+      StrRef(self.0 | USER_TLK_MASK)
+    } else {
+      StrRef(self.0 & !USER_TLK_MASK)
+    }
+  }
   /// Определяет, является ли строка индексом не из основного TLK файла игры, а из TLK
   /// файла модуля. Строка является строкой из TLK файла модуля, если старший бит в ее
   /// идентификаторе взведен
@@ -22,6 +46,12 @@ impl StrRef {
   pub fn code(&self) -> u32 { self.0 & !USER_TLK_MASK }
 }
 
+impl From<u32> for StrRef {
+  /// Создает ссылку на строку из значения, хранимого в GFF файле, включая бит TLK файла модуля
+  #[inline]
+  fn from(value: u32) -> Self { StrRef(value) }
+}
+
 impl fmt::Debug for StrRef {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "code: {}, user: {}", self.code(), self.is_user())
@@ -53,6 +83,87 @@ pub enum Language {
   /// Японский
   Japanese= 131,
 }
+impl Language {
+  /// Все известные языки
+  pub(crate) const ALL: [Language; 10] = [
+    Language::English, Language::French, Language::German, Language::Italian, Language::Spanish,
+    Language::Polish, Language::Korean, Language::ChineseTraditional, Language::ChineseSimplified,
+    Language::Japanese,
+  ];
+
+  /// Кодировка, в которой движок Aurora хранит текст на данном языке в локализуемых строках
+  pub fn encoding(self) -> EncodingRef {
+    use self::Language::*;
+
+    match self {
+      English | French | German | Italian | Spanish => WINDOWS_1252,
+      Polish             => WINDOWS_1250,
+      Korean             => WINDOWS_949,
+      ChineseTraditional => BIG5_2003,
+      ChineseSimplified  => GBK,
+      Japanese           => WINDOWS_31J,
+    }
+  }
+}
+
+/// Преобразует код языка, хранимый в ключе многоязыковой строки, в язык.
+///
+/// # Ошибки
+/// Для неизвестных кодов возвращает ошибку [`Error::UnknownLanguage`]
+///
+/// [`Error::UnknownLanguage`]: error/enum.Error.html#variant.UnknownLanguage
+impl TryFrom<u32> for Language {
+  type Error = Error;
+
+  fn try_from(value: u32) -> Result<Self> {
+    use self::Language::*;
+
+    Ok(match value {
+        0 => English,
+        1 => French,
+        2 => German,
+        3 => Italian,
+        4 => Spanish,
+        5 => Polish,
+      128 => Korean,
+      129 => ChineseTraditional,
+      130 => ChineseSimplified,
+      131 => Japanese,
+      _ => return Err(Error::UnknownLanguage(value)),
+    })
+  }
+}
+
+/// Игры, использующие GFF файлы. Определяет кодировки, в которых игра хранит строки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Game {
+  /// Neverwinter Nights. Строки хранятся в однобайтовых кодовых страницах Windows, а части
+  /// локализуемых строк -- в кодировке своего языка (см. [`Language::encoding`])
+  ///
+  /// [`Language::encoding`]: enum.Language.html#method.encoding
+  Nwn1,
+  /// Neverwinter Nights 2. Все строки хранятся в кодировке `UTF-8`
+  Nwn2,
+  /// The Witcher. Все строки хранятся в кодировке `UTF-8`
+  Witcher,
+}
+impl Game {
+  /// Кодировка, в которой игра хранит строки, не привязанные к языку
+  pub fn encoding(self) -> EncodingRef {
+    match self {
+      Game::Nwn1 => WINDOWS_1252,
+      Game::Nwn2 | Game::Witcher => UTF_8,
+    }
+  }
+  /// Определяет, хранит ли игра части локализуемых строк в кодировках их языков
+  /// (см. [`Language::encoding`]), а не в общей кодировке строк
+  ///
+  /// [`Language::encoding`]: enum.Language.html#method.encoding
+  #[inline]
+  pub fn uses_language_encodings(self) -> bool {
+    self == Game::Nwn1
+  }
+}
 
 /// Виды пола персонажа, на которых могут храниться локализованные строки в объекте `LocString`
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -70,15 +181,36 @@ pub enum Gender {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct StringKey(pub(crate) u32);
 impl StringKey {
-  /// Язык, на котором записан текст этой части многоязыковой строки
-  pub fn language(&self) -> Language { unsafe { transmute(self.0 >> 1) } }
+  /// Создает ключ для строки на указанном языке для персонажа указанного пола. В отличие от
+  /// преобразования из кортежа `(Language, Gender)` может использоваться в константах
+  #[inline]
+  pub const fn new(language: Language, gender: Gender) -> Self {
+    StringKey(((language as u32) << 1) | gender as u32)
+  }
+  /// Язык, на котором записан текст этой части многоязыковой строки.
+  ///
+  /// # Ошибки
+  /// Если код языка не соответствует ни одному из известных языков, возвращает ошибку
+  /// [`Error::UnknownLanguage`] с прочитанным кодом языка. Такие коды встречаются в файлах
+  /// NWN2 и в поврежденных файлах
+  ///
+  /// [`Error::UnknownLanguage`]: error/enum.Error.html#variant.UnknownLanguage
+  pub fn language(&self) -> Result<Language> {
+    Language::try_from(self.0 >> 1)
+  }
   /// Пол персонажа, для которого написан текст этой части многоязыковой строки
-  pub fn gender(&self) -> Gender { unsafe { transmute(self.0 % 2) } }
+  pub fn gender(&self) -> Gender {
+    if self.0 & 1 == 0 { Gender::Male } else { Gender::Female }
+  }
+  /// Значение ключа в том виде, в котором оно хранится в GFF файле. Позволяет без потерь
+  /// сохранить ключ с неизвестным кодом языка
+  #[inline]
+  pub fn raw(&self) -> u32 { self.0 }
 }
 impl From<(Language, Gender)> for StringKey {
   #[inline]
   fn from(value: (Language, Gender)) -> Self {
-    StringKey(((value.0 as u32) << 1) | value.1 as u32)
+    StringKey::new(value.0, value.1)
   }
 }
 /// Преобразует ключ в число, в котором он храниться в GFF файле по формуле:
@@ -126,6 +258,56 @@ pub struct LocString {
   pub strings: Vec<SubString>,
 }
 
+/// Построитель локализуемой строки, упаковывающий язык и пол каждой части в ключ
+/// [`StringKey`] самостоятельно.
+///
+/// # Пример
+/// ```rust
+/// use serde_gff::{Gender, Language, LocStringBuilder};
+///
+/// let string = LocStringBuilder::new()
+///   .add(Language::English, Gender::Male, "Hello")
+///   .add(Language::French, Gender::Female, "Bonjour, madame")
+///   .build();
+/// assert_eq!(string.strings.len(), 2);
+/// ```
+///
+/// [`StringKey`]: struct.StringKey.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocStringBuilder {
+  /// Строящаяся строка
+  string: LocString,
+}
+impl LocStringBuilder {
+  /// Создает построитель строки без ссылки на TLK файл (`StrRef(0xFFFFFFFF)`) и без частей
+  #[inline]
+  pub fn new() -> Self {
+    LocStringBuilder { string: LocString { str_ref: StrRef::invalid(), strings: Vec::new() } }
+  }
+  /// Устанавливает ссылку на строку в TLK файле
+  #[inline]
+  pub fn str_ref(mut self, str_ref: StrRef) -> Self {
+    self.string.str_ref = str_ref;
+    self
+  }
+  /// Добавляет текст строки на указанном языке для персонажа указанного пола. Части
+  /// записываются в порядке добавления
+  #[inline]
+  pub fn add<S: Into<String>>(mut self, language: Language, gender: Gender, text: S) -> Self {
+    self.string.strings.push(SubString { key: (language, gender).into(), string: text.into() });
+    self
+  }
+  /// Возвращает построенную строку
+  #[inline]
+  pub fn build(self) -> LocString {
+    self.string
+  }
+}
+impl Default for LocStringBuilder {
+  #[inline]
+  fn default() -> Self { Self::new() }
+}
+
 /// Локализуемая строка, представленная в виде, в котором некорректные значения
 /// непредставимы.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -137,6 +319,51 @@ pub enum GffString {
   /// языка и пола персонажа
   Internal(HashMap<StringKey, String>),
 }
+impl GffString {
+  /// Возвращает текст строки на указанном языке для персонажа указанного пола, если он есть.
+  /// Для внешнего представления всегда возвращает `None`, так как текст хранится в TLK файле
+  pub fn get(&self, language: Language, gender: Gender) -> Option<&str> {
+    match self {
+      GffString::External(_) => None,
+      GffString::Internal(strings) => strings.get(&(language, gender).into()).map(String::as_str),
+    }
+  }
+  /// Возвращает текст строки, наиболее подходящий для указанного языка. Варианты перебираются
+  /// в следующем порядке:
+  /// - указанный язык, мужской пол;
+  /// - указанный язык, женский пол;
+  /// - английский язык, мужской пол;
+  /// - английский язык, женский пол.
+  ///
+  /// Для внешнего представления всегда возвращает `None`, так как текст хранится в TLK файле
+  pub fn best(&self, language: Language) -> Option<&str> {
+    self.get(language, Gender::Male)
+      .or_else(|| self.get(language, Gender::Female))
+      .or_else(|| self.get(Language::English, Gender::Male))
+      .or_else(|| self.get(Language::English, Gender::Female))
+  }
+}
+impl fmt::Display for GffString {
+  /// Выводит внешнее представление строки в виде `StrRef(<code>)`, а для внутреннего --
+  /// текст строки на английском языке для персонажа мужского пола. Если такой строки нет,
+  /// выводится строка с наименьшим ключом (или ничего, если строк нет совсем)
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    use self::GffString::*;
+
+    match self {
+      External(str_ref) => write!(f, "StrRef({})", str_ref.code()),
+      Internal(strings) => {
+        let english = StringKey::from((Language::English, Gender::Male));
+        let string = strings.get(&english)
+          .or_else(|| strings.iter().min_by_key(|(k, _)| k.0).map(|(_, v)| v));
+        match string {
+          Some(string) => write!(f, "{}", string),
+          None => Ok(()),
+        }
+      },
+    }
+  }
+}
 impl From<LocString> for GffString {
   /// Преобразует вариант строки, наиболее приближенный к хранимому в файле варианту (и, таким
   /// образом, хранящий без потерь все содержимое файла) в вариант строки, в котором компилятор
@@ -169,8 +396,191 @@ impl From<GffString> for LocString {
       External(str_ref) => LocString { str_ref, strings: vec![] },
       Internal(strings) => {
         let strings = strings.into_iter().map(Into::into).collect();
-        LocString { str_ref: StrRef(0xFFFFFFFF), strings }
+        LocString { str_ref: StrRef::invalid(), strings }
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use super::*;
+
+  #[test]
+  fn str_ref() {
+    assert!(StrRef::new(5, true).is_user());
+    assert_eq!(StrRef::new(5, true).code(), 5);
+    assert!(!StrRef::new(5, false).is_user());
+    assert_eq!(StrRef::new(5, false), StrRef::from(5));
+
+    // Старший бит индекса не влияет на принадлежность к TLK файлу модуля
+    assert!(!StrRef::new(0x8000_0005, false).is_user());
+    assert_eq!(StrRef::new(0x8000_0005, false).code(), 5);
+    assert_eq!(StrRef::from(0x8000_0005), StrRef::new(5, true));
+
+    assert_eq!(StrRef::new(5, true).with_user(false), StrRef::new(5, false));
+    assert_eq!(StrRef::new(5, false).with_user(true), StrRef::new(5, true));
+    assert_eq!(StrRef::invalid(), StrRef::from(0xFFFFFFFF));
+    assert_eq!(StrRef::invalid().code(), 0x7FFF_FFFF);
+  }
+
+  #[test]
+  fn loc_string_builder() {
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::ser::to_vec;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Storage { text: LocString }
+
+    let text = LocStringBuilder::new()
+      .str_ref(StrRef::from(42))
+      .add(Language::English, Gender::Male, "Hello")
+      .add(Language::German, Gender::Female, "Hallo, Frau")
+      .build();
+    assert_eq!(text.str_ref, StrRef(42));
+    assert_eq!(text.strings, vec![
+      SubString { key: StringKey(0), string: "Hello".into() },
+      SubString { key: StringKey(2 << 1 | 1), string: "Hallo, Frau".into() },
+    ]);
+
+    let storage = Storage { text };
+    let data = to_vec((*b"GFF ").into(), &storage).expect("can't serialize LocString");
+    let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+    assert_eq!(Storage::deserialize(&mut de).expect("can't deserialize LocString"), storage);
+  }
+
+  #[test]
+  fn gff_string_get() {
+    let mut strings = HashMap::new();
+    strings.insert((Language::French, Gender::Female).into(), "Bonjour, madame".to_owned());
+    let string = GffString::Internal(strings);
+
+    assert_eq!(string.get(Language::French, Gender::Female), Some("Bonjour, madame"));
+    assert_eq!(string.get(Language::French, Gender::Male), None);
+    assert_eq!(GffString::External(StrRef(42)).get(Language::English, Gender::Male), None);
+  }
+
+  #[test]
+  fn gff_string_best() {
+    let mut strings = HashMap::new();
+    let best = |s: &HashMap<_, _>| GffString::Internal(s.clone()).best(Language::German).map(str::to_owned);
+
+    assert_eq!(best(&strings), None);
+    strings.insert((Language::English, Gender::Female).into(), "Hello, lady".to_owned());
+    assert_eq!(best(&strings).as_deref(), Some("Hello, lady"));
+    strings.insert((Language::English, Gender::Male).into(), "Hello".to_owned());
+    assert_eq!(best(&strings).as_deref(), Some("Hello"));
+    strings.insert((Language::German, Gender::Female).into(), "Hallo, Frau".to_owned());
+    assert_eq!(best(&strings).as_deref(), Some("Hallo, Frau"));
+    strings.insert((Language::German, Gender::Male).into(), "Hallo".to_owned());
+    assert_eq!(best(&strings).as_deref(), Some("Hallo"));
+
+    assert_eq!(GffString::External(StrRef(42)).best(Language::English), None);
+  }
+
+  #[test]
+  fn gff_string_display() {
+    assert_eq!(GffString::External(StrRef(42)).to_string(), "StrRef(42)");
+    assert_eq!(GffString::External(StrRef(0x8000_0000 | 42)).to_string(), "StrRef(42)");
+
+    let mut strings = HashMap::new();
+    strings.insert((Language::French, Gender::Male).into(), "Bonjour".to_owned());
+    assert_eq!(GffString::Internal(strings.clone()).to_string(), "Bonjour");
+
+    strings.insert((Language::English, Gender::Male).into(), "Hello".to_owned());
+    strings.insert((Language::English, Gender::Female).into(), "Hi".to_owned());
+    assert_eq!(GffString::Internal(strings).to_string(), "Hello");
+
+    assert_eq!(GffString::Internal(HashMap::new()).to_string(), "");
+  }
+
+  #[test]
+  fn string_key_parts() {
+    let key: StringKey = (Language::Japanese, Gender::Female).into();
+    assert_eq!(key.language().unwrap(), Language::Japanese);
+    assert_eq!(key.gender(), Gender::Female);
+    assert_eq!(key.raw(), 131 << 1 | 1);
+    assert_eq!(Language::try_from(131).unwrap(), Language::Japanese);
+
+    let key = StringKey(200 << 1);
+    match key.language() {
+      Err(Error::UnknownLanguage(code)) => assert_eq!(code, 200),
+      result => panic!("expected Error::UnknownLanguage, but {:?} found", result),
+    }
+    assert_eq!(key.gender(), Gender::Male);
+    assert_eq!(key.raw(), 400);
+  }
+
+  /// Проверяет, что ключ для любого сочетания языка и пола раскладывается обратно на те же части
+  #[test]
+  fn string_key_round_trip() {
+    const GERMAN_FEMALE: StringKey = StringKey::new(Language::German, Gender::Female);
+    assert_eq!(GERMAN_FEMALE.raw(), 2 << 1 | 1);
+
+    for &language in Language::ALL.iter() {
+      for &gender in &[Gender::Male, Gender::Female] {
+        let key = StringKey::new(language, gender);
+        assert_eq!(key, StringKey::from((language, gender)));
+        assert_eq!(key.language().unwrap(), language, "{:?}", key);
+        assert_eq!(key.gender(), gender, "{:?}", key);
+      }
+    }
+  }
+
+  #[test]
+  fn loc_string_duplicates() {
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::ser::to_vec;
+    use crate::value::{from_value, Value};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Test { name: LocString }
+
+    let english: StringKey = (Language::English, Gender::Male).into();
+    let value = Test {
+      name: LocString {
+        str_ref: StrRef(42),
+        strings: vec![
+          SubString { key: english.clone(), string: "Hello".into() },
+          SubString { key: (Language::French, Gender::Male).into(), string: "Bonjour".into() },
+          SubString { key: english.clone(), string: "Hi".into() },
+        ],
       },
+    };
+    let data = to_vec((*b"GFF ").into(), &value).unwrap();
+    let mut de = Deserializer::new(Cursor::new(data)).unwrap();
+    assert_eq!(Test::deserialize(&mut de).unwrap(), value);
+
+    let pair = Value::List(vec![Value::Dword(english.raw()), Value::String("Hello".into())]);
+    assert_eq!(from_value::<SubString>(pair).unwrap(), value.name.strings[0]);
+    assert_eq!(from_value::<StrRef>(Value::Dword(42)).unwrap(), StrRef(42));
+  }
+
+  #[test]
+  fn gff_string_deserialize() {
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::de::Deserializer;
+    use crate::ser::to_vec;
+
+    #[derive(Serialize)]
+    struct Write { name: LocString }
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Read { name: GffString }
+
+    let mut strings = HashMap::new();
+    strings.insert((Language::English, Gender::Male).into(), "Hello".to_owned());
+    strings.insert((Language::French, Gender::Female).into(), "Bonjour".to_owned());
+
+    for name in [GffString::External(StrRef(42)), GffString::Internal(strings)] {
+      let data = to_vec((*b"GFF ").into(), &Write { name: name.clone().into() }).unwrap();
+      let mut de = Deserializer::new(Cursor::new(data)).unwrap();
+      assert_eq!(Read::deserialize(&mut de).unwrap(), Read { name });
     }
   }
 }
@@ -1,8 +1,15 @@
 //! Содержит описания значений, которые может хранить GFF файл
 
+use std::iter::FromIterator;
+use std::ops::Index;
 use indexmap::IndexMap;
+use serde::Serialize;
+use serde::de::{DeserializeOwned, IntoDeserializer};
 
-use crate::{Label, LocString, ResRef};
+use crate::{GffString, Label, LocString, ResRef};
+use crate::error::{Error, Result};
+use crate::ser::{NoneRepresentation, ValueSerializer};
+use crate::raw::FieldType;
 use crate::index::{U64Index, I64Index, F64Index, StringIndex, ResRefIndex, LocStringIndex, BinaryIndex};
 
 /// Перечисление, представляющее все примитивные типы данных, который может хранить GFF файл.
@@ -76,6 +83,30 @@ pub enum SimpleValueRef {
   /// Тег, ассоциированный с типом, равен `13`.
   Void(BinaryIndex),
 }
+impl SimpleValueRef {
+  /// Возвращает тип поля, значение которого представлено данной ссылкой. Позволяет узнать
+  /// тип значения, не читая его данные, хранящиеся в других областях файла
+  pub fn field_type(&self) -> FieldType {
+    use self::SimpleValueRef::*;
+
+    match *self {
+      Byte(_)      => FieldType::Byte,
+      Char(_)      => FieldType::Char,
+      Word(_)      => FieldType::Word,
+      Short(_)     => FieldType::Short,
+      Dword(_)     => FieldType::Dword,
+      Int(_)       => FieldType::Int,
+      Dword64(_)   => FieldType::Dword64,
+      Int64(_)     => FieldType::Int64,
+      Float(_)     => FieldType::Float,
+      Double(_)    => FieldType::Double,
+      String(_)    => FieldType::String,
+      ResRef(_)    => FieldType::ResRef,
+      LocString(_) => FieldType::LocString,
+      Void(_)      => FieldType::Void,
+    }
+  }
+}
 
 /// Перечисление, представляющее все примитивные типы данных, который может хранить GFF файл.
 /// В отличие от [`SimpleValueRef`] содержит полностью прочитанные данные из файла, а не только
@@ -224,7 +255,12 @@ pub enum Value {
   /// Вложенная структура. Порядок полей в структуре постоянный и определяется порядком их добавления
   ///
   /// Тег, ассоциированный с типом, равен `14`.
-  Struct(IndexMap<Label, Value>),
+  Struct {
+    /// Идентификатор типа структуры, прочитанный из файла. Если он неизвестен, равен `0`
+    tag: u32,
+    /// Поля структуры
+    fields: IndexMap<Label, Value>,
+  },
   /// Список значений любой длины.
   ///
   /// Тег, ассоциированный с типом, равен `15`.
@@ -254,3 +290,935 @@ impl From<SimpleValue> for Value {
     }
   }
 }
+
+/// Реализует преобразование в `Value` из примитивного типа, хранящегося в указанном варианте
+macro_rules! from_primitive {
+  ($($type:ty => $variant:ident,)*) => ($(
+    impl From<$type> for Value {
+      #[inline]
+      fn from(value: $type) -> Value { Value::$variant(value) }
+    }
+  )*);
+}
+from_primitive!(
+  u8     => Byte,
+  i8     => Char,
+  u16    => Word,
+  i16    => Short,
+  u32    => Dword,
+  i32    => Int,
+  u64    => Dword64,
+  i64    => Int64,
+  f32    => Float,
+  f64    => Double,
+  String => String,
+);
+impl From<&str> for Value {
+  #[inline]
+  fn from(value: &str) -> Value { Value::String(value.to_owned()) }
+}
+
+/// Пустая структура
+impl Default for Value {
+  #[inline]
+  fn default() -> Value { Value::Struct { tag: 0, fields: IndexMap::new() } }
+}
+/// Собирает структуру с идентификатором типа `0` из пар "метка - значение поля" в порядке их следования
+impl FromIterator<(Label, Value)> for Value {
+  #[inline]
+  fn from_iter<I: IntoIterator<Item = (Label, Value)>>(iter: I) -> Value {
+    Value::Struct { tag: 0, fields: iter.into_iter().collect() }
+  }
+}
+/// Собирает список из значений его элементов
+impl FromIterator<Value> for Value {
+  #[inline]
+  fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Value {
+    Value::List(iter.into_iter().collect())
+  }
+}
+
+/// Статистика по дереву значений, собираемая методом [`Value::stats`]. Может использоваться
+/// для оценки сложности документа или для отсечения патологически больших или глубоких
+/// документов перед их обработкой
+///
+/// [`Value::stats`]: enum.Value.html#method.stats
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ValueStats {
+  /// Общее количество узлов в дереве, включая корневой, структуры, списки и все примитивные значения
+  pub nodes: usize,
+  /// Максимальная глубина вложенности. Примитивное значение имеет глубину 1, структура или
+  /// список -- на 1 больше максимальной глубины своих элементов
+  pub max_depth: usize,
+  /// Количество списков в дереве
+  pub list_count: usize,
+  /// Количество структур в дереве, включая корневую
+  pub struct_count: usize,
+}
+
+/// Итератор по элементам структуры или списка, возвращаемый методом [`Value::iter`]. Для полей
+/// структуры возвращает их метки и значения, для элементов списка -- `None` и значения
+///
+/// [`Value::iter`]: enum.Value.html#method.iter
+#[derive(Debug, Clone)]
+pub struct Iter<'a>(IterImpl<'a>);
+
+/// Внутреннее представление итератора, зависящее от вида обходимого значения
+#[derive(Debug, Clone)]
+enum IterImpl<'a> {
+  /// Обход полей структуры
+  Struct(indexmap::map::Iter<'a, Label, Value>),
+  /// Обход элементов списка
+  List(std::slice::Iter<'a, Value>),
+  /// Значение не является контейнером и не содержит элементов
+  Empty,
+}
+
+impl<'a> Iterator for Iter<'a> {
+  type Item = (Option<&'a Label>, &'a Value);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    match self.0 {
+      IterImpl::Struct(ref mut iter) => iter.next().map(|(k, v)| (Some(k), v)),
+      IterImpl::List(ref mut iter) => iter.next().map(|v| (None, v)),
+      IterImpl::Empty => None,
+    }
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    match self.0 {
+      IterImpl::Struct(ref iter) => iter.size_hint(),
+      IterImpl::List(ref iter) => iter.size_hint(),
+      IterImpl::Empty => (0, Some(0)),
+    }
+  }
+}
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+/// Реализация 64-битной хеш-функции FNV-1a, используемая для вычисления хеша содержимого
+/// [`Value::content_hash`]. В отличие от стандартного хешера, ее результат зафиксирован и не
+/// зависит от версии компилятора
+///
+/// [`Value::content_hash`]: enum.Value.html#method.content_hash
+struct Fnv1a(u64);
+impl Fnv1a {
+  /// Создает хешер в начальном состоянии
+  #[inline]
+  fn new() -> Self { Fnv1a(0xcbf2_9ce4_8422_2325) }
+  /// Добавляет к хешу указанные байты
+  fn write(&mut self, bytes: &[u8]) {
+    for &b in bytes {
+      self.0 ^= b as u64;
+      self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+  }
+  /// Добавляет к хешу длину в виде 8 байт в порядке little-endian
+  #[inline]
+  fn write_len(&mut self, len: usize) {
+    self.write(&(len as u64).to_le_bytes());
+  }
+  /// Добавляет к хешу длину указанных байт и сами байты
+  #[inline]
+  fn write_bytes(&mut self, bytes: &[u8]) {
+    self.write_len(bytes.len());
+    self.write(bytes);
+  }
+}
+
+impl Value {
+  /// Возвращает значение поля структуры с указанной меткой. Если значение не является
+  /// структурой или в ней нет такого поля, возвращает `None`
+  pub fn get(&self, label: &str) -> Option<&Value> {
+    let label: Label = label.parse().ok()?;
+    self.as_struct()?.get(&label)
+  }
+  /// Возвращает элемент списка с указанным индексом. Если значение не является списком
+  /// или индекс выходит за его пределы, возвращает `None`
+  pub fn get_index(&self, index: usize) -> Option<&Value> {
+    self.as_list()?.get(index)
+  }
+  /// Возвращает значение беззнакового целого, если значение является `Byte`, `Word` или `Dword`
+  pub fn as_u32(&self) -> Option<u32> {
+    match *self {
+      Value::Byte(val)  => Some(val.into()),
+      Value::Word(val)  => Some(val.into()),
+      Value::Dword(val) => Some(val),
+      _ => None,
+    }
+  }
+  /// Возвращает текст, если значение является строкой `String` или ссылкой на ресурс `ResRef`,
+  /// содержащей корректную строку UTF-8
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Value::String(val) => Some(val),
+      Value::ResRef(val) => val.as_str().ok(),
+      _ => None,
+    }
+  }
+  /// Возвращает элементы списка, если значение является списком
+  pub fn as_list(&self) -> Option<&[Value]> {
+    match self {
+      Value::List(val) => Some(val),
+      _ => None,
+    }
+  }
+  /// Возвращает поля структуры, если значение является структурой
+  pub fn as_struct(&self) -> Option<&IndexMap<Label, Value>> {
+    match self {
+      Value::Struct { fields, .. } => Some(fields),
+      _ => None,
+    }
+  }
+  /// Возвращает количество полей структуры или элементов списка. Для остальных значений
+  /// возвращает `None`
+  pub fn len(&self) -> Option<usize> {
+    match self {
+      Value::Struct { fields, .. } => Some(fields.len()),
+      Value::List(val) => Some(val.len()),
+      _ => None,
+    }
+  }
+  /// Возвращает `true`, если структура не содержит полей или список не содержит элементов.
+  /// Для остальных значений возвращает `None`
+  #[inline]
+  pub fn is_empty(&self) -> Option<bool> {
+    self.len().map(|len| len == 0)
+  }
+  /// Возвращает итератор по полям структуры или элементам списка. Для полей структуры итератор
+  /// возвращает их метки, для элементов списка -- `None`. Для остальных значений итератор пуст
+  pub fn iter(&self) -> Iter<'_> {
+    Iter(match self {
+      Value::Struct { fields, .. } => IterImpl::Struct(fields.iter()),
+      Value::List(val) => IterImpl::List(val.iter()),
+      _ => IterImpl::Empty,
+    })
+  }
+  /// Возвращает название типа значения, совпадающее с названием варианта перечисления
+  pub fn type_name(&self) -> &'static str {
+    match *self {
+      Value::Byte(_)      => "Byte",
+      Value::Char(_)      => "Char",
+      Value::Word(_)      => "Word",
+      Value::Short(_)     => "Short",
+      Value::Dword(_)     => "Dword",
+      Value::Int(_)       => "Int",
+      Value::Dword64(_)   => "Dword64",
+      Value::Int64(_)     => "Int64",
+      Value::Float(_)     => "Float",
+      Value::Double(_)    => "Double",
+      Value::String(_)    => "String",
+      Value::ResRef(_)    => "ResRef",
+      Value::LocString(_) => "LocString",
+      Value::Void(_)      => "Void",
+      Value::Struct {..}  => "Struct",
+      Value::List(_)      => "List",
+    }
+  }
+  /// Возвращает `true`, если значение является структурой
+  #[inline]
+  pub fn is_struct(&self) -> bool {
+    matches!(self, Value::Struct {..})
+  }
+  /// Возвращает `true`, если значение является списком
+  #[inline]
+  pub fn is_list(&self) -> bool {
+    matches!(self, Value::List(_))
+  }
+  /// Возвращает `true`, если значение является нелокализуемой строкой `String`
+  #[inline]
+  pub fn is_string(&self) -> bool {
+    matches!(self, Value::String(_))
+  }
+  /// Возвращает `true`, если значение является целым числом любого размера (включая `Char`)
+  /// или числом с плавающей запятой
+  pub fn is_number(&self) -> bool {
+    use self::Value::*;
+
+    matches!(self, Byte(_) | Char(_) | Word(_) | Short(_) | Dword(_) | Int(_) | Dword64(_) | Int64(_) | Float(_) | Double(_))
+  }
+  /// Разбирает каждый элемент списка, хранящегося в поле `label` структуры, в значение
+  /// типа `T` с помощью функции [`from_value`].
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку, если значение не является структурой, не содержит поля с указанной
+  /// меткой, значение поля не является списком или какой-либо элемент не удалось разобрать
+  ///
+  /// [`from_value`]: fn.from_value.html
+  pub fn list_of<T>(&self, label: &str) -> Result<Vec<T>>
+    where T: DeserializeOwned,
+  {
+    let field = self.get(label).ok_or_else(|| Error::Deserialize(format!("Field `{}` not found", label)))?;
+    let list = field.as_list().ok_or_else(|| Error::Deserialize(format!("Field `{}`: expected list, but {:?} found", label, field)))?;
+    list.iter().cloned().map(from_value).collect()
+  }
+  /// Добавляет в структуру поле `key` с локализуемой строкой, преобразуя ее в представление
+  /// [`LocString`], в котором она хранится в файле. Если поле с такой меткой уже существует,
+  /// его значение заменяется.
+  ///
+  /// # Ошибки
+  /// Возвращает ошибку, если значение не является структурой или метка длиннее 16 байт
+  ///
+  /// [`LocString`]: ../struct.LocString.html
+  pub fn insert_loc(&mut self, key: &str, loc: GffString) -> Result<()> {
+    let label = key.parse()?;
+    match self {
+      Value::Struct { fields, .. } => {
+        fields.insert(label, Value::LocString(loc.into()));
+        Ok(())
+      },
+      value => Err(Error::Serialize(format!("Field `{}`: cannot insert into non-struct value {:?}", key, value))),
+    }
+  }
+  /// Накладывает значение `other` на данное. Если оба значения являются структурами, поля
+  /// `other` добавляются в данную структуру, а поля с совпадающими метками заменяются; при
+  /// этом, если оба значения поля также являются структурами, они объединяются рекурсивно.
+  /// Новые поля добавляются в конец структуры, заменяемые поля сохраняют свое место.
+  /// Идентификатор типа структуры заменяется идентификатором `other`, если тот не равен `0`.
+  ///
+  /// Во всех остальных случаях, в том числе для списков, данное значение целиком заменяется
+  /// значением `other`
+  pub fn merge(&mut self, other: Value) {
+    match (self, other) {
+      (Value::Struct { tag, fields }, Value::Struct { tag: other_tag, fields: other }) => {
+        if other_tag != 0 {
+          *tag = other_tag;
+        }
+        for (label, value) in other {
+          match fields.get_mut(&label) {
+            Some(field) => field.merge(value),
+            None => { fields.insert(label, value); },
+          }
+        }
+      },
+      (this, other) => *this = other,
+    }
+  }
+  /// Подсчитывает количество узлов различных видов в дереве значений и его максимальную глубину
+  pub fn stats(&self) -> ValueStats {
+    let mut stats = ValueStats::default();
+    stats.max_depth = self.collect_stats(&mut stats);
+    stats
+  }
+  /// Вычисляет хеш содержимого дерева значений. Хеш структуры не зависит от порядка ее полей,
+  /// а хеш списка, напротив, учитывает порядок элементов. Таким образом, два документа с
+  /// одинаковым содержимым, но разным порядком полей, имеют одинаковый хеш.
+  ///
+  /// Хеш вычисляется 64-битным алгоритмом FNV-1a по следующему представлению значения:
+  /// - тег типа поля (см. [`FieldType`]) в виде 4 байт;
+  /// - числа -- в виде байт в порядке little-endian, числа с плавающей точкой -- в виде их
+  ///   битового представления;
+  /// - строки, ссылки на ресурсы и двоичные данные -- длина в виде 8 байт, затем сами байты;
+  /// - локализуемые строки -- ссылка на строку в TLK, количество частей в виде 8 байт, затем
+  ///   для каждой части ее ключ и строка;
+  /// - структуры -- идентификатор типа, количество полей в виде 8 байт, затем поля в порядке
+  ///   возрастания меток, каждое в виде 16 байт метки, дополненной нулями, и значения;
+  /// - списки -- количество элементов в виде 8 байт, затем элементы.
+  ///
+  /// Все многобайтовые числа записываются в порядке little-endian, поэтому хеш не зависит ни
+  /// от платформы, ни от версии компилятора и его можно сохранять для кеширования и
+  /// обнаружения изменений
+  ///
+  /// [`FieldType`]: ../raw/enum.FieldType.html
+  pub fn content_hash(&self) -> u64 {
+    let mut hasher = Fnv1a::new();
+    self.hash_content(&mut hasher);
+    hasher.0
+  }
+  /// Рекурсивно передает содержимое дерева в `state`, упорядочивая поля структур по меткам
+  fn hash_content(&self, state: &mut Fnv1a) {
+    use self::Value::*;
+
+    let tag = match self {
+      Byte(_)      => FieldType::Byte,
+      Char(_)      => FieldType::Char,
+      Word(_)      => FieldType::Word,
+      Short(_)     => FieldType::Short,
+      Dword(_)     => FieldType::Dword,
+      Int(_)       => FieldType::Int,
+      Dword64(_)   => FieldType::Dword64,
+      Int64(_)     => FieldType::Int64,
+      Float(_)     => FieldType::Float,
+      Double(_)    => FieldType::Double,
+      String(_)    => FieldType::String,
+      ResRef(_)    => FieldType::ResRef,
+      LocString(_) => FieldType::LocString,
+      Void(_)      => FieldType::Void,
+      Struct { .. } => FieldType::Struct,
+      List(_)      => FieldType::List,
+    };
+    state.write(&(tag as u32).to_le_bytes());
+    match self {
+      Byte(val)      => state.write(&val.to_le_bytes()),
+      Char(val)      => state.write(&val.to_le_bytes()),
+      Word(val)      => state.write(&val.to_le_bytes()),
+      Short(val)     => state.write(&val.to_le_bytes()),
+      Dword(val)     => state.write(&val.to_le_bytes()),
+      Int(val)       => state.write(&val.to_le_bytes()),
+      Dword64(val)   => state.write(&val.to_le_bytes()),
+      Int64(val)     => state.write(&val.to_le_bytes()),
+      Float(val)     => state.write(&val.to_bits().to_le_bytes()),
+      Double(val)    => state.write(&val.to_bits().to_le_bytes()),
+      String(val)    => state.write_bytes(val.as_bytes()),
+      ResRef(val)    => state.write_bytes(&val.0),
+      LocString(val) => {
+        state.write(&val.str_ref.0.to_le_bytes());
+        state.write_len(val.strings.len());
+        for s in &val.strings {
+          state.write(&s.key.0.to_le_bytes());
+          state.write_bytes(s.string.as_bytes());
+        }
+      },
+      Void(val)      => state.write_bytes(val),
+      Struct { tag, fields } => {
+        state.write(&tag.to_le_bytes());
+        let mut fields: Vec<_> = fields.iter().collect();
+        fields.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+
+        state.write_len(fields.len());
+        for (label, value) in fields {
+          state.write(label.as_ref());
+          value.hash_content(state);
+        }
+      },
+      List(items) => {
+        state.write_len(items.len());
+        for item in items {
+          item.hash_content(state);
+        }
+      },
+    }
+  }
+  /// Обходит дерево значений в прямом порядке, вызывая `f` для каждого узла, включая корень.
+  /// Функция получает путь к узлу в виде меток полей структур и индексов элементов списков
+  /// (путь к корню пуст) и изменяемую ссылку на сам узел, что позволяет выполнять массовые
+  /// замены по всему документу.
+  ///
+  /// Функция вызывается для узла раньше, чем для его потомков, поэтому если она заменит
+  /// структуру или список, обход продолжится уже по новому содержимому
+  pub fn visit_mut(&mut self, f: &mut dyn FnMut(&[&str], &mut Value)) {
+    let mut path = Vec::new();
+    self.visit_mut_impl(&mut path, f);
+  }
+  /// Вызывает `f` для узла и рекурсивно для всех его потомков, пополняя путь `path`
+  fn visit_mut_impl(&mut self, path: &mut Vec<String>, f: &mut dyn FnMut(&[&str], &mut Value)) {
+    {
+      let segments: Vec<&str> = path.iter().map(String::as_str).collect();
+      f(&segments, self);
+    }
+    match self {
+      Value::Struct { fields, .. } => {
+        for (label, value) in fields {
+          path.push(label.to_string());
+          value.visit_mut_impl(path, f);
+          path.pop();
+        }
+      },
+      Value::List(items) => {
+        for (i, item) in items.iter_mut().enumerate() {
+          path.push(i.to_string());
+          item.visit_mut_impl(path, f);
+          path.pop();
+        }
+      },
+      _ => {},
+    }
+  }
+  /// Рекурсивно обходит дерево, накапливая статистику в `stats`. Возвращает глубину поддерева
+  fn collect_stats(&self, stats: &mut ValueStats) -> usize {
+    stats.nodes += 1;
+    match self {
+      Value::Struct { fields, .. } => {
+        stats.struct_count += 1;
+        1 + fields.values().map(|v| v.collect_stats(stats)).max().unwrap_or(0)
+      },
+      Value::List(items) => {
+        stats.list_count += 1;
+        1 + items.iter().map(|v| v.collect_stats(stats)).max().unwrap_or(0)
+      },
+      _ => 1,
+    }
+  }
+}
+
+/// Доступ к полю структуры по его метке.
+///
+/// # Паника
+/// Если значение не является структурой или не содержит поля с указанной меткой
+impl Index<&str> for Value {
+  type Output = Value;
+
+  fn index(&self, label: &str) -> &Value {
+    match self {
+      Value::Struct {..} => self.get(label).unwrap_or_else(|| panic!("field `{}` not found in struct", label)),
+      value => panic!("cannot access field `{}` of non-struct value {:?}", label, value),
+    }
+  }
+}
+/// Доступ к элементу списка по его индексу.
+///
+/// # Паника
+/// Если значение не является списком или индекс выходит за его пределы
+impl Index<usize> for Value {
+  type Output = Value;
+
+  fn index(&self, index: usize) -> &Value {
+    match self {
+      Value::List(list) => list.get(index).unwrap_or_else(|| panic!(
+        "index {} out of range for list of length {}", index, list.len()
+      )),
+      value => panic!("cannot access item {} of non-list value {:?}", index, value),
+    }
+  }
+}
+
+/// Преобразует значение в произвольный десериализуемый тип по тем же правилам, по которым
+/// GFF десериализатор разбирает поля структур
+#[inline]
+pub fn from_value<T>(value: Value) -> Result<T>
+  where T: DeserializeOwned,
+{
+  T::deserialize(IntoDeserializer::<Error>::into_deserializer(value))
+}
+/// Преобразует произвольное сериализуемое значение в `Value` по тем же правилам, по которым
+/// GFF сериализатор с настройками по умолчанию записывает поля структур. В частности, поля
+/// структур и значения карт, равные `None`, в результат не попадают
+/// (см. [`NoneRepresentation::Absent`])
+///
+/// [`NoneRepresentation::Absent`]: ../ser/enum.NoneRepresentation.html#variant.Absent
+#[inline]
+pub fn to_value<T>(value: &T) -> Result<Value>
+  where T: ?Sized + Serialize,
+{
+  to_value_with_none(value, NoneRepresentation::default())
+}
+/// Преобразует произвольное сериализуемое значение в `Value` так же, как [`to_value`], но
+/// представляет отсутствующие значения в полях структур указанным способом, так же, как
+/// GFF сериализатор с настройкой [`Serializer::set_none_representation`]
+///
+/// [`to_value`]: fn.to_value.html
+/// [`Serializer::set_none_representation`]: ../ser/struct.Serializer.html#method.set_none_representation
+#[inline]
+pub fn to_value_with_none<T>(value: &T, none: NoneRepresentation) -> Result<Value>
+  where T: ?Sized + Serialize,
+{
+  value.serialize(ValueSerializer::new(none))
+}
+
+#[cfg(test)]
+mod tests {
+  use indexmap::IndexMap;
+  use super::{Value, ValueStats};
+
+  /// Создает структуру из пар "метка - значение"
+  fn structure(fields: Vec<(&str, Value)>) -> Value {
+    let map: IndexMap<_, _> = fields.into_iter()
+      .map(|(k, v)| (k.parse().unwrap(), v))
+      .collect();
+    Value::Struct { tag: 0, fields: map }
+  }
+
+  #[test]
+  fn collect() {
+    let value: Value = vec![
+      ("byte".parse().unwrap(), 1u8.into()),
+      ("list".parse().unwrap(), vec![Value::default(), Value::from(2i32)].into_iter().collect()),
+      ("string".parse().unwrap(), "3".into()),
+    ].into_iter().collect();
+
+    assert_eq!(value, structure(vec![
+      ("byte", Value::Byte(1)),
+      ("list", Value::List(vec![Value::Struct { tag: 0, fields: IndexMap::new() }, Value::Int(2)])),
+      ("string", Value::String("3".into())),
+    ]));
+    assert_eq!(Value::from(-1i64), Value::Int64(-1));
+    assert_eq!(Value::from(0.5f64), Value::Double(0.5));
+    assert_eq!(Value::from(String::from("s")), Value::String("s".into()));
+  }
+
+  #[test]
+  fn merge_nested() {
+    let mut value = structure(vec![
+      ("byte", Value::Byte(1)),
+      ("struct", structure(vec![("a", Value::Int(2)), ("b", Value::Float(3.0))])),
+      ("list", Value::List(vec![Value::Byte(4), Value::Byte(5)])),
+    ]);
+    value.merge(structure(vec![
+      ("struct", structure(vec![("b", Value::Float(6.0)), ("c", Value::Char(7))])),
+      ("list", Value::List(vec![Value::Byte(8)])),
+      ("new", Value::Word(9)),
+    ]));
+
+    assert_eq!(value, structure(vec![
+      ("byte", Value::Byte(1)),
+      ("struct", structure(vec![("a", Value::Int(2)), ("b", Value::Float(6.0)), ("c", Value::Char(7))])),
+      // Списки заменяются целиком
+      ("list", Value::List(vec![Value::Byte(8)])),
+      ("new", Value::Word(9)),
+    ]));
+  }
+
+  #[test]
+  fn merge_mismatch() {
+    // Поле-структура заменяется простым значением и наоборот
+    let mut value = structure(vec![
+      ("struct", structure(vec![("a", Value::Int(2))])),
+      ("int", Value::Int(3)),
+    ]);
+    value.merge(structure(vec![
+      ("struct", Value::Int(4)),
+      ("int", structure(vec![("b", Value::Int(5))])),
+    ]));
+    assert_eq!(value, structure(vec![
+      ("struct", Value::Int(4)),
+      ("int", structure(vec![("b", Value::Int(5))])),
+    ]));
+
+    // Наложение не структуры на структуру заменяет ее целиком
+    value.merge(Value::List(vec![]));
+    assert_eq!(value, Value::List(vec![]));
+  }
+
+  #[test]
+  fn stats_of_primitive() {
+    let stats = Value::Byte(42).stats();
+    assert_eq!(stats, ValueStats { nodes: 1, max_depth: 1, list_count: 0, struct_count: 0 });
+  }
+
+  #[test]
+  fn stats_of_nested() {
+    // Root { byte, list: [Item { int }, Item {}], struct: Struct { string } }
+    let value = structure(vec![
+      ("byte", Value::Byte(1)),
+      ("list", Value::List(vec![
+        structure(vec![("int", Value::Int(2))]),
+        structure(vec![]),
+      ])),
+      ("struct", structure(vec![("string", Value::String("3".into()))])),
+    ]);
+
+    let stats = value.stats();
+    assert_eq!(stats, ValueStats {
+      nodes: 8,
+      max_depth: 4,
+      list_count: 1,
+      struct_count: 4,
+    });
+  }
+
+  #[test]
+  fn content_hash_ignores_field_order() {
+    let value1 = structure(vec![
+      ("byte", Value::Byte(1)),
+      ("struct", structure(vec![("a", Value::Int(2)), ("b", Value::Float(3.0))])),
+    ]);
+    let value2 = structure(vec![
+      ("struct", structure(vec![("b", Value::Float(3.0)), ("a", Value::Int(2))])),
+      ("byte", Value::Byte(1)),
+    ]);
+    assert_eq!(value1.content_hash(), value2.content_hash());
+
+    let value3 = structure(vec![("byte", Value::Char(1))]);
+    let value4 = structure(vec![("byte", Value::Byte(1))]);
+    assert_ne!(value3.content_hash(), value4.content_hash());
+  }
+
+  #[test]
+  fn content_hash_respects_list_order() {
+    let value1 = Value::List(vec![Value::Byte(1), Value::Byte(2)]);
+    let value2 = Value::List(vec![Value::Byte(2), Value::Byte(1)]);
+    assert_ne!(value1.content_hash(), value2.content_hash());
+    assert_eq!(value1.content_hash(), value1.clone().content_hash());
+  }
+
+  /// Проверяет, что хеш вычисляется по документированному алгоритму и не меняется со временем
+  #[test]
+  fn content_hash_is_stable() {
+    let value = structure(vec![
+      ("b", Value::Dword(42)),
+      ("a", Value::String("x".into())),
+    ]);
+    assert_eq!(value.content_hash(), 0x5cad_a7fe_fb98_2e4d);
+  }
+
+  /// Создает дерево, похожее на фрагмент файла существа
+  fn creature() -> Value {
+    use crate::ResRef;
+
+    structure(vec![
+      ("FirstName", Value::String("Aribeth".into())),
+      ("TemplateResRef", Value::ResRef(ResRef(b"aribeth".to_vec()))),
+      ("Tag", Value::Byte(7)),
+      ("ClassList", Value::List(vec![
+        structure(vec![("Class", Value::Int(3)), ("ClassLevel", Value::Word(10))]),
+      ])),
+    ])
+  }
+
+  #[test]
+  fn visit_mut() {
+    let mut value = creature();
+    let mut paths = Vec::new();
+    value.visit_mut(&mut |path, value| {
+      paths.push(path.join("/"));
+      if let Value::String(string) = value {
+        *string = string.to_uppercase();
+      }
+    });
+
+    assert_eq!(value["FirstName"], Value::String("ARIBETH".into()));
+    // Ссылки на ресурсы не являются строками и не изменяются
+    assert_eq!(value["TemplateResRef"].as_str(), Some("aribeth"));
+    assert_eq!(paths, vec![
+      "",
+      "FirstName",
+      "TemplateResRef",
+      "Tag",
+      "ClassList",
+      "ClassList/0",
+      "ClassList/0/Class",
+      "ClassList/0/ClassLevel",
+    ]);
+  }
+
+  #[test]
+  fn accessors() {
+    let value = creature();
+
+    assert_eq!(value.get("FirstName").and_then(Value::as_str), Some("Aribeth"));
+    assert_eq!(value.get("TemplateResRef").and_then(Value::as_str), Some("aribeth"));
+    assert_eq!(value.get("Tag").and_then(Value::as_u32), Some(7));
+    assert_eq!(value.get("Missing"), None);
+    assert_eq!(value.get("TooLongLabelForGff"), None);
+    assert_eq!(value.get_index(0), None);
+    assert_eq!(value.as_struct().map(|s| s.len()), Some(4));
+
+    let classes = &value["ClassList"];
+    assert_eq!(classes.as_list().map(|l| l.len()), Some(1));
+    assert_eq!(classes.get_index(0).and_then(|c| c.get("ClassLevel")).and_then(Value::as_u32), Some(10));
+    assert_eq!(classes[0]["Class"], Value::Int(3));
+    assert_eq!(classes[0]["Class"].as_u32(), None);
+    assert_eq!(classes.as_struct(), None);
+  }
+
+  #[test]
+  fn iter_struct() {
+    let value = creature();
+    assert_eq!(value.len(), Some(4));
+    assert_eq!(value.is_empty(), Some(false));
+
+    let iter = value.iter();
+    assert_eq!(iter.len(), 4);
+    let labels: Vec<_> = iter.map(|(label, _)| label.map(|l| l.to_string())).collect();
+    assert_eq!(labels, vec![
+      Some("FirstName".to_owned()),
+      Some("TemplateResRef".to_owned()),
+      Some("Tag".to_owned()),
+      Some("ClassList".to_owned()),
+    ]);
+    let (_, first) = value.iter().next().unwrap();
+    assert_eq!(first, &value["FirstName"]);
+
+    assert_eq!(Value::default().is_empty(), Some(true));
+    assert_eq!(Value::default().iter().next(), None);
+  }
+
+  #[test]
+  fn iter_list() {
+    let list: Value = vec![Value::Byte(1), Value::Int(-2)].into_iter().collect();
+    assert_eq!(list.len(), Some(2));
+    assert_eq!(list.is_empty(), Some(false));
+    let items: Vec<_> = list.iter().collect();
+    assert_eq!(items, vec![(None, &Value::Byte(1)), (None, &Value::Int(-2))]);
+
+    assert_eq!(Value::List(Vec::new()).is_empty(), Some(true));
+
+    // Примитивные значения не являются контейнерами
+    assert_eq!(Value::Byte(1).len(), None);
+    assert_eq!(Value::Byte(1).is_empty(), None);
+    assert_eq!(Value::Byte(1).iter().len(), 0);
+  }
+
+  #[test]
+  fn type_predicates() {
+    use crate::raw::FieldType;
+    use crate::{LocString, ResRef, StrRef};
+
+    let values = vec![
+      Value::Byte(1),
+      Value::Char(-1),
+      Value::Word(2),
+      Value::Short(-2),
+      Value::Dword(3),
+      Value::Int(-3),
+      Value::Dword64(4),
+      Value::Int64(-4),
+      Value::Float(5.0),
+      Value::Double(6.0),
+      Value::String("String".into()),
+      Value::ResRef(ResRef(b"resref".to_vec())),
+      Value::LocString(LocString { str_ref: StrRef(0), strings: Vec::new() }),
+      Value::Void(vec![1, 2]),
+      Value::default(),
+      Value::List(Vec::new()),
+    ];
+    // Названия типов совпадают с названиями типов полей, перечисленных в порядке их тегов
+    let names: Vec<_> = values.iter().map(Value::type_name).collect();
+    let expected: Vec<_> = FieldType::all().map(|t| t.name()).collect();
+    assert_eq!(names, expected);
+
+    for (i, value) in values.iter().enumerate() {
+      assert_eq!(value.is_number(), i < 10, "{}", value.type_name());
+      assert_eq!(value.is_string(), i == 10, "{}", value.type_name());
+      assert_eq!(value.is_struct(), i == 14, "{}", value.type_name());
+      assert_eq!(value.is_list(), i == 15, "{}", value.type_name());
+    }
+  }
+
+  #[test]
+  #[should_panic(expected = "field `Missing` not found in struct")]
+  fn index_missing_field() {
+    let _ = &creature()["Missing"];
+  }
+
+  #[test]
+  #[should_panic(expected = "cannot access item 0 of non-list value")]
+  fn index_non_list() {
+    let _ = &creature()[0];
+  }
+
+  #[test]
+  fn insert_loc() {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use crate::{GffString, Language, Gender, LocString, StrRef, SubString};
+    use crate::parser::Parser;
+    use crate::ser::to_vec;
+
+    let mut strings = HashMap::new();
+    strings.insert((Language::French, Gender::Female).into(), "Bonjour".to_owned());
+
+    let mut value = structure(vec![]);
+    value.insert_loc("Description", GffString::External(StrRef(42))).unwrap();
+    value.insert_loc("FirstName", GffString::Internal(strings)).unwrap();
+
+    assert!(value.insert_loc("TooLongLabelForGff", GffString::External(StrRef(0))).is_err());
+    assert!(Value::Byte(0).insert_loc("Name", GffString::External(StrRef(0))).is_err());
+
+    // Поля записываются в файл, как поля типа `LocString`
+    let data = to_vec((*b"GFF ").into(), &value).expect("can't serialize value");
+    let read = Parser::new(Cursor::new(data)).unwrap().read_all().expect("can't read value");
+    assert_eq!(read, value);
+    assert_eq!(read["Description"], Value::LocString(LocString { str_ref: StrRef(42), strings: vec![] }));
+    assert_eq!(read["FirstName"], Value::LocString(LocString {
+      str_ref: StrRef(0xFFFFFFFF),
+      strings: vec![SubString { key: (Language::French, Gender::Female).into(), string: "Bonjour".into() }],
+    }));
+  }
+
+  mod conversion {
+    use serde::{Serialize, Deserialize};
+    use crate::{LocString, StrRef, SubString, Language, Gender};
+    use crate::value::{from_value, to_value, Value};
+    use super::structure;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Kind { Unit, Newtype(u32), Tuple(u8, u8), Struct { level: u16 } }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Class { class: i32, level: u16 }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Creature {
+      name: String,
+      hp: i16,
+      plot: bool,
+      id: u64,
+      ratio: f32,
+      race: Option<u8>,
+      kinds: Vec<Kind>,
+      classes: Vec<Class>,
+      marker: (),
+    }
+
+    fn creature() -> Creature {
+      Creature {
+        name: "Aribeth".into(),
+        hp: -5,
+        plot: true,
+        id: 42,
+        ratio: 0.5,
+        race: Some(6),
+        kinds: vec![Kind::Unit, Kind::Newtype(1), Kind::Tuple(2, 3), Kind::Struct { level: 4 }],
+        classes: vec![Class { class: 3, level: 10 }],
+        marker: (),
+      }
+    }
+
+    #[test]
+    fn to_value_uses_gff_types() {
+      let value = to_value(&creature()).expect("can't convert to Value");
+
+      assert_eq!(value["name"], Value::String("Aribeth".into()));
+      assert_eq!(value["hp"], Value::Short(-5));
+      assert_eq!(value["plot"], Value::Byte(1));
+      assert_eq!(value["id"], Value::Dword64(42));
+      assert_eq!(value["ratio"], Value::Float(0.5));
+      assert_eq!(value["race"], Value::Byte(6));
+      assert_eq!(value["kinds"][0], Value::String("Unit".into()));
+      assert_eq!(value["kinds"][1], structure(vec![("Newtype", Value::Dword(1))]));
+      assert_eq!(value["kinds"][2], structure(vec![("Tuple", Value::List(vec![Value::Byte(2), Value::Byte(3)]))]));
+      assert_eq!(value["kinds"][3], structure(vec![("Struct", structure(vec![("level", Value::Word(4))]))]));
+      assert_eq!(value["classes"][0], structure(vec![("class", Value::Int(3)), ("level", Value::Word(10))]));
+      assert_eq!(value["marker"], structure(vec![]));
+    }
+
+    #[test]
+    fn round_trip() {
+      let value = to_value(&creature()).expect("can't convert to Value");
+      let result: Creature = from_value(value).expect("can't convert from Value");
+      assert_eq!(result, creature());
+    }
+
+    #[test]
+    fn loc_string() {
+      let string = LocString {
+        str_ref: StrRef(0xFFFFFFFF),
+        strings: vec![SubString { key: (Language::French, Gender::Female).into(), string: "Bonjour".into() }],
+      };
+      assert_eq!(to_value(&string).unwrap(), Value::LocString(string));
+    }
+
+    #[test]
+    fn list_of() {
+      use std::io::Cursor;
+      use crate::de::Deserializer;
+      use crate::ser::to_vec;
+
+      #[derive(Debug, PartialEq, Serialize, Deserialize)]
+      struct Item { tag: String, stack: u16 }
+      #[derive(Serialize)]
+      struct Inventory { name: String, items: Vec<Item> }
+
+      let items = vec![
+        Item { tag: "potion".into(), stack: 5 },
+        Item { tag: "sword".into(), stack: 1 },
+      ];
+      let inventory = Inventory { name: "Chest".into(), items };
+      let data = to_vec((*b"GFF ").into(), &inventory).expect("can't serialize value");
+      let mut de = Deserializer::new(Cursor::new(data)).expect("can't read GFF header");
+      let value = Value::deserialize(&mut de).expect("can't deserialize value");
+
+      assert_eq!(value.list_of::<Item>("items").unwrap(), inventory.items);
+      assert!(value.list_of::<Item>("missing").is_err());
+      assert!(value.list_of::<Item>("name").is_err());
+      assert!(value.list_of::<Class>("items").is_err());
+    }
+
+    #[test]
+    fn long_label() {
+      #[derive(Serialize)]
+      struct Long { this_label_is_too_long: u8 }
+
+      assert!(to_value(&Long { this_label_is_too_long: 0 }).is_err());
+    }
+  }
+}
@@ -1,8 +1,13 @@
 //! Содержит реализацию структуры, описывающей версию GFF файла, реализацию типажей для
 //! конвертации других типов данных в версию и обратно и известные версии файлов
 
+use std::cmp::Ordering;
 use std::fmt::{self, Display, Formatter};
 use std::io::{Read, Write, Result};
+use std::result;
+use std::str::FromStr;
+
+use crate::error::Error;
 
 /// Версия формата файла. Записана во вторых 4-х байтах файла, сразу после сигнатуры
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -41,9 +46,65 @@ impl Version {
   }
 }
 
+/// Версии упорядочиваются по старшему номеру, затем по младшему
+impl Ord for Version {
+  fn cmp(&self, other: &Self) -> Ordering {
+    // Сравниваем байты, а не номера, чтобы не паниковать на некорректных версиях из файла.
+    // Остальные байты сравниваются последними для согласованности с `Eq`
+    (self.0[1], self.0[3], self.0).cmp(&(other.0[1], other.0[3], other.0))
+  }
+}
+impl PartialOrd for Version {
+  #[inline]
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+/// Разбирает версию в формате `<major>.<minor>` или `V<major>.<minor>`, например, `3.2` или `V3.2`
+impl FromStr for Version {
+  type Err = Error;
+
+  fn from_str(value: &str) -> result::Result<Self, Error> {
+    let digits = value.strip_prefix('V').unwrap_or(value).as_bytes();
+    match *digits {
+      [major @ b'0'..=b'9', b'.', minor @ b'0'..=b'9'] => Ok(Version::new(major - b'0', minor - b'0')),
+      _ => Err(Error::InvalidVersion(value.to_owned())),
+    }
+  }
+}
+
 impl Display for Version {
   /// Выводит версию в поток в формате `<major>.<minor>`
   fn fmt(&self, f: &mut Formatter) -> fmt::Result {
     write!(f, "{}.{}", self.major(), self.minor())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::error::Error;
+  use super::Version;
+
+  #[test]
+  fn ordering() {
+    assert!(Version::V3_2 < Version::new(3, 3));
+    assert!(Version::new(3, 3) >= Version::V3_2);
+    assert!(Version::new(2, 9) < Version::V3_2);
+    assert_eq!(Version::V3_2.max(Version::new(3, 1)), Version::V3_2);
+  }
+
+  #[test]
+  fn from_str() {
+    assert_eq!("3.2".parse::<Version>().unwrap(), Version::V3_2);
+    assert_eq!("V3.2".parse::<Version>().unwrap(), Version::V3_2);
+    assert_eq!("V3.3".parse::<Version>().unwrap(), Version::new(3, 3));
+
+    for ver in &["", "3", "3.", "V32", "3.22", "v3.2", "VV3.2"] {
+      match ver.parse::<Version>() {
+        Err(Error::InvalidVersion(ref v)) if v == ver => {},
+        res => panic!("expected Error::InvalidVersion for {:?}, but {:?} found", ver, res),
+      }
+    }
+  }
+}
@@ -0,0 +1,382 @@
+//! Содержит реализацию представления GFF файла, которое заимствует байты файла и декодирует
+//! только те структуры, поля и значения, к которым происходит обращение.
+//!
+//! В отличие от [`Gff`] и [`Value`], при создании представления читается только заголовок
+//! файла, поэтому его удобно использовать для быстрого анализа большого количества файлов,
+//! из которых требуется лишь несколько значений.
+//!
+//! # Пример
+//! ```rust
+//! # use serde::Serialize;
+//! # use serde_gff::ser::to_vec;
+//! use serde_gff::value::SimpleValue;
+//! use serde_gff::view::{FieldValue, GffView};
+//!
+//! # #[derive(Serialize)]
+//! # struct Creature { name: String, hp: u16 }
+//! # let data = to_vec((*b"UTC ").into(), &Creature { name: "Aribeth".into(), hp: 10 }).unwrap();
+//! let view = GffView::new(&data).expect("can't read GFF header");
+//! let root = view.root().expect("can't read root struct");
+//! let hp = root.get("hp").unwrap().expect("field `hp` not found");
+//!
+//! match hp.value().unwrap() {
+//!   FieldValue::Simple(SimpleValue::Word(hp)) => assert_eq!(hp, 10),
+//!   value => panic!("unexpected value {:?}", value),
+//! }
+//! ```
+//!
+//! [`Gff`]: ../raw/struct.Gff.html
+//! [`Value`]: ../value/enum.Value.html
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Cursor;
+use encoding::{DecoderTrap, EncodingRef};
+
+use crate::Label;
+use crate::error::Result;
+use crate::index::{FieldIndex, FieldIndicesIndex, LabelIndex, ListIndicesIndex, StructIndex};
+use crate::parser::{Parser, Tag};
+use crate::raw::FieldType;
+use crate::string::Language;
+use crate::value::SimpleValue;
+
+/// Представление GFF файла, заимствующее его байты. Структуры, поля и их значения
+/// декодируются только при обращении к ним
+pub struct GffView<'a> {
+  /// Парсер, используемый для чтения отдельных записей файла по их индексам
+  parser: RefCell<Parser<Cursor<&'a [u8]>>>,
+}
+
+impl<'a> GffView<'a> {
+  /// Создает представление GFF файла с использованием кодировки `UTF-8` для декодирования
+  /// строк и генерацией ошибки в случае, если декодировать набор байт, как строку в этой
+  /// кодировке, не удалось.
+  ///
+  /// # Параметры
+  /// - `data`: Байты GFF файла
+  ///
+  /// # Ошибки
+  /// В случае, если не удалось прочитать заголовок GFF файла или если области, описываемые
+  /// заголовком, выходят за пределы файла
+  pub fn new(data: &'a [u8]) -> Result<Self> {
+    Ok(GffView { parser: RefCell::new(Parser::new(Cursor::new(data))?) })
+  }
+  /// Создает представление GFF файла с использованием указанной кодировки для декодирования строк.
+  ///
+  /// # Параметры
+  /// - `data`: Байты GFF файла
+  /// - `encoding`: Кодировка для декодирования символов в строках
+  /// - `trap`: Способ обработки символов в строках, которые не удалось декодировать с
+  ///   использованием выбранной кодировки
+  ///
+  /// # Ошибки
+  /// В случае, если не удалось прочитать заголовок GFF файла или если области, описываемые
+  /// заголовком, выходят за пределы файла
+  pub fn with_encoding(data: &'a [u8], encoding: EncodingRef, trap: DecoderTrap) -> Result<Self> {
+    let parser = Parser::with_encoding(Cursor::new(data), encoding, trap)?;
+    Ok(GffView { parser: RefCell::new(parser) })
+  }
+  /// Устанавливает кодировку для декодирования частей локализуемых строк на указанном языке.
+  /// См. [`Parser::set_language_encoding`]
+  ///
+  /// [`Parser::set_language_encoding`]: ../parser/struct.Parser.html#method.set_language_encoding
+  #[inline]
+  pub fn set_language_encoding(&mut self, language: Language, encoding: EncodingRef) {
+    self.parser.get_mut().set_language_encoding(language, encoding)
+  }
+  /// Устанавливает для всех известных языков кодировки, используемые движком Aurora.
+  /// См. [`Parser::use_language_encodings`]
+  ///
+  /// [`Parser::use_language_encodings`]: ../parser/struct.Parser.html#method.use_language_encodings
+  #[inline]
+  pub fn use_language_encodings(&mut self) {
+    self.parser.get_mut().use_language_encodings()
+  }
+  /// Читает описание корневой структуры файла
+  #[inline]
+  pub fn root(&self) -> Result<StructView<'_, 'a>> {
+    self.read_struct(StructIndex(0))
+  }
+
+  /// Читает описание структуры с указанным индексом
+  fn read_struct(&self, index: StructIndex) -> Result<StructView<'_, 'a>> {
+    let mut parser = self.parser.borrow_mut();
+    parser.seek(index)?;
+    let tag    = parser.read_u32()?;
+    let offset = parser.read_u32()?;
+    let count  = parser.read_u32()?;
+
+    Ok(StructView { view: self, tag: Tag(tag), offset, count })
+  }
+  /// Читает описание поля с указанным индексом, не читая его значения
+  fn read_field(&self, index: FieldIndex) -> Result<FieldView<'_, 'a>> {
+    let mut parser = self.parser.borrow_mut();
+    parser.seek(index)?;
+    let tag   = parser.read_u32()?;
+    let label = LabelIndex(parser.read_u32()?);
+
+    Ok(FieldView { view: self, index, tag, label })
+  }
+}
+
+impl<'a> fmt::Debug for GffView<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("GffView").finish()
+  }
+}
+
+/// Представление структуры GFF файла. Поля структуры читаются только при обращении к ним
+#[derive(Debug, Clone, Copy)]
+pub struct StructView<'v, 'a> {
+  /// Представление файла, которому принадлежит структура
+  view: &'v GffView<'a>,
+  /// Идентификатор типа структуры
+  tag: Tag,
+  /// Индекс единственного поля (если поле одно) или смещение в массиве индексов полей
+  offset: u32,
+  /// Количество полей структуры
+  count: u32,
+}
+
+impl<'v, 'a> StructView<'v, 'a> {
+  /// Идентификатор типа структуры
+  #[inline]
+  pub fn tag(&self) -> Tag { self.tag }
+  /// Количество полей структуры
+  #[inline]
+  pub fn len(&self) -> usize { self.count as usize }
+  /// Возвращает `true`, если структура не содержит полей
+  #[inline]
+  pub fn is_empty(&self) -> bool { self.count == 0 }
+  /// Читает описание поля структуры с указанным порядковым номером. Значение поля не читается.
+  /// Если номер выходит за пределы количества полей, возвращает `None`
+  pub fn field(&self, index: usize) -> Result<Option<FieldView<'v, 'a>>> {
+    if index >= self.len() {
+      return Ok(None);
+    }
+    let field = match self.count {
+      1 => FieldIndex(self.offset),
+      _ => {
+        let mut parser = self.view.parser.borrow_mut();
+        parser.seek(FieldIndicesIndex(self.offset, index as u32))?;
+        FieldIndex(parser.read_u32()?)
+      },
+    };
+    self.view.read_field(field).map(Some)
+  }
+  /// Возвращает итератор по описаниям полей структуры
+  pub fn fields(&self) -> impl Iterator<Item = Result<FieldView<'v, 'a>>> {
+    let this = *self;
+    (0..self.len()).filter_map(move |i| this.field(i).transpose())
+  }
+  /// Ищет поле структуры с указанной меткой. Для поиска читаются только метки полей этой
+  /// структуры, их значения не читаются. Если поле не найдено, возвращает `None`
+  pub fn get(&self, label: &str) -> Result<Option<FieldView<'v, 'a>>> {
+    let label: Label = match label.parse() {
+      Ok(label) => label,
+      // Слишком длинная метка не может присутствовать в файле
+      Err(_) => return Ok(None),
+    };
+    for field in self.fields() {
+      let field = field?;
+      if field.label()? == label {
+        return Ok(Some(field));
+      }
+    }
+    Ok(None)
+  }
+}
+
+/// Представление поля структуры GFF файла. Метка и значение поля читаются только при
+/// обращении к ним
+#[derive(Debug, Clone, Copy)]
+pub struct FieldView<'v, 'a> {
+  /// Представление файла, которому принадлежит поле
+  view: &'v GffView<'a>,
+  /// Индекс поля в массиве полей файла
+  index: FieldIndex,
+  /// Идентификатор типа поля
+  tag: u32,
+  /// Индекс метки поля
+  label: LabelIndex,
+}
+
+impl<'v, 'a> FieldView<'v, 'a> {
+  /// Читает метку поля
+  #[inline]
+  pub fn label(&self) -> Result<Label> {
+    self.view.parser.borrow_mut().read_label(self.label)
+  }
+  /// Читает значение поля. Для вложенных структур и списков читается только их описание
+  pub fn value(&self) -> Result<FieldValue<'v, 'a>> {
+    let mut parser = self.view.parser.borrow_mut();
+    // Пропускаем тег и метку поля
+    parser.seek(self.index)?;
+    parser.read_u32()?;
+    parser.read_u32()?;
+
+    match FieldType::from_u32(self.tag) {
+      Some(FieldType::Struct) => {// Структура
+        let index = StructIndex(parser.read_u32()?);
+        drop(parser);
+        self.view.read_struct(index).map(FieldValue::Struct)
+      },
+      Some(FieldType::List) => {// Список элементов
+        let index = ListIndicesIndex(parser.read_u32()?, 0);
+        parser.seek(index)?;
+        let count = parser.read_u32()?;
+        Ok(FieldValue::List(ListView { view: self.view, index, count }))
+      },
+      _ => {
+        let value = parser.read_value_ref(self.tag)?;
+        Ok(FieldValue::Simple(parser.read_value(value)?))
+      },
+    }
+  }
+}
+
+/// Значение поля структуры GFF файла
+#[derive(Debug, Clone)]
+pub enum FieldValue<'v, 'a> {
+  /// Значение простого типа, полностью прочитанное
+  Simple(SimpleValue),
+  /// Вложенная структура, поля которой еще не прочитаны
+  Struct(StructView<'v, 'a>),
+  /// Список структур, элементы которого еще не прочитаны
+  List(ListView<'v, 'a>),
+}
+
+/// Представление списка структур GFF файла. Элементы списка читаются только при обращении к ним
+#[derive(Debug, Clone, Copy)]
+pub struct ListView<'v, 'a> {
+  /// Представление файла, которому принадлежит список
+  view: &'v GffView<'a>,
+  /// Индекс начала списка в массиве индексов списков (указывает на длину списка)
+  index: ListIndicesIndex,
+  /// Количество элементов списка
+  count: u32,
+}
+
+impl<'v, 'a> ListView<'v, 'a> {
+  /// Количество элементов списка
+  #[inline]
+  pub fn len(&self) -> usize { self.count as usize }
+  /// Возвращает `true`, если список не содержит элементов
+  #[inline]
+  pub fn is_empty(&self) -> bool { self.count == 0 }
+  /// Читает описание элемента списка с указанным номером. Если номер выходит за пределы
+  /// списка, возвращает `None`
+  pub fn get(&self, index: usize) -> Result<Option<StructView<'v, 'a>>> {
+    if index >= self.len() {
+      return Ok(None);
+    }
+    let item = {
+      let mut parser = self.view.parser.borrow_mut();
+      // Первым элементом идет длина списка
+      parser.seek(self.index + (index as u32 + 1))?;
+      StructIndex(parser.read_u32()?)
+    };
+    self.view.read_struct(item).map(Some)
+  }
+  /// Возвращает итератор по элементам списка
+  pub fn iter(&self) -> impl Iterator<Item = Result<StructView<'v, 'a>>> {
+    let this = *self;
+    (0..self.len()).filter_map(move |i| this.get(i).transpose())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+  use serde::{Deserialize, Serialize};
+  use crate::de::Deserializer;
+  use crate::ser::to_vec;
+  use crate::value::{SimpleValue, Value};
+  use super::{FieldValue, GffView};
+
+  /// Формирует файл с большим количеством строковых полей, из которых только поле `f500`
+  /// содержит корректную строку в UTF-8, а остальные не могут быть декодированы
+  fn large_file() -> Vec<u8> {
+    let map: BTreeMap<_, _> = (0..1000)
+      .map(|i| (format!("f{}", i), if i == 500 { "target" } else { "xx" }))
+      .collect();
+    let mut data = to_vec((*b"GFF ").into(), &map).expect("can't serialize value");
+
+    // Портим данные всех строк, кроме искомой
+    let len = data.len();
+    for i in 0..len - 1 {
+      if &data[i..i + 2] == b"xx" {
+        data[i] = 0xFF;
+        data[i + 1] = 0xFF;
+      }
+    }
+    data
+  }
+
+  #[test]
+  fn decodes_only_accessed_fields() {
+    let data = large_file();
+
+    // Полное чтение файла невозможно из-за некорректных строк
+    let mut de = Deserializer::new(std::io::Cursor::new(&data[..])).expect("can't read GFF header");
+    assert!(Value::deserialize(&mut de).is_err());
+
+    let view = GffView::new(&data).expect("can't read GFF header");
+    let root = view.root().expect("can't read root struct");
+    assert_eq!(root.len(), 1000);
+
+    let field = root.get("f500").unwrap().expect("field `f500` not found");
+    match field.value().unwrap() {
+      FieldValue::Simple(SimpleValue::String(value)) => assert_eq!(value, "target"),
+      value => panic!("expected string, but {:?} found", value),
+    }
+    assert!(root.get("f501").unwrap().unwrap().value().is_err());
+    assert!(root.get("missing").unwrap().is_none());
+    assert!(root.get("too_long_label_for_gff").unwrap().is_none());
+  }
+
+  #[test]
+  fn navigation() {
+    #[derive(Serialize)]
+    struct Item { value: u32 }
+    #[derive(Serialize)]
+    struct Inner { value: i16 }
+    #[derive(Serialize)]
+    struct Root { inner: Inner, list: Vec<Item> }
+
+    let data = to_vec((*b"GFF ").into(), &Root {
+      inner: Inner { value: -1 },
+      list: vec![Item { value: 1 }, Item { value: 2 }],
+    }).expect("can't serialize value");
+
+    let view = GffView::new(&data).expect("can't read GFF header");
+    let root = view.root().expect("can't read root struct");
+    let labels: Vec<_> = root.fields().map(|f| f.unwrap().label().unwrap().to_string()).collect();
+    assert_eq!(labels, vec!["inner", "list"]);
+
+    match root.field(0).unwrap().unwrap().value().unwrap() {
+      FieldValue::Struct(inner) => {
+        let value = inner.get("value").unwrap().unwrap().value().unwrap();
+        assert!(matches!(value, FieldValue::Simple(SimpleValue::Short(-1))));
+      },
+      value => panic!("expected struct, but {:?} found", value),
+    }
+    assert!(root.field(2).unwrap().is_none());
+
+    match root.get("list").unwrap().unwrap().value().unwrap() {
+      FieldValue::List(list) => {
+        assert_eq!(list.len(), 2);
+        let values: Vec<_> = list.iter().map(|item| {
+          match item.unwrap().field(0).unwrap().unwrap().value().unwrap() {
+            FieldValue::Simple(SimpleValue::Dword(value)) => value,
+            value => panic!("expected dword, but {:?} found", value),
+          }
+        }).collect();
+        assert_eq!(values, vec![1, 2]);
+        assert!(list.get(2).unwrap().is_none());
+      },
+      value => panic!("expected list, but {:?} found", value),
+    }
+  }
+}